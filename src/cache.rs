@@ -0,0 +1,279 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+    error::Result,
+    runtime::{
+        thread::RwLock,
+        time::{Duration, Instant},
+    },
+};
+
+/// Something that can produce a fresh value for a [`Cache`], e.g. an OAuth token provider.
+#[async_trait]
+pub trait Refresher<T> {
+    /// Fetches a new value, along with how long it will stay valid.
+    async fn refresh(&mut self) -> Result<(T, Duration)>;
+}
+
+#[async_trait]
+impl<T, R: Refresher<T> + Send + ?Sized> Refresher<T> for Box<R> {
+    async fn refresh(&mut self) -> Result<(T, Duration)> {
+        (**self).refresh().await
+    }
+}
+
+/// Holds a value that expires, refreshing it when it is requested after it expired.
+pub struct Cache<T> {
+    value: Option<T>,
+    expires_at: Option<Instant>,
+    /// How long before the actual expiry we already consider the value to be expired.
+    margin: Duration,
+}
+
+impl<T: Send> Default for Cache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Send> Cache<T> {
+    /// The default margin, to make sure a value does not expire while it is being used.
+    const DEFAULT_MARGIN: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        Self {
+            value: None,
+            expires_at: None,
+            margin: Self::DEFAULT_MARGIN,
+        }
+    }
+
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+
+        self
+    }
+
+    /// Stores a value that is valid for the given duration.
+    pub fn set(&mut self, value: T, valid_for: Duration) {
+        self.value = Some(value);
+        self.expires_at = Some(Instant::now() + valid_for);
+    }
+
+    /// Removes the cached value, forcing a refresh on the next call to [`Cache::get`].
+    pub fn invalidate(&mut self) {
+        self.value = None;
+        self.expires_at = None;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Instant::now() + self.margin >= expires_at,
+            None => true,
+        }
+    }
+
+    /// Returns the cached value, refreshing it first if it is missing or expired.
+    pub async fn get<R: Refresher<T> + Send>(&mut self, refresher: &mut R) -> Result<&T> {
+        if self.value.is_none() || self.is_expired() {
+            let (value, valid_for) = refresher.refresh().await?;
+
+            self.set(value, valid_for);
+        }
+
+        // We just made sure there is a value.
+        Ok(self.value.as_ref().unwrap())
+    }
+}
+
+type SharedEntry<T> = (Cache<T>, Box<dyn Refresher<T> + Send + Sync>);
+
+/// A [`Cache`] together with the [`Refresher`] that fills it, which can be cloned to share the value between connections.
+pub struct SharedCache<T> {
+    inner: Arc<RwLock<SharedEntry<T>>>,
+}
+
+impl<T> Clone for SharedCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync> SharedCache<T> {
+    pub fn new<R: Refresher<T> + Send + Sync + 'static>(cache: Cache<T>, refresher: R) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new((cache, Box::new(refresher)))),
+        }
+    }
+
+    /// Returns a copy of the cached value, refreshing it first if it is missing or expired.
+    pub async fn get(&self) -> Result<T> {
+        let mut inner = self.inner.write().await;
+
+        let (cache, refresher) = &mut *inner;
+
+        cache.get(refresher).await.cloned()
+    }
+}
+
+/// A map that holds a limited amount of items, dropping the least recently used item when a new one does not fit.
+///
+/// Lookups are linear, so this is meant for small caches (up to a few hundred items).
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// The items, with the most recently used item at the front.
+    items: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.items.iter().any(|(item_key, _)| item_key == key)
+    }
+
+    /// Returns the value for a key, marking it as the most recently used item.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let index = self
+            .items
+            .iter()
+            .position(|(item_key, _)| item_key == key)?;
+
+        let item = self.items.remove(index)?;
+
+        self.items.push_front(item);
+
+        self.items.front().map(|(_, value)| value)
+    }
+
+    /// Stores a value, replacing any value with the same key and dropping the least recently used item if the cache is full.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.items.len() >= self.capacity {
+            self.items.pop_back();
+        }
+
+        self.items.push_front((key, value));
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self
+            .items
+            .iter()
+            .position(|(item_key, _)| item_key == key)?;
+
+        self.items.remove(index).map(|(_, value)| value)
+    }
+
+    /// Removes every item for which `keep` returns false.
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        self.items.retain(|(key, value)| keep(key, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct CountingRefresher {
+        count: usize,
+        valid_for: Duration,
+    }
+
+    #[async_trait]
+    impl Refresher<String> for CountingRefresher {
+        async fn refresh(&mut self) -> Result<(String, Duration)> {
+            self.count += 1;
+
+            Ok((format!("token-{}", self.count), self.valid_for))
+        }
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn reuses_valid_value() {
+        let mut refresher = CountingRefresher {
+            count: 0,
+            valid_for: Duration::from_secs(3600),
+        };
+
+        let mut cache: Cache<String> = Cache::new();
+
+        assert_eq!(cache.get(&mut refresher).await.unwrap(), "token-1");
+        assert_eq!(cache.get(&mut refresher).await.unwrap(), "token-1");
+        assert_eq!(refresher.count, 1);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn refreshes_expired_value() {
+        let mut refresher = CountingRefresher {
+            count: 0,
+            // Shorter than the margin, so the value is expired immediately.
+            valid_for: Duration::from_secs(30),
+        };
+
+        let mut cache: Cache<String> = Cache::new();
+
+        assert_eq!(cache.get(&mut refresher).await.unwrap(), "token-1");
+        assert_eq!(cache.get(&mut refresher).await.unwrap(), "token-2");
+
+        cache.invalidate();
+
+        assert_eq!(cache.get(&mut refresher).await.unwrap(), "token-3");
+    }
+
+    #[test]
+    fn drops_least_recently_used() {
+        let mut cache = LruCache::new(2);
+
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+
+        // Using `a` makes `b` the least recently used item.
+        assert_eq!(cache.get(&"a"), Some(&1));
+
+        cache.insert("c", 3);
+
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&"b"));
+        assert_eq!(cache.remove(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+
+        let mut disabled = LruCache::new(0);
+
+        disabled.insert("a", 1);
+
+        assert!(disabled.is_empty());
+    }
+}
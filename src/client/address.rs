@@ -1,3 +1,5 @@
+use std::result;
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -5,7 +7,7 @@ use crate::error::Result;
 
 use super::parser;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EmailAddress {
     name: Option<String>,
@@ -20,11 +22,27 @@ impl EmailAddress {
     pub fn name(&self) -> Option<&String> {
         self.name.as_ref()
     }
+
+    /// The name to show for this address: the display name if it has one, otherwise the email address itself.
+    pub fn display_name(&self) -> &str {
+        match self.name.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => name,
+            _ => &self.email,
+        }
+    }
+
+    /// Just the local part of the email address, e.g. `user` for `user@example.com`.
+    pub fn short_display(&self) -> &str {
+        match self.email.rsplit_once('@') {
+            Some((local_part, _)) if !local_part.is_empty() => local_part,
+            _ => &self.email,
+        }
+    }
 }
 
-impl Into<Address> for EmailAddress {
-    fn into(self) -> Address {
-        Address::Single(self)
+impl From<EmailAddress> for Address {
+    fn from(val: EmailAddress) -> Self {
+        Address::Single(val)
     }
 }
 
@@ -40,7 +58,7 @@ impl EmailAddress {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Address {
     Group {
@@ -88,6 +106,22 @@ impl Address {
             Address::Single(addr) => Some(addr),
         }
     }
+
+    /// The name to show for this address. For a group this is the group's name, falling back to its first member.
+    pub fn display_name(&self) -> &str {
+        match self {
+            Address::Single(addr) => addr.display_name(),
+            Address::Group { name, .. } => match name.as_deref().map(str::trim) {
+                Some(name) if !name.is_empty() => name,
+                _ => self.first().map_or("", EmailAddress::display_name),
+            },
+        }
+    }
+
+    /// The local part of the (first) email address, see [`EmailAddress::short_display`].
+    pub fn short_display(&self) -> &str {
+        self.first().map_or("", EmailAddress::short_display)
+    }
 }
 
 impl From<email::Address> for Address {
@@ -124,9 +158,9 @@ impl<N: Into<String>, A: Into<String>> From<(N, A)> for Address {
     }
 }
 
-impl<'a> Into<mail_builder::headers::address::Address<'a>> for Address {
-    fn into(self) -> mail_builder::headers::address::Address<'a> {
-        match self {
+impl<'a> From<Address> for mail_builder::headers::address::Address<'a> {
+    fn from(val: Address) -> Self {
+        match val {
             Address::Group { name, list } => mail_builder::headers::address::Address::new_group(
                 name,
                 list.into_iter().map(|item| item.into()).collect(),
@@ -142,4 +176,64 @@ impl Address {
     pub fn from_header<H: Into<String>>(header: H) -> Result<Vec<Self>> {
         parser::address::address_list(header)
     }
+
+    /// Checks whether all of the email addresses in this address are valid, returning the first invalid one if there is one.
+    pub fn validate(&self) -> result::Result<(), &EmailAddress> {
+        match self
+            .as_list()
+            .into_iter()
+            .find(|address| !validate_email_address(address.email()))
+        {
+            Some(invalid) => Err(invalid),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks whether a given string is a valid email address, e.g. `user@example.com`.
+pub fn validate_email_address<A: AsRef<str>>(address: A) -> bool {
+    validator::validate_email(address.as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_addresses() {
+        assert!(validate_email_address("user@example.com"));
+        assert!(!validate_email_address("user@"));
+        assert!(!validate_email_address("not an address"));
+
+        let address: Address = vec![
+            Address::from(("User", "user@example.com")),
+            Address::from(("Invalid", "invalid.example.com")),
+        ]
+        .into();
+
+        assert_eq!(
+            address.validate().unwrap_err().email(),
+            "invalid.example.com"
+        );
+    }
+
+    #[test]
+    fn display_names() {
+        let named = Address::from(("User", "user@example.com"));
+        let unnamed = Address::single(Some(" ".into()), "other@example.com".into());
+
+        assert_eq!(named.display_name(), "User");
+        assert_eq!(unnamed.display_name(), "other@example.com");
+        assert_eq!(unnamed.short_display(), "other");
+
+        let group: Address = vec![unnamed, named].into();
+
+        assert_eq!(group.display_name(), "other@example.com");
+        assert_eq!(group.short_display(), "other");
+
+        let named_group = Address::group(Some("Team".into()), Vec::new());
+
+        assert_eq!(named_group.display_name(), "Team");
+        assert_eq!(named_group.short_display(), "");
+    }
 }
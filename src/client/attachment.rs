@@ -1,20 +1,37 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// How a part of a message is meant to be presented.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Disposition {
+    /// Shown as part of the message body, like an image that is referenced from the html.
+    Inline,
+    /// Offered as a separate file to download.
+    Attachment,
+}
+
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Attachment {
     id: String,
     file_name: Option<String>,
     size: usize,
+    disposition: Disposition,
 }
 
 impl Attachment {
-    pub fn new(id: String, file_name: Option<String>, size: usize) -> Self {
+    pub fn new(
+        id: String,
+        file_name: Option<String>,
+        size: usize,
+        disposition: Disposition,
+    ) -> Self {
         Self {
             id,
             file_name,
             size,
+            disposition,
         }
     }
 
@@ -29,4 +46,13 @@ impl Attachment {
     pub fn size(&self) -> usize {
         self.size
     }
+
+    pub fn disposition(&self) -> Disposition {
+        self.disposition
+    }
+
+    /// Whether this part is shown in the message body instead of being offered as a download.
+    pub fn is_inline(&self) -> bool {
+        self.disposition == Disposition::Inline
+    }
 }
@@ -0,0 +1,35 @@
+use super::digest::{to_hex, Hash};
+
+/// The answer to a CRAM-MD5 challenge (RFC 2195), which proves we know the password without sending it.
+pub fn cram_md5(username: &str, password: &str, challenge: &[u8]) -> String {
+    let digest = Hash::Md5.hmac(password.as_bytes(), challenge);
+
+    format!("{} {}", username, to_hex(&digest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn challenge_response() {
+        // The example from RFC 2195 section 2.
+        assert_eq!(
+            cram_md5(
+                "tim",
+                "tanstaaftanstaaf",
+                b"<1896.697170952@postoffice.reston.mci.net>"
+            ),
+            "tim b913a602c7eda7a495b4e6e7334d3890"
+        );
+
+        assert_eq!(
+            cram_md5(
+                "user@example.com",
+                "secret",
+                b"<17893.1320679123@mail.example.com>"
+            ),
+            "user@example.com e958d9c4f426b83e5887dcaa1158e8b5"
+        );
+    }
+}
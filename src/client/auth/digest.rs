@@ -0,0 +1,128 @@
+//! The hash functions used by the challenge-response mechanisms.
+
+use hmac::{
+    digest::{FixedOutput, KeyInit, OutputSizeUser},
+    Hmac, Mac,
+};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hash {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl Hash {
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Md5 => Md5::digest(data).to_vec(),
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    /// HMAC (RFC 2104) using this hash function.
+    pub fn hmac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Md5 => mac::<Hmac<Md5>>(key, message),
+            Self::Sha1 => mac::<Hmac<Sha1>>(key, message),
+            Self::Sha256 => mac::<Hmac<Sha256>>(key, message),
+        }
+    }
+
+    /// PBKDF2 (RFC 8018) using HMAC with this hash function, with a key as long as the hash.
+    pub fn pbkdf2(&self, password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        match self {
+            Self::Md5 => derive_key::<Hmac<Md5>>(password, salt, iterations),
+            Self::Sha1 => derive_key::<Hmac<Sha1>>(password, salt, iterations),
+            Self::Sha256 => derive_key::<Hmac<Sha256>>(password, salt, iterations),
+        }
+    }
+}
+
+fn mac<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    // HMAC hashes keys that are too long, so any key length is accepted.
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn derive_key<M: Mac + KeyInit + FixedOutput + Clone + Sync>(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+) -> Vec<u8> {
+    let mut key = vec![0; <M as OutputSizeUser>::output_size()];
+
+    pbkdf2::pbkdf2::<M>(password, salt, iterations, &mut key)
+        .expect("HMAC accepts keys of any length");
+
+    key
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_digest() {
+        // From RFC 6070 section 2 and RFC 7914 section 11.
+        assert_eq!(
+            to_hex(&Hash::Sha1.pbkdf2(b"password", b"salt", 4096)),
+            "4b007901b765489abead49d926f721d065a429c1"
+        );
+        assert_eq!(
+            to_hex(&Hash::Sha256.pbkdf2(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+
+    #[test]
+    fn md5_digest() {
+        // Test suite from RFC 1321 appendix A.5.
+        let vectors = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+
+        for (input, digest) in vectors {
+            assert_eq!(to_hex(&Hash::Md5.digest(input.as_bytes())), digest);
+        }
+    }
+
+    #[test]
+    fn hmac_digest() {
+        // From RFC 2104 and RFC 4231 section 4.3.
+        assert_eq!(
+            to_hex(&Hash::Md5.hmac(b"Jefe", b"what do ya want for nothing?")),
+            "750c783e6ab0b503eaa86e310a5db738"
+        );
+        assert_eq!(
+            to_hex(&Hash::Sha256.hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        // Keys longer than a block are hashed first (RFC 2202 section 2, test case 6).
+        assert_eq!(
+            to_hex(&Hash::Md5.hmac(
+                &[0xaa; 80],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "6b1ab7fe4bd7bf8f0b62e6ce61b9d0cd"
+        );
+    }
+}
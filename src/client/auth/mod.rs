@@ -0,0 +1,179 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::fmt::{self, Display};
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio")
+))]
+use super::Credentials;
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+mod cram;
+#[cfg(any(feature = "imap", feature = "pop"))]
+mod digest;
+#[cfg(any(feature = "imap", feature = "pop"))]
+mod scram;
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+pub(crate) use self::{cram::cram_md5, digest::Hash, scram::ScramClient};
+
+/// How a session logged in to the mail server, useful to diagnose login problems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AuthMechanism {
+    /// The login command of the protocol itself (Imap LOGIN or Pop USER/PASS), used when the server does not advertise a SASL mechanism we support.
+    LoginCommand,
+    /// SASL PLAIN (RFC 4616).
+    Plain,
+    /// CRAM-MD5 (RFC 2195), which does not send the password but requires the server to store it as is.
+    CramMd5,
+    /// SCRAM-SHA-1 (RFC 5802), which does not send the password and also proves the server knows it.
+    ScramSha1,
+    /// SCRAM-SHA-256 (RFC 7677).
+    ScramSha256,
+    /// SASL LOGIN, only used by Smtp servers.
+    Login,
+    XOAuth2,
+}
+
+impl AuthMechanism {
+    /// The name of the mechanism as advertised by the server, if it is a SASL mechanism.
+    pub fn sasl_name(&self) -> Option<&'static str> {
+        match self {
+            Self::LoginCommand => None,
+            Self::Plain => Some("PLAIN"),
+            Self::CramMd5 => Some("CRAM-MD5"),
+            Self::ScramSha1 => Some("SCRAM-SHA-1"),
+            Self::ScramSha256 => Some("SCRAM-SHA-256"),
+            Self::Login => Some("LOGIN"),
+            Self::XOAuth2 => Some("XOAUTH2"),
+        }
+    }
+
+    /// The hash function used by the mechanism, if it is a SCRAM mechanism.
+    #[cfg(any(feature = "imap", feature = "pop"))]
+    pub(crate) fn scram_hash(&self) -> Option<Hash> {
+        match self {
+            Self::ScramSha1 => Some(Hash::Sha1),
+            Self::ScramSha256 => Some(Hash::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AuthMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sasl_name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "login command"),
+        }
+    }
+}
+
+/// The mechanisms that can log in with a password, strongest first. The challenge-response mechanisms never send the password itself, so they are preferred even over Tls.
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio")
+))]
+const PASSWORD_MECHANISMS: [AuthMechanism; 5] = [
+    AuthMechanism::ScramSha256,
+    AuthMechanism::ScramSha1,
+    AuthMechanism::CramMd5,
+    AuthMechanism::Plain,
+    AuthMechanism::Login,
+];
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio")
+))]
+const OAUTH_MECHANISMS: [AuthMechanism; 1] = [AuthMechanism::XOAuth2];
+
+/// Picks the strongest mechanism that can be used with the given credentials, out of the ones the client implements and the server advertises.
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio")
+))]
+pub(crate) fn negotiate<M: AsRef<str>>(
+    credentials: &Credentials,
+    implemented: &[AuthMechanism],
+    advertised: &[M],
+) -> Option<AuthMechanism> {
+    let preference: &[AuthMechanism] = match credentials {
+        Credentials::Password { .. } => &PASSWORD_MECHANISMS,
+        Credentials::OAuth { .. } => &OAUTH_MECHANISMS,
+    };
+
+    preference
+        .iter()
+        .filter(|mechanism| implemented.contains(mechanism))
+        .find(|mechanism| match mechanism.sasl_name() {
+            Some(name) => advertised
+                .iter()
+                .any(|advertised| advertised.as_ref().eq_ignore_ascii_case(name)),
+            None => false,
+        })
+        .copied()
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio")
+    )
+))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate_mechanism() {
+        let password = Credentials::password("user", "secret");
+        let oauth = Credentials::oauth("user", "token");
+
+        let implemented = [
+            AuthMechanism::Plain,
+            AuthMechanism::Login,
+            AuthMechanism::XOAuth2,
+        ];
+
+        assert_eq!(
+            negotiate(&password, &implemented, &["login", "PLAIN", "XOAUTH2"]),
+            Some(AuthMechanism::Plain)
+        );
+        assert_eq!(
+            negotiate(&password, &[AuthMechanism::Plain], &["LOGIN"]),
+            None
+        );
+
+        let challenge_response = [
+            AuthMechanism::Plain,
+            AuthMechanism::CramMd5,
+            AuthMechanism::ScramSha1,
+        ];
+
+        assert_eq!(
+            negotiate(&password, &challenge_response, &["PLAIN", "CRAM-MD5"]),
+            Some(AuthMechanism::CramMd5)
+        );
+        assert_eq!(
+            negotiate(
+                &password,
+                &challenge_response,
+                &["PLAIN", "SCRAM-SHA-1", "SCRAM-SHA-256", "CRAM-MD5"]
+            ),
+            Some(AuthMechanism::ScramSha1)
+        );
+        assert_eq!(
+            negotiate(&oauth, &implemented, &["PLAIN", "XOAUTH2"]),
+            Some(AuthMechanism::XOAuth2)
+        );
+        assert_eq!(negotiate(&oauth, &implemented, &["PLAIN"]), None);
+    }
+}
@@ -0,0 +1,288 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::error::{err, ErrorKind, Result};
+
+use super::digest::Hash;
+
+/// The length of the nonce we generate, the examples in RFC 5802 use 24 characters as well.
+const NONCE_LENGTH: usize = 24;
+
+/// We do not support channel binding, so the GS2 header is always the same.
+const GS2_HEADER: &str = "n,,";
+
+/// Servers use at least 4096 iterations, this only guards against a server making us compute forever.
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+#[derive(Debug, Clone)]
+enum State {
+    Initial,
+    /// We sent our proof and expect the server to prove it knows the password as well.
+    ProofSent {
+        server_signature: Vec<u8>,
+    },
+    Verified,
+}
+
+/// The client side of a SCRAM exchange (RFC 5802), which proves we know the password without sending it and checks the server knows it too.
+///
+/// The password is used as is, without SASLprep, which only matters for passwords with unusual unicode characters.
+#[derive(Debug, Clone)]
+pub struct ScramClient {
+    hash: Hash,
+    username: String,
+    password: String,
+    nonce: String,
+    state: State,
+}
+
+/// Escapes the characters that have a meaning in SCRAM messages.
+fn sasl_name(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+/// Finds the value of an attribute like `r=` in a server message.
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|field| {
+        let mut chars = field.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(key), Some('=')) if key == name => Some(&field[2..]),
+            _ => None,
+        }
+    })
+}
+
+fn xor(left: &[u8], right: &[u8]) -> Vec<u8> {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| left ^ right)
+        .collect()
+}
+
+impl ScramClient {
+    pub fn new<U: Into<String>, P: Into<String>>(hash: Hash, username: U, password: P) -> Self {
+        let nonce = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(NONCE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self::with_nonce(hash, username, password, nonce)
+    }
+
+    fn with_nonce<U: Into<String>, P: Into<String>>(
+        hash: Hash,
+        username: U,
+        password: P,
+        nonce: String,
+    ) -> Self {
+        Self {
+            hash,
+            username: username.into(),
+            password: password.into(),
+            nonce,
+            state: State::Initial,
+        }
+    }
+
+    fn client_first_bare(&self) -> String {
+        format!("n={},r={}", sasl_name(&self.username), self.nonce)
+    }
+
+    /// The first message, which the client sends to start the exchange.
+    pub fn client_first(&self) -> String {
+        format!("{}{}", GS2_HEADER, self.client_first_bare())
+    }
+
+    /// PBKDF2 with a key as long as the hash, called `Hi` in RFC 5802. This takes a while with a lot of iterations, so the exchange should not run on an async task.
+    fn salted_password(&self, salt: &[u8], iterations: u32) -> Vec<u8> {
+        self.hash.pbkdf2(self.password.as_bytes(), salt, iterations)
+    }
+
+    /// Answers the first message of the server with our proof.
+    pub fn client_final(&mut self, server_first: &[u8]) -> Result<String> {
+        let server_first = std::str::from_utf8(server_first)?;
+
+        if attribute(server_first, 'm').is_some() {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server requires a SCRAM extension we do not support"
+            )
+        }
+
+        let (nonce, salt, iterations) = match (
+            attribute(server_first, 'r'),
+            attribute(server_first, 's'),
+            attribute(server_first, 'i').and_then(|iterations| iterations.parse::<u32>().ok()),
+        ) {
+            (Some(nonce), Some(salt), Some(iterations)) => (nonce, salt, iterations),
+            _ => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server sent an invalid SCRAM challenge: {}",
+                server_first
+            ),
+        };
+
+        // The server appends its own part to our nonce, so a reply to another exchange is refused.
+        if !nonce.starts_with(&self.nonce) || nonce.len() == self.nonce.len() {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server sent a SCRAM nonce that does not match ours"
+            )
+        }
+
+        if iterations == 0 || iterations > MAX_ITERATIONS {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server requested an unreasonable amount of SCRAM iterations: {}",
+                iterations
+            )
+        }
+
+        let salt = match STANDARD.decode(salt) {
+            Ok(salt) => salt,
+            Err(_) => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server sent a SCRAM salt that is not valid base64"
+            ),
+        };
+
+        let salted_password = self.salted_password(&salt, iterations);
+
+        let client_final_without_proof = format!("c={},r={}", STANDARD.encode(GS2_HEADER), nonce);
+
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare(),
+            server_first,
+            client_final_without_proof
+        );
+
+        let client_key = self.hash.hmac(&salted_password, b"Client Key");
+        let stored_key = self.hash.digest(&client_key);
+        let client_signature = self.hash.hmac(&stored_key, auth_message.as_bytes());
+        let client_proof = xor(&client_key, &client_signature);
+
+        let server_key = self.hash.hmac(&salted_password, b"Server Key");
+        let server_signature = self.hash.hmac(&server_key, auth_message.as_bytes());
+
+        self.state = State::ProofSent { server_signature };
+
+        Ok(format!(
+            "{},p={}",
+            client_final_without_proof,
+            STANDARD.encode(client_proof)
+        ))
+    }
+
+    /// Checks the signature in the last message of the server, which proves it knows the password.
+    pub fn verify(&mut self, server_final: &[u8]) -> Result<()> {
+        let server_final = std::str::from_utf8(server_final)?;
+
+        if let Some(error) = attribute(server_final, 'e') {
+            err!(
+                ErrorKind::MailServer,
+                "Server refused the SCRAM login: {}",
+                error
+            )
+        }
+
+        let verified = match (&self.state, attribute(server_final, 'v')) {
+            (State::ProofSent { server_signature }, Some(signature)) => STANDARD
+                .decode(signature)
+                .map(|signature| &signature == server_signature)
+                .unwrap_or(false),
+            _ => false,
+        };
+
+        if !verified {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server could not prove it knows the password, it may not be the server it claims to be"
+            )
+        }
+
+        self.state = State::Verified;
+
+        Ok(())
+    }
+
+    /// Whether the server proved it knows the password, which is required for the login to be trusted.
+    #[cfg(any(feature = "imap", test))]
+    pub fn is_verified(&self) -> bool {
+        matches!(self.state, State::Verified)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn exchange(
+        hash: Hash,
+        nonce: &str,
+        server_first: &str,
+        client_final: &str,
+        server_final: &str,
+    ) {
+        let mut client = ScramClient::with_nonce(hash, "user", "pencil", nonce.to_string());
+
+        assert_eq!(client.client_first(), format!("n,,n=user,r={}", nonce));
+        assert_eq!(
+            client.client_final(server_first.as_bytes()).unwrap(),
+            client_final
+        );
+        assert!(!client.is_verified());
+
+        client.verify(server_final.as_bytes()).unwrap();
+
+        assert!(client.is_verified());
+    }
+
+    #[test]
+    fn sha1_exchange() {
+        // The example from RFC 5802 section 5.
+        exchange(
+            Hash::Sha1,
+            "fyko+d2lbbFgONRv9qkxdawL",
+            "r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096",
+            "c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts=",
+            "v=rmF9pqV8S7suAoZWja4dJRkFsKQ=",
+        );
+    }
+
+    #[test]
+    fn sha256_exchange() {
+        // The example from RFC 7677 section 3.
+        exchange(
+            Hash::Sha256,
+            "rOprNGfwEbeRWgbNEkqO",
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+            "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=",
+        );
+    }
+
+    #[test]
+    fn refuse_invalid_server() {
+        let nonce = "fyko+d2lbbFgONRv9qkxdawL";
+        let server_first = "r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096";
+
+        let mut client = ScramClient::with_nonce(Hash::Sha1, "user", "pencil", nonce.to_string());
+
+        // A nonce that does not start with ours.
+        assert!(client
+            .client_final(b"r=3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096")
+            .is_err());
+
+        client.client_final(server_first.as_bytes()).unwrap();
+
+        // A signature computed with another password.
+        assert!(client.verify(b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAA=").is_err());
+        assert!(client.verify(b"e=invalid-proof").is_err());
+        assert!(!client.is_verified());
+
+        assert_eq!(sasl_name("a=b,c"), "a=3Db=2Cc");
+    }
+}
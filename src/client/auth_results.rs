@@ -0,0 +1,66 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The outcome of a single authentication check, as reported by the receiving mail server.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AuthStatus {
+    Pass,
+    Fail,
+    /// The check failed, but the domain does not ask for the message to be rejected.
+    SoftFail,
+    /// The domain makes no statement about whether the sender is authorized.
+    Neutral,
+    /// The message could not be checked, e.g. because it was not signed or the domain has no policy.
+    None,
+    /// The check could not be completed because of a temporary error, like a DNS timeout.
+    TempError,
+    /// The check could not be completed because the domain's records are invalid.
+    PermError,
+    /// The message passed the check, but was rejected because of a local policy.
+    Policy,
+}
+
+impl AuthStatus {
+    pub fn is_pass(&self) -> bool {
+        self == &Self::Pass
+    }
+}
+
+/// The SPF, DKIM and DMARC results of a message, taken from its `Authentication-Results` and `Received-SPF` headers.
+///
+/// These headers are added by the receiving mail server, so they can only be trusted if that server removes any such headers that were already present in the message.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AuthResults {
+    pub(crate) spf: Option<AuthStatus>,
+    pub(crate) dkim: Option<AuthStatus>,
+    pub(crate) dmarc: Option<AuthStatus>,
+}
+
+impl AuthResults {
+    /// Whether the sending server was authorized to send mail for the sender's domain.
+    pub fn spf(&self) -> Option<&AuthStatus> {
+        self.spf.as_ref()
+    }
+
+    /// Whether the message has a valid signature. When there are multiple signatures, this passes if any of them is valid.
+    pub fn dkim(&self) -> Option<&AuthStatus> {
+        self.dkim.as_ref()
+    }
+
+    /// Whether the message passed the policy of the domain in its `From` header.
+    pub fn dmarc(&self) -> Option<&AuthStatus> {
+        self.dmarc.as_ref()
+    }
+
+    /// Whether every check that was performed passed, and at least one check was performed.
+    pub fn is_trusted(&self) -> bool {
+        let results = [&self.spf, &self.dkim, &self.dmarc];
+
+        results.iter().any(|result| result.is_some())
+            && results
+                .iter()
+                .all(|result| result.as_ref().map(AuthStatus::is_pass).unwrap_or(true))
+    }
+}
@@ -3,13 +3,24 @@ use std::{collections::HashMap, fmt::Display, result};
 use crate::error::{err, Error, ErrorKind, Result};
 
 use super::{
-    address::Address, attachment::Attachment, content::Content, incoming::types::flag::Flag,
-    parser, Headers,
+    address::Address,
+    attachment::Attachment,
+    auth_results::AuthResults,
+    content::Content,
+    delivery_status::DeliveryStatus,
+    incoming::types::{flag::Flag, message::Message},
+    outgoing::types::sendable::SendableMessage,
+    parser, HeaderList, Headers,
 };
 
 #[derive(Debug)]
 pub struct MessageBuilder {
     pub(crate) from: Option<Address>,
+    pub(crate) sender: Option<Address>,
+    pub(crate) return_path: Option<String>,
+    pub(crate) envelope_from: Option<String>,
+    pub(crate) read_receipt_to: Option<Address>,
+    pub(crate) disposition_notification: Option<String>,
     pub(crate) to: Option<Address>,
     pub(crate) cc: Option<Address>,
     pub(crate) bcc: Option<Address>,
@@ -18,8 +29,17 @@ pub struct MessageBuilder {
     pub(crate) sent: Option<i64>,
     pub(crate) subject: Option<String>,
     pub(crate) headers: Option<Headers>,
+    pub(crate) raw_headers: HeaderList,
     pub(crate) attachments: Vec<Attachment>,
     pub(crate) content: Content,
+    pub(crate) delivery_status: Option<DeliveryStatus>,
+    pub(crate) auth_results: Option<AuthResults>,
+    pub(crate) languages: Vec<String>,
+    pub(crate) thread_index: Option<String>,
+    pub(crate) thread_topic: Option<String>,
+    pub(crate) gmail_thread_id: Option<String>,
+    pub(crate) signed: bool,
+    pub(crate) encrypted: bool,
 }
 
 #[cfg(feature = "maildir")]
@@ -63,11 +83,22 @@ impl TryFrom<&[u8]> for MessageBuilder {
     }
 }
 
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MessageBuilder {
     pub fn new() -> Self {
         Self {
             flags: Vec::new(),
             from: None,
+            sender: None,
+            return_path: None,
+            envelope_from: None,
+            read_receipt_to: None,
+            disposition_notification: None,
             bcc: None,
             cc: None,
             to: None,
@@ -77,15 +108,20 @@ impl MessageBuilder {
             content: Content::default(),
             attachments: Vec::new(),
             headers: None,
+            raw_headers: Vec::new(),
+            delivery_status: None,
+            auth_results: None,
+            languages: Vec::new(),
+            thread_index: None,
+            thread_topic: None,
+            gmail_thread_id: None,
+            signed: false,
+            encrypted: false,
         }
     }
 
     pub fn flags<F: IntoIterator<Item = Flag>>(mut self, flags: F) -> Self {
-        let mut iter = flags.into_iter();
-
-        while let Some(flag) = iter.next() {
-            self.flags.push(flag)
-        }
+        self.flags.extend(flags);
 
         self
     }
@@ -102,6 +138,37 @@ impl MessageBuilder {
         self
     }
 
+    /// The mailbox that actually sent the message, when it differs from the author(s) in the `From` header.
+    pub fn sender<C: Into<Address>>(mut self, sender: C) -> Self {
+        self.sender = Some(sender.into());
+
+        self
+    }
+
+    pub fn return_path<R: Into<String>>(mut self, return_path: R) -> Self {
+        self.return_path = Some(return_path.into());
+
+        self
+    }
+
+    /// The address that is used as the envelope sender (SMTP `MAIL FROM`) when sending the message, which is where bounces are delivered.
+    ///
+    /// Defaults to the first address in the `From` header.
+    pub fn envelope_from<E: Into<String>>(mut self, envelope_from: E) -> Self {
+        self.envelope_from = Some(envelope_from.into());
+
+        self
+    }
+
+    /// Where a read receipt should be sent, from the `Disposition-Notification-To` header.
+    ///
+    /// When composing a message, this requests a read receipt from the recipients.
+    pub fn read_receipt_to<C: Into<Address>>(mut self, address: C) -> Self {
+        self.read_receipt_to = Some(address.into());
+
+        self
+    }
+
     pub fn recipients<C: Into<Address>>(mut self, recipient: C) -> Self {
         self.to = Some(recipient.into());
 
@@ -126,6 +193,7 @@ impl MessageBuilder {
         self
     }
 
+    /// The date the message was sent in seconds since epoch, which becomes the `Date` header of an outgoing message.
     pub fn sent(mut self, sent: i64) -> Self {
         self.sent = Some(sent);
 
@@ -150,8 +218,15 @@ impl MessageBuilder {
         self
     }
 
+    /// The headers in the order they appear in the message, including duplicates.
+    pub fn raw_headers(mut self, raw_headers: HeaderList) -> Self {
+        self.raw_headers = raw_headers;
+
+        self
+    }
+
     pub fn header<H: Into<String>, V: Display>(mut self, header: H, value: V) -> Self {
-        if let None = self.headers {
+        if self.headers.is_none() {
             self.headers = Some(HashMap::new());
         }
 
@@ -162,6 +237,62 @@ impl MessageBuilder {
         self
     }
 
+    /// The delivery status report, if the message is a bounce.
+    pub fn delivery_status(mut self, delivery_status: DeliveryStatus) -> Self {
+        self.delivery_status = Some(delivery_status);
+
+        self
+    }
+
+    /// The SPF, DKIM and DMARC results reported by the receiving mail server.
+    pub fn auth_results(mut self, auth_results: AuthResults) -> Self {
+        self.auth_results = Some(auth_results);
+
+        self
+    }
+
+    /// The languages of the intended audience, from the `Content-Language` header.
+    pub fn languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = languages;
+
+        self
+    }
+
+    /// The Outlook `Thread-Index` header.
+    pub fn thread_index<T: Into<String>>(mut self, thread_index: T) -> Self {
+        self.thread_index = Some(thread_index.into());
+
+        self
+    }
+
+    /// The Outlook `Thread-Topic` header.
+    pub fn thread_topic<T: Into<String>>(mut self, thread_topic: T) -> Self {
+        self.thread_topic = Some(thread_topic.into());
+
+        self
+    }
+
+    /// The conversation id Gmail assigned to the message.
+    pub fn gmail_thread_id<T: Into<String>>(mut self, gmail_thread_id: T) -> Self {
+        self.gmail_thread_id = Some(gmail_thread_id.into());
+
+        self
+    }
+
+    /// Whether the message is signed using S/MIME or PGP.
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.signed = signed;
+
+        self
+    }
+
+    /// Whether the message is encrypted using S/MIME or PGP.
+    pub fn encrypted(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+
+        self
+    }
+
     pub fn html<H: Into<String>>(mut self, html: H) -> Self {
         self.content.set_html(html);
 
@@ -174,6 +305,54 @@ impl MessageBuilder {
         self
     }
 
+    /// Builds the read receipt (a message disposition notification, RFC 8098) for a message that requested one, to be sent from the sender set on this builder.
+    pub fn build_read_receipt(self, original: &Message) -> Result<SendableMessage> {
+        let read_receipt_to = match original.read_receipt_to() {
+            Some(address) => address.clone(),
+            None => err!(
+                ErrorKind::InvalidMessage,
+                "The message did not request a read receipt"
+            ),
+        };
+
+        let recipient = match self.from.as_ref().and_then(|from| from.first()) {
+            Some(from) => from.email().to_string(),
+            None => err!(ErrorKind::InvalidMessage, "Missing message sender"),
+        };
+
+        let subject = original.subject().unwrap_or_default();
+
+        let mut report = format!(
+            "Reporting-UA: {}; Dust-Mail\r\nFinal-Recipient: rfc822; {}\r\n",
+            recipient
+                .rsplit_once('@')
+                .map_or("localhost", |(_, domain)| domain),
+            recipient
+        );
+
+        if let Some((_, message_id)) = original
+            .raw_headers()
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Message-ID"))
+        {
+            report.push_str(&format!("Original-Message-ID: {}\r\n", message_id.trim()));
+        }
+
+        report.push_str("Disposition: manual-action/MDN-sent-manually; displayed\r\n");
+
+        let mut builder = self
+            .recipients(read_receipt_to)
+            .subject(format!("Read: {}", subject))
+            .text(format!(
+                "The message sent to {} with the subject \"{}\" was displayed.",
+                recipient, subject
+            ));
+
+        builder.disposition_notification = Some(report);
+
+        builder.build()
+    }
+
     pub fn build<T: TryFrom<Self, Error = impl Display>>(self) -> Result<T> {
         match self.try_into() {
             Ok(message) => Ok(message),
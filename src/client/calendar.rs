@@ -0,0 +1,52 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A meeting invite that was found in the `text/calendar` part of a message.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CalendarInvite {
+    pub(crate) method: Option<String>,
+    pub(crate) uid: Option<String>,
+    pub(crate) summary: Option<String>,
+    pub(crate) location: Option<String>,
+    pub(crate) organizer: Option<String>,
+    pub(crate) start: Option<i64>,
+    pub(crate) end: Option<i64>,
+}
+
+impl CalendarInvite {
+    /// The iTIP method of the invite, e.g. `REQUEST`, `REPLY` or `CANCEL`.
+    pub fn method(&self) -> Option<&str> {
+        self.method.as_deref()
+    }
+
+    /// The unique identifier of the event.
+    pub fn uid(&self) -> Option<&str> {
+        self.uid.as_deref()
+    }
+
+    /// What the event is about.
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Where the event takes place.
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// The email address of the person that organized the event.
+    pub fn organizer(&self) -> Option<&str> {
+        self.organizer.as_deref()
+    }
+
+    /// Start of the event in seconds since epoch
+    pub fn start(&self) -> Option<&i64> {
+        self.start.as_ref()
+    }
+
+    /// End of the event in seconds since epoch
+    pub fn end(&self) -> Option<&i64> {
+        self.end.as_ref()
+    }
+}
@@ -1,10 +1,349 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio")
+))]
+use std::future::Future;
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use log::{debug, warn};
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use socket2::{SockRef, TcpKeepalive};
+
+use crate::runtime::time::Duration;
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use crate::error::Result;
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use crate::runtime::net::TcpStream;
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use super::protocol::RemoteServer;
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const LOG_TARGET: &str = "dust_mail::connection";
+
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ConnectionSecurity {
     Tls,
     StartTls,
     Plain,
 }
+
+/// The state of a connection with a mail server.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ConnectionState {
+    /// The client is logged in and ready for requests, `EmailClient` is only created with sessions that already logged in.
+    Connected,
+    /// The client logged out.
+    Disconnected,
+    /// The client is logging in to the mail server.
+    Authenticating,
+    /// The last request failed because of a problem with the connection.
+    Error(String),
+}
+
+/// A `ConnectionState` that is shared between the client and its protocols, so it can be read while a login or request holds the client.
+#[derive(Debug, Clone)]
+pub struct SharedConnectionState(Arc<RwLock<ConnectionState>>);
+
+impl Default for SharedConnectionState {
+    fn default() -> Self {
+        Self::new(ConnectionState::Disconnected)
+    }
+}
+
+impl SharedConnectionState {
+    pub fn new(state: ConnectionState) -> Self {
+        Self(Arc::new(RwLock::new(state)))
+    }
+
+    pub fn get(&self) -> ConnectionState {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn set(&self, state: ConnectionState) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+
+    /// Reports `ConnectionState::Authenticating` while the given login runs, going back to the previous state once it finishes.
+    #[cfg(any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio")
+    ))]
+    pub(crate) async fn authenticating<T, F: Future<Output = Result<T>>>(
+        &self,
+        login: F,
+    ) -> Result<T> {
+        let previous = self.get();
+
+        self.set(ConnectionState::Authenticating);
+
+        let result = login.await;
+
+        self.set(previous);
+
+        result
+    }
+}
+
+/// Options for the tcp sockets that are used to connect to mail servers.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SocketOptions {
+    keepalive: Option<Duration>,
+    nodelay: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            keepalive: Some(Self::DEFAULT_KEEPALIVE),
+            nodelay: false,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Most NAT routers and firewalls drop idle connections after a few minutes, so we probe well before that.
+    pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(60);
+
+    /// How long a connection may be idle before the operating system starts sending TCP keepalive probes, or `None` to disable them.
+    ///
+    /// This keeps connections alive between the keep alive requests of the mail protocols themselves.
+    pub fn with_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.keepalive = keepalive;
+
+        self
+    }
+
+    /// Whether to disable Nagle's algorithm, sending small writes (like commands) immediately.
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+
+        self
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.keepalive
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.nodelay
+    }
+
+    #[cfg(any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    ))]
+    fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        with_socket(stream, |socket| {
+            match self.keepalive {
+                Some(time) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?,
+                None => socket.set_keepalive(false)?,
+            }
+
+            socket.set_nodelay(self.nodelay)
+        })
+    }
+}
+
+#[cfg(all(
+    feature = "runtime-tokio",
+    any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    )
+))]
+fn with_socket<T, F: FnOnce(SockRef<'_>) -> T>(stream: &TcpStream, f: F) -> T {
+    f(SockRef::from(stream))
+}
+
+/// async-std does not implement the io safety traits for its streams, so we borrow the raw socket ourselves.
+#[cfg(all(
+    feature = "runtime-async-std",
+    unix,
+    any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    )
+))]
+fn with_socket<T, F: FnOnce(SockRef<'_>) -> T>(stream: &TcpStream, f: F) -> T {
+    use std::os::fd::{AsRawFd, BorrowedFd};
+
+    // The stream stays borrowed (and thus open) while the socket is used.
+    let fd = unsafe { BorrowedFd::borrow_raw(stream.as_raw_fd()) };
+
+    f(SockRef::from(&fd))
+}
+
+#[cfg(all(
+    feature = "runtime-async-std",
+    windows,
+    any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    )
+))]
+fn with_socket<T, F: FnOnce(SockRef<'_>) -> T>(stream: &TcpStream, f: F) -> T {
+    use std::os::windows::io::{AsRawSocket, BorrowedSocket};
+
+    // The stream stays borrowed (and thus open) while the socket is used.
+    let socket = unsafe { BorrowedSocket::borrow_raw(stream.as_raw_socket()) };
+
+    f(SockRef::from(&socket))
+}
+
+/// Opens a tcp connection to a remote server, going through its proxy if one is configured.
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+pub(crate) async fn connect_tcp(server: &RemoteServer) -> Result<TcpStream> {
+    let stream = match server.proxy() {
+        Some(proxy) => {
+            debug!(
+                target: LOG_TARGET,
+                "Connecting to {}:{} through proxy {}:{}",
+                server.domain(),
+                server.port(),
+                proxy.host(),
+                proxy.port()
+            );
+
+            proxy.connect(server.domain(), server.port()).await?
+        }
+        None => {
+            debug!(
+                target: LOG_TARGET,
+                "Connecting to {}:{}",
+                server.domain(),
+                server.port()
+            );
+
+            TcpStream::connect((server.domain(), server.port())).await?
+        }
+    };
+
+    // The connection works without these options, so failing to set them is not fatal.
+    if let Err(err) = server.socket_options().apply(&stream) {
+        warn!(target: LOG_TARGET, "Failed to set socket options: {}", err);
+    }
+
+    Ok(stream)
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    )
+))]
+mod test {
+    use super::*;
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn apply_socket_options() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let port = listener.local_addr().unwrap().port();
+
+        let server = RemoteServer::new("127.0.0.1", port, ConnectionSecurity::Plain)
+            .with_socket_options(SocketOptions::default().with_nodelay(true));
+
+        let stream = connect_tcp(&server).await.unwrap();
+
+        with_socket(&stream, |socket| {
+            assert!(socket.keepalive().unwrap());
+            assert!(socket.nodelay().unwrap());
+        });
+
+        let server = RemoteServer::new("127.0.0.1", port, ConnectionSecurity::Plain)
+            .with_socket_options(SocketOptions::default().with_keepalive(None));
+
+        let stream = connect_tcp(&server).await.unwrap();
+
+        with_socket(&stream, |socket| {
+            assert!(!socket.keepalive().unwrap());
+            assert!(!socket.nodelay().unwrap());
+        });
+    }
+
+    #[cfg(any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio")
+    ))]
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn authenticating_state() {
+        let state = SharedConnectionState::new(ConnectionState::Connected);
+
+        let result: Result<()> = state
+            .authenticating(async {
+                assert_eq!(state.get(), ConnectionState::Authenticating);
+
+                Ok(())
+            })
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(state.get(), ConnectionState::Connected);
+    }
+}
@@ -1,7 +1,14 @@
+use std::borrow::Cow;
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use super::parser::{self, SanitizePolicy, SanitizedHtml};
+
+/// The maximum amount of characters in the snippet of a message preview.
+const SNIPPET_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Content {
     pub(crate) text: Option<String>,
@@ -14,15 +21,6 @@ impl<T: Into<String>> From<T> for Content {
     }
 }
 
-impl Default for Content {
-    fn default() -> Self {
-        Self {
-            html: None,
-            text: None,
-        }
-    }
-}
-
 impl Content {
     pub fn new(text: Option<String>, html: Option<String>) -> Self {
         Self { text, html }
@@ -55,4 +53,57 @@ impl Content {
             None => None,
         }
     }
+
+    /// The message in pure text form, derived from the html page by stripping its tags if the message has no text part.
+    ///
+    /// Useful for search indexing and notifications, which can not display html.
+    pub fn as_plain_text(&self) -> Cow<'_, str> {
+        match (&self.text, &self.html) {
+            (Some(text), _) => Cow::Borrowed(text),
+            (None, Some(html)) => Cow::Owned(parser::html::html_to_text(html)),
+            (None, None) => Cow::Borrowed(""),
+        }
+    }
+
+    /// A short summary of the text of the message on a single line, used in message previews.
+    pub(crate) fn snippet(&self) -> Option<String> {
+        let text = self.as_plain_text();
+
+        let snippet = text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(SNIPPET_LENGTH)
+            .collect::<String>();
+
+        if snippet.is_empty() {
+            None
+        } else {
+            Some(snippet)
+        }
+    }
+
+    /// Splits the plain text of the message into what the sender wrote and the quoted message it replies to, if one was found.
+    ///
+    /// Useful to collapse the quoted history of a conversation.
+    pub fn split_quoted(&self) -> (String, Option<String>) {
+        let text = self.as_plain_text();
+
+        let (new, quoted) = parser::quote::split_quoted(&text);
+
+        (new.to_string(), quoted.map(str::to_string))
+    }
+
+    /// The message as a html page, with everything that is not allowed by the given policy removed.
+    pub fn sanitized_html(&self, policy: &SanitizePolicy) -> Option<String> {
+        self.html()
+            .map(|html| parser::sanitize_html_with_policy(html, policy))
+    }
+
+    /// Like `sanitized_html`, but also reports how many trackers were removed from the page.
+    pub fn sanitized_html_with_report(&self, policy: &SanitizePolicy) -> Option<SanitizedHtml> {
+        self.html()
+            .map(|html| parser::sanitize_html_with_report(html, policy))
+    }
 }
@@ -0,0 +1,48 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What happened to a message that was sent to a recipient, as reported by a mail server.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeliveryAction {
+    /// The message could not be delivered and the server gave up.
+    Failed,
+    /// The message has not been delivered yet, but the server is still trying.
+    Delayed,
+    Delivered,
+    /// The message was passed on to a server that does not send delivery notifications.
+    Relayed,
+    /// The message was delivered to a mailing list or alias that forwards it to multiple recipients.
+    Expanded,
+}
+
+/// The delivery status notification (bounce) found in a `message/delivery-status` report, see RFC 3464.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeliveryStatus {
+    pub(crate) action: DeliveryAction,
+    pub(crate) status: Option<String>,
+    pub(crate) recipient: Option<String>,
+    pub(crate) diagnostic: Option<String>,
+}
+
+impl DeliveryStatus {
+    pub fn action(&self) -> &DeliveryAction {
+        &self.action
+    }
+
+    /// The enhanced status code, e.g. `5.1.1` for a mailbox that does not exist.
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    /// The email address of the recipient the message was sent to.
+    pub fn recipient(&self) -> Option<&str> {
+        self.recipient.as_deref()
+    }
+
+    /// The error message the receiving mail server gave, if any.
+    pub fn diagnostic(&self) -> Option<&str> {
+        self.diagnostic.as_deref()
+    }
+}
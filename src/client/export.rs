@@ -0,0 +1,72 @@
+use chrono::{TimeZone, Utc};
+
+/// The amount of messages that are fetched at once when exporting a mailbox.
+pub(crate) const EXPORT_PAGE_SIZE: usize = 50;
+
+const UNKNOWN_SENDER: &str = "MAILER-DAEMON";
+
+/// Formats a raw message as an entry in an mbox file.
+///
+/// Uses the mboxrd variant, where every line in the message that looks like a `From ` separator (optionally preceded by `>` characters) is quoted with an extra `>`.
+pub(crate) fn to_mbox_entry(sender: Option<&str>, sent: Option<i64>, raw: &[u8]) -> Vec<u8> {
+    let date = sent
+        .and_then(|sent| Utc.timestamp_opt(sent, 0).single())
+        .unwrap_or_default();
+
+    let separator = format!(
+        "From {} {}\n",
+        sender.unwrap_or(UNKNOWN_SENDER),
+        date.format("%a %b %e %H:%M:%S %Y")
+    );
+
+    let mut entry = Vec::with_capacity(separator.len() + raw.len() + 2);
+
+    entry.extend_from_slice(separator.as_bytes());
+
+    for line in raw.split(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        let quote_level = line.iter().take_while(|byte| **byte == b'>').count();
+
+        if line[quote_level..].starts_with(b"From ") {
+            entry.push(b'>');
+        }
+
+        entry.extend_from_slice(line);
+        entry.push(b'\n');
+    }
+
+    // Messages ending with a newline would otherwise get an extra empty line from the split above.
+    if raw.ends_with(b"\n") {
+        entry.pop();
+    }
+
+    // An empty line separates the entry from the next one.
+    entry.push(b'\n');
+
+    entry
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_mbox_entry;
+
+    #[test]
+    fn mbox_entry() {
+        let raw = b"Subject: Hi\r\n\r\nFrom now on\r\n>From the start\r\nBye\r\n";
+
+        let entry = to_mbox_entry(Some("john@example.com"), Some(1057049557), raw);
+
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "From john@example.com Tue Jul  1 08:52:37 2003\nSubject: Hi\n\n>From now on\n>>From the start\nBye\n\n"
+        );
+
+        let entry = to_mbox_entry(None, None, b"Subject: Hi\n\nNo newline");
+
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "From MAILER-DAEMON Thu Jan  1 00:00:00 1970\nSubject: Hi\n\nNo newline\n\n"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+macro_rules! impl_id {
+    ($name:ident) => {
+        impl From<&str> for $name {
+            fn from(id: &str) -> Self {
+                Self(id.to_string())
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(id: String) -> Self {
+                Self(id)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+/// The id of a mailbox, as returned by `Mailbox::id`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MailboxId(String);
+
+/// The id of a message within a mailbox, as returned by `Preview::id` or `Message::id`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MessageId(String);
+
+impl_id!(MailboxId);
+impl_id!(MessageId);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let mailbox_id: MailboxId = "INBOX".into();
+        let message_id = MessageId::from(String::from("42"));
+
+        assert_eq!(mailbox_id.as_ref(), "INBOX");
+        assert_eq!(message_id.to_string(), "42");
+    }
+}
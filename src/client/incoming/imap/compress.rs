@@ -0,0 +1,391 @@
+use std::{
+    fmt::{self, Debug},
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures::ready;
+use miniz_oxide::{
+    deflate::{
+        core::{create_comp_flags_from_zip_params, CompressorOxide},
+        stream::deflate,
+    },
+    inflate::stream::{inflate, InflateState},
+    DataFormat, MZError, MZFlush,
+};
+
+use crate::runtime::io::{Read, Write};
+
+/// Imap traffic is mostly short commands and text, which already compresses well at the default level.
+const COMPRESSION_LEVEL: i32 = 6;
+/// Negative window bits produce raw DEFLATE data without a zlib header, which is what COMPRESS=DEFLATE uses (RFC 4978).
+const WINDOW_BITS: i32 = -15;
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Turns on compression for a `CompressStream`, once the server has accepted the COMPRESS command.
+#[derive(Debug, Clone, Default)]
+pub struct CompressHandle(Arc<AtomicBool>);
+
+impl CompressHandle {
+    pub fn enable(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A stream that passes data through untouched until compression is enabled, after which everything written to it is deflated and everything read from it is inflated.
+pub struct CompressStream<S> {
+    inner: S,
+    handle: CompressHandle,
+    compressor: Box<CompressorOxide>,
+    decompressor: Box<InflateState>,
+    /// Compressed data that still has to be written to the inner stream.
+    output: Vec<u8>,
+    /// Whether data was compressed since the last flush, so the compressor holds data that has not been sent yet.
+    unflushed: bool,
+    /// Compressed data that was read from the inner stream but not inflated yet.
+    input: Vec<u8>,
+    input_pos: usize,
+}
+
+impl<S> CompressStream<S> {
+    pub fn new(inner: S) -> Self {
+        let flags = create_comp_flags_from_zip_params(COMPRESSION_LEVEL, WINDOW_BITS, 0);
+
+        Self {
+            inner,
+            handle: CompressHandle::default(),
+            compressor: Box::new(CompressorOxide::new(flags)),
+            decompressor: InflateState::new_boxed(DataFormat::Raw),
+            output: Vec::new(),
+            unflushed: false,
+            input: Vec::new(),
+            input_pos: 0,
+        }
+    }
+
+    pub fn handle(&self) -> CompressHandle {
+        self.handle.clone()
+    }
+
+    fn compress(&mut self, mut data: &[u8], flush: MZFlush) -> io::Result<()> {
+        let mut chunk = [0; CHUNK_SIZE];
+
+        loop {
+            let result = deflate(&mut self.compressor, data, &mut chunk, flush);
+
+            match result.status {
+                // Buf only means there was nothing to compress.
+                Ok(_) | Err(MZError::Buf) => {}
+                Err(err) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("Failed to compress data: {:?}", err),
+                    ))
+                }
+            }
+
+            self.output
+                .extend_from_slice(&chunk[..result.bytes_written]);
+
+            data = &data[result.bytes_consumed..];
+
+            // A full chunk means the compressor may have more output waiting.
+            if data.is_empty() && result.bytes_written < chunk.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Inflates buffered input into `output`, returning how many bytes were consumed and written.
+    fn decompress(&mut self, output: &mut [u8]) -> io::Result<(usize, usize)> {
+        let result = inflate(
+            &mut self.decompressor,
+            &self.input[self.input_pos..],
+            output,
+            MZFlush::None,
+        );
+
+        self.input_pos += result.bytes_consumed;
+
+        if self.input_pos == self.input.len() {
+            self.input.clear();
+            self.input_pos = 0;
+        }
+
+        match result.status {
+            // Buf means more input is needed.
+            Ok(_) | Err(MZError::Buf) => Ok((result.bytes_consumed, result.bytes_written)),
+            Err(err) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to decompress data: {:?}", err),
+            )),
+        }
+    }
+}
+
+impl<S: Write + Unpin> CompressStream<S> {
+    fn poll_write_compressed(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // Keep the amount of buffered output bounded by sending earlier data first.
+        ready!(self.poll_drain(cx))?;
+
+        self.compress(buf, MZFlush::None)?;
+
+        self.unflushed = true;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Writes all compressed data to the inner stream, after making the compressor output everything it holds.
+    fn poll_flush_compressed(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.unflushed {
+            self.compress(&[], MZFlush::Sync)?;
+
+            self.unflushed = false;
+        }
+
+        self.poll_drain(cx)
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.output.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.output.drain(..written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: Debug> Debug for CompressStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressStream")
+            .field("inner", &self.inner)
+            .field("enabled", &self.handle.is_enabled())
+            .finish()
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl<S: Read + Unpin> Read for CompressStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.handle.is_enabled() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        loop {
+            let (consumed, written) = this.decompress(buf.initialize_unfilled())?;
+
+            if written > 0 {
+                buf.advance(written);
+
+                return Poll::Ready(Ok(()));
+            }
+
+            if consumed > 0 {
+                continue;
+            }
+
+            let mut chunk = [0; CHUNK_SIZE];
+            let mut chunk_buf = tokio::io::ReadBuf::new(&mut chunk);
+
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+
+            // The connection was closed.
+            if chunk_buf.filled().is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            this.input.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+#[cfg(feature = "runtime-async-std")]
+impl<S: Read + Unpin> Read for CompressStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if !this.handle.is_enabled() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        loop {
+            let (consumed, written) = this.decompress(buf)?;
+
+            if written > 0 {
+                return Poll::Ready(Ok(written));
+            }
+
+            if consumed > 0 {
+                continue;
+            }
+
+            let mut chunk = [0; CHUNK_SIZE];
+
+            let read = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+
+            // The connection was closed.
+            if read == 0 {
+                return Poll::Ready(Ok(0));
+            }
+
+            this.input.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl<S: Write + Unpin> Write for CompressStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if !this.handle.is_enabled() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        this.poll_write_compressed(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.handle.is_enabled() {
+            ready!(this.poll_flush_compressed(cx))?;
+        }
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.handle.is_enabled() {
+            ready!(this.poll_flush_compressed(cx))?;
+        }
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "runtime-async-std")]
+impl<S: Write + Unpin> Write for CompressStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if !this.handle.is_enabled() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        this.poll_write_compressed(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.handle.is_enabled() {
+            ready!(this.poll_flush_compressed(cx))?;
+        }
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.handle.is_enabled() {
+            ready!(this.poll_flush_compressed(cx))?;
+        }
+
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod test {
+    use super::*;
+
+    use crate::runtime::io::{ReadExt, WriteExt};
+
+    #[tokio::test]
+    async fn compressed_round_trip() {
+        let (client, server) = tokio::io::duplex(1024);
+
+        let mut client = CompressStream::new(client);
+        let mut server = CompressStream::new(server);
+
+        // Before compression is enabled, data is passed through as is.
+        client.write_all(b"a1 COMPRESS DEFLATE\r\n").await.unwrap();
+        client.flush().await.unwrap();
+
+        let mut command = [0; 21];
+
+        server.inner.read_exact(&mut command).await.unwrap();
+
+        assert_eq!(&command, b"a1 COMPRESS DEFLATE\r\n");
+
+        client.handle().enable();
+        server.handle().enable();
+
+        let large = "* 1 FETCH (BODY[] {4096}\r\n".repeat(2000);
+
+        let writer = tokio::spawn(async move {
+            client.write_all(b"a2 NOOP\r\n").await.unwrap();
+            client.flush().await.unwrap();
+
+            let mut response = vec![0; large.len()];
+
+            client.read_exact(&mut response).await.unwrap();
+
+            assert_eq!(response, large.as_bytes());
+        });
+
+        let mut command = [0; 9];
+
+        server.read_exact(&mut command).await.unwrap();
+
+        assert_eq!(&command, b"a2 NOOP\r\n");
+
+        let large = "* 1 FETCH (BODY[] {4096}\r\n".repeat(2000);
+
+        server.write_all(large.as_bytes()).await.unwrap();
+        server.flush().await.unwrap();
+
+        writer.await.unwrap();
+    }
+}
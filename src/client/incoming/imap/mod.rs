@@ -1,100 +1,202 @@
-mod oauth;
+mod compress;
 mod query;
+mod sasl;
+pub(crate) mod utf7;
 mod utils;
+mod watch;
 
-// use std::collections::HashMap;
-use std::fmt::Debug;
+use std::{cmp::Reverse, collections::HashMap, fmt::Debug, io};
 
 use crate::{
     client::{
+        auth::{negotiate, AuthMechanism, ScramClient},
         builder::MessageBuilder,
-        connection::ConnectionSecurity,
-        protocol::{ImapCredentials, IncomingConfig, IncomingProtocol},
-        Credentials, ServerCredentials,
+        connection::{self, ConnectionSecurity, SharedConnectionState},
+        metrics::{CountingStream, MetricsHandle, SessionMetrics},
+        parser,
+        protocol::{ImapCredentials, IncomingConfig, IncomingProtocol, RemoteServer},
+        Credentials, Headers, ServerCredentials,
     },
     error::{err, Error, ErrorKind, Result},
     runtime::{
-        io::{Read, Write},
+        io::{shutdown, Read, ReadExt, Write, WriteExt},
         net::TcpStream,
+        thread,
         time::{Duration, Instant},
     },
     tree::Node,
 };
 
 use async_imap::{
-    imap_proto::SectionPath,
-    types::{Fetch, Name},
+    error::Error as ImapError,
+    imap_proto::{
+        BodyStructure, Capability as ImapCapability, RequestId, Response, SectionPath, Status,
+    },
+    types::{Capability, Fetch, Name, UnsolicitedResponse},
 };
 use async_native_tls::{TlsConnector, TlsStream};
 use async_trait::async_trait;
-use futures::StreamExt;
-use log::{debug, info};
+use futures::{executor::block_on, stream::BoxStream, StreamExt};
+use log::{debug, info, warn};
+use mime::Mime;
 
 use self::{
-    oauth::OAuthCredentials,
+    compress::{CompressHandle, CompressStream},
     query::QueryBuilder,
+    sasl::{CramMd5Credentials, OAuthCredentials, PlainCredentials, ScramAuthenticator},
     utils::{BodyStructureParser, MailboxFinder, PartNumber},
+    watch::Watch,
 };
 
 use super::types::{
-    flag::Flag,
+    acl::AclEntry,
+    event::MailboxEvent,
+    flag::{Flag, FlagMode},
     mailbox::{Mailbox, MailboxStats},
     message::{Message, Preview},
+    sort::SortOrder,
 };
 
+/// The log target used by the imap client, use `RUST_LOG=dust_mail::imap=debug` to debug imap connections.
+const LOG_TARGET: &str = "dust_mail::imap";
+
+/// The SASL mechanisms we can log in with, other mechanisms fall back to the LOGIN command.
+const SASL_MECHANISMS: [AuthMechanism; 5] = [
+    AuthMechanism::ScramSha256,
+    AuthMechanism::ScramSha1,
+    AuthMechanism::CramMd5,
+    AuthMechanism::Plain,
+    AuthMechanism::XOAuth2,
+];
+
 const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(29 * 60);
 
+/// The amount of bytes that is fetched per request when downloading an attachment with progress reporting.
+const ATTACHMENT_CHUNK_SIZE: usize = 512 * 1024;
+
+/// The amount of bytes that is read from a stream at once when appending a message.
+const APPEND_CHUNK_SIZE: usize = 64 * 1024;
+
+const CONDSTORE_CAPABILITY: &str = "CONDSTORE";
+const UTF8_ACCEPT_CAPABILITY: &str = "UTF8=ACCEPT";
+const LIST_EXTENDED_CAPABILITY: &str = "LIST-EXTENDED";
+const SPECIAL_USE_CAPABILITY: &str = "SPECIAL-USE";
+const ACL_CAPABILITY: &str = "ACL";
+const COMPRESS_CAPABILITY: &str = "COMPRESS=DEFLATE";
+
+/// The maximum amount of body structures that are remembered for the selected mailbox.
+const MAX_CACHED_BODY_STRUCTURES: usize = 1000;
+
 pub struct ImapClient<S: Read + Write + Unpin + Debug + Send> {
     client: async_imap::Client<S>,
+    banner: Option<String>,
+    compression: Option<CompressHandle>,
+    metrics: MetricsHandle,
+}
+
+/// Info about a mailbox that was returned by the server when it was selected.
+struct SelectedMailbox {
+    id: String,
+    /// The mailbox as it was listed when it was selected, so it does not have to be listed again while it stays selected.
+    mailbox: Mailbox,
+    stats: MailboxStats,
+    /// The flags that can be permanently stored on messages in this box.
+    flags: Vec<Flag>,
+    /// Fetches that contain the body structure of messages in this box by uid. These are fetched while listing messages anyway and save a round trip when one of them is opened.
+    body_structures: HashMap<u32, Fetch>,
+}
+
+impl SelectedMailbox {
+    fn cache_body_structure(&mut self, fetch: Fetch) {
+        let uid = match fetch.uid {
+            Some(uid) if fetch.bodystructure().is_some() => uid,
+            _ => return,
+        };
+
+        if self.body_structures.len() >= MAX_CACHED_BODY_STRUCTURES {
+            self.body_structures.clear();
+        }
+
+        self.body_structures.insert(uid, fetch);
+    }
 }
 
 pub struct ImapSession<S: Write + Read + Unpin + Debug + Send + Sync> {
     session: async_imap::Session<S>,
     /// The currently selected box
-    selected_box: Option<(String, MailboxStats)>,
+    selected_box: Option<SelectedMailbox>,
     last_keep_alive: Option<Instant>,
+    /// Whether we logged out of the session or closed its connection.
+    closed: bool,
+    /// Whether the server accepts UTF-8 mailbox names instead of modified UTF-7.
+    utf8_enabled: bool,
+    /// The `RETURN` options added to LIST commands if the server supports LIST-EXTENDED.
+    list_return_options: Option<String>,
+    /// The text of the greeting the server sent.
+    banner: Option<String>,
+    /// Turns on compression of the connection, if the stream supports it.
+    compression: Option<CompressHandle>,
+    metrics: MetricsHandle,
+    /// How we logged in.
+    auth_mechanism: AuthMechanism,
+    /// The mailboxes `next_events` reports changes for.
+    watching: Option<Watch>,
 }
 
-pub async fn connect<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<ImapClient<TlsStream<TcpStream>>> {
+pub async fn connect(
+    server: &RemoteServer,
+) -> Result<ImapClient<CompressStream<CountingStream<TlsStream<TcpStream>>>>> {
     let tls = TlsConnector::new();
 
-    let tcp_stream = TcpStream::connect((server.as_ref(), port.into())).await?;
+    let tcp_stream = connection::connect_tcp(server).await?;
 
-    let tls_stream = tls.connect(server.as_ref(), tcp_stream).await?;
+    let tls_stream = tls.connect(server.domain(), tcp_stream).await?;
 
-    let client = async_imap::Client::new(tls_stream);
-
-    let imap_client = ImapClient { client };
-
-    Ok(imap_client)
+    ImapClient::from_stream(tls_stream).await
 }
 
-pub async fn connect_plain<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<ImapClient<TcpStream>> {
-    let stream = TcpStream::connect((server.as_ref(), port.into())).await?;
+pub async fn connect_plain(
+    server: &RemoteServer,
+) -> Result<ImapClient<CompressStream<CountingStream<TcpStream>>>> {
+    let tcp_stream = connection::connect_tcp(server).await?;
 
-    let client = async_imap::Client::new(stream);
-
-    Ok(ImapClient { client })
+    ImapClient::from_stream(tcp_stream).await
 }
 
-async fn create_session<S: Read + Write + Unpin + Debug + Send + Sync>(
+async fn create_session<S: Read + Write + Unpin + Debug + Send + Sync + 'static>(
     imap_client: ImapClient<S>,
     credentials: &Credentials,
+    config: &IncomingConfig,
+    state: &SharedConnectionState,
 ) -> Result<ImapSession<S>> {
-    info!("Creating new imap session");
+    info!(target: LOG_TARGET, "Creating new imap session");
 
-    let imap_session = match credentials {
-        Credentials::OAuth { username, token } => imap_client.oauth2_login(username, token).await?,
-        Credentials::Password { username, password } => {
-            imap_client.login(username, password).await?
-        }
-    };
+    let mut imap_session = state
+        .authenticating(imap_client.authenticate(credentials))
+        .await?;
+
+    info!(
+        target: LOG_TARGET,
+        "Logged in as {} using {}",
+        credentials.username(),
+        imap_session.auth_mechanism
+    );
+
+    if config.compression() {
+        imap_session.enable_compression().await?;
+    }
+
+    // Without UTF-8 mailbox names are encoded in modified UTF-7, so the session is still usable.
+    match imap_session.enable_utf8().await {
+        Err(err) if err.is_connection_error() => return Err(err),
+        Err(err) => warn!(
+            target: LOG_TARGET,
+            "Failed to enable UTF-8, continuing without it: {}", err
+        ),
+        Ok(()) => {}
+    }
+
+    imap_session.detect_list_extended().await?;
 
     Ok(imap_session)
 }
@@ -102,34 +204,151 @@ async fn create_session<S: Read + Write + Unpin + Debug + Send + Sync>(
 /// Creates a new imap client from a given set of credentials
 pub async fn create(
     credentials: &ImapCredentials,
-    _config: IncomingConfig,
+    config: IncomingConfig,
+    state: &SharedConnectionState,
 ) -> Result<Box<dyn IncomingProtocol + Sync + Send>> {
     match credentials.server().security() {
         ConnectionSecurity::Tls => {
-            let imap_client =
-                connect(credentials.server().domain(), credentials.server().port()).await?;
+            let imap_client = connect(credentials.server()).await?;
 
-            let session = create_session(imap_client, &credentials.credentials()).await?;
+            let session =
+                create_session(imap_client, credentials.credentials(), &config, state).await?;
 
             Ok(Box::new(session))
         }
         _ => {
-            let imap_client =
-                connect_plain(credentials.server().domain(), credentials.server().port()).await?;
+            let imap_client = connect_plain(credentials.server()).await?;
 
-            let session = create_session(imap_client, &credentials.credentials()).await?;
+            let session =
+                create_session(imap_client, credentials.credentials(), &config, state).await?;
 
             Ok(Box::new(session))
         }
     }
 }
 
+impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapClient<CompressStream<CountingStream<S>>> {
+    /// Counts the traffic on the stream, compressing it later on if the server supports that.
+    async fn from_stream(stream: S) -> Result<Self> {
+        let stream = CountingStream::new(stream);
+        let metrics = stream.handle();
+
+        let stream = CompressStream::new(stream);
+        let compression = stream.handle();
+
+        Self::new(async_imap::Client::new(stream), Some(compression), metrics).await
+    }
+}
+
 impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapClient<S> {
-    fn new_imap_session(session: async_imap::Session<S>) -> ImapSession<S> {
+    /// Reads the greeting the server sends as soon as the connection is opened.
+    async fn new(
+        mut client: async_imap::Client<S>,
+        compression: Option<CompressHandle>,
+        metrics: MetricsHandle,
+    ) -> Result<Self> {
+        let banner = match client.read_response().await {
+            Some(response) => utils::greeting_text(response?.parsed())?,
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Imap server closed the connection before sending a greeting"
+            ),
+        };
+
+        Ok(Self {
+            client,
+            banner,
+            compression,
+            metrics,
+        })
+    }
+
+    fn new_imap_session(
+        session: async_imap::Session<S>,
+        banner: Option<String>,
+        compression: Option<CompressHandle>,
+        metrics: MetricsHandle,
+        auth_mechanism: AuthMechanism,
+    ) -> ImapSession<S> {
         ImapSession {
             session,
             selected_box: None,
             last_keep_alive: None,
+            closed: false,
+            utf8_enabled: false,
+            list_return_options: None,
+            banner,
+            compression,
+            metrics,
+            auth_mechanism,
+            watching: None,
+        }
+    }
+
+    /// The SASL mechanisms the server advertises (`AUTH=` capabilities), which have to be requested before logging in.
+    async fn auth_mechanisms(&mut self) -> Result<Vec<String>> {
+        // async-imap only parses capabilities once logged in, so the untagged response is received as unsolicited data.
+        let (sender, receiver) = async_channel::unbounded();
+
+        self.client
+            .run_command_and_check_ok("CAPABILITY", Some(sender))
+            .await?;
+
+        let mut mechanisms = Vec::new();
+
+        while let Ok(response) = receiver.try_recv() {
+            if let UnsolicitedResponse::Other(response) = response {
+                if let Response::Capabilities(capabilities) = response.parsed() {
+                    for capability in capabilities {
+                        if let ImapCapability::Auth(mechanism) = capability {
+                            mechanisms.push(mechanism.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(mechanisms)
+    }
+
+    /// Logs in using the strongest mechanism the server advertises, falling back to the LOGIN command for passwords.
+    pub async fn authenticate(mut self, credentials: &Credentials) -> Result<ImapSession<S>>
+    where
+        S: 'static,
+    {
+        let credentials = credentials.refreshed().await?;
+        let credentials = credentials.as_ref();
+
+        let advertised = self.auth_mechanisms().await?;
+
+        let mechanism = negotiate(credentials, &SASL_MECHANISMS, &advertised);
+
+        debug!(
+            target: LOG_TARGET,
+            "Server advertises {:?}, logging in with {:?}", advertised, mechanism
+        );
+
+        match (credentials, mechanism) {
+            (Credentials::Password { username, password }, Some(AuthMechanism::Plain)) => {
+                self.plain_login(username, password).await
+            }
+            (Credentials::Password { username, password }, Some(AuthMechanism::CramMd5)) => {
+                self.cram_md5_login(username, password).await
+            }
+            (
+                Credentials::Password { username, password },
+                Some(mechanism @ (AuthMechanism::ScramSha1 | AuthMechanism::ScramSha256)),
+            ) => self.scram_login(mechanism, username, password).await,
+            (Credentials::Password { username, password }, _) => {
+                self.login(username, password).await
+            }
+            // Not every server advertises XOAUTH2, so it is tried regardless.
+            (
+                Credentials::OAuth {
+                    username, token, ..
+                },
+                _,
+            ) => self.oauth2_login(username, token).await,
         }
     }
 
@@ -144,7 +363,13 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapClient<S> {
             .await
             .map_err(|(error, _)| Error::from(error))?;
 
-        let imap_session = Self::new_imap_session(session);
+        let imap_session = Self::new_imap_session(
+            session,
+            self.banner,
+            self.compression,
+            self.metrics,
+            AuthMechanism::LoginCommand,
+        );
 
         Ok(imap_session)
     }
@@ -156,19 +381,109 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapClient<S> {
     ) -> Result<ImapSession<S>> {
         let auth = OAuthCredentials::new(user.as_ref(), token.as_ref());
 
+        self.sasl_login(AuthMechanism::XOAuth2, auth).await
+    }
+
+    pub async fn plain_login<U: AsRef<str>, P: AsRef<str>>(
+        self,
+        username: U,
+        password: P,
+    ) -> Result<ImapSession<S>> {
+        let auth = PlainCredentials::new(username.as_ref(), password.as_ref());
+
+        self.sasl_login(AuthMechanism::Plain, auth).await
+    }
+
+    pub async fn cram_md5_login<U: AsRef<str>, P: AsRef<str>>(
+        self,
+        username: U,
+        password: P,
+    ) -> Result<ImapSession<S>> {
+        let auth = CramMd5Credentials::new(username.as_ref(), password.as_ref());
+
+        self.sasl_login(AuthMechanism::CramMd5, auth).await
+    }
+
+    /// Logs in with SCRAM-SHA-1 or SCRAM-SHA-256, failing if the server cannot prove it knows the password.
+    pub async fn scram_login<U: AsRef<str>, P: AsRef<str>>(
+        self,
+        mechanism: AuthMechanism,
+        username: U,
+        password: P,
+    ) -> Result<ImapSession<S>>
+    where
+        S: 'static,
+    {
+        let hash = match mechanism.scram_hash() {
+            Some(hash) => hash,
+            None => err!(
+                ErrorKind::Unsupported,
+                "{} is not a SCRAM mechanism",
+                mechanism
+            ),
+        };
+
+        let mut scram = ScramClient::new(hash, username.as_ref(), password.as_ref());
+
+        let Self {
+            client,
+            banner,
+            compression,
+            metrics,
+        } = self;
+
+        // async-imap answers the challenges synchronously and salting the password can take a while, so the whole exchange runs on a blocking thread where it does not hold up other tasks.
+        let (result, finished) = thread::spawn_blocking(move || {
+            let mut auth = ScramAuthenticator::new(&mut scram);
+
+            let result =
+                block_on(client.authenticate(mechanism.sasl_name().unwrap_or_default(), &mut auth))
+                    .map_err(|(error, _)| Error::from(error));
+
+            (result, auth.finish())
+        })
+        .await;
+
+        // A problem on our side explains why the server refused the login, so it is reported first.
+        finished?;
+
+        let imap_session = Self::new_imap_session(result?, banner, compression, metrics, mechanism);
+
+        Ok(imap_session)
+    }
+
+    async fn sasl_login<A: async_imap::Authenticator + Send>(
+        self,
+        mechanism: AuthMechanism,
+        authenticator: A,
+    ) -> Result<ImapSession<S>> {
         let session = self
             .client
-            .authenticate("XOAUTH2", auth)
+            .authenticate(mechanism.sasl_name().unwrap_or_default(), authenticator)
             .await
             .map_err(|(error, _)| Error::from(error))?;
 
-        let imap_session = Self::new_imap_session(session);
+        let imap_session = Self::new_imap_session(
+            session,
+            self.banner,
+            self.compression,
+            self.metrics,
+            mechanism,
+        );
 
         Ok(imap_session)
     }
 }
 
 impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
+    fn list_pattern(&self, pattern: Option<&str>) -> Option<String> {
+        // The pattern is sent as is, so the return options can be appended to it.
+        match (pattern, &self.list_return_options) {
+            (Some(pattern), Some(options)) => Some(format!("{} {}", pattern, options)),
+            (pattern, _) => pattern.map(String::from),
+        }
+    }
+
     async fn list(
         &mut self,
         reference: Option<&str>,
@@ -178,8 +493,10 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
 
         self.close().await?;
 
+        let pattern = self.list_pattern(pattern);
+
         {
-            let mut name_stream = self.session.list(reference, pattern).await?;
+            let mut name_stream = self.session.list(reference, pattern.as_deref()).await?;
 
             while let Some(name) = name_stream.next().await {
                 names.push(name?);
@@ -194,20 +511,116 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
         uid: U,
         query: Q,
     ) -> Result<Fetch> {
-        let mut fetch_stream = self.session.uid_fetch(uid.as_ref(), query).await?;
+        let uid = uid.as_ref();
+
+        let requested = match utils::parse_uid(uid) {
+            Some(requested) => requested,
+            None => err!(
+                ErrorKind::MessageNotFound,
+                "`{}` is not a valid message id",
+                uid
+            ),
+        };
+
+        let mut found = None;
+
+        let result: Result<()> = async {
+            let mut fetch_stream = self.session.uid_fetch(uid, query).await?;
+
+            // Servers may send unsolicited responses for other messages (like flag changes), so only the one for the requested uid is used.
+            while let Some(fetched) = fetch_stream.next().await {
+                let fetched = fetched?;
+
+                if found.is_none() && fetched.uid == Some(requested) {
+                    found = Some(fetched);
+                }
+            }
+
+            Ok(())
+        }
+        .await;
 
-        let fetched = fetch_stream.next().await;
+        if let Err(err) = result {
+            // Some servers refuse to fetch from an empty mailbox instead of returning nothing.
+            if err.is_connection_error() || !self.is_selected_box_empty().await {
+                return Err(err);
+            }
+        }
 
-        match fetched {
-            Some(fetched) => Ok(fetched?),
+        match found {
+            Some(fetched) => Ok(fetched),
             None => err!(
                 ErrorKind::MessageNotFound,
                 "Could not find a message with id `{}`",
-                uid.as_ref(),
+                uid,
             ),
         }
     }
 
+    /// Whether the selected mailbox is empty right now.
+    ///
+    /// The stats from when the mailbox was selected may be outdated, as messages can be expunged by other clients since, so the server is asked again.
+    async fn is_selected_box_empty(&mut self) -> bool {
+        let box_id = match self.selected_box.as_ref() {
+            Some(selected) => selected.id.clone(),
+            None => return false,
+        };
+
+        match self.session.status(&box_id, "(MESSAGES)").await {
+            Ok(status) => status.exists == 0,
+            Err(_) => false,
+        }
+    }
+
+    /// Takes a fetch containing the body structure of a message in the selected mailbox out of the cache, or fetches it if it was not cached while listing messages.
+    ///
+    /// Hand it back using `cache_body_structure` when it is no longer needed.
+    async fn take_body_structure(&mut self, uid: &str) -> Result<Fetch> {
+        let cached = uid.parse::<u32>().ok().and_then(|uid| {
+            self.selected_box
+                .as_mut()
+                .and_then(|selected| selected.body_structures.remove(&uid))
+        });
+
+        if let Some(fetch) = cached {
+            return Ok(fetch);
+        }
+
+        self.uid_fetch_single(uid, QueryBuilder::new().uid().bodystructure().build())
+            .await
+    }
+
+    fn cache_body_structure(&mut self, fetch: Fetch) {
+        if let Some(selected) = self.selected_box.as_mut() {
+            selected.cache_body_structure(fetch);
+        }
+    }
+
+    /// Fetches and parses the entire message, for when the server does not tell us how the message is structured.
+    async fn get_message_from_source(&mut self, uid: &str) -> Result<Message> {
+        let message_data = self
+            .uid_fetch_single(uid, QueryBuilder::new().flags().uid().body().build())
+            .await?;
+
+        let builder: MessageBuilder = match message_data.body() {
+            Some(body) => body.try_into()?,
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server did not return the source of message `{}`",
+                uid
+            ),
+        };
+
+        let flags = message_data
+            .flags()
+            .filter_map(|flag| Flag::from_imap(&flag));
+
+        builder
+            .flags(flags)
+            .id(message_uid(&message_data, uid))
+            .build()
+    }
+
     async fn get_name<I: AsRef<str>>(&mut self, id: I) -> Result<Name> {
         let mut name_stream = self.session.list(Some(id.as_ref()), None).await?;
 
@@ -235,8 +648,17 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
     }
 
     async fn close(&mut self) -> Result<()> {
-        if let Some(_) = self.selected_box {
-            self.session.close().await?;
+        if let Some(selected) = self.selected_box.as_ref() {
+            let result = self.session.close().await.map_err(Error::from);
+
+            match result {
+                // There is nothing left to close, which should not keep us from selecting another mailbox.
+                Err(error) if utils::is_missing_mailbox(&error) => warn!(
+                    target: LOG_TARGET,
+                    "Selected mailbox {} no longer exists: {}", selected.id, error
+                ),
+                result => result?,
+            }
 
             self.selected_box = None;
         }
@@ -244,13 +666,57 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
         Ok(())
     }
 
+    /// Forgets the selected mailbox if a command failed because it no longer exists, e.g. because another client deleted or renamed it.
+    ///
+    /// Otherwise every later command would assume it is still selected and fail the same way.
+    async fn check_missing_mailbox(&mut self, error: Error) -> Error {
+        let box_id = match self.selected_box.as_ref() {
+            Some(selected) => selected.id.clone(),
+            None => return error,
+        };
+
+        // async-imap ignores servers refusing to fetch or search, which makes messages in a mailbox that is gone look missing instead.
+        let error = if matches!(error.kind(), ErrorKind::MessageNotFound) {
+            match self
+                .session
+                .status(&box_id, "(MESSAGES)")
+                .await
+                .map_err(Error::from)
+            {
+                Err(status_error) if utils::is_missing_mailbox(&status_error) => status_error,
+                _ => error,
+            }
+        } else {
+            error
+        };
+
+        if !utils::is_missing_mailbox(&error) {
+            return error;
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Forgetting selected mailbox {}: {}", box_id, error
+        );
+
+        self.selected_box = None;
+
+        Error::new(
+            ErrorKind::MailBoxNotFound,
+            format!(
+                "Mailbox {} does not exist, it may have been deleted or renamed by another client",
+                box_id
+            ),
+        )
+    }
+
     /// Select a given box if it hasn't already been selected, otherwise return the already selected box.
     async fn select(&mut self, mailbox: &Mailbox) -> Result<&MailboxStats> {
         let box_id = mailbox.id().to_string();
 
         // If there is no box selected yet or the box we have selected is not the box we want to select, we have to request the server.
-        if !self.selected_box.is_some() || self.selected_box.as_ref().unwrap().0 != box_id {
-            debug!("Selecting box: {}", box_id);
+        if self.selected_box.is_none() || self.selected_box.as_ref().unwrap().id != box_id {
+            debug!(target: LOG_TARGET, "Selecting box: {}", box_id);
 
             // If there is already a box selected we must close it first
             self.close().await?;
@@ -259,11 +725,33 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
 
             let imap_stats = self.session.select(&box_id).await?;
 
-            self.selected_box = Some((box_id, imap_stats.into()));
+            // Servers that do not send the permanent flags allow all of the flags to be stored permanently.
+            let flags = if imap_stats.permanent_flags.is_empty() {
+                &imap_stats.flags
+            } else {
+                &imap_stats.permanent_flags
+            }
+            .iter()
+            .filter_map(Flag::from_imap_permanent)
+            .collect();
+
+            let stats: MailboxStats = imap_stats.into();
+
+            let mut mailbox = mailbox.clone();
+
+            mailbox.set_stats(stats.clone());
+
+            self.selected_box = Some(SelectedMailbox {
+                id: box_id,
+                mailbox,
+                stats,
+                flags,
+                body_structures: HashMap::new(),
+            });
         };
 
-        if let Some((_id, stats)) = self.selected_box.as_ref() {
-            return Ok(stats);
+        if let Some(selected) = self.selected_box.as_ref() {
+            return Ok(&selected.stats);
         }
 
         err!(
@@ -272,334 +760,2161 @@ impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
         )
     }
 
-    async fn get_mailbox_no_children<M: AsRef<str>>(&mut self, mailbox_id: M) -> Result<Mailbox> {
-        let mailbox_node = self.get_mailbox(mailbox_id.as_ref()).await?;
+    /// Enables UTF-8 support (RFC 6855) if the server advertises it, so non-ASCII mailbox names are not mangled.
+    async fn enable_utf8(&mut self) -> Result<()> {
+        if self.has_capability(UTF8_ACCEPT_CAPABILITY).await? {
+            debug!(target: LOG_TARGET, "Enabling UTF-8 support");
 
-        match mailbox_node.into_data() {
-            Some(data) => Ok(data),
-            None => unreachable!("Get mailbox has to return node with data"),
+            self.session
+                .run_command_and_check_ok(format!("ENABLE {}", UTF8_ACCEPT_CAPABILITY))
+                .await?;
+
+            self.utf8_enabled = true;
         }
+
+        Ok(())
     }
-}
 
-#[async_trait]
-impl<S: Read + Write + Unpin + Debug + Send + Sync> IncomingProtocol for ImapSession<S> {
-    async fn send_keep_alive(&mut self) -> Result<()> {
-        self.last_keep_alive = Some(Instant::now());
+    /// Compresses the rest of the session if the server supports COMPRESS=DEFLATE (RFC 4978).
+    async fn enable_compression(&mut self) -> Result<()> {
+        let compression = match &self.compression {
+            Some(compression) if !compression.is_enabled() => compression.clone(),
+            _ => return Ok(()),
+        };
 
-        self.session.noop().await?;
+        if self.has_capability(COMPRESS_CAPABILITY).await? {
+            debug!(target: LOG_TARGET, "Enabling compression");
 
-        Ok(())
-    }
+            self.session
+                .run_command_and_check_ok("COMPRESS DEFLATE")
+                .await?;
 
-    fn should_keep_alive(&self) -> bool {
-        if let Some(last_keep_alive) = self.last_keep_alive {
-            Instant::now().duration_since(last_keep_alive) >= KEEP_ALIVE_INTERVAL
-        } else {
-            true
+            // The server compresses everything after its response, so this has to happen before the next command.
+            compression.enable();
         }
-    }
 
-    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
-        self.list(None, Some("*")).await
+        Ok(())
     }
 
-    async fn get_mailbox(&mut self, mailbox_id: &str) -> Result<Node<Mailbox>> {
-        let list = self.list(Some(mailbox_id), Some("*")).await?;
-
-        match list.into_find(&MailboxFinder::with_id(mailbox_id)) {
-            Some(mut node) => {
-                let mailbox = match node.data_mut() {
-                    Some(data) => data,
-                    None => unreachable!("Find cannot return root node"),
-                };
+    /// Uses LIST-EXTENDED (RFC 5258) if the server advertises it, so whether a mailbox has children and what it is used for are listed in a single round trip.
+    async fn detect_list_extended(&mut self) -> Result<()> {
+        let capabilities = match self.session.capabilities().await {
+            Ok(capabilities) => capabilities,
+            Err(err) => {
+                let err: Error = err.into();
 
-                let stats = self.select(&mailbox).await?.clone();
+                if err.is_connection_error() {
+                    return Err(err);
+                }
 
-                mailbox.set_stats(stats);
+                // Plain LIST works on every server, it only takes more commands to find out which boxes have children.
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to read the server capabilities, listing mailboxes without LIST-EXTENDED: {}",
+                    err
+                );
 
-                Ok(node)
-            }
-            None => {
-                err!(
-                    ErrorKind::MailBoxNotFound,
-                    "Could not find a mailbox with that id",
-                )
+                return Ok(());
             }
-        }
-    }
+        };
 
-    async fn logout(&mut self) -> Result<()> {
-        self.session.logout().await?;
+        if capabilities.has_str(LIST_EXTENDED_CAPABILITY) {
+            let mut options = vec!["CHILDREN"];
 
-        Ok(())
-    }
+            if capabilities.has_str(SPECIAL_USE_CAPABILITY) {
+                options.push(SPECIAL_USE_CAPABILITY);
+            }
 
-    async fn delete_mailbox(&mut self, box_id: &str) -> Result<()> {
-        self.session.delete(box_id).await?;
+            debug!(target: LOG_TARGET, "Using LIST-EXTENDED with {:?}", options);
+
+            self.list_return_options = Some(format!("RETURN ({})", options.join(" ")));
+        }
 
         Ok(())
     }
 
-    async fn rename_mailbox(&mut self, box_id: &str, new_name: &str) -> Result<()> {
-        let name = self.get_name(box_id).await?;
-
-        let new_name = match name.delimiter() {
-            Some(delimiter) => {
-                let item_count = box_id.matches(delimiter).count();
-
-                if item_count >= 2 {
-                    let split = box_id.split(delimiter);
-
-                    let mut prefix = split
-                        .take(item_count)
-                        .collect::<Vec<&str>>()
-                        .join(delimiter);
-
-                    prefix.push_str(new_name);
+    /// Encodes a mailbox name entered by the user into the form the server expects.
+    fn encode_mailbox_name(&self, name: &str) -> String {
+        if self.utf8_enabled {
+            name.to_string()
+        } else {
+            utf7::encode(name)
+        }
+    }
 
-                    prefix
-                } else {
-                    new_name.to_string()
+    /// Reads responses until the server completes the command with the given tag, passing every other response to `handle`.
+    async fn read_until_done<F: FnMut(&Response) + Send>(
+        &mut self,
+        request_id: &RequestId,
+        mut handle: F,
+    ) -> Result<()> {
+        while let Some(response) = self.session.read_response().await.transpose()? {
+            match response.parsed() {
+                Response::Done {
+                    tag,
+                    status,
+                    code,
+                    information,
+                } if tag == request_id => {
+                    return match status {
+                        Status::Ok => Ok(()),
+                        status => Err(utils::rejected(status, code, information)),
+                    };
                 }
+                parsed => handle(parsed),
             }
-            None => new_name.to_string(),
-        };
-
-        self.close().await?;
-
-        self.session.rename(box_id, &new_name).await?;
+        }
 
-        Ok(())
+        Err(ImapError::ConnectionLost.into())
     }
 
-    async fn create_mailbox(&mut self, box_id: &str) -> Result<()> {
-        self.session.create(box_id).await?;
+    async fn check_acl_supported(&mut self) -> Result<()> {
+        if !self.has_capability(ACL_CAPABILITY).await? {
+            err!(
+                ErrorKind::Unsupported,
+                "The server does not support mailbox access control lists"
+            )
+        }
 
         Ok(())
     }
 
-    async fn get_messages(
-        &mut self,
-        box_id: &str,
-        start: usize,
-        end: usize,
-    ) -> Result<Vec<Preview>> {
-        let mailbox = self.get_mailbox_no_children(box_id).await?;
-
-        let stats = self.select(&mailbox).await?;
+    /// Whether the server advertises a given capability.
+    async fn has_capability(&mut self, capability: &str) -> Result<bool> {
+        let capabilities = self.session.capabilities().await?;
 
-        let total_messages = stats.total();
+        Ok(capabilities.has_str(capability))
+    }
 
-        if total_messages < 1 {
-            return Ok(Vec::new());
+    async fn get_mailbox_no_children<M: AsRef<str>>(&mut self, mailbox_id: M) -> Result<Mailbox> {
+        // The selected box is already known to exist, so we can skip listing and selecting it again.
+        if let Some(selected) = self.selected_box.as_ref() {
+            if selected.id == mailbox_id.as_ref() {
+                return Ok(selected.mailbox.clone());
+            }
         }
 
-        let sequence_start = if total_messages < end {
-            1
+        let mailbox_node = self.get_mailbox(mailbox_id.as_ref()).await?;
+
+        match mailbox_node.into_data() {
+            Some(data) => Ok(data),
+            None => unreachable!("Get mailbox has to return node with data"),
+        }
+    }
+}
+
+/// The uid the server returned for a message, or the one we asked for if the server left it out.
+fn message_uid(fetch: &Fetch, requested: &str) -> String {
+    match fetch.uid {
+        Some(uid) => uid.to_string(),
+        None => requested.to_string(),
+    }
+}
+
+/// The query used to fetch the data needed for a message preview.
+fn preview_query() -> QueryBuilder {
+    QueryBuilder::default()
+        .headers(vec!["From", "Date", "Subject"])
+        .bodystructure()
+}
+
+fn parse_preview(fetch: &Fetch) -> Result<Preview> {
+    let flags = fetch.flags().filter_map(|flag| Flag::from_imap(&flag));
+
+    build_preview(fetch.uid, fetch.header(), fetch.bodystructure(), flags)
+}
+
+/// Builds a preview from the items of a fetch response.
+///
+/// Some servers leave out items we asked for. Without a uid or headers there is nothing useful to show, so that is an error, but a missing body structure only means we cannot tell whether the message has attachments.
+fn build_preview<F: IntoIterator<Item = Flag>>(
+    uid: Option<u32>,
+    headers: Option<&[u8]>,
+    body_structure: Option<&BodyStructure<'_>>,
+    flags: F,
+) -> Result<Preview> {
+    let message_id = match uid {
+        Some(uid) => uid,
+        None => err!(
+            ErrorKind::UnexpectedBehavior,
+            "Server did not return the uid of a message"
+        ),
+    };
+
+    let builder: MessageBuilder = match headers {
+        Some(headers) => headers.try_into()?,
+        None => err!(
+            ErrorKind::UnexpectedBehavior,
+            "Server did not return the headers of message {}",
+            message_id
+        ),
+    };
+
+    let attachments = match body_structure {
+        Some(body_structure) => BodyStructureParser::from(body_structure).extract_attachments(),
+        None => {
+            warn!(
+                target: LOG_TARGET,
+                "Server did not return the body structure of message {}, not looking for attachments",
+                message_id
+            );
+
+            Vec::new()
+        }
+    };
+
+    let preview: Preview = builder
+        .flags(flags)
+        .attachments(attachments)
+        .id(message_id)
+        .build()?;
+
+    Ok(preview)
+}
+
+impl<S: Read + Write + Unpin + Debug + Send + Sync> Drop for ImapSession<S> {
+    fn drop(&mut self) {
+        if !self.closed {
+            warn!(
+                target: LOG_TARGET,
+                "Imap session was dropped without logging out, the connection will be closed abruptly"
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Read + Write + Unpin + Debug + Send + Sync> IncomingProtocol for ImapSession<S> {
+    async fn send_keep_alive(&mut self) -> Result<()> {
+        self.last_keep_alive = Some(Instant::now());
+
+        self.session.noop().await?;
+
+        Ok(())
+    }
+
+    fn should_keep_alive(&self) -> bool {
+        if let Some(last_keep_alive) = self.last_keep_alive {
+            Instant::now().duration_since(last_keep_alive) >= KEEP_ALIVE_INTERVAL
         } else {
-            total_messages.saturating_sub(end).saturating_add(1)
+            true
+        }
+    }
+
+    fn protocol_name(&self) -> &'static str {
+        "IMAP"
+    }
+
+    fn server_banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+
+    fn metrics(&self) -> SessionMetrics {
+        self.metrics.metrics()
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        Some(self.auth_mechanism)
+    }
+
+    async fn is_authenticated(&mut self) -> Result<bool> {
+        // STATUS requires authentication but does not change the selected mailbox.
+        match self.session.status("INBOX", "(MESSAGES)").await {
+            Ok(_) => Ok(true),
+            Err(err) if utils::is_logged_out(&err) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn capabilities(&mut self) -> Result<Vec<String>> {
+        let capabilities = self.session.capabilities().await?;
+
+        let capabilities = capabilities
+            .iter()
+            .map(|capability| match capability {
+                Capability::Imap4rev1 => String::from("IMAP4rev1"),
+                Capability::Auth(mechanism) => format!("AUTH={}", mechanism),
+                Capability::Atom(atom) => atom.clone(),
+            })
+            .collect();
+
+        Ok(capabilities)
+    }
+
+    async fn get_mailbox_stream<'a>(&'a mut self) -> Result<BoxStream<'a, Result<Mailbox>>> {
+        self.close().await?;
+
+        let pattern = self.list_pattern(Some("*"));
+
+        let names = self.session.list(None, pattern.as_deref()).await?;
+
+        Ok(names.map(|name| Ok(Mailbox::from(&name?))).boxed())
+    }
+
+    /// Imap mailboxes are nested, so the tree is built from the names the server sends instead of collecting `get_mailbox_stream`.
+    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
+        self.list(None, Some("*")).await
+    }
+
+    async fn get_mailbox(&mut self, mailbox_id: &str) -> Result<Node<Mailbox>> {
+        let list = self.list(Some(mailbox_id), Some("*")).await?;
+
+        match list.into_find(&MailboxFinder::with_id(mailbox_id)) {
+            Some(mut node) => {
+                let mailbox = match node.data_mut() {
+                    Some(data) => data,
+                    None => unreachable!("Find cannot return root node"),
+                };
+
+                let stats = self.select(mailbox).await?.clone();
+
+                mailbox.set_stats(stats);
+
+                Ok(node)
+            }
+            None => {
+                err!(
+                    ErrorKind::MailBoxNotFound,
+                    "Could not find a mailbox with that id",
+                )
+            }
+        }
+    }
+
+    async fn logout(&mut self) -> Result<()> {
+        self.session.logout().await?;
+
+        self.closed = true;
+
+        Ok(())
+    }
+
+    async fn delete_mailbox(&mut self, box_id: &str) -> Result<()> {
+        if self
+            .selected_box
+            .as_ref()
+            .map(|selected| selected.id == box_id)
+            == Some(true)
+        {
+            self.close().await?;
+        }
+
+        self.session.delete(box_id).await?;
+
+        Ok(())
+    }
+
+    async fn rename_mailbox(&mut self, box_id: &str, new_name: &str) -> Result<()> {
+        let name = self.get_name(box_id).await?;
+
+        let new_name = self.encode_mailbox_name(new_name);
+
+        let new_id = utils::renamed_mailbox_id(box_id, &new_name, name.delimiter());
+
+        // The server moves the children of the mailbox along with it, so the selected box could be renamed as well.
+        self.close().await?;
+
+        debug!(target: LOG_TARGET, "Renaming mailbox {} to {}", box_id, new_id);
+
+        self.session.rename(box_id, &new_id).await?;
+
+        Ok(())
+    }
+
+    async fn create_mailbox(&mut self, box_id: &str) -> Result<()> {
+        let box_id = self.encode_mailbox_name(box_id);
+
+        self.session.create(box_id).await?;
+
+        Ok(())
+    }
+
+    async fn get_messages(
+        &mut self,
+        box_id: &str,
+        start: usize,
+        end: usize,
+        order: SortOrder,
+    ) -> Result<Vec<Preview>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        let stats = self.select(&mailbox).await?;
+
+        let (sequence_start, sequence_end) = match order.sequence_range(stats.total(), start, end) {
+            Some(range) => range,
+            None => return Ok(Vec::new()),
         };
 
-        let sequence_end = if total_messages < start {
-            1
-        } else {
-            total_messages.saturating_sub(start)
+        let sequence = format!("{}:{}", sequence_start, sequence_end);
+
+        let mut fetched = Vec::new();
+
+        let query = preview_query().build();
+
+        {
+            let mut preview_stream = self.session.fetch(sequence, &query).await?;
+
+            // A single malformed message should not make the rest of the mailbox unviewable, so those are skipped.
+            while let Some(fetch) = preview_stream.next().await {
+                let fetch = match fetch {
+                    Ok(fetch) => fetch,
+                    Err(err) => {
+                        let err = Error::from(err);
+
+                        if err.is_connection_error() {
+                            return Err(err);
+                        }
+
+                        warn!(
+                            target: LOG_TARGET,
+                            "Skipping a message in {} that the server sent an invalid response for: {}",
+                            box_id,
+                            err
+                        );
+
+                        continue;
+                    }
+                };
+
+                match parse_preview(&fetch) {
+                    Ok(preview) => fetched.push((fetch.message, preview)),
+                    Err(err) => warn!(
+                        target: LOG_TARGET,
+                        "Skipping message {} in {} because it could not be parsed: {}",
+                        fetch.message,
+                        box_id,
+                        err
+                    ),
+                }
+
+                if let Some(selected) = self.selected_box.as_mut() {
+                    selected.cache_body_structure(fetch);
+                }
+            }
+        }
+
+        // The server does not have to respond in the order of the sequence set.
+        match order {
+            SortOrder::NewestFirst => fetched.sort_by_key(|a| Reverse(a.0)),
+            SortOrder::OldestFirst => fetched.sort_by_key(|a| a.0),
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Fetched {} message previews from {}",
+            fetched.len(),
+            box_id
+        );
+
+        Ok(fetched.into_iter().map(|(_, preview)| preview).collect())
+    }
+
+    async fn get_changes_since(&mut self, box_id: &str, modseq: u64) -> Result<Vec<Preview>> {
+        if !self.has_capability(CONDSTORE_CAPABILITY).await? {
+            err!(
+                ErrorKind::Unsupported,
+                "The mail server does not support the CONDSTORE extension",
+            );
+        }
+
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        // The stats are from when the mailbox was selected, so they can not tell whether new messages arrived since.
+        self.select(&mailbox).await?;
+
+        let query = format!("{} (CHANGEDSINCE {})", preview_query().build(), modseq);
+
+        let mut previews = Vec::new();
+
+        {
+            let mut preview_stream = self.session.uid_fetch("1:*", &query).await?;
+
+            while let Some(fetch) = preview_stream.next().await {
+                let fetch = fetch?;
+
+                match parse_preview(&fetch) {
+                    Ok(preview) => previews.push(preview),
+                    Err(err) => warn!(
+                        target: LOG_TARGET,
+                        "Skipping changed message {} in {} because it could not be parsed: {}",
+                        fetch.message,
+                        box_id,
+                        err
+                    ),
+                }
+            }
+        }
+
+        Ok(previews)
+    }
+
+    async fn get_messages_since_uid(
+        &mut self,
+        box_id: &str,
+        last_uid: u32,
+    ) -> Result<Vec<Preview>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        // The stats are from when the mailbox was selected, so they can not tell whether new messages arrived since.
+        self.select(&mailbox).await?;
+
+        let query = preview_query().build();
+
+        let mut fetched = Vec::new();
+
+        {
+            let mut preview_stream = self
+                .session
+                .uid_fetch(format!("{}:*", last_uid.saturating_add(1)), &query)
+                .await?;
+
+            while let Some(fetch) = preview_stream.next().await {
+                let fetch = fetch?;
+
+                // `n:*` always includes the message with the highest uid, even if it is lower than n.
+                let uid = match fetch.uid {
+                    Some(uid) if uid > last_uid => uid,
+                    _ => continue,
+                };
+
+                match parse_preview(&fetch) {
+                    Ok(preview) => fetched.push((uid, preview)),
+                    Err(err) => warn!(
+                        target: LOG_TARGET,
+                        "Skipping new message {} in {} because it could not be parsed: {}",
+                        fetch.message,
+                        box_id,
+                        err
+                    ),
+                }
+            }
+        }
+
+        fetched.sort_unstable_by_key(|(uid, _)| *uid);
+
+        Ok(fetched.into_iter().map(|(_, preview)| preview).collect())
+    }
+
+    async fn watch(&mut self, box_ids: &[&str]) -> Result<()> {
+        self.start_watching(box_ids).await
+    }
+
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        self.wait_for_events().await
+    }
+
+    async fn stop_watching(&mut self) -> Result<()> {
+        self.end_watching().await
+    }
+
+    async fn check_error(&mut self, error: Error) -> Error {
+        self.check_missing_mailbox(error).await
+    }
+
+    async fn get_unread_messages(&mut self, box_id: &str, limit: usize) -> Result<Vec<Preview>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let mut uids: Vec<u32> = self
+            .session
+            .uid_search("UNSEEN")
+            .await?
+            .into_iter()
+            .collect();
+
+        // Uids are assigned in ascending order, so the highest uids belong to the newest messages.
+        uids.sort_unstable_by_key(|uid| Reverse(*uid));
+        uids.truncate(limit);
+
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = preview_query().build();
+
+        let mut fetched = Vec::with_capacity(uids.len());
+
+        {
+            let mut preview_stream = self
+                .session
+                .uid_fetch(utils::to_sequence_set(&uids), &query)
+                .await?;
+
+            while let Some(fetch) = preview_stream.next().await {
+                let fetch = fetch?;
+
+                match parse_preview(&fetch) {
+                    Ok(preview) => fetched.push((fetch.uid, preview)),
+                    Err(err) => warn!(
+                        target: LOG_TARGET,
+                        "Skipping unread message {} in {} because it could not be parsed: {}",
+                        fetch.message,
+                        box_id,
+                        err
+                    ),
+                }
+
+                if let Some(selected) = self.selected_box.as_mut() {
+                    selected.cache_body_structure(fetch);
+                }
+            }
+        }
+
+        fetched.sort_by_key(|(uid, _)| Reverse(*uid));
+
+        Ok(fetched.into_iter().map(|(_, preview)| preview).collect())
+    }
+
+    async fn get_unread_count(&mut self, box_id: &str) -> Result<usize> {
+        // STATUS does not need the mailbox to be selected, so polling it does not disturb the selected box.
+        let status = self.session.status(box_id, "(UNSEEN)").await?;
+
+        Ok(status.unseen.unwrap_or(0) as usize)
+    }
+
+    async fn get_message(&mut self, box_id: &str, msg_id: &str) -> Result<Message> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let structure_data = self.take_body_structure(msg_id).await?;
+
+        let body_structure: BodyStructureParser<'_> = match structure_data.bodystructure() {
+            Some(body_structure) => body_structure.into(),
+            None => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Server did not return the body structure of message {}, parsing its full source instead",
+                    msg_id
+                );
+
+                return self.get_message_from_source(msg_id).await;
+            }
+        };
+
+        let attachments = body_structure.extract_attachments();
+
+        let protection = body_structure.protection();
+
+        let text_part = body_structure.find_part_for(mime::TEXT_PLAIN);
+        let html_part = body_structure.find_part_for(mime::TEXT_HTML);
+        let status_part = body_structure.find_part_for(parser::delivery_status::mime_type());
+
+        // The headers, flags and text parts are all fetched at once.
+        let mut query = QueryBuilder::new()
+            .flags()
+            .uid()
+            .headers::<String>(Vec::new());
+
+        for (part_number, _) in [text_part.as_ref(), html_part.as_ref(), status_part.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            query = query.section(part_number);
+        }
+
+        let message_data = self.uid_fetch_single(msg_id, query.build()).await?;
+
+        let flags = message_data
+            .flags()
+            .filter_map(|flag| Flag::from_imap(&flag));
+
+        let message_id = message_uid(&message_data, msg_id);
+
+        let mut builder: MessageBuilder = match message_data.header() {
+            Some(headers) => headers.try_into()?,
+            None => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Server did not return the headers of message {}, parsing its full source instead",
+                    msg_id
+                );
+
+                return self.get_message_from_source(msg_id).await;
+            }
+        };
+
+        if let Some((html_part_number, encoding)) = html_part {
+            let section_path: SectionPath = html_part_number.into();
+
+            if let Some(html) = message_data.section(&section_path) {
+                let html = utils::decode_transfer_encoding(html, encoding)?;
+
+                builder = builder.html(std::str::from_utf8(&html)?);
+            }
+        }
+
+        if let Some((text_part_number, encoding)) = text_part {
+            let section_path: SectionPath = text_part_number.into();
+
+            if let Some(text) = message_data.section(&section_path) {
+                let text = utils::decode_transfer_encoding(text, encoding)?;
+
+                builder = builder.text(std::str::from_utf8(&text)?);
+            }
+        }
+
+        if let Some((status_part_number, encoding)) = status_part {
+            let section_path: SectionPath = status_part_number.into();
+
+            if let Some(report) = message_data.section(&section_path) {
+                let report = utils::decode_transfer_encoding(report, encoding)?;
+
+                if let Some(status) =
+                    parser::delivery_status::parse_delivery_status(String::from_utf8_lossy(&report))
+                {
+                    builder = builder.delivery_status(status);
+                }
+            }
+        }
+
+        // Keep the body structure around in case the message is opened again.
+        self.cache_body_structure(structure_data);
+
+        let message: Message = builder
+            .flags(flags)
+            .attachments(attachments)
+            .signed(protection.signed)
+            .encrypted(protection.encrypted)
+            .id(message_id)
+            .build()?;
+
+        Ok(message)
+    }
+
+    async fn mark_all_read(&mut self, box_id: &str) -> Result<()> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        // Storing flags on an empty range is an error on most servers.
+        if self.is_selected_box_empty().await {
+            return Ok(());
+        }
+
+        {
+            let mut store_stream = self.session.store("1:*", "+FLAGS.SILENT (\\Seen)").await?;
+
+            while let Some(fetch) = store_stream.next().await {
+                fetch?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn set_flags_bulk(
+        &mut self,
+        box_id: &str,
+        message_ids: &[&str],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()> {
+        if message_ids.is_empty() {
+            return Ok(());
+        }
+
+        let uids = message_ids
+            .iter()
+            .map(|id| id.parse::<u32>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let flags: Vec<String> = flags.iter().filter_map(Flag::to_imap).collect();
+
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let query = format!("{} ({})", mode.to_imap(), flags.join(" "));
+
+        {
+            let mut store_stream = self
+                .session
+                .uid_store(utils::to_sequence_set(&uids), query)
+                .await?;
+
+            while let Some(fetch) = store_stream.next().await {
+                fetch?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_attachment(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+    ) -> Result<Vec<u8>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let part_number: PartNumber = attachment_id.parse()?;
+
+        let query = QueryBuilder::new().section(&part_number).build();
+
+        let attachment_data = self.uid_fetch_single(message_id, query).await?;
+
+        let section_path: SectionPath = part_number.into();
+
+        if let Some(bytes) = attachment_data.section(&section_path).and_then(|bytes| {
+            if bytes.is_empty() {
+                None
+            } else {
+                Some(bytes)
+            }
+        }) {
+            return Ok(bytes.to_vec());
+        }
+
+        err!(
+            ErrorKind::AttachmentNotFound,
+            "Could not find an attachment with id '{}'",
+            attachment_id
+        );
+    }
+
+    async fn get_attachment_with_progress(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+        progress: &mut (dyn FnMut(usize, usize) + Send),
+    ) -> Result<Vec<u8>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let structure_data = self
+            .uid_fetch_single(message_id, QueryBuilder::new().bodystructure().build())
+            .await?;
+
+        let body_structure: BodyStructureParser<'_> = match structure_data.bodystructure() {
+            Some(body_structure) => body_structure.into(),
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server did not return the body structure of message `{}`",
+                message_id
+            ),
+        };
+
+        let total = match body_structure
+            .extract_attachments()
+            .into_iter()
+            .find(|attachment| attachment.id() == attachment_id)
+        {
+            Some(attachment) => attachment.size(),
+            None => err!(
+                ErrorKind::AttachmentNotFound,
+                "Could not find an attachment with id '{}'",
+                attachment_id
+            ),
+        };
+
+        let part_number: PartNumber = attachment_id.parse()?;
+
+        let mut attachment: Vec<u8> = Vec::with_capacity(total);
+
+        progress(0, total);
+
+        // Fetch the attachment in chunks, so we can report the progress in between.
+        loop {
+            let query = QueryBuilder::new()
+                .partial_section(&part_number, attachment.len(), ATTACHMENT_CHUNK_SIZE)
+                .build();
+
+            let chunk_data = self.uid_fetch_single(message_id, query).await?;
+
+            let section_path: SectionPath = part_number.clone().into();
+
+            let chunk = match chunk_data.section(&section_path) {
+                Some(chunk) => chunk,
+                None => err!(
+                    ErrorKind::AttachmentNotFound,
+                    "Could not find an attachment with id '{}'",
+                    attachment_id
+                ),
+            };
+
+            attachment.extend_from_slice(chunk);
+
+            progress(attachment.len(), total.max(attachment.len()));
+
+            // Stop once the whole attachment is in, as some servers answer with `NIL` instead of an empty chunk past the end.
+            if chunk.len() < ATTACHMENT_CHUNK_SIZE || attachment.len() >= total {
+                break;
+            }
+        }
+
+        Ok(attachment)
+    }
+
+    async fn append(&mut self, box_id: &str, message: &[u8], flags: &[Flag]) -> Result<()> {
+        let mut reader = message;
+
+        self.append_from_reader(box_id, &mut reader, message.len(), flags)
+            .await
+    }
+
+    async fn append_from_reader(
+        &mut self,
+        box_id: &str,
+        message: &mut (dyn Read + Unpin + Send),
+        length: usize,
+        flags: &[Flag],
+    ) -> Result<()> {
+        let flags = flags
+            .iter()
+            .filter_map(Flag::to_imap)
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        // The APPEND command in async-imap does not support flags, so we send it ourselves.
+        let request_id = self
+            .session
+            .run_command(format!(
+                "APPEND {} ({}) {{{}}}",
+                utils::quote(box_id)?,
+                flags,
+                length
+            ))
+            .await?;
+
+        // Wait for the server to ask for the message, it may send unrelated untagged responses first.
+        loop {
+            let response = match self.session.read_response().await.transpose()? {
+                Some(response) => response,
+                None => return Err(ImapError::ConnectionLost.into()),
+            };
+
+            match response.parsed() {
+                Response::Continue { .. } => break,
+                Response::Done {
+                    tag,
+                    status,
+                    code,
+                    information,
+                } if tag == &request_id => {
+                    return Err(utils::rejected(status, code, information));
+                }
+                _ => {}
+            }
+        }
+
+        let stream = self.session.as_mut();
+
+        let sent: Result<()> = async {
+            // Send the message in chunks, so it never has to be fully loaded into memory.
+            let mut buffer = vec![0; APPEND_CHUNK_SIZE.min(length)];
+            let mut remaining = length;
+
+            while remaining > 0 {
+                let chunk_size = remaining.min(buffer.len());
+
+                let read = message.read(&mut buffer[..chunk_size]).await?;
+
+                if read == 0 {
+                    err!(
+                        ErrorKind::Io(io::ErrorKind::UnexpectedEof.into()),
+                        "Message ended {} bytes before its announced length",
+                        remaining
+                    )
+                }
+
+                stream.write_all(&buffer[..read]).await?;
+
+                remaining -= read;
+            }
+
+            stream.write_all(b"\r\n").await?;
+            stream.flush().await?;
+
+            Ok(())
+        }
+        .await;
+
+        if let Err(err) = sent {
+            // The server would read our next command as the rest of the message, so the connection is closed to make every later command fail instead.
+            let _ = shutdown(self.session.as_mut()).await;
+
+            self.closed = true;
+
+            return Err(err);
+        }
+
+        self.read_until_done(&request_id, |_| {}).await
+    }
+
+    async fn get_acl(&mut self, box_id: &str) -> Result<Vec<AclEntry>> {
+        self.check_acl_supported().await?;
+
+        let request_id = self
+            .session
+            .run_command(format!("GETACL {}", utils::quote(box_id)?))
+            .await?;
+
+        let mut entries = Vec::new();
+
+        self.read_until_done(&request_id, |response| {
+            if let Response::Acl(acl) = response {
+                for entry in &acl.acls {
+                    entries.push(AclEntry::new(
+                        entry.identifier.as_ref(),
+                        utils::rights_to_string(&entry.rights),
+                    ));
+                }
+            }
+        })
+        .await?;
+
+        Ok(entries)
+    }
+
+    async fn set_acl(&mut self, box_id: &str, identifier: &str, rights: &str) -> Result<()> {
+        self.check_acl_supported().await?;
+
+        self.session
+            .run_command_and_check_ok(format!(
+                "SETACL {} {} {}",
+                utils::quote(box_id)?,
+                utils::quote(identifier)?,
+                utils::quote(rights)?
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_my_rights(&mut self, box_id: &str) -> Result<String> {
+        self.check_acl_supported().await?;
+
+        let request_id = self
+            .session
+            .run_command(format!("MYRIGHTS {}", utils::quote(box_id)?))
+            .await?;
+
+        let mut rights = String::new();
+
+        self.read_until_done(&request_id, |response| {
+            if let Response::MyRights(my_rights) = response {
+                rights = utils::rights_to_string(&my_rights.rights);
+            }
+        })
+        .await?;
+
+        Ok(rights)
+    }
+
+    async fn get_mailbox_flags(&mut self, box_id: &str) -> Result<Vec<Flag>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        match self.selected_box.as_ref() {
+            Some(selected) => Ok(selected.flags.clone()),
+            None => err!(
+                ErrorKind::MailBoxNotFound,
+                "Could not find a mailbox with that id",
+            ),
+        }
+    }
+
+    async fn get_specific_headers(
+        &mut self,
+        box_id: &str,
+        msg_id: &str,
+        headers: &[&str],
+    ) -> Result<Headers> {
+        if headers.is_empty() {
+            return Ok(Headers::new());
+        }
+
+        // A line break in a name would end the command early.
+        if let Some(header) = headers
+            .iter()
+            .find(|header| !utils::is_header_field_name(header))
+        {
+            err!(
+                ErrorKind::Unsupported,
+                "`{}` is not a valid header name",
+                header
+            )
+        }
+
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let fields = headers
+            .iter()
+            .map(|header| utils::quote(header))
+            .collect::<Result<Vec<String>>>()?;
+
+        let query = QueryBuilder::new().uid().peek_header_fields(&fields);
+
+        let fetch = self.uid_fetch_single(msg_id, query.build()).await?;
+
+        match fetch.header() {
+            Some(raw) => parser::message::parse_specific_headers(raw, headers),
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server did not return the headers of message {}",
+                msg_id
+            ),
+        }
+    }
+
+    async fn get_message_source(&mut self, box_id: &str, message_id: &str) -> Result<Vec<u8>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let message_data = self
+            .uid_fetch_single(message_id, QueryBuilder::new().uid().body().build())
+            .await?;
+
+        match message_data.body() {
+            Some(body) => Ok(body.to_vec()),
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server did not return the source of message `{}`",
+                message_id
+            ),
+        }
+    }
+
+    async fn get_message_part(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>> {
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let structure_data = self
+            .uid_fetch_single(message_id, QueryBuilder::new().bodystructure().build())
+            .await?;
+
+        let body_structure: BodyStructureParser<'_> = match structure_data.bodystructure() {
+            Some(body_structure) => body_structure.into(),
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Server did not return the body structure of message `{}`",
+                message_id
+            ),
+        };
+
+        let (part_number, encoding) = match body_structure.find_part_for(mime_type) {
+            Some(part) => part,
+            None => return Ok(None),
+        };
+
+        let query = QueryBuilder::new().section(&part_number).build();
+
+        let part_data = self.uid_fetch_single(message_id, query).await?;
+
+        let section_path: SectionPath = part_number.into();
+
+        match part_data.section(&section_path) {
+            Some(bytes) => Ok(Some(utils::decode_transfer_encoding(bytes, encoding)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::client::protocol::RemoteServer;
+
+    use async_imap::imap_proto::AttributeValue;
+
+    use super::*;
+
+    use dotenv::dotenv;
+
+    use std::env;
+
+    async fn create_test_session() -> Box<dyn IncomingProtocol + Send + Sync> {
+        dotenv().ok();
+
+        let username = env::var("IMAP_USERNAME").unwrap();
+        let password = env::var("IMAP_PASSWORD").unwrap();
+
+        let server = env::var("IMAP_SERVER").unwrap();
+        let port: u16 = 993;
+
+        let server = RemoteServer::new(server, port, ConnectionSecurity::Tls);
+        let credentials = Credentials::password(username, password);
+
+        let creds = ImapCredentials::new(server, credentials);
+
+        let session = create(&creds, Default::default(), &Default::default())
+            .await
+            .unwrap();
+
+        session
+    }
+
+    /// Plays the server side of a login, advertising the given capabilities and returning every line the client sent.
+    #[cfg(feature = "runtime-tokio")]
+    async fn fake_login_server(stream: tokio::io::DuplexStream, capabilities: &str) -> Vec<String> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+
+        let mut stream = BufStream::new(stream);
+        let mut received = Vec::new();
+        let mut authenticate_tag = String::new();
+
+        stream.write_all(b"* OK ready\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+
+        loop {
+            let mut line = String::new();
+
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                return received;
+            }
+
+            let reply = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [tag, "CAPABILITY"] => {
+                    format!("* CAPABILITY {}\r\n{} OK done\r\n", capabilities, tag)
+                }
+                [tag, "AUTHENTICATE", mechanism] => {
+                    authenticate_tag = tag.to_string();
+
+                    match mechanism {
+                        // The challenge from RFC 2195.
+                        "CRAM-MD5" => {
+                            "+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n"
+                                .to_string()
+                        }
+                        _ => "+ \r\n".to_string(),
+                    }
+                }
+                [tag, "LOGIN", ..] => format!("{} OK logged in\r\n", tag),
+                // The answer to the PLAIN challenge.
+                [_] => format!("{} OK logged in\r\n", authenticate_tag),
+                _ => panic!("Unexpected command {:?}", line),
+            };
+
+            received.push(line.trim_end().to_string());
+
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
+
+            if reply.contains("logged in") {
+                return received;
+            }
+        }
+    }
+
+    #[cfg(all(feature = "runtime-tokio", feature = "smtp"))]
+    #[tokio::test]
+    async fn authenticating_state() {
+        use crate::client::{
+            connection::ConnectionState, create_with_state, protocol::SmtpCredentials,
+            IncomingEmailProtocol, OutgoingEmailProtocol,
         };
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
 
-        let sequence = format!("{}:{}", sequence_start, sequence_end);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
 
-        let mut previews = Vec::new();
+        let state = SharedConnectionState::default();
+        let server_state = state.clone();
 
-        let query = QueryBuilder::default()
-            .headers(vec!["From", "Date", "Subject"])
-            .bodystructure()
-            .build();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufStream::new(stream);
 
-        {
-            let mut preview_stream = self.session.fetch(sequence, &query).await?;
+            stream.write_all(b"* OK ready\r\n").await.unwrap();
+            stream.flush().await.unwrap();
 
-            while let Some(fetch) = preview_stream.next().await {
-                let fetch = fetch?;
+            let mut states = Vec::new();
+
+            loop {
+                let mut line = String::new();
+
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return states;
+                }
+
+                let reply = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [tag, "CAPABILITY"] => {
+                        format!("* CAPABILITY IMAP4rev1\r\n{} OK done\r\n", tag)
+                    }
+                    [tag, "LOGIN", ..] => {
+                        states.push(server_state.get());
+
+                        format!("{} OK logged in\r\n", tag)
+                    }
+                    [tag, ..] => format!("{} OK done\r\n", tag),
+                    [] => continue,
+                };
+
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+        });
+
+        let client = create_with_state(
+            IncomingEmailProtocol::Imap(ImapCredentials::new(
+                RemoteServer::new("127.0.0.1", port, ConnectionSecurity::Plain),
+                Credentials::password("user", "secret"),
+            )),
+            OutgoingEmailProtocol::Smtp(SmtpCredentials::new(
+                RemoteServer::new("127.0.0.1", port, ConnectionSecurity::Plain),
+                Credentials::password("user", "secret"),
+            )),
+            Default::default(),
+            state.clone(),
+        )
+        .await
+        .unwrap();
 
-                let body_structure: BodyStructureParser<'_> = fetch
-                    .bodystructure()
-                    .expect("'BODYSTRUCTURE' was expected to have been specified in the query")
-                    .into();
+        assert_eq!(client.state(), ConnectionState::Connected);
 
-                let attachments = body_structure.extract_attachments();
+        drop(client);
 
-                let headers = fetch
-                    .header()
-                    .expect("'HEADER' was expected to have been specified in the query'");
+        assert_eq!(server.await.unwrap(), vec![ConnectionState::Authenticating]);
+    }
 
-                let message_id = fetch
-                    .uid
-                    .expect("'UID' was expected to have been specified in the query'");
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn negotiate_login() {
+        let credentials = Credentials::password("user", "secret");
 
-                let flags = fetch
-                    .flags()
-                    .into_iter()
-                    .filter_map(|flag| Flag::from_imap(&flag));
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(async move {
+            fake_login_server(server_stream, "IMAP4rev1 AUTH=XOAUTH2 AUTH=PLAIN").await
+        });
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client.authenticate(&credentials).await.unwrap();
+
+        assert_eq!(session.auth_mechanism, AuthMechanism::Plain);
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                "A0001 CAPABILITY",
+                "A0002 AUTHENTICATE PLAIN",
+                "AHVzZXIAc2VjcmV0"
+            ]
+        );
+
+        // CRAM-MD5 is preferred over PLAIN, as it does not send the password.
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(async move {
+            fake_login_server(server_stream, "IMAP4rev1 AUTH=PLAIN AUTH=CRAM-MD5").await
+        });
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client.authenticate(&credentials).await.unwrap();
+
+        assert_eq!(session.auth_mechanism, AuthMechanism::CramMd5);
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                "A0001 CAPABILITY",
+                "A0002 AUTHENTICATE CRAM-MD5",
+                // "user 733ba87e64ed7e441f062c4ac6db0103"
+                "dXNlciA3MzNiYTg3ZTY0ZWQ3ZTQ0MWYwNjJjNGFjNmRiMDEwMw=="
+            ]
+        );
+
+        // Without a SASL mechanism we support, the LOGIN command is used.
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server =
+            tokio::spawn(
+                async move { fake_login_server(server_stream, "IMAP4rev1 AUTH=GSSAPI").await },
+            );
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client.authenticate(&credentials).await.unwrap();
+
+        assert_eq!(session.auth_mechanism, AuthMechanism::LoginCommand);
+        assert_eq!(
+            server.await.unwrap(),
+            vec!["A0001 CAPABILITY", "A0002 LOGIN \"user\" \"secret\""]
+        );
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn utf8_rejected() {
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(fake_server(server_stream, |tag, command| match command {
+            "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 UTF8=ACCEPT\r\n{} OK done\r\n", tag),
+            "ENABLE" => format!("{} NO not now\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        }));
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
 
-                let builder: MessageBuilder = headers.try_into()?;
+        let mut session = create_session(
+            client,
+            &Credentials::password("user", "secret"),
+            &IncomingConfig::default(),
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(!session.utf8_enabled);
+
+        session.send_keep_alive().await.unwrap();
+        session.logout().await.unwrap();
+        drop(session);
+
+        assert!(server
+            .await
+            .unwrap()
+            .contains(&String::from("ENABLE UTF8=ACCEPT")));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn capability_rejected() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "CAPABILITY" => format!("{} BAD not now\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        session.detect_list_extended().await.unwrap();
 
-                let preview: Preview = builder
-                    .flags(flags)
-                    .attachments(attachments)
-                    .id(message_id)
-                    .build()?;
+        assert_eq!(session.list_return_options, None);
 
-                previews.push(preview);
+        session.send_keep_alive().await.unwrap();
+        session.logout().await.unwrap();
+        drop(session);
+
+        assert_eq!(
+            server.await.unwrap(),
+            vec!["LOGIN \"user\" \"secret\"", "CAPABILITY", "NOOP", "LOGOUT"]
+        );
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn refresh_expired_token() {
+        use crate::cache::{Cache, Refresher, SharedCache};
+        use crate::runtime::time::Duration;
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        struct Provider;
+
+        #[async_trait::async_trait]
+        impl Refresher<String> for Provider {
+            async fn refresh(&mut self) -> Result<(String, Duration)> {
+                Ok((String::from("fresh"), Duration::from_secs(3600)))
             }
         }
 
-        Ok(previews)
+        let mut cache = Cache::new();
+
+        // Valid for less than the margin, so it counts as expired.
+        cache.set(String::from("stale"), Duration::from_secs(1));
+
+        let credentials = Credentials::oauth_refreshing("user", SharedCache::new(cache, Provider));
+
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(async move {
+            fake_login_server(server_stream, "IMAP4rev1 AUTH=XOAUTH2").await
+        });
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client.authenticate(&credentials).await.unwrap();
+
+        assert_eq!(session.auth_mechanism, AuthMechanism::XOAuth2);
+
+        let received = server.await.unwrap();
+        let response =
+            String::from_utf8(STANDARD.decode(received.last().unwrap()).unwrap()).unwrap();
+
+        assert_eq!(response, "user=user\x01auth=Bearer fresh\x01\x01");
     }
 
-    async fn get_message(&mut self, box_id: &str, msg_id: &str) -> Result<Message> {
-        let mailbox = self.get_mailbox_no_children(box_id).await?;
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn scram_login_exchange() {
+        use crate::client::auth::Hash;
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream, DuplexStream};
 
-        self.select(&mailbox).await?;
+        async fn exchange(stream: &mut BufStream<DuplexStream>, reply: String) -> String {
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
 
-        let message_data = self
-            .uid_fetch_single(
-                msg_id,
-                QueryBuilder::new()
-                    .flags()
-                    .uid()
-                    .bodystructure()
-                    .headers::<String>(Vec::new())
-                    .build(),
+            let mut line = String::new();
+
+            stream.read_line(&mut line).await.unwrap();
+
+            line.trim_end().to_string()
+        }
+
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        // The server side of SCRAM-SHA-256, using the salt from RFC 7677.
+        let server = tokio::spawn(async move {
+            let mut stream = BufStream::new(server_stream);
+            let decode = |line: String| String::from_utf8(STANDARD.decode(line).unwrap()).unwrap();
+
+            let command = exchange(&mut stream, "* OK ready\r\n".to_string()).await;
+            let (tag, _) = command.split_once(' ').unwrap();
+
+            let client_first = decode(exchange(&mut stream, "+ \r\n".to_string()).await);
+            let client_first_bare = client_first.strip_prefix("n,,").unwrap();
+            let (_, nonce) = client_first_bare.split_once(",r=").unwrap();
+
+            let server_first = format!("r={}server,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096", nonce);
+
+            let client_final = decode(
+                exchange(
+                    &mut stream,
+                    format!("+ {}\r\n", STANDARD.encode(&server_first)),
+                )
+                .await,
+            );
+            let (without_proof, proof) = client_final.rsplit_once(",p=").unwrap();
+
+            let auth_message = format!("{},{},{}", client_first_bare, server_first, without_proof);
+            let salted_password = Hash::Sha256.pbkdf2(
+                b"secret",
+                &STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(),
+                4096,
+            );
+
+            // The proof xor the client signature gives back the client key.
+            let client_key = Hash::Sha256.hmac(&salted_password, b"Client Key");
+            let client_signature =
+                Hash::Sha256.hmac(&Hash::Sha256.digest(&client_key), auth_message.as_bytes());
+            let recovered: Vec<u8> = STANDARD
+                .decode(proof)
+                .unwrap()
+                .iter()
+                .zip(client_signature)
+                .map(|(left, right)| left ^ right)
+                .collect();
+
+            assert_eq!(recovered, client_key);
+
+            let server_key = Hash::Sha256.hmac(&salted_password, b"Server Key");
+            let server_signature = Hash::Sha256.hmac(&server_key, auth_message.as_bytes());
+
+            let last = exchange(
+                &mut stream,
+                format!(
+                    "+ {}\r\n",
+                    STANDARD.encode(format!("v={}", STANDARD.encode(server_signature)))
+                ),
             )
-            .await?;
+            .await;
 
-        let body_structure: BodyStructureParser<'_> = message_data
-            .bodystructure()
-            .expect("'BODYSTRUCTURE' was expected to have been specified in the query")
-            .into();
+            assert_eq!(last, "");
 
-        let attachments = body_structure.extract_attachments();
+            stream
+                .write_all(format!("{} OK logged in\r\n", tag).as_bytes())
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
 
-        let flags = message_data
-            .flags()
-            .into_iter()
-            .filter_map(|flag| Flag::from_imap(&flag));
+            stream
+        });
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client
+            .scram_login(AuthMechanism::ScramSha256, "user", "secret")
+            .await
+            .unwrap();
+
+        assert_eq!(session.auth_mechanism, AuthMechanism::ScramSha256);
 
-        let message_id = message_data
-            .uid
-            .expect("'UID' was expected to have been specified in the query");
+        server.await.unwrap();
+    }
 
-        let headers = message_data
-            .header()
-            .expect("'HEADER' was expected to have been specified in the query");
+    /// Plays a logged in server, answering every command with what `reply` returns for its tag and name. Returns the commands it received once the client hangs up.
+    #[cfg(feature = "runtime-tokio")]
+    async fn fake_server(
+        stream: tokio::io::DuplexStream,
+        reply: fn(&str, &str) -> String,
+    ) -> Vec<String> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
 
-        let mut builder: MessageBuilder = headers.try_into()?;
+        let mut stream = BufStream::new(stream);
+        let mut received = Vec::new();
 
-        let text_part_number = body_structure.find_part_number_for(mime::TEXT_PLAIN);
-        let html_part_number = body_structure.find_part_number_for(mime::TEXT_HTML);
+        stream.write_all(b"* PREAUTH ready\r\n").await.unwrap();
+        stream.flush().await.unwrap();
 
-        if text_part_number.is_some() || html_part_number.is_some() {
-            let mut query = QueryBuilder::new();
+        loop {
+            let mut line = String::new();
 
-            if let Some(text_part_number) = text_part_number.as_ref() {
-                query = query.section(text_part_number);
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                return received;
             }
 
-            if let Some(html_part_number) = html_part_number.as_ref() {
-                query = query.section(html_part_number);
+            let (tag, command) = line.trim_end().split_once(' ').unwrap();
+
+            let reply = reply(tag, command.split_whitespace().next().unwrap());
+
+            received.push(command.to_string());
+
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
+        }
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    async fn fake_session(
+        reply: fn(&str, &str) -> String,
+    ) -> (
+        ImapSession<CompressStream<CountingStream<tokio::io::DuplexStream>>>,
+        tokio::task::JoinHandle<Vec<String>>,
+    ) {
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(fake_server(server_stream, reply));
+
+        let client = ImapClient::from_stream(client_stream).await.unwrap();
+        let session = client.login("user", "secret").await.unwrap();
+
+        (session, server)
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn watch_with_notify() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 IDLE NOTIFY\r\n{} OK done\r\n", tag),
+            // The first change is reported while the command runs, the others after it.
+            "NOTIFY" => format!(
+                "* STATUS \"INBOX\" (MESSAGES 3 UNSEEN 1)\r\n{} OK done\r\n\
+                 * STATUS \"Trash\" (MESSAGES 1)\r\n\
+                 * STATUS \"Sent\" (MESSAGES 2 UNSEEN 0)\r\n",
+                tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        session.watch(&["INBOX", "Sent"]).await.unwrap();
+
+        let events = session.next_events().await.unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].box_id(), "INBOX");
+
+        // Trash is not being watched, so its change is skipped.
+        let events = session.next_events().await.unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].box_id(), "Sent");
+
+        session.logout().await.unwrap();
+        drop(session);
+
+        assert_eq!(
+            server.await.unwrap()[2],
+            "NOTIFY SET STATUS (MAILBOXES (\"INBOX\" \"Sent\") (MessageNew MessageExpunge FlagChange))"
+        );
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn stop_watching_dropped_stream() {
+        use crate::client::{testing::MockOutgoing, EmailClient};
+        use futures::StreamExt;
+
+        let (session, server) = fake_session(|tag, command| match command {
+            "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 NOTIFY\r\n{} OK done\r\n", tag),
+            "NOTIFY" => format!(
+                "{} OK done\r\n* STATUS \"INBOX\" (MESSAGES 3 UNSEEN 1)\r\n",
+                tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let mut client = EmailClient::new(Box::new(session), Box::new(MockOutgoing::new()));
+
+        let mut events = Box::pin(client.watch_all(&["INBOX".into()]).await.unwrap());
+
+        assert_eq!(events.next().await.unwrap().unwrap().box_id(), "INBOX");
+
+        drop(events);
+
+        client.create_mailbox("Work").await.unwrap();
+
+        client.logout().await.unwrap();
+        drop(client);
+
+        let received = server.await.unwrap();
+
+        let notify_none = received
+            .iter()
+            .position(|command| command == "NOTIFY NONE")
+            .unwrap();
+
+        assert_eq!(received[notify_none + 1], "CREATE \"Work\"");
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn logged_out_session() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "STATUS" => format!("{} NO [UNAVAILABLE] Session timed out\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        assert!(!session.is_authenticated().await.unwrap());
+
+        drop(session);
+
+        server.await.unwrap();
+
+        // Other refusals say nothing about the session, so they are passed on.
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "STATUS" => format!("{} NO Mailbox doesn't exist: INBOX\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let error = session.is_authenticated().await.unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::Imap(ImapError::No(_))));
+
+        drop(session);
+
+        server.await.unwrap();
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn mailbox_stream() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "LIST" => format!(
+                "* LIST () \"/\" \"INBOX/Work\"\r\n\
+                 * LIST () \"/\" \"INBOX\"\r\n\
+                 * LIST () \"/\" \"Sent\"\r\n{} OK done\r\n",
+                tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let streamed: Vec<String> = session
+            .get_mailbox_stream()
+            .await
+            .unwrap()
+            .map(|mailbox| mailbox.unwrap().id().to_string())
+            .collect()
+            .await;
+
+        // The mailboxes are passed on in the order the server sent them, the list nests them.
+        assert_eq!(streamed, vec!["INBOX/Work", "INBOX", "Sent"]);
+
+        let list = session.get_mailbox_list().await.unwrap();
+
+        let inbox = list.find(&MailboxFinder::with_id("INBOX")).unwrap();
+
+        assert!(matches!(inbox, Node::Branch { children, .. } if children.len() == 1));
+
+        drop(session);
+
+        server.await.unwrap();
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn forget_deleted_mailbox() {
+        // Work was deleted by another client after it was selected.
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"Work\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 2 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" | "STATUS" => {
+                format!("{} NO [TRYCREATE] Mailbox doesn't exist: Work\r\n", tag)
             }
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-            let body_data = self.uid_fetch_single(msg_id, query.build()).await?;
+        let error = session.get_message_source("Work", "1").await.unwrap_err();
+        let error = session.check_error(error).await;
 
-            if let Some(html_part_number) = html_part_number {
-                let section_path: SectionPath = html_part_number.into();
+        assert!(matches!(error.kind(), ErrorKind::MailBoxNotFound));
+        assert!(session.selected_box.is_none());
 
-                if let Some(html) = body_data.section(&section_path) {
-                    builder = builder.html(std::str::from_utf8(html)?);
-                }
+        session.logout().await.unwrap();
+        drop(session);
+
+        let received = server.await.unwrap();
+
+        // The mailbox is not assumed to be selected anymore, so it is not closed.
+        assert!(!received.iter().any(|command| command == "CLOSE"));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn expunged_message() {
+        // The message was expunged by another client after the mailbox was listed, so the server only reports that.
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 3 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" => format!(
+                "* 3 EXPUNGE\r\n* 1 FETCH (UID 4 FLAGS (\\Seen))\r\n{} OK done\r\n",
+                tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let error = session.get_message("INBOX", "5").await.unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::MessageNotFound));
+
+        let error = session.get_message("INBOX", "1:*").await.unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::MessageNotFound));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn fetch_from_emptied_mailbox() {
+        // The mailbox had messages when it was selected, but another client expunged all of them since.
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 2 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" => format!("* 2 EXPUNGE\r\n* 1 EXPUNGE\r\n{} BAD No messages\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        for id in ["1", "2"] {
+            let error = session.get_message("INBOX", id).await.unwrap_err();
+
+            assert!(matches!(error.kind(), ErrorKind::MessageNotFound));
+        }
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn changes_since_modseq() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 CONDSTORE\r\n{} OK done\r\n", tag),
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 2 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" => {
+                let headers = "From: test@example.com\r\nSubject: Changed\r\n\r\n";
+
+                format!(
+                    "* 2 FETCH (UID 7 MODSEQ (12) FLAGS (\\Seen) BODY[HEADER.FIELDS (FROM DATE SUBJECT)] {{{}}}\r\n{})\r\n{} OK done\r\n",
+                    headers.len(),
+                    headers,
+                    tag
+                )
             }
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-            if let Some(text_part_number) = text_part_number {
-                let section_path: SectionPath = text_part_number.into();
+        let changes = session.get_changes_since("INBOX", 10).await.unwrap();
 
-                if let Some(text) = body_data.section(&section_path) {
-                    builder = builder.text(std::str::from_utf8(text)?);
-                }
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].id(), "7");
+        assert_eq!(changes[0].subject(), Some("Changed"));
+        assert_eq!(changes[0].flags(), &vec![Flag::Read]);
+
+        session.logout().await.unwrap();
+        drop(session);
+
+        let received = server.await.unwrap();
+
+        assert!(received
+            .iter()
+            .any(|command| command.starts_with("UID FETCH 1:* (")
+                && command.ends_with(" (CHANGEDSINCE 10)")));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn messages_since_uid_after_select() {
+        // The mailbox was empty when it was selected, the message arrived after that.
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 0 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" => {
+                let headers = "From: test@example.com\r\nSubject: New\r\n\r\n";
+
+                format!(
+                    "* 1 EXISTS\r\n* 1 FETCH (UID 8 FLAGS () BODY[HEADER.FIELDS (FROM DATE SUBJECT)] {{{}}}\r\n{})\r\n{} OK done\r\n",
+                    headers.len(),
+                    headers,
+                    tag
+                )
             }
-        }
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-        let message: Message = builder
-            .flags(flags)
-            .attachments(attachments)
-            .id(message_id)
-            .build()?;
+        let previews = session.get_messages_since_uid("INBOX", 7).await.unwrap();
 
-        Ok(message)
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].id(), "8");
+        assert_eq!(previews[0].subject(), Some("New"));
+
+        drop(session);
+
+        server.await.unwrap();
     }
 
-    async fn get_attachment(
-        &mut self,
-        box_id: &str,
-        message_id: &str,
-        attachment_id: &str,
-    ) -> Result<Vec<u8>> {
-        let mailbox = self.get_mailbox_no_children(box_id).await?;
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn changes_since_without_condstore() {
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 IDLE\r\n{} OK done\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-        self.select(&mailbox).await?;
+        let error = session.get_changes_since("INBOX", 10).await.unwrap_err();
 
-        let part_number: PartNumber = attachment_id.parse()?;
+        assert!(matches!(error.kind(), ErrorKind::Unsupported));
 
-        let query = QueryBuilder::new().section(&part_number).build();
+        session.logout().await.unwrap();
+        drop(session);
 
-        let attachment_data = self.uid_fetch_single(message_id, query).await?;
+        // Nothing is selected or fetched when the server can not tell what changed.
+        assert_eq!(server.await.unwrap()[1..], ["CAPABILITY", "LOGOUT"]);
+    }
 
-        let section_path: SectionPath = part_number.into();
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn rename_moves_subtree() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static RENAMED: AtomicBool = AtomicBool::new(false);
+
+        let (mut session, server) = fake_session(|tag, command| match command {
+            // The mailbox is looked up before the rename, the whole list after it.
+            "LIST" if !RENAMED.load(Ordering::SeqCst) => {
+                format!("* LIST () \".\" \"INBOX.Work\"\r\n{} OK done\r\n", tag)
+            }
+            "LIST" => format!(
+                "* LIST () \".\" \"INBOX\"\r\n* LIST () \".\" \"INBOX.Projects\"\r\n* LIST () \".\" \"INBOX.Projects.Old\"\r\n{} OK done\r\n",
+                tag
+            ),
+            "RENAME" => {
+                RENAMED.store(true, Ordering::SeqCst);
+
+                format!("{} OK done\r\n", tag)
+            }
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        session
+            .rename_mailbox("INBOX.Work", "Projects")
+            .await
+            .unwrap();
 
-        if let Some(bytes) = attachment_data
-            .section(&section_path)
-            .map(|bytes| if bytes.is_empty() { None } else { Some(bytes) })
+        let list = session.get_mailbox_list().await.unwrap();
+
+        let projects = list
+            .find(&MailboxFinder::with_id("INBOX.Projects"))
+            .unwrap();
+        let ids: Vec<_> = projects
             .flatten()
-        {
-            return Ok(bytes.to_vec());
-        }
+            .iter()
+            .map(|mailbox| mailbox.id())
+            .collect();
 
-        err!(
-            ErrorKind::AttachmentNotFound,
-            "Could not find an attachment with id '{}'",
-            attachment_id
-        );
+        // The child moved along with its parent.
+        assert_eq!(ids, ["INBOX.Projects", "INBOX.Projects.Old"]);
+        assert!(list
+            .find(&MailboxFinder::with_id("INBOX.Work.Old"))
+            .is_none());
+
+        session.logout().await.unwrap();
+        drop(session);
+
+        let received = server.await.unwrap();
+
+        assert!(received
+            .iter()
+            .any(|command| command == "RENAME \"INBOX.Work\" \"INBOX.Projects\""));
     }
-}
 
-#[cfg(test)]
-mod tests {
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn append_after_untagged_responses() {
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "APPEND" => format!("* 3 EXISTS\r\n+ Ready\r\n{} OK done\r\n", tag),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-    use crate::client::protocol::RemoteServer;
+        session
+            .append("INBOX", b"Subject: Hi", &[Flag::Read])
+            .await
+            .unwrap();
 
-    use super::*;
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "APPEND" => format!(
+                "* 3 EXISTS\r\n{} NO [TRYCREATE] Mailbox does not exist\r\n",
+                tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-    use dotenv::dotenv;
+        let error = session
+            .append("Archive", b"Subject: Hi", &[])
+            .await
+            .unwrap_err();
 
-    use std::env;
+        assert!(utils::is_missing_mailbox(&error));
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Imap(ImapError::No(message)) if message.contains("Mailbox does not exist")
+        ));
+    }
 
-    async fn create_test_session() -> Box<dyn IncomingProtocol> {
-        dotenv().ok();
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn append_short_message() {
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "APPEND" => "+ Ready\r\n".to_string(),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
 
-        let username = env::var("IMAP_USERNAME").unwrap();
-        let password = env::var("IMAP_PASSWORD").unwrap();
+        let mut message: &[u8] = b"Subject: Hi";
 
-        let server = env::var("IMAP_SERVER").unwrap();
-        let port: u16 = 993;
+        // The message is shorter than announced, which leaves the server waiting for the rest.
+        let error = session
+            .append_from_reader("INBOX", &mut message, 100, &[])
+            .await
+            .unwrap_err();
 
-        let server = RemoteServer::new(server, port, ConnectionSecurity::Tls);
-        let credentials = Credentials::password(username, password);
+        assert!(error.is_connection_error());
 
-        let creds = ImapCredentials::new(server, credentials);
+        // The connection was closed, so the next command is never sent.
+        let error = session.send_keep_alive().await.unwrap_err();
 
-        let session = create(&creds, Default::default()).await.unwrap();
+        assert!(error.is_connection_error());
+    }
 
-        session
+    #[cfg(feature = "runtime-tokio")]
+    const ATTACHMENT_STRUCTURE: &str = "BODYSTRUCTURE ((\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 5 1)(\"APPLICATION\" \"OCTET-STREAM\" NIL NIL NIL \"BASE64\" 12 NIL (\"ATTACHMENT\" (\"FILENAME\" \"notes.bin\")) NIL) \"MIXED\")";
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn attachment_with_progress() {
+        // The body structure and the chunk are sent for every fetch, the client only picks what it asked for.
+        let (mut session, server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 1 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            "UID" => format!(
+                "* 1 FETCH (UID 4 {} BODY[2]<0> {{12}}\r\nYXR0YWNobWVu)\r\n{} OK done\r\n",
+                ATTACHMENT_STRUCTURE, tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let mut reported = Vec::new();
+
+        let attachment = session
+            .get_attachment_with_progress("INBOX", "4", "2", &mut |done, total| {
+                reported.push((done, total))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(attachment, b"YXR0YWNobWVu");
+        assert_eq!(reported, [(0, 12), (12, 12)]);
+
+        session.logout().await.unwrap();
+        drop(session);
+
+        let received = server.await.unwrap();
+
+        assert!(received
+            .iter()
+            .any(|command| command.starts_with("UID FETCH 4 ") && command.contains("BODY[2]<0.")));
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn attachment_chunk_missing() {
+        let (mut session, _server) = fake_session(|tag, command| match command {
+            "LIST" => format!("* LIST () \"/\" \"INBOX\"\r\n{} OK done\r\n", tag),
+            "SELECT" => format!("* 1 EXISTS\r\n{} OK [READ-WRITE] done\r\n", tag),
+            // The server never returns the requested part.
+            "UID" => format!(
+                "* 1 FETCH (UID 4 {})\r\n{} OK done\r\n",
+                ATTACHMENT_STRUCTURE, tag
+            ),
+            _ => format!("{} OK done\r\n", tag),
+        })
+        .await;
+
+        let error = session
+            .get_attachment_with_progress("INBOX", "4", "2", &mut |_, _| {})
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::AttachmentNotFound));
+    }
+
+    #[test]
+    fn preview_with_missing_items() {
+        let headers: &[u8] = b"From: Tester <test@example.com>\r\nSubject: Hello\r\n\r\n";
+
+        assert!(build_preview(None, Some(headers), None, Vec::new()).is_err());
+
+        let preview = build_preview(Some(4), Some(headers), None, vec![Flag::Read]).unwrap();
+
+        assert_eq!(preview.id(), "4");
+        assert_eq!(preview.subject(), Some("Hello"));
+        assert_eq!(preview.flags(), &vec![Flag::Read]);
+
+        let (_, response) = async_imap::imap_proto::parser::parse_response(
+            b"* 1 FETCH (UID 4 BODYSTRUCTURE (\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 5 1))\r\n",
+        )
+        .unwrap();
+
+        let body_structure = match response {
+            Response::Fetch(_, attributes) => {
+                attributes
+                    .into_iter()
+                    .find_map(|attribute| match attribute {
+                        AttributeValue::BodyStructure(body_structure) => Some(body_structure),
+                        _ => None,
+                    })
+            }
+            _ => None,
+        }
+        .unwrap();
+
+        assert!(build_preview(Some(4), None, Some(&body_structure), Vec::new()).is_err());
+
+        let preview =
+            build_preview(Some(4), Some(headers), Some(&body_structure), Vec::new()).unwrap();
+
+        assert_eq!(preview.id(), "4");
     }
 
     #[cfg_attr(feature = "runtime-async-std", async_std::test)]
@@ -627,7 +2942,10 @@ mod tests {
 
         let box_name = "INBOX";
 
-        let messages = session.get_messages(box_name, 0, 10).await.unwrap();
+        let messages = session
+            .get_messages(box_name, 0, 10, SortOrder::NewestFirst)
+            .await
+            .unwrap();
 
         for preview in messages.into_iter() {
             println!("{:?}", preview);
@@ -34,6 +34,21 @@ impl QueryBuilder {
         self
     }
 
+    /// Fetch `length` bytes of a section, starting at `offset`.
+    pub fn partial_section(mut self, section: &PartNumber, offset: usize, length: usize) -> Self {
+        self.query
+            .push(format!("BODY[{}]<{}.{}>", section, offset, length));
+
+        self
+    }
+
+    /// Fetch the entire message, without setting the `\Seen` flag.
+    pub fn body(mut self) -> Self {
+        self.query.push(String::from("BODY.PEEK[]"));
+
+        self
+    }
+
     pub fn bodystructure(mut self) -> Self {
         self.query.push(String::from("BODYSTRUCTURE"));
 
@@ -58,6 +73,16 @@ impl QueryBuilder {
         self
     }
 
+    /// Fetch only the given header fields, without setting the `\Seen` flag.
+    pub fn peek_header_fields<H: AsRef<str>>(mut self, headers: &[H]) -> Self {
+        let headers: Vec<&str> = headers.iter().map(|header| header.as_ref()).collect();
+
+        self.query
+            .push(format!("BODY.PEEK[HEADER.FIELDS ({})]", headers.join(" ")));
+
+        self
+    }
+
     pub fn new() -> Self {
         Self { query: Vec::new() }
     }
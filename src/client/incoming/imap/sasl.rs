@@ -0,0 +1,140 @@
+use crate::{
+    client::auth::{cram_md5, ScramClient},
+    error::{err, Error, ErrorKind, Result},
+};
+
+pub struct OAuthCredentials {
+    username: String,
+    token: String,
+}
+
+impl async_imap::Authenticator for OAuthCredentials {
+    type Response = String;
+
+    fn process(&mut self, _: &[u8]) -> Self::Response {
+        format!(
+            "user={}\x01auth=Bearer {}\x01\x01",
+            self.username, self.token
+        )
+    }
+}
+
+impl OAuthCredentials {
+    pub fn new<Username: Into<String>, Token: Into<String>>(
+        username: Username,
+        token: Token,
+    ) -> Self {
+        Self {
+            username: username.into(),
+            token: token.into(),
+        }
+    }
+}
+
+/// Sends a username and password with SASL PLAIN (RFC 4616).
+pub struct PlainCredentials {
+    username: String,
+    password: String,
+}
+
+impl async_imap::Authenticator for PlainCredentials {
+    type Response = String;
+
+    fn process(&mut self, _: &[u8]) -> Self::Response {
+        format!("\0{}\0{}", self.username, self.password)
+    }
+}
+
+impl PlainCredentials {
+    pub fn new<Username: Into<String>, Password: Into<String>>(
+        username: Username,
+        password: Password,
+    ) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+/// Answers the CRAM-MD5 challenge (RFC 2195) of the server.
+pub struct CramMd5Credentials {
+    username: String,
+    password: String,
+}
+
+impl async_imap::Authenticator for CramMd5Credentials {
+    type Response = String;
+
+    fn process(&mut self, challenge: &[u8]) -> Self::Response {
+        cram_md5(&self.username, &self.password, challenge)
+    }
+}
+
+impl CramMd5Credentials {
+    pub fn new<Username: Into<String>, Password: Into<String>>(
+        username: Username,
+        password: Password,
+    ) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+/// Runs a SCRAM exchange, the client is borrowed so whether the server proved itself can be checked afterwards.
+///
+/// `process` cannot fail, so an error is kept until the exchange is over and an empty answer is sent instead, which the server refuses.
+pub struct ScramAuthenticator<'a> {
+    client: &'a mut ScramClient,
+    step: usize,
+    error: Option<Error>,
+}
+
+impl async_imap::Authenticator for &mut ScramAuthenticator<'_> {
+    type Response = String;
+
+    fn process(&mut self, challenge: &[u8]) -> Self::Response {
+        self.step += 1;
+
+        let result = match self.step {
+            // The server starts with an empty challenge, as Imap has no initial response without SASL-IR.
+            1 => Ok(self.client.client_first()),
+            2 => self.client.client_final(challenge),
+            _ => self.client.verify(challenge).map(|_| String::new()),
+        };
+
+        result.unwrap_or_else(|error| {
+            self.error.get_or_insert(error);
+
+            String::new()
+        })
+    }
+}
+
+impl<'a> ScramAuthenticator<'a> {
+    pub fn new(client: &'a mut ScramClient) -> Self {
+        Self {
+            client,
+            step: 0,
+            error: None,
+        }
+    }
+
+    /// Fails if the exchange went wrong on our side or the server did not prove it knows the password.
+    pub fn finish(self) -> Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        if !self.client.is_verified() {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Imap server accepted the login without proving it knows the password"
+            )
+        }
+
+        Ok(())
+    }
+}
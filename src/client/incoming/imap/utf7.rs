@@ -0,0 +1,132 @@
+//! Encoding and decoding of the modified UTF-7 that IMAP uses for non-ASCII mailbox names (RFC 3501 section 5.1.3).
+
+use base64::{
+    alphabet::IMAP_MUTF7,
+    engine::{general_purpose::NO_PAD, GeneralPurpose},
+    Engine,
+};
+
+const MUTF7: GeneralPurpose = GeneralPurpose::new(&IMAP_MUTF7, NO_PAD);
+
+const SHIFT_START: char = '&';
+const SHIFT_END: char = '-';
+
+fn is_direct(c: char) -> bool {
+    (' '..='~').contains(&c) && c != SHIFT_START
+}
+
+fn encode_shifted(output: &mut String, shifted: &mut String) {
+    if shifted.is_empty() {
+        return;
+    }
+
+    let bytes: Vec<u8> = shifted
+        .encode_utf16()
+        .flat_map(|unit| unit.to_be_bytes())
+        .collect();
+
+    output.push(SHIFT_START);
+    output.push_str(&MUTF7.encode(bytes));
+    output.push(SHIFT_END);
+
+    shifted.clear();
+}
+
+/// Encodes a mailbox name into modified UTF-7 so it can be sent to the server.
+pub fn encode<S: AsRef<str>>(name: S) -> String {
+    let mut output = String::new();
+    let mut shifted = String::new();
+
+    for c in name.as_ref().chars() {
+        if is_direct(c) {
+            encode_shifted(&mut output, &mut shifted);
+
+            output.push(c);
+        } else if c == SHIFT_START {
+            encode_shifted(&mut output, &mut shifted);
+
+            output.push(SHIFT_START);
+            output.push(SHIFT_END);
+        } else {
+            shifted.push(c);
+        }
+    }
+
+    encode_shifted(&mut output, &mut shifted);
+
+    output
+}
+
+/// Decodes a modified UTF-7 mailbox name, returning `None` if it is not validly encoded.
+pub fn decode<S: AsRef<str>>(name: S) -> Option<String> {
+    let mut output = String::new();
+    let mut remaining = name.as_ref();
+
+    while let Some(start) = remaining.find(SHIFT_START) {
+        output.push_str(&remaining[..start]);
+
+        remaining = &remaining[start + 1..];
+
+        let end = remaining.find(SHIFT_END)?;
+
+        let encoded = &remaining[..end];
+
+        if encoded.is_empty() {
+            output.push(SHIFT_START);
+        } else {
+            let bytes = MUTF7.decode(encoded).ok()?;
+
+            if bytes.len() % 2 != 0 {
+                return None;
+            }
+
+            let units: Vec<u16> = bytes
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+
+            output.push_str(&String::from_utf16(&units).ok()?);
+        }
+
+        remaining = &remaining[end + 1..];
+    }
+
+    output.push_str(remaining);
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_names() {
+        assert_eq!(decode("INBOX").unwrap(), "INBOX");
+        assert_eq!(decode("Gel&APY-scht").unwrap(), "Gelöscht");
+        assert_eq!(decode("&XfJSIJZk-").unwrap(), "已删除");
+        assert_eq!(decode("Tom &- Jerry").unwrap(), "Tom & Jerry");
+        assert_eq!(decode("INBOX.Entw&APw-rfe").unwrap(), "INBOX.Entwürfe");
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode("Unterminated &APY"), None);
+        assert_eq!(decode("Bad &!!!-"), None);
+    }
+
+    #[test]
+    fn encode_names() {
+        assert_eq!(encode("INBOX"), "INBOX");
+        assert_eq!(encode("Gelöscht"), "Gel&APY-scht");
+        assert_eq!(encode("已删除"), "&XfJSIJZk-");
+        assert_eq!(encode("Tom & Jerry"), "Tom &- Jerry");
+    }
+
+    #[test]
+    fn roundtrip() {
+        for name in ["Éléments envoyés", "Корзина", "📁 Archive", "a&b/ü"] {
+            assert_eq!(decode(encode(name)).unwrap(), name);
+        }
+    }
+}
@@ -1,20 +1,43 @@
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{borrow::Cow, fmt::Display, str::FromStr};
 
 use async_imap::{
+    error::Error as ImapError,
     imap_proto::{
-        BodyContentCommon, BodyContentSinglePart, BodyStructure, ContentDisposition, ContentType,
-        SectionPath,
+        AclRight, BodyContentCommon, BodyContentSinglePart, BodyParams, BodyStructure,
+        ContentEncoding, ContentType, Response, ResponseCode, SectionPath, Status,
     },
     types::Name,
 };
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use mime::Mime;
 
 use crate::{
-    client::{attachment::Attachment, incoming::types::mailbox::Mailbox},
-    error,
+    client::{
+        attachment::{Attachment, Disposition},
+        incoming::types::mailbox::Mailbox,
+        parser::security::{self, Protection},
+    },
+    error::{self, err, ErrorKind},
     tree::{Find, Node},
 };
 
+/// The human readable text of the greeting a server sends when the connection opens, failing if the server refuses the connection (`* BYE`).
+pub fn greeting_text(response: &Response<'_>) -> error::Result<Option<String>> {
+    match response {
+        Response::Data {
+            status: Status::Bye,
+            information,
+            ..
+        } => err!(
+            ErrorKind::MailServer,
+            "Imap server refused the connection: {}",
+            information.as_deref().unwrap_or_default()
+        ),
+        Response::Data { information, .. } => Ok(information.as_ref().map(|text| text.to_string())),
+        _ => Ok(None),
+    }
+}
+
 pub struct MailboxFinder(String);
 
 impl Find<Mailbox> for MailboxFinder {
@@ -47,7 +70,26 @@ pub fn build_mailbox_tree<L: IntoIterator<Item = Name>>(names: L) -> Node<Mailbo
         }
     }
 
-    Node::create_leaves(root)
+    let mut tree = Node::create_leaves(root);
+
+    mark_children(&mut tree);
+
+    tree
+}
+
+/// Marks every mailbox that has child mailboxes in the tree, for servers that do not report the `\HasChildren` attribute.
+fn mark_children(node: &mut Node<Mailbox>) {
+    match node {
+        Node::Branch { data, children } => {
+            if !children.is_empty() {
+                data.set_has_children(true);
+            }
+
+            children.iter_mut().for_each(mark_children);
+        }
+        Node::Root(children) => children.iter_mut().for_each(mark_children),
+        Node::Leaf(_) => {}
+    }
 }
 
 fn add_children(
@@ -57,7 +99,7 @@ fn add_children(
     parts: Vec<&str>,
     index: usize,
 ) {
-    if let Some(_) = parts.get(index) {
+    if parts.get(index).is_some() {
         let id = parts[0..index + 1].join(delimiter);
 
         let child = node.find_mut(&MailboxFinder::with_id(&id));
@@ -75,6 +117,112 @@ fn add_children(
     }
 }
 
+/// The id a mailbox gets when it is renamed, which keeps it under the same parent mailbox.
+///
+/// Mailboxes inside the renamed mailbox keep their place relative to it, so `INBOX.Work.Old` becomes `INBOX.Projects.Old` when `INBOX.Work` is renamed to `Projects`.
+pub fn renamed_mailbox_id(box_id: &str, new_name: &str, delimiter: Option<&str>) -> String {
+    let parent = delimiter.and_then(|delimiter| {
+        box_id
+            .rsplit_once(delimiter)
+            .map(|(parent, _)| (parent, delimiter))
+    });
+
+    match parent {
+        Some((parent, delimiter)) => format!("{}{}{}", parent, delimiter, new_name),
+        None => new_name.to_string(),
+    }
+}
+
+/// Whether a command was refused because the session is no longer logged in, e.g. because the server ended it after being idle.
+///
+/// Servers report this with the AUTHENTICATIONFAILED or UNAVAILABLE codes (RFC 5530), which async-imap only passes on as text, or by saying the command is not allowed before logging in.
+pub fn is_logged_out(error: &ImapError) -> bool {
+    let message = match error {
+        ImapError::No(message) | ImapError::Bad(message) => message.to_ascii_lowercase(),
+        _ => return false,
+    };
+
+    [
+        "[authenticationfailed]",
+        "[unavailable]",
+        "in this state",
+        "login first",
+        "not authenticated",
+    ]
+    .iter()
+    .any(|reason| message.contains(reason))
+}
+
+/// Whether an error means the mailbox a command was run on does not exist, e.g. because another client deleted or renamed it.
+///
+/// Servers use the TRYCREATE and NONEXISTENT (RFC 5530) codes for this, async-imap only passes those on as text, as it does the messages of servers that use neither.
+pub fn is_missing_mailbox(error: &error::Error) -> bool {
+    let message = match error.kind() {
+        ErrorKind::MailBoxNotFound => return true,
+        ErrorKind::Imap(ImapError::No(message)) | ErrorKind::Imap(ImapError::Bad(message)) => {
+            message.to_ascii_lowercase()
+        }
+        _ => return false,
+    };
+
+    if message.contains("trycreate") || message.contains("nonexistent") {
+        return true;
+    }
+
+    // Creating or renaming to a mailbox that is already there fails with ALREADYEXISTS.
+    if message.contains("already") {
+        return false;
+    }
+
+    (message.contains("mailbox") || message.contains("folder"))
+        && (message.contains("exist") || message.contains("not found"))
+}
+
+/// The error for a command the server completed with `NO` or `BAD`, formatted like async-imap does so the response code (like `TRYCREATE`) is kept.
+pub fn rejected(
+    status: &Status,
+    code: &Option<ResponseCode<'_>>,
+    information: &Option<Cow<'_, str>>,
+) -> error::Error {
+    let message = format!("code: {:?}, info: {:?}", code, information);
+
+    match status {
+        Status::No => ImapError::No(message).into(),
+        _ => ImapError::Bad(message).into(),
+    }
+}
+
+/// Formats ACL rights as the string of single letter codes used in the ACL extension.
+pub fn rights_to_string(rights: &[AclRight]) -> String {
+    rights.iter().map(|right| char::from(*right)).collect()
+}
+
+/// Formats a value as an IMAP quoted string, escaping any quotes and backslashes in it.
+///
+/// Quoted strings can not contain line breaks or NUL characters (RFC 3501 section 4.3), so values with those are rejected instead of letting them end the command early.
+pub fn quote(value: &str) -> error::Result<String> {
+    if value.contains(['\r', '\n', '\0']) {
+        err!(
+            ErrorKind::InvalidInput,
+            "Value {:?} contains characters that can not be sent to the Imap server",
+            value
+        )
+    }
+
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+/// Whether a name is a valid header field name, which consists of printable ascii characters other than the colon (RFC 5322 section 2.2).
+pub fn is_header_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_graphic() && byte != b':')
+}
+
 const PART_NUMBER_DELIM: &str = ".";
 
 #[derive(Clone, Debug)]
@@ -96,9 +244,9 @@ impl FromStr for PartNumber {
     }
 }
 
-impl Into<SectionPath> for PartNumber {
-    fn into(self) -> SectionPath {
-        SectionPath::Part(self.inner.into_iter().map(|u| u as u32).collect(), None)
+impl From<PartNumber> for SectionPath {
+    fn from(val: PartNumber) -> Self {
+        SectionPath::Part(val.inner.into_iter().map(|u| u as u32).collect(), None)
     }
 }
 
@@ -145,19 +293,27 @@ impl<'a> From<&'a BodyStructure<'a>> for BodyStructureParser<'a> {
 }
 
 impl<'a> BodyStructureParser<'a> {
-    fn extract_file_name(disposition: &ContentDisposition) -> Option<String> {
-        if let Some(params) = &disposition.params {
-            let params: HashMap<_, _> = params
-                .iter()
-                .map(|(key, value)| (key.to_lowercase(), value))
-                .collect();
+    fn find_param(params: &BodyParams, name: &str) -> Option<String> {
+        params
+            .iter()
+            .flatten()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_string())
+    }
 
-            if let Some(file_name) = params.get("filename") {
-                return Some(file_name.to_string());
-            }
-        }
+    /// The file name from the content disposition, or from the content type for older mail clients that only set it there.
+    fn extract_file_name(common: &BodyContentCommon) -> Option<String> {
+        common
+            .disposition
+            .as_ref()
+            .and_then(|disposition| Self::find_param(&disposition.params, "filename"))
+            .or_else(|| Self::find_param(&common.ty.params, "name"))
+    }
 
-        None
+    fn is_body_text(content_type: &ContentType) -> bool {
+        content_type.ty.eq_ignore_ascii_case("text")
+            && (content_type.subtype.eq_ignore_ascii_case("plain")
+                || content_type.subtype.eq_ignore_ascii_case("html"))
     }
 
     fn extract_attachment(
@@ -165,19 +321,36 @@ impl<'a> BodyStructureParser<'a> {
         common: &BodyContentCommon<'a>,
         other: &BodyContentSinglePart,
     ) -> Option<Attachment> {
-        if let Some(disposition) = &common.disposition {
-            if disposition.ty.to_lowercase() == String::from("attachment") {
-                let file_name = Self::extract_file_name(disposition);
-
-                let size = other.octets as usize;
-
-                let attachment = Attachment::new(part_number.to_string(), file_name, size);
+        let file_name = Self::extract_file_name(common);
 
-                return Some(attachment);
+        let disposition = match &common.disposition {
+            Some(disposition) if disposition.ty.eq_ignore_ascii_case("attachment") => {
+                Disposition::Attachment
             }
+            Some(disposition) if disposition.ty.eq_ignore_ascii_case("inline") => {
+                Disposition::Inline
+            }
+            // Parts without a (known) disposition are only files if they are named.
+            _ if file_name.is_some() => Disposition::Inline,
+            _ => return None,
+        };
+
+        // The text of the message itself is not an attachment, unless it is a named file that happens to be text.
+        if disposition == Disposition::Inline
+            && file_name.is_none()
+            && Self::is_body_text(&common.ty)
+        {
+            return None;
         }
 
-        None
+        let size = other.octets as usize;
+
+        Some(Attachment::new(
+            part_number.to_string(),
+            file_name,
+            size,
+            disposition,
+        ))
     }
 
     fn extract_attachments_rec(
@@ -188,7 +361,7 @@ impl<'a> BodyStructureParser<'a> {
 
         match body_structure {
             BodyStructure::Multipart { bodies, .. } => {
-                for (i, body) in bodies.into_iter().enumerate() {
+                for (i, body) in bodies.iter().enumerate() {
                     for attachment in
                         Self::extract_attachments_rec(body, part_number.clone_and_add(i + 1))
                     {
@@ -230,34 +403,34 @@ impl<'a> BodyStructureParser<'a> {
         mime == &to_match
     }
 
-    fn find_part_number_rec(
-        body_structure: &BodyStructure,
+    fn find_part_rec(
+        body_structure: &'a BodyStructure<'a>,
         mime: &Mime,
         part_number: PartNumber,
-    ) -> Option<PartNumber> {
+    ) -> Option<(PartNumber, &'a ContentEncoding<'a>)> {
         match body_structure {
             BodyStructure::Multipart { bodies, .. } => {
-                for (i, body) in bodies.into_iter().enumerate() {
-                    if let Some(part_number) =
-                        Self::find_part_number_rec(body, mime, part_number.clone_and_add(i + 1))
+                for (i, body) in bodies.iter().enumerate() {
+                    if let Some(found) =
+                        Self::find_part_rec(body, mime, part_number.clone_and_add(i + 1))
                     {
-                        return Some(part_number);
+                        return Some(found);
                     }
                 }
             }
-            BodyStructure::Message { common, .. } => {
+            BodyStructure::Message { common, other, .. } => {
                 if Self::check_mime_type(mime, &common.ty) {
-                    return Some(part_number);
+                    return Some((part_number, &other.transfer_encoding));
                 }
             }
-            BodyStructure::Basic { common, .. } => {
+            BodyStructure::Basic { common, other, .. } => {
                 if Self::check_mime_type(mime, &common.ty) {
-                    return Some(part_number);
+                    return Some((part_number, &other.transfer_encoding));
                 }
             }
-            BodyStructure::Text { common, .. } => {
+            BodyStructure::Text { common, other, .. } => {
                 if Self::check_mime_type(mime, &common.ty) {
-                    return Some(part_number);
+                    return Some((part_number, &other.transfer_encoding));
                 }
             }
         };
@@ -265,7 +438,510 @@ impl<'a> BodyStructureParser<'a> {
         None
     }
 
-    pub fn find_part_number_for(&self, mime_type: Mime) -> Option<PartNumber> {
-        Self::find_part_number_rec(self.structure, &mime_type, PartNumber::new())
+    /// Detects whether the message is signed and/or encrypted from the content type of its top level part.
+    ///
+    /// The text parts of a signed message are found by `find_part_for` like any other multipart message.
+    pub fn protection(&self) -> Protection {
+        let content_type = match self.structure {
+            BodyStructure::Multipart { common, .. }
+            | BodyStructure::Message { common, .. }
+            | BodyStructure::Basic { common, .. }
+            | BodyStructure::Text { common, .. } => &common.ty,
+        };
+
+        let smime_type = content_type.params.as_ref().and_then(|params| {
+            params
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("smime-type"))
+                .map(|(_, value)| value.as_ref())
+        });
+
+        security::detect(
+            &format!("{}/{}", content_type.ty, content_type.subtype),
+            smime_type,
+        )
+    }
+
+    /// Finds the part number of the first part with the given mime type, along with the content transfer encoding of that part.
+    pub fn find_part_for(&self, mime_type: Mime) -> Option<(PartNumber, &'a ContentEncoding<'a>)> {
+        Self::find_part_rec(self.structure, &mime_type, PartNumber::new())
+    }
+}
+
+/// Parses a message id into a uid, which has to be a positive number.
+pub fn parse_uid(id: &str) -> Option<u32> {
+    id.trim().parse().ok().filter(|uid| *uid > 0)
+}
+
+/// Compresses a list of uids into an imap sequence set, collapsing consecutive uids into ranges, e.g. `1,3,5:10`.
+pub fn to_sequence_set(uids: &[u32]) -> String {
+    let mut uids = uids.to_vec();
+
+    uids.sort_unstable();
+    uids.dedup();
+
+    let mut ranges: Vec<String> = Vec::new();
+
+    let mut iter = uids.into_iter();
+
+    if let Some(first) = iter.next() {
+        let mut start = first;
+        let mut end = first;
+
+        for uid in iter {
+            if uid == end + 1 {
+                end = uid;
+            } else {
+                ranges.push(format_range(start, end));
+
+                start = uid;
+                end = uid;
+            }
+        }
+
+        ranges.push(format_range(start, end));
+    }
+
+    ranges.join(",")
+}
+
+fn format_range(start: u32, end: u32) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{}:{}", start, end)
+    }
+}
+
+const SEQUENCE_SET_DELIM: char = ',';
+const SEQUENCE_RANGE_DELIM: char = ':';
+
+/// Parses an imap sequence set like `1,3,5:10` into a sorted list of inclusive uid ranges, merging the ones that overlap or touch.
+///
+/// Ranges may be written in either direction (`10:5` equals `5:10`). The `*` wildcard is not supported, as its value depends on the mailbox. The ranges are not expanded, as a single range can span billions of uids.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn parse_sequence_set(set: &str) -> error::Result<Vec<(u32, u32)>> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+
+    for item in set.split(SEQUENCE_SET_DELIM) {
+        let item = item.trim();
+
+        let (start, end): (u32, u32) = match item.split_once(SEQUENCE_RANGE_DELIM) {
+            Some((start, end)) => (start.parse()?, end.parse()?),
+            None => {
+                let uid = item.parse()?;
+
+                (uid, uid)
+            }
+        };
+
+        if start == 0 || end == 0 {
+            err!(
+                ErrorKind::InvalidInput,
+                "Sequence set '{}' contains 0, which is not a valid uid",
+                set
+            );
+        }
+
+        ranges.push((start.min(end), start.max(end)));
+    }
+
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= last_end.saturating_add(1) => {
+                *last_end = (*last_end).max(end)
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Decodes the raw bytes of a body section that were encoded using the given content transfer encoding.
+pub fn decode_transfer_encoding(
+    bytes: &[u8],
+    encoding: &ContentEncoding,
+) -> error::Result<Vec<u8>> {
+    match encoding {
+        ContentEncoding::Base64 => {
+            // Base64 encoded bodies are wrapped into lines, which the decoder does not accept.
+            let stripped: Vec<u8> = bytes
+                .iter()
+                .copied()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+
+            match BASE64.decode(stripped) {
+                Ok(decoded) => Ok(decoded),
+                Err(error) => err!(
+                    ErrorKind::DecodeContent,
+                    "Failed to decode base64 encoded body: {}",
+                    error
+                ),
+            }
+        }
+        ContentEncoding::QuotedPrintable => {
+            match quoted_printable::decode(bytes, quoted_printable::ParseMode::Robust) {
+                Ok(decoded) => Ok(decoded),
+                Err(error) => err!(
+                    ErrorKind::DecodeContent,
+                    "Failed to decode quoted-printable encoded body: {}",
+                    error
+                ),
+            }
+        }
+        _ => Ok(bytes.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn missing_mailbox_errors() {
+        let no = |message: &str| error::Error::from(ImapError::No(message.to_string()));
+
+        assert!(is_missing_mailbox(&no(
+            "code: Some(TryCreate), info: Some(\"Mailbox doesn't exist: Work\")"
+        )));
+        assert!(is_missing_mailbox(&no(
+            "code: None, info: Some(\"Mailbox does not exist\")"
+        )));
+        assert!(!is_missing_mailbox(&no(
+            "code: None, info: Some(\"Message does not exist\")"
+        )));
+        assert!(!is_missing_mailbox(&no(
+            "code: Some(AlreadyExists), info: Some(\"Mailbox already exists\")"
+        )));
+        assert!(!is_missing_mailbox(&ImapError::ConnectionLost.into()));
+    }
+
+    #[test]
+    fn logged_out_errors() {
+        assert!(is_logged_out(&ImapError::No(
+            "code: None, info: Some(\"[UNAVAILABLE] Session expired\")".to_string()
+        )));
+        assert!(is_logged_out(&ImapError::Bad(
+            "code: None, info: Some(\"Command not valid in this state\")".to_string()
+        )));
+        assert!(!is_logged_out(&ImapError::No(
+            "code: Some(TryCreate), info: Some(\"Mailbox doesn't exist: INBOX\")".to_string()
+        )));
+        assert!(!is_logged_out(&ImapError::ConnectionLost));
+    }
+
+    #[test]
+    fn mark_mailbox_children() {
+        let mut tree = Node::Root(vec![
+            Node::leaf(Mailbox::new(None, true, "INBOX", "INBOX")),
+            Node::branch(
+                Mailbox::new(None, true, "Archive", "Archive"),
+                vec![Node::leaf(Mailbox::new(None, true, "Archive/2023", "2023"))],
+            ),
+        ]);
+
+        mark_children(&mut tree);
+
+        let has_children = |id: &str| {
+            tree.find(&MailboxFinder::with_id(id))
+                .and_then(|node| node.data())
+                .map(Mailbox::has_children)
+        };
+
+        assert_eq!(has_children("INBOX"), Some(false));
+        assert_eq!(has_children("Archive"), Some(true));
+        assert_eq!(has_children("Archive/2023"), Some(false));
+    }
+
+    #[test]
+    fn renamed_mailbox_ids() {
+        let new_id = renamed_mailbox_id("INBOX.Work", "Projects", Some("."));
+
+        assert_eq!(new_id, "INBOX.Projects");
+        assert_eq!(
+            renamed_mailbox_id("Archive/2023/Q1", "Q2", Some("/")),
+            "Archive/2023/Q2"
+        );
+        assert_eq!(
+            renamed_mailbox_id("Work", "Projects", Some(".")),
+            "Projects"
+        );
+        assert_eq!(renamed_mailbox_id("Work", "Projects", None), "Projects");
+    }
+
+    #[test]
+    fn quote_strings() {
+        assert_eq!(quote("INBOX").unwrap(), "\"INBOX\"");
+        assert_eq!(
+            quote(r#"My "Work" \ Stuff"#).unwrap(),
+            r#""My \"Work\" \\ Stuff""#
+        );
+        assert!(quote("INBOX\r\na2 LOGOUT").is_err());
+        assert!(quote("INBOX\0").is_err());
+        assert_eq!(
+            rights_to_string(&[AclRight::Lookup, AclRight::Read, AclRight::Custom('z')]),
+            "lrz"
+        );
+    }
+
+    #[test]
+    fn header_field_names() {
+        assert!(is_header_field_name("List-Id"));
+        assert!(is_header_field_name("X-Spam-Status"));
+        assert!(!is_header_field_name(""));
+        assert!(!is_header_field_name("Subject:"));
+        assert!(!is_header_field_name("List Id"));
+        assert!(!is_header_field_name("Subject)\r\na2 LOGOUT"));
+    }
+
+    #[test]
+    fn greeting() {
+        let (_, ok) = async_imap::imap_proto::parser::parse_response(
+            b"* OK [CAPABILITY IMAP4rev1] Dovecot ready.\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            greeting_text(&ok).unwrap().as_deref(),
+            Some("Dovecot ready.")
+        );
+
+        let (_, bye) =
+            async_imap::imap_proto::parser::parse_response(b"* BYE Too many connections\r\n")
+                .unwrap();
+
+        assert!(greeting_text(&bye).is_err());
+    }
+
+    #[test]
+    fn sequence_set() {
+        assert_eq!(to_sequence_set(&[1, 3, 5, 6, 7, 8, 9, 10]), "1,3,5:10");
+    }
+
+    #[test]
+    fn sequence_set_edge_cases() {
+        assert_eq!(to_sequence_set(&[]), "");
+        assert_eq!(to_sequence_set(&[7]), "7");
+        assert_eq!(to_sequence_set(&[1, 2]), "1:2");
+        assert_eq!(to_sequence_set(&[10, 2, 1, 3]), "1:3,10");
+        assert_eq!(to_sequence_set(&[4, 4, 4, 5, 5]), "4:5");
+        assert_eq!(to_sequence_set(&[1, 3, 5]), "1,3,5");
+        assert_eq!(
+            to_sequence_set(&[u32::MAX - 1, u32::MAX]),
+            "4294967294:4294967295"
+        );
+    }
+
+    #[test]
+    fn parse_sequence_sets() {
+        assert_eq!(
+            parse_sequence_set("1,3,5:10").unwrap(),
+            vec![(1, 1), (3, 3), (5, 10)]
+        );
+        assert_eq!(parse_sequence_set("7").unwrap(), vec![(7, 7)]);
+        assert_eq!(parse_sequence_set("10:8").unwrap(), vec![(8, 10)]);
+        assert_eq!(parse_sequence_set("3,1:4,2").unwrap(), vec![(1, 4)]);
+        assert_eq!(parse_sequence_set("5:5").unwrap(), vec![(5, 5)]);
+        assert_eq!(parse_sequence_set("1:3,4,6").unwrap(), vec![(1, 4), (6, 6)]);
+
+        // Huge ranges are kept as they are.
+        assert_eq!(
+            parse_sequence_set("1:4294967295,7").unwrap(),
+            vec![(1, u32::MAX)]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_sequence_sets() {
+        assert!(parse_sequence_set("").is_err());
+        assert!(parse_sequence_set("1,,2").is_err());
+        assert!(parse_sequence_set("1:*").is_err());
+        assert!(parse_sequence_set("a:b").is_err());
+        assert!(parse_sequence_set("1:2:3").is_err());
+        assert!(parse_sequence_set("4294967296").is_err());
+
+        for set in ["0", "0:3"] {
+            assert!(matches!(
+                parse_sequence_set(set).unwrap_err().kind(),
+                ErrorKind::InvalidInput
+            ));
+        }
+    }
+
+    #[test]
+    fn sequence_set_roundtrip() {
+        let expand = |set: &str| -> Vec<u32> {
+            parse_sequence_set(set)
+                .unwrap()
+                .into_iter()
+                .flat_map(|(start, end)| start..=end)
+                .collect()
+        };
+
+        let cases: Vec<Vec<u32>> = vec![
+            vec![1],
+            vec![1, 2, 3, 4, 5],
+            vec![2, 4, 6, 8],
+            vec![1, 2, 4, 5, 7, 100, 101, 102, 1000],
+        ];
+
+        for uids in cases {
+            assert_eq!(expand(&to_sequence_set(&uids)), uids);
+        }
+
+        // Every subset of 1..=8, which covers all combinations of runs and gaps.
+        for mask in 1u32..(1 << 8) {
+            let uids: Vec<u32> = (1..=8).filter(|i| mask & (1 << (i - 1)) != 0).collect();
+
+            assert_eq!(expand(&to_sequence_set(&uids)), uids);
+        }
+    }
+
+    fn single_part<'a>(
+        ty: &'a str,
+        subtype: &'a str,
+        disposition: Option<(&'a str, Option<&'a str>)>,
+    ) -> BodyStructure<'a> {
+        use async_imap::imap_proto::ContentDisposition;
+
+        BodyStructure::Basic {
+            common: BodyContentCommon {
+                ty: ContentType {
+                    ty: ty.into(),
+                    subtype: subtype.into(),
+                    params: None,
+                },
+                disposition: disposition.map(|(ty, file_name)| ContentDisposition {
+                    ty: ty.into(),
+                    params: file_name.map(|name| vec![("filename".into(), name.into())]),
+                }),
+                language: None,
+                location: None,
+            },
+            other: BodyContentSinglePart {
+                id: None,
+                md5: None,
+                description: None,
+                transfer_encoding: ContentEncoding::Base64,
+                octets: 100,
+            },
+            extension: None,
+        }
+    }
+
+    #[test]
+    fn attachment_dispositions() {
+        let structure = BodyStructure::Multipart {
+            common: BodyContentCommon {
+                ty: ContentType {
+                    ty: "multipart".into(),
+                    subtype: "mixed".into(),
+                    params: None,
+                },
+                disposition: None,
+                language: None,
+                location: None,
+            },
+            bodies: vec![
+                single_part("text", "plain", None),
+                single_part("text", "html", Some(("inline", None))),
+                single_part("image", "png", Some(("inline", Some("logo.png")))),
+                single_part(
+                    "application",
+                    "pdf",
+                    Some(("attachment", Some("invoice.pdf"))),
+                ),
+                single_part("text", "plain", Some(("inline", Some("notes.txt")))),
+            ],
+            extension: None,
+        };
+
+        let parser: BodyStructureParser = (&structure).into();
+
+        let attachments: Vec<_> = parser
+            .extract_attachments()
+            .into_iter()
+            .map(|attachment| {
+                (
+                    attachment.id().to_string(),
+                    attachment.file_name().cloned(),
+                    attachment.disposition(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            attachments,
+            vec![
+                (
+                    "3".to_string(),
+                    Some("logo.png".to_string()),
+                    Disposition::Inline
+                ),
+                (
+                    "4".to_string(),
+                    Some("invoice.pdf".to_string()),
+                    Disposition::Attachment
+                ),
+                (
+                    "5".to_string(),
+                    Some("notes.txt".to_string()),
+                    Disposition::Inline
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn uids() {
+        assert_eq!(parse_uid("42"), Some(42));
+        assert_eq!(parse_uid(" 7 "), Some(7));
+        assert_eq!(parse_uid("0"), None);
+        assert_eq!(parse_uid("-1"), None);
+        assert_eq!(parse_uid("1:*"), None);
+        assert_eq!(parse_uid(""), None);
+    }
+
+    #[test]
+    fn decode_base64() {
+        let encoded = b"PGh0bWw+PGJvZHk+SGVs\r\nbG8gd29ybGQ8L2JvZHk+PC9odG1sPg==\r\n";
+
+        let decoded = decode_transfer_encoding(encoded, &ContentEncoding::Base64).unwrap();
+
+        assert_eq!(decoded, b"<html><body>Hello world</body></html>");
+    }
+
+    #[test]
+    fn decode_quoted_printable() {
+        let encoded =
+            b"Caf=C3=A9 au lait, a very long line that has been wrapped by the=\r\n sender";
+
+        let decoded = decode_transfer_encoding(encoded, &ContentEncoding::QuotedPrintable).unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&decoded).unwrap(),
+            "Café au lait, a very long line that has been wrapped by the sender"
+        );
+    }
+
+    #[test]
+    fn decode_plain() {
+        let decoded =
+            decode_transfer_encoding(b"Hello=20world", &ContentEncoding::SevenBit).unwrap();
+
+        assert_eq!(decoded, b"Hello=20world");
+    }
+
+    #[test]
+    fn decode_invalid_base64() {
+        let result = decode_transfer_encoding(b"not base64!", &ContentEncoding::Base64);
+
+        assert!(result.is_err());
     }
 }
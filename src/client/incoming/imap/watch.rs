@@ -0,0 +1,312 @@
+use std::{fmt::Debug, iter};
+
+use async_imap::{
+    error::Error as ImapError,
+    imap_proto::{MailboxDatum, Response},
+    types::UnsolicitedResponse,
+};
+use log::{debug, warn};
+
+use crate::{
+    client::incoming::types::event::{MailboxEvent, MailboxEventKind},
+    error::{err, ErrorKind, Result},
+    runtime::{
+        io::{Read, Write},
+        time::{timeout, Instant},
+    },
+};
+
+use super::{utils, ImapSession, KEEP_ALIVE_INTERVAL, LOG_TARGET};
+
+const NOTIFY_CAPABILITY: &str = "NOTIFY";
+const IDLE_CAPABILITY: &str = "IDLE";
+
+/// How the server reports changes to the mailboxes we are watching.
+pub enum Watch {
+    /// The server reports changes to any of these mailboxes by itself (RFC 5465).
+    Notify(Vec<String>),
+    /// The server reports changes to this mailbox while it is selected and we are idling (RFC 2177).
+    Idle(String),
+}
+
+/// Turns a response the server sent by itself into an event, if it reports a change to a mailbox.
+///
+/// Only STATUS responses name their mailbox, the others are about the selected mailbox.
+fn mailbox_event(response: &Response, selected: Option<&str>) -> Option<MailboxEvent> {
+    let (box_id, kind) = match response {
+        Response::MailboxData(MailboxDatum::Status { mailbox, status }) => (
+            mailbox.as_ref(),
+            MailboxEventKind::Stats(status.as_slice().into()),
+        ),
+        Response::MailboxData(MailboxDatum::Exists(total)) => {
+            (selected?, MailboxEventKind::Exists(*total as usize))
+        }
+        Response::Expunge(number) => (selected?, MailboxEventKind::Expunge(*number as usize)),
+        Response::Fetch(number, _) => (selected?, MailboxEventKind::FlagChange(*number as usize)),
+        _ => return None,
+    };
+
+    Some(MailboxEvent::new(box_id, kind))
+}
+
+/// Like `mailbox_event`, for responses async-imap received while running another command.
+fn unsolicited_event(
+    response: UnsolicitedResponse,
+    selected: Option<&str>,
+) -> Option<MailboxEvent> {
+    let (box_id, kind) = match response {
+        UnsolicitedResponse::Status {
+            mailbox,
+            attributes,
+        } => {
+            return Some(MailboxEvent::new(
+                mailbox,
+                MailboxEventKind::Stats(attributes.as_slice().into()),
+            ))
+        }
+        UnsolicitedResponse::Exists(total) => (selected?, MailboxEventKind::Exists(total as usize)),
+        UnsolicitedResponse::Expunge(number) => {
+            (selected?, MailboxEventKind::Expunge(number as usize))
+        }
+        UnsolicitedResponse::Other(response) => return mailbox_event(response.parsed(), selected),
+        _ => return None,
+    };
+
+    Some(MailboxEvent::new(box_id, kind))
+}
+
+impl<S: Read + Write + Unpin + Debug + Send + Sync> ImapSession<S> {
+    /// Uses NOTIFY to watch all of the given mailboxes if the server supports it, otherwise IDLE is used to watch the first one.
+    pub(super) async fn start_watching(&mut self, box_ids: &[&str]) -> Result<()> {
+        let first = match box_ids.first() {
+            Some(box_id) => *box_id,
+            None => err!(
+                ErrorKind::MailBoxNotFound,
+                "No mailboxes were given to watch"
+            ),
+        };
+
+        if self.has_capability(NOTIFY_CAPABILITY).await? {
+            self.notify(box_ids).await?;
+
+            self.watching = Some(Watch::Notify(
+                box_ids.iter().map(|box_id| box_id.to_string()).collect(),
+            ));
+        } else if self.has_capability(IDLE_CAPABILITY).await? {
+            if box_ids.len() > 1 {
+                warn!(
+                    target: LOG_TARGET,
+                    "Server does not support NOTIFY, only watching {}", first
+                );
+            }
+
+            let mailbox = self.get_mailbox_no_children(first).await?;
+
+            self.select(&mailbox).await?;
+
+            self.watching = Some(Watch::Idle(first.to_string()));
+        } else {
+            err!(
+                ErrorKind::Unsupported,
+                "The mail server does not support the IDLE or NOTIFY extension",
+            )
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the server reports changes to the mailboxes passed to `start_watching`.
+    pub(super) async fn wait_for_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        match &self.watching {
+            Some(Watch::Notify(box_ids)) => {
+                let box_ids = box_ids.clone();
+
+                self.wait_for_notifications(&box_ids).await
+            }
+            Some(Watch::Idle(box_id)) => {
+                let box_id = box_id.clone();
+
+                loop {
+                    let events = self.idle(&box_id).await?;
+
+                    if !events.is_empty() {
+                        return Ok(events);
+                    }
+                }
+            }
+            None => err!(
+                ErrorKind::UnexpectedBehavior,
+                "No mailboxes are being watched",
+            ),
+        }
+    }
+
+    /// Makes the server stop reporting changes, otherwise its notifications keep piling up while other commands run.
+    pub(super) async fn end_watching(&mut self) -> Result<()> {
+        if let Some(Watch::Notify(box_ids)) = self.watching.take() {
+            debug!(target: LOG_TARGET, "No longer watching {:?}", box_ids);
+
+            while self.session.unsolicited_responses.try_recv().is_ok() {}
+
+            self.session.run_command_and_check_ok("NOTIFY NONE").await?;
+
+            // Changes reported before the server stopped are not of use to anyone anymore.
+            while self.session.unsolicited_responses.try_recv().is_ok() {}
+        }
+
+        Ok(())
+    }
+
+    async fn notify(&mut self, box_ids: &[&str]) -> Result<()> {
+        debug!(target: LOG_TARGET, "Watching {:?} using NOTIFY", box_ids);
+
+        // Changes to the selected mailbox are reported differently, so all of the watched mailboxes are kept unselected.
+        self.close().await?;
+
+        // Anything that is still waiting in the channel happened before we started watching.
+        while self.session.unsolicited_responses.try_recv().is_ok() {}
+
+        let mailboxes = box_ids
+            .iter()
+            .map(|box_id| utils::quote(box_id))
+            .collect::<Result<Vec<String>>>()?;
+
+        // STATUS makes the server report the current counts of every mailbox right away.
+        self.session
+            .run_command_and_check_ok(format!(
+                "NOTIFY SET STATUS (MAILBOXES ({}) (MessageNew MessageExpunge FlagChange))",
+                mailboxes.join(" ")
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn wait_for_notifications(&mut self, box_ids: &[String]) -> Result<Vec<MailboxEvent>> {
+        let selected = self
+            .selected_box
+            .as_ref()
+            .map(|selected| selected.id.clone());
+
+        let is_watched = |event: &MailboxEvent| box_ids.iter().any(|id| id == event.box_id());
+
+        loop {
+            // Changes reported while another command was running were passed to the channel of unsolicited responses.
+            let events: Vec<MailboxEvent> =
+                iter::from_fn(|| self.session.unsolicited_responses.try_recv().ok())
+                    .filter_map(|response| unsolicited_event(response, selected.as_deref()))
+                    .filter(is_watched)
+                    .collect();
+
+            if !events.is_empty() {
+                return Ok(events);
+            }
+
+            match timeout(KEEP_ALIVE_INTERVAL, self.session.read_response()).await {
+                Ok(Some(response)) => {
+                    let response = response?;
+
+                    let event = mailbox_event(response.parsed(), selected.as_deref());
+
+                    if let Some(event) = event.filter(is_watched) {
+                        return Ok(vec![event]);
+                    }
+                }
+                Ok(None) => return Err(ImapError::ConnectionLost.into()),
+                // Servers log out clients that stay quiet for too long.
+                Err(_) => {
+                    self.session.noop().await?;
+
+                    self.last_keep_alive = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    /// Idles in the given mailbox until the server reports a change or it is time to send a keep alive, returning the changes.
+    async fn idle(&mut self, box_id: &str) -> Result<Vec<MailboxEvent>> {
+        // Another command may have selected a different mailbox in the meantime.
+        let mailbox = self.get_mailbox_no_children(box_id).await?;
+
+        self.select(&mailbox).await?;
+
+        let request_id = self.session.run_command("IDLE").await?;
+
+        let mut events = Vec::new();
+
+        // Changes the server already knew of may be reported before it confirms it is idling.
+        loop {
+            match self.session.read_response().await.transpose()? {
+                Some(response) => match response.parsed() {
+                    Response::Continue { .. } => break,
+                    Response::Done { .. } => err!(
+                        ErrorKind::UnexpectedBehavior,
+                        "Server refused to start idling"
+                    ),
+                    parsed => events.extend(mailbox_event(parsed, Some(box_id))),
+                },
+                None => return Err(ImapError::ConnectionLost.into()),
+            }
+        }
+
+        if events.is_empty() {
+            // Idling is restarted after a while, as servers stop idling clients that stay quiet for too long.
+            if let Ok(response) = timeout(KEEP_ALIVE_INTERVAL, self.session.read_response()).await {
+                match response.transpose()? {
+                    Some(response) => events.extend(mailbox_event(response.parsed(), Some(box_id))),
+                    None => return Err(ImapError::ConnectionLost.into()),
+                }
+            }
+        }
+
+        self.session.run_command_untagged("DONE").await?;
+
+        self.read_until_done(&request_id, |response| {
+            events.extend(mailbox_event(response, Some(box_id)))
+        })
+        .await?;
+
+        self.last_keep_alive = Some(Instant::now());
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_imap::imap_proto::StatusAttribute;
+
+    use super::*;
+
+    #[test]
+    fn events_from_responses() {
+        let status = Response::MailboxData(MailboxDatum::Status {
+            mailbox: "Sent".into(),
+            status: vec![StatusAttribute::Messages(5), StatusAttribute::Unseen(2)],
+        });
+
+        let event = mailbox_event(&status, Some("INBOX")).unwrap();
+
+        assert_eq!(event.box_id(), "Sent");
+        assert!(matches!(
+            event.kind(),
+            MailboxEventKind::Stats(stats) if stats.total() == 5 && stats.unseen() == 2
+        ));
+
+        let exists = Response::MailboxData(MailboxDatum::Exists(3));
+
+        assert!(mailbox_event(&exists, None).is_none());
+        assert!(matches!(
+            mailbox_event(&exists, Some("INBOX")).unwrap().kind(),
+            MailboxEventKind::Exists(3)
+        ));
+
+        assert!(matches!(
+            unsolicited_event(UnsolicitedResponse::Expunge(4), Some("INBOX"))
+                .unwrap()
+                .kind(),
+            MailboxEventKind::Expunge(4)
+        ));
+        assert!(unsolicited_event(UnsolicitedResponse::Recent(1), Some("INBOX")).is_none());
+    }
+}
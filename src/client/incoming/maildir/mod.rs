@@ -1,24 +1,89 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
 use async_trait::async_trait;
-use maildir::Maildir;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use maildir::{MailEntry, Maildir};
+use mime::Mime;
 
 use crate::{
     client::{
+        acl::AclEntry,
+        auth::AuthMechanism,
         builder::MessageBuilder,
+        event::MailboxEvent,
+        flag::{Flag, FlagMode},
         mailbox::{Mailbox, MailboxStats},
         message::{Message, Preview},
+        metrics::SessionMetrics,
+        parser,
         protocol::IncomingProtocol,
+        sort::SortOrder,
+        Headers,
     },
-    error::{err, ErrorKind, Result},
+    error::{err, Error, ErrorKind, Result},
+    runtime::io::{Read, ReadExt},
     tree::Node,
 };
 
+/// The maildir info flag that marks a message as seen.
+const SEEN_FLAG: &str = "S";
+
 pub enum DirType {
     Current,
     New,
 }
 
+/// When a message was sent, or when it was delivered if it has no valid date header.
+fn received_at(builder: &MessageBuilder) -> Option<i64> {
+    // Maildir file names start with the unix time the message was delivered at.
+    builder.sent.or_else(|| {
+        builder
+            .id
+            .as_deref()
+            .and_then(|id| id.split('.').next())
+            .and_then(|time| time.parse().ok())
+    })
+}
+
+fn message_id(builder: &MessageBuilder) -> Option<&str> {
+    builder
+        .raw_headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Message-ID"))
+        .map(|(_, value)| value.trim())
+}
+
+/// Sorts the messages from the `cur` and `new` directories newest first and removes duplicates, so pages are the same between calls.
+///
+/// A message that is moved from `new` to `cur` while the directories are listed can show up in both.
+fn sort_and_dedup(mut builders: Vec<MessageBuilder>) -> Vec<MessageBuilder> {
+    builders.sort_by(|a, b| {
+        received_at(b)
+            .cmp(&received_at(a))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let mut seen_ids = HashSet::new();
+    let mut seen_message_ids = HashSet::new();
+
+    builders.retain(|builder| {
+        let is_new_id = builder
+            .id
+            .as_ref()
+            .map_or(true, |id| seen_ids.insert(id.clone()));
+
+        let is_new_message_id =
+            message_id(builder).map_or(true, |id| seen_message_ids.insert(id.to_string()));
+
+        is_new_id && is_new_message_id
+    });
+
+    builders
+}
+
 pub struct MaildirClient {
     maildir: Maildir,
 }
@@ -45,13 +110,9 @@ impl MaildirClient {
         Ok(list)
     }
 
-    pub fn retr<I: AsRef<str>>(&self, id: I) -> Result<MessageBuilder> {
+    fn find<I: AsRef<str>>(&self, id: I) -> Result<MailEntry> {
         match self.maildir.find(id.as_ref()) {
-            Some(mail_entry) => {
-                let builder: MessageBuilder = mail_entry.try_into()?;
-
-                Ok(builder.id(id.as_ref()))
-            }
+            Some(mail_entry) => Ok(mail_entry),
             None => {
                 err!(
                     ErrorKind::MessageNotFound,
@@ -62,6 +123,14 @@ impl MaildirClient {
         }
     }
 
+    pub fn retr<I: AsRef<str>>(&self, id: I) -> Result<MessageBuilder> {
+        let mail_entry = self.find(id.as_ref())?;
+
+        let builder: MessageBuilder = mail_entry.try_into()?;
+
+        Ok(builder.id(id.as_ref()))
+    }
+
     pub fn get_inbox(&self) -> Result<Node<Mailbox>> {
         let new = self.maildir.count_new();
 
@@ -83,8 +152,36 @@ impl IncomingProtocol for MaildirClient {
         false
     }
 
-    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
-        self.get_inbox()
+    fn protocol_name(&self) -> &'static str {
+        "Maildir"
+    }
+
+    fn server_banner(&self) -> Option<&str> {
+        None
+    }
+
+    fn metrics(&self) -> SessionMetrics {
+        // Nothing is sent over a network.
+        SessionMetrics::default()
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        None
+    }
+
+    async fn is_authenticated(&mut self) -> Result<bool> {
+        // There is no server to log in to.
+        Ok(true)
+    }
+
+    async fn capabilities(&mut self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_mailbox_stream<'a>(&'a mut self) -> Result<BoxStream<'a, Result<Mailbox>>> {
+        let inbox = self.get_inbox()?;
+
+        Ok(stream::iter(inbox.into_data().map(Ok)).boxed())
     }
 
     async fn get_mailbox(&mut self, _id: &str) -> Result<Node<Mailbox>> {
@@ -103,29 +200,90 @@ impl IncomingProtocol for MaildirClient {
         Ok(())
     }
 
+    async fn get_mailbox_flags(&mut self, _box_id: &str) -> Result<Vec<Flag>> {
+        Ok(vec![
+            Flag::Read,
+            Flag::Answered,
+            Flag::Flagged,
+            Flag::Draft,
+            Flag::Deleted,
+        ])
+    }
+
     async fn get_messages(
         &mut self,
         _box_id: &str,
         start: usize,
         end: usize,
+        order: SortOrder,
     ) -> Result<Vec<Preview>> {
-        let mut previews = Vec::new();
+        let mut builders = self.list(DirType::Current)?;
 
-        for builder in self.list(DirType::Current)? {
-            previews.push(builder.try_into()?)
-        }
+        builders.extend(self.list(DirType::New)?);
 
-        for builder in self.list(DirType::New)? {
-            previews.push(builder.try_into()?)
+        let mut builders = sort_and_dedup(builders);
+
+        if order == SortOrder::OldestFirst {
+            builders.reverse();
         }
 
-        if previews.len() <= start {
+        if builders.len() <= start {
             return Ok(Vec::new());
         }
 
-        let end = end.min(previews.len());
+        let end = end.min(builders.len());
+
+        builders.drain(start..end).map(Preview::try_from).collect()
+    }
+
+    async fn get_changes_since(&mut self, _: &str, _: u64) -> Result<Vec<Preview>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support fetching changes since a mod-sequence",
+        )
+    }
+
+    async fn get_messages_since_uid(&mut self, _: &str, _: u32) -> Result<Vec<Preview>> {
+        err!(ErrorKind::Unsupported, "Maildir messages do not have uids")
+    }
+
+    async fn watch(&mut self, _: &[&str]) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support watching mailboxes for changes",
+        )
+    }
+
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support watching mailboxes for changes",
+        )
+    }
+
+    async fn stop_watching(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_error(&mut self, error: Error) -> Error {
+        error
+    }
+
+    async fn get_unread_messages(&mut self, _box_id: &str, limit: usize) -> Result<Vec<Preview>> {
+        let mut builders = self.list(DirType::Current)?;
+
+        builders.extend(self.list(DirType::New)?);
+
+        sort_and_dedup(builders)
+            .into_iter()
+            .filter(|builder| !builder.flags.contains(&Flag::Read))
+            .take(limit)
+            .map(Preview::try_from)
+            .collect()
+    }
 
-        Ok(previews.drain(start..end).collect())
+    async fn get_unread_count(&mut self, _box_id: &str) -> Result<usize> {
+        Ok(self.maildir.count_new())
     }
 
     async fn get_message(&mut self, _box_id: &str, msg_id: &str) -> Result<Message> {
@@ -134,13 +292,173 @@ impl IncomingProtocol for MaildirClient {
         Ok(message.build()?)
     }
 
+    async fn mark_all_read(&mut self, _box_id: &str) -> Result<()> {
+        let new_ids = self
+            .maildir
+            .list_new()
+            .map(|entry| entry.map(|entry| entry.id().to_string()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for id in new_ids {
+            self.maildir.move_new_to_cur_with_flags(&id, SEEN_FLAG)?;
+        }
+
+        let unseen_ids = self
+            .maildir
+            .list_cur()
+            .filter_map(|entry| match entry {
+                Ok(entry) if entry.is_seen() => None,
+                entry => Some(entry.map(|entry| entry.id().to_string())),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for id in unseen_ids {
+            self.maildir.add_flags(&id, SEEN_FLAG)?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_flags_bulk(
+        &mut self,
+        _box_id: &str,
+        message_ids: &[&str],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()> {
+        let maildir_flags = to_maildir_flags(flags);
+
+        let new_ids = self
+            .maildir
+            .list_new()
+            .map(|entry| entry.map(|entry| entry.id().to_string()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for id in message_ids {
+            // Flags can only be stored on messages in the `cur` directory.
+            if new_ids.iter().any(|new_id| new_id == id) {
+                self.maildir.move_new_to_cur(id)?;
+            }
+
+            match mode {
+                FlagMode::Add => self.maildir.add_flags(id, &maildir_flags)?,
+                FlagMode::Remove => self.maildir.remove_flags(id, &maildir_flags)?,
+                FlagMode::Replace => self.maildir.set_flags(id, &maildir_flags)?,
+            }
+        }
+
+        Ok(())
+    }
+
     async fn get_attachment(
+        &mut self,
+        _box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+    ) -> Result<Vec<u8>> {
+        let mut mail_entry = self.find(message_id)?;
+
+        let parsed = mail_entry.parsed()?;
+
+        match parser::message::find_attachment(&parsed, attachment_id) {
+            Some(attachment) => Ok(attachment),
+            None => err!(
+                ErrorKind::AttachmentNotFound,
+                "Could not find an attachment with id '{}'",
+                attachment_id
+            ),
+        }
+    }
+
+    async fn get_attachment_with_progress(
         &mut self,
         box_id: &str,
         message_id: &str,
         attachment_id: &str,
+        progress: &mut (dyn FnMut(usize, usize) + Send),
     ) -> Result<Vec<u8>> {
-        todo!()
+        // Maildir cannot fetch part of a message, so the progress is only reported once the attachment is there.
+        let attachment = self
+            .get_attachment(box_id, message_id, attachment_id)
+            .await?;
+
+        progress(attachment.len(), attachment.len());
+
+        Ok(attachment)
+    }
+
+    async fn get_specific_headers(
+        &mut self,
+        _: &str,
+        message_id: &str,
+        headers: &[&str],
+    ) -> Result<Headers> {
+        let mail_entry = self.find(message_id)?;
+
+        // Parsing stops at the end of the headers, so the size of the body does not matter.
+        parser::message::parse_specific_headers(std::fs::read(mail_entry.path())?, headers)
+    }
+
+    async fn get_message_source(&mut self, _: &str, message_id: &str) -> Result<Vec<u8>> {
+        let mail_entry = self.find(message_id)?;
+
+        Ok(std::fs::read(mail_entry.path())?)
+    }
+
+    async fn get_message_part(
+        &mut self,
+        _box_id: &str,
+        message_id: &str,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut mail_entry = self.find(message_id)?;
+
+        let parsed = mail_entry.parsed()?;
+
+        parser::message::find_part(&parsed, &mime_type)
+    }
+
+    async fn append(&mut self, _box_id: &str, message: &[u8], flags: &[Flag]) -> Result<()> {
+        self.maildir
+            .store_cur_with_flags(message, &to_maildir_flags(flags))?;
+
+        Ok(())
+    }
+
+    async fn append_from_reader(
+        &mut self,
+        box_id: &str,
+        message: &mut (dyn Read + Unpin + Send),
+        length: usize,
+        flags: &[Flag],
+    ) -> Result<()> {
+        // Maildir can only store a message from memory.
+        let mut buffer = Vec::with_capacity(length);
+
+        message.read_to_end(&mut buffer).await?;
+
+        self.append(box_id, &buffer, flags).await
+    }
+
+    async fn get_acl(&mut self, _: &str) -> Result<Vec<AclEntry>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support mailbox access control lists",
+        )
+    }
+
+    async fn set_acl(&mut self, _: &str, _: &str, _: &str) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support mailbox access control lists",
+        )
+    }
+
+    async fn get_my_rights(&mut self, _: &str) -> Result<String> {
+        err!(
+            ErrorKind::Unsupported,
+            "Maildir does not support mailbox access control lists",
+        )
     }
 
     async fn logout(&mut self) -> Result<()> {
@@ -148,6 +466,27 @@ impl IncomingProtocol for MaildirClient {
     }
 }
 
+/// Converts flags to the characters used in maildir file names.
+fn to_maildir_flags(flags: &[Flag]) -> String {
+    let mut maildir_flags: Vec<char> = flags
+        .iter()
+        .filter_map(|flag| match flag {
+            Flag::Draft => Some('D'),
+            Flag::Flagged => Some('F'),
+            Flag::Answered => Some('R'),
+            Flag::Read => Some('S'),
+            Flag::Deleted => Some('T'),
+            _ => None,
+        })
+        .collect();
+
+    // Maildir requires the flags to be in alphabetical order.
+    maildir_flags.sort_unstable();
+    maildir_flags.dedup();
+
+    maildir_flags.into_iter().collect()
+}
+
 pub fn create(dir: PathBuf) -> Result<Box<dyn IncomingProtocol + Send + Sync>> {
     let session = MaildirClient {
         maildir: Maildir::from(dir),
@@ -155,3 +494,97 @@ pub fn create(dir: PathBuf) -> Result<Box<dyn IncomingProtocol + Send + Sync>> {
 
     Ok(Box::new(session))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OLDEST: &str = "From: Tester <test@example.com>\r\nMessage-ID: <1@example.com>\r\nDate: Tue, 1 Jul 2003 10:52:37 +0200\r\nSubject: Oldest\r\n\r\nHello";
+    const MIDDLE: &str = "From: Tester <test@example.com>\r\nMessage-ID: <2@example.com>\r\nDate: Thu, 1 Jul 2004 10:52:37 +0200\r\nSubject: Middle\r\n\r\nHello";
+    const NEWEST: &str = "From: Tester <test@example.com>\r\nMessage-ID: <3@example.com>\r\nDate: Fri, 1 Jul 2005 10:52:37 +0200\r\nSubject: Newest\r\n\r\nHello";
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn stable_message_order() {
+        let dir = std::env::temp_dir().join(format!("dust-mail-maildir-{}", std::process::id()));
+
+        let maildir = Maildir::from(dir.clone());
+
+        maildir.create_dirs().unwrap();
+
+        maildir
+            .store_cur_with_flags(NEWEST.as_bytes(), SEEN_FLAG)
+            .unwrap();
+        maildir.store_cur_with_flags(OLDEST.as_bytes(), "").unwrap();
+        maildir.store_new(MIDDLE.as_bytes()).unwrap();
+        // The same message in both directories, as if it was moved while listing.
+        maildir.store_new(NEWEST.as_bytes()).unwrap();
+
+        let mut client = MaildirClient { maildir };
+
+        let subjects = |previews: Vec<Preview>| -> Vec<String> {
+            previews
+                .iter()
+                .filter_map(|preview| preview.subject().map(String::from))
+                .collect()
+        };
+
+        let previews = client
+            .get_messages("", 0, 10, SortOrder::NewestFirst)
+            .await
+            .unwrap();
+
+        assert_eq!(subjects(previews), vec!["Newest", "Middle", "Oldest"]);
+
+        let previews = client
+            .get_messages("", 0, 2, SortOrder::OldestFirst)
+            .await
+            .unwrap();
+
+        assert_eq!(subjects(previews), vec!["Oldest", "Middle"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn get_attachment() {
+        let dir = std::env::temp_dir().join(format!(
+            "dust-mail-maildir-attachment-{}",
+            std::process::id()
+        ));
+
+        let maildir = Maildir::from(dir.clone());
+
+        maildir.create_dirs().unwrap();
+
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nSubject: Invoice\r\nContent-Type: multipart/mixed; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n--sep\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"invoice.pdf\"\r\n\r\n%PDF-1.4\r\n--sep--\r\n";
+
+        let id = maildir.store_new(raw.as_bytes()).unwrap();
+
+        let mut client = MaildirClient { maildir };
+
+        let message = client.get_message("", &id).await.unwrap();
+
+        let attachment = &message.attachments()[0];
+
+        let mut reported = Vec::new();
+
+        let body = client
+            .get_attachment_with_progress("", &id, attachment.id(), &mut |received, total| {
+                reported.push((received, total))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(body, b"%PDF-1.4\r\n");
+        assert_eq!(body.len(), attachment.size());
+        assert_eq!(reported, vec![(10, 10)]);
+
+        let missing = client.get_attachment("", &id, "3").await.unwrap_err();
+
+        assert!(matches!(missing.kind(), ErrorKind::AttachmentNotFound));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
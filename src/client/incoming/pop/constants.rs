@@ -1,3 +1,12 @@
 use std::time::Duration;
 
+/// The log target used by the pop client, use `RUST_LOG=dust_mail::pop=debug` to debug pop connections.
+pub const LOG_TARGET: &str = "dust_mail::pop";
+
 pub const ACTIVITY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The amount of body lines that are fetched using TOP to generate the snippet of a message preview.
+pub const PREVIEW_BODY_LINES: usize = 20;
+
+/// The amount of bytes of a message that are parsed for its preview if the server does not support TOP and the whole message has to be retrieved.
+pub const PREVIEW_MAX_SIZE: usize = 64 * 1024;
@@ -1,24 +1,41 @@
 mod constants;
+mod sasl;
 
 use std::{collections::HashMap, fmt::Display};
 
 use async_native_tls::{TlsConnector, TlsStream};
 use async_pop::{
+    error::ErrorKind as PopErrorKind,
+    request::Request as PopRequest,
     response::{
+        capability::Capability,
         types::DataType,
-        uidl::{UidlResponse, UniqueId},
+        uidl::{Uidl, UidlResponse, UniqueId},
+        Response as PopResponse,
     },
-    sasl::OAuth2Authenticator,
+    sasl::{OAuth2Authenticator, PlainAuthenticator},
 };
 use async_trait::async_trait;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use log::{debug, info, warn};
+use mime::Mime;
 
 use crate::{
     client::{
+        auth::{self, AuthMechanism},
         builder::MessageBuilder,
-        connection::ConnectionSecurity,
-        protocol::{Credentials, IncomingProtocol, PopCredentials, ServerCredentials},
+        connection::{self, ConnectionSecurity, SharedConnectionState},
+        metrics::{CountingStream, MetricsHandle, SessionMetrics},
+        parser,
+        protocol::{
+            Credentials, IncomingProtocol, PopCredentials, RemoteServer, ServerCredentials,
+        },
+        Headers,
     },
-    error::{err, ErrorKind, Result},
+    error::{err, Error, ErrorKind, Result},
     runtime::{
         io::{Read, Write},
         net::TcpStream,
@@ -26,16 +43,94 @@ use crate::{
     tree::Node,
 };
 
-use self::constants::ACTIVITY_TIMEOUT;
+use self::{
+    constants::{ACTIVITY_TIMEOUT, LOG_TARGET, PREVIEW_BODY_LINES, PREVIEW_MAX_SIZE},
+    sasl::{CramMd5Authenticator, ScramAuthenticator},
+};
 
 use super::types::{
-    flag::Flag,
-    mailbox::{Mailbox, MailboxStats},
+    acl::AclEntry,
+    event::MailboxEvent,
+    flag::{Flag, FlagMode},
+    mailbox::{Mailbox, MailboxStats, DEFAULT_MAILBOX_ID, INBOX_ID},
     message::{Message, Preview},
+    sort::SortOrder,
 };
 
+/// Whether we can fall back to message numbers after an error in response to listing all unique ids.
+///
+/// Only a server that does not support UIDL at all gets the fallback. Once UIDL worked in the session the application already knows messages by their unique id, so switching to message numbers would change the ids of every message.
+fn can_fall_back_from_uidl(err: &async_pop::error::Error, uidl_used: bool) -> bool {
+    !uidl_used && matches!(err.kind(), PopErrorKind::FeatureUnsupported)
+}
+
+/// Whether a command was refused because the session is no longer logged in.
+///
+/// Servers report this with the AUTH and SYS/TEMP response codes (RFC 3206). A session that is not in the transaction state anymore is refused before the command is even sent.
+fn is_logged_out(err: &async_pop::error::Error) -> bool {
+    match err.kind() {
+        PopErrorKind::IncorrectStateForCommand => true,
+        PopErrorKind::ServerError(message) => {
+            let message = message.to_ascii_uppercase();
+
+            message.contains("[AUTH]") || message.contains("[SYS/TEMP]")
+        }
+        _ => false,
+    }
+}
+
+/// Gets the list of unique ids from the response to a UIDL command for all messages.
+fn expect_uidl_list(response: UidlResponse) -> Result<Uidl> {
+    match response {
+        UidlResponse::Multiple(list) => Ok(list),
+        UidlResponse::Single(_) => err!(
+            ErrorKind::UnexpectedBehavior,
+            "Pop server returned a single unique id when the unique ids of all messages were requested",
+        ),
+    }
+}
+
+/// Gets the unique id from the response to a UIDL command for a single message.
+fn expect_unique_id(response: &UidlResponse) -> Result<String> {
+    match response {
+        UidlResponse::Single(item) => Ok(item.id().value()?),
+        UidlResponse::Multiple(_) => err!(
+            ErrorKind::UnexpectedBehavior,
+            "Pop server returned a list of unique ids when the unique id of a single message was requested",
+        ),
+    }
+}
+
+fn warn_uidl_unsupported() {
+    warn!(
+        target: LOG_TARGET,
+        "Pop server does not support UIDL, message numbers will be used as ids, which change when messages are deleted"
+    );
+}
+
+/// The SASL mechanisms we can log in with, other mechanisms fall back to USER and PASS.
+const SASL_MECHANISMS: [AuthMechanism; 5] = [
+    AuthMechanism::ScramSha256,
+    AuthMechanism::ScramSha1,
+    AuthMechanism::CramMd5,
+    AuthMechanism::Plain,
+    AuthMechanism::XOAuth2,
+];
+
 pub struct PopClient<S: Read + Write + Unpin + Send> {
     session: async_pop::Client<S>,
+    metrics: MetricsHandle,
+}
+
+impl<S: Read + Write + Unpin + Send> PopClient<CountingStream<S>> {
+    async fn from_stream(stream: S) -> Result<Self> {
+        let stream = CountingStream::new(stream);
+        let metrics = stream.handle();
+
+        let session = async_pop::new(stream).await?;
+
+        Ok(PopClient { session, metrics })
+    }
 }
 
 impl<S: Read + Write + Unpin + Send> PopClient<S> {
@@ -46,7 +141,7 @@ impl<S: Read + Write + Unpin + Send> PopClient<S> {
     ) -> Result<PopSession<S>> {
         self.session.login(username, password).await?;
 
-        let session = PopSession::new(self.session);
+        let session = PopSession::new(self.session, self.metrics, AuthMechanism::LoginCommand);
 
         Ok(session)
     }
@@ -60,10 +155,117 @@ impl<S: Read + Write + Unpin + Send> PopClient<S> {
 
         self.session.auth(oauth_authenticator).await?;
 
-        let session = PopSession::new(self.session);
+        let session = PopSession::new(self.session, self.metrics, AuthMechanism::XOAuth2);
+
+        Ok(session)
+    }
+
+    pub async fn plain_login<U: AsRef<str>, P: AsRef<str>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Result<PopSession<S>> {
+        let plain_authenticator = PlainAuthenticator::new(username.as_ref(), password.as_ref());
+
+        self.session.auth(plain_authenticator).await?;
+
+        let session = PopSession::new(self.session, self.metrics, AuthMechanism::Plain);
+
+        Ok(session)
+    }
+
+    pub async fn cram_md5_login<U: AsRef<str>, P: AsRef<str>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Result<PopSession<S>> {
+        let cram_md5_authenticator =
+            CramMd5Authenticator::new(username.as_ref(), password.as_ref());
+
+        self.session.auth(cram_md5_authenticator).await?;
+
+        let session = PopSession::new(self.session, self.metrics, AuthMechanism::CramMd5);
 
         Ok(session)
     }
+
+    /// Logs in with SCRAM-SHA-1 or SCRAM-SHA-256, failing if the server cannot prove it knows the password.
+    pub async fn scram_login<U: AsRef<str>, P: AsRef<str>>(
+        mut self,
+        mechanism: AuthMechanism,
+        username: U,
+        password: P,
+    ) -> Result<PopSession<S>> {
+        let (name, hash) = match (mechanism.sasl_name(), mechanism.scram_hash()) {
+            (Some(name), Some(hash)) => (name, hash),
+            _ => err!(
+                ErrorKind::Unsupported,
+                "{} is not a SCRAM mechanism",
+                mechanism
+            ),
+        };
+
+        let scram_authenticator =
+            ScramAuthenticator::new(name, hash, username.as_ref(), password.as_ref());
+
+        self.session.auth(scram_authenticator).await?;
+
+        let session = PopSession::new(self.session, self.metrics, mechanism);
+
+        Ok(session)
+    }
+
+    /// The SASL mechanisms the server advertised in its capabilities (RFC 2449 section 6.3).
+    fn auth_mechanisms(&self) -> Vec<String> {
+        self.session
+            .capabilities()
+            .iter()
+            .filter_map(|capability| match capability {
+                Capability::Sasl(mechanisms) => Some(mechanisms),
+                _ => None,
+            })
+            .flatten()
+            .map(|mechanism| String::from_utf8_lossy(mechanism).to_string())
+            .collect()
+    }
+
+    /// Logs in using the strongest mechanism the server advertises, falling back to USER and PASS for passwords.
+    pub async fn authenticate(self, credentials: &Credentials) -> Result<PopSession<S>> {
+        let credentials = credentials.refreshed().await?;
+        let credentials = credentials.as_ref();
+
+        let advertised = self.auth_mechanisms();
+
+        let mechanism = auth::negotiate(credentials, &SASL_MECHANISMS, &advertised);
+
+        debug!(
+            target: LOG_TARGET,
+            "Server advertises {:?}, logging in with {:?}", advertised, mechanism
+        );
+
+        match (credentials, mechanism) {
+            (Credentials::Password { username, password }, Some(AuthMechanism::Plain)) => {
+                self.plain_login(username, password).await
+            }
+            (Credentials::Password { username, password }, Some(AuthMechanism::CramMd5)) => {
+                self.cram_md5_login(username, password).await
+            }
+            (
+                Credentials::Password { username, password },
+                Some(mechanism @ (AuthMechanism::ScramSha1 | AuthMechanism::ScramSha256)),
+            ) => self.scram_login(mechanism, username, password).await,
+            (Credentials::Password { username, password }, _) => {
+                self.login(username, password).await
+            }
+            // Servers that do not support CAPA cannot advertise XOAUTH2, so it is tried regardless.
+            (
+                Credentials::OAuth {
+                    username, token, ..
+                },
+                _,
+            ) => self.oauth_login(username, token).await,
+        }
+    }
 }
 
 struct UniqueIdMap {
@@ -92,7 +294,7 @@ impl UniqueIdMap {
     }
 
     fn get<I: AsRef<str>>(&self, id: I) -> Option<usize> {
-        self.map.get(id.as_ref()).map(|index| *index)
+        self.map.get(id.as_ref()).copied()
     }
 
     fn set<I: Display>(&mut self, id: I, index: usize) {
@@ -111,69 +313,76 @@ impl UniqueIdMap {
 pub struct PopSession<S: Read + Write + Unpin + Send> {
     session: async_pop::Client<S>,
     unique_id_map: UniqueIdMap,
+    /// Whether the server supports the UIDL command, if it does not, message numbers are used as ids instead.
+    uidl_supported: bool,
+    /// Whether a UIDL command succeeded in this session.
+    uidl_used: bool,
+    /// Whether the server supports the TOP command, if it does not, whole messages are retrieved to create previews. `None` until we tried it on a server that does not advertise it.
+    top_supported: Option<bool>,
+    /// Whether we sent a QUIT command to the server.
+    closed: bool,
+    /// The greeting the server sent, which contains the timestamp used for APOP.
+    banner: Option<String>,
+    metrics: MetricsHandle,
+    /// How we logged in.
+    auth_mechanism: AuthMechanism,
 }
 
-pub async fn connect<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<PopClient<TlsStream<TcpStream>>> {
+pub async fn connect(
+    server: &RemoteServer,
+) -> Result<PopClient<CountingStream<TlsStream<TcpStream>>>> {
     let tls = TlsConnector::new();
 
-    let tcp_stream = TcpStream::connect((server.as_ref(), port.into())).await?;
-
-    let tls_stream = tls.connect(server.as_ref(), tcp_stream).await?;
+    let tcp_stream = connection::connect_tcp(server).await?;
 
-    let session = async_pop::new(tls_stream).await?;
+    let tls_stream = tls.connect(server.domain(), tcp_stream).await?;
 
-    Ok(PopClient { session })
+    PopClient::from_stream(tls_stream).await
 }
 
-pub async fn connect_plain<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<PopClient<TcpStream>> {
-    let tcp_stream = TcpStream::connect((server.as_ref(), port.into())).await?;
+pub async fn connect_plain(server: &RemoteServer) -> Result<PopClient<CountingStream<TcpStream>>> {
+    let tcp_stream = connection::connect_tcp(server).await?;
 
-    let session = async_pop::new(tcp_stream).await?;
-
-    Ok(PopClient { session })
+    PopClient::from_stream(tcp_stream).await
 }
 
 async fn login<S: Read + Write + Unpin + Send>(
     client: PopClient<S>,
     credentials: &Credentials,
+    state: &SharedConnectionState,
 ) -> Result<PopSession<S>> {
-    match credentials {
-        Credentials::Password { username, password } => {
-            let session = client.login(username, password).await?;
+    info!(target: LOG_TARGET, "Creating new pop session");
 
-            Ok(session)
-        }
-        Credentials::OAuth { username, token } => {
-            let session = client.oauth_login(username, token).await?;
+    let session = state
+        .authenticating(client.authenticate(credentials))
+        .await?;
 
-            Ok(session)
-        }
-    }
+    info!(
+        target: LOG_TARGET,
+        "Logged in as {} using {}",
+        credentials.username(),
+        session.auth_mechanism
+    );
+
+    Ok(session)
 }
 
 pub async fn create(
     credentials: &PopCredentials,
+    state: &SharedConnectionState,
 ) -> Result<Box<dyn IncomingProtocol + Sync + Send>> {
     match credentials.server().security() {
         ConnectionSecurity::Tls => {
-            let client =
-                connect(credentials.server().domain(), credentials.server().port()).await?;
+            let client = connect(credentials.server()).await?;
 
-            let session = login(client, credentials.credentials()).await?;
+            let session = login(client, credentials.credentials(), state).await?;
 
             Ok(Box::new(session))
         }
         _ => {
-            let client =
-                connect_plain(credentials.server().domain(), credentials.server().port()).await?;
+            let client = connect_plain(credentials.server()).await?;
 
-            let session = login(client, credentials.credentials()).await?;
+            let session = login(client, credentials.credentials(), state).await?;
 
             Ok(Box::new(session))
         }
@@ -181,11 +390,32 @@ pub async fn create(
 }
 
 impl<S: Read + Write + Unpin + Send> PopSession<S> {
-    pub fn new(session: async_pop::Client<S>) -> Self {
+    pub fn new(
+        mut session: async_pop::Client<S>,
+        metrics: MetricsHandle,
+        auth_mechanism: AuthMechanism,
+    ) -> Self {
+        let uidl_supported = session.has_capability([Capability::Uidl]);
+
+        if !uidl_supported {
+            warn_uidl_unsupported();
+        }
+
+        let top_supported = session.has_capability([Capability::Top]).then_some(true);
+
+        let banner = session.greeting().map(|greeting| greeting.to_string());
+
         Self {
             session,
 
             unique_id_map: UniqueIdMap::new(),
+            uidl_supported,
+            uidl_used: false,
+            top_supported,
+            closed: false,
+            banner,
+            metrics,
+            auth_mechanism,
         }
     }
 
@@ -194,11 +424,28 @@ impl<S: Read + Write + Unpin + Send> PopSession<S> {
 
         let message_count = stats.counter();
 
-        let stats = MailboxStats::new(0, message_count.value()?);
+        let total = message_count.value()?;
+
+        // No message is ever marked as read (see `get_flags`), so all of them count as unseen.
+        let stats = MailboxStats::new(total, total);
 
         Ok(stats)
     }
 
+    /// The flags of a message, as far as we know them.
+    ///
+    /// Pop has no concept of read messages, so unlike Imap the `Read` flag is never set. Applications that want to show which messages were read have to keep track of that themselves, e.g. using the message ids.
+    fn get_flags(&mut self, msg_number: usize) -> Vec<Flag> {
+        let mut flags = Vec::new();
+
+        // If we have marked a message as deleted, we will add the corresponding flag
+        if self.session.is_deleted(&msg_number) {
+            flags.push(Flag::Deleted)
+        }
+
+        flags
+    }
+
     /// Fetches the message count from the pop server and creates a new 'fake' mailbox.
     ///
     /// We do this because Pop does not support mailboxes.
@@ -212,34 +459,202 @@ impl<S: Read + Write + Unpin + Send> PopSession<S> {
     }
 
     async fn update_uidl_map(&mut self) -> Result<()> {
-        let uidl = match self.session.uidl(None).await? {
-            UidlResponse::Multiple(list) => list,
-            _ => unreachable!(),
+        let response = match self.session.uidl(None).await {
+            Ok(response) => response,
+            Err(err) if can_fall_back_from_uidl(&err, self.uidl_used) => {
+                warn_uidl_unsupported();
+
+                self.uidl_supported = false;
+
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
         };
 
+        let uidl = expect_uidl_list(response)?;
+
+        self.uidl_used = true;
+
         self.unique_id_map.extend(uidl.items())?;
 
         Ok(())
     }
 
-    async fn get_index<T: AsRef<str>>(&mut self, unique_id: T) -> Result<usize> {
-        if let Some(index) = self.unique_id_map.get(&unique_id) {
-            return Ok(index);
-        };
+    /// Fetches the headers and the first lines of the body of a message, which is enough to create a preview with a snippet without downloading the whole message.
+    ///
+    /// Falls back to retrieving the whole message if the server does not support TOP, only the start of which is parsed.
+    async fn get_preview_body(&mut self, msg_number: usize) -> Result<Vec<u8>> {
+        if let Some(body) = self.top(msg_number, PREVIEW_BODY_LINES).await? {
+            return Ok(body);
+        }
 
-        self.update_uidl_map().await?;
+        // This downloads every message in full, so listing a mailbox costs as much traffic as the size of the messages in it.
+        let body = self.session.retr(msg_number).await?;
 
-        match self.unique_id_map.get(&unique_id) {
-            Some(msg_number) => Ok(msg_number),
-            None => err!(
-                ErrorKind::MessageNotFound,
-                "Could not find a message with id {}",
-                unique_id.as_ref()
+        Ok(body[..body.len().min(PREVIEW_MAX_SIZE)].to_vec())
+    }
+
+    /// Retrieves the headers and the given amount of body lines of a message, or `None` if the server does not support TOP.
+    ///
+    /// Servers without CAPA cannot advertise TOP, but most of them support it anyway, so it is tried once before we give up on it.
+    async fn top(&mut self, msg_number: usize, lines: usize) -> Result<Option<Vec<u8>>> {
+        match self.top_supported {
+            Some(true) => return Ok(Some(self.session.top(msg_number, lines).await?.to_vec())),
+            Some(false) => return Ok(None),
+            // The server would reject a deleted message, which says nothing about TOP.
+            None if self.session.is_deleted(&msg_number) => return Ok(None),
+            None => {}
+        }
+
+        let mut request: PopRequest = "TOP".parse()?;
+
+        request.add_arg(msg_number);
+        request.add_arg(lines);
+
+        match self.session.send_request(request).await {
+            Ok(PopResponse::Bytes(body)) => {
+                self.top_supported = Some(true);
+
+                Ok(Some(body.to_vec()))
+            }
+            Ok(_) => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Pop server sent an unexpected response to TOP"
             ),
+            Err(err) if matches!(err.kind(), PopErrorKind::ServerError(_)) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Pop server does not support TOP, whole messages will be retrieved to create previews"
+                );
+
+                self.top_supported = Some(false);
+
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Creates the previews of the given messages, in the order of the message numbers.
+    async fn get_previews(&mut self, msg_numbers: Vec<usize>) -> Result<Vec<Preview>> {
+        let mut previews: Vec<Preview> = Vec::with_capacity(msg_numbers.len());
+
+        for msg_number in msg_numbers {
+            let unique_id = self.get_unique_id(msg_number).await?;
+
+            let body = self.get_preview_body(msg_number).await?;
+
+            let flags = self.get_flags(msg_number);
+
+            let preview: Result<Preview> = MessageBuilder::try_from(body.as_slice())
+                .and_then(|builder| builder.flags(flags).id(&unique_id).build());
+
+            // A single malformed message should not make the rest of the mailbox unviewable, so those are skipped.
+            match preview {
+                Ok(preview) => previews.push(preview),
+                Err(err) => warn!(
+                    target: LOG_TARGET,
+                    "Skipping message {} because it could not be parsed: {}",
+                    unique_id,
+                    err
+                ),
+            }
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Fetched {} message previews",
+            previews.len()
+        );
+
+        Ok(previews)
+    }
+
+    async fn get_index<T: AsRef<str>>(&mut self, unique_id: T) -> Result<usize> {
+        if self.uidl_supported {
+            if let Some(index) = self.unique_id_map.get(&unique_id) {
+                return Ok(index);
+            };
+
+            self.update_uidl_map().await?;
+
+            if let Some(msg_number) = self.unique_id_map.get(&unique_id) {
+                return Ok(msg_number);
+            }
+        }
+
+        // Without UIDL, the message number is used as the id.
+        if !self.uidl_supported {
+            if let Ok(msg_number) = unique_id.as_ref().parse::<usize>() {
+                if msg_number > 0 {
+                    return Ok(msg_number);
+                }
+            }
+        }
+
+        err!(
+            ErrorKind::MessageNotFound,
+            "Could not find a message with id {}",
+            unique_id.as_ref()
+        )
+    }
+
+    /// Gets the unique id of a message, or its message number if the server does not support UIDL.
+    async fn get_unique_id(&mut self, msg_number: usize) -> Result<String> {
+        if let Some(id) = self.unique_id_map.get_id(msg_number) {
+            return Ok(id.to_string());
+        }
+
+        if !self.uidl_supported {
+            return Ok(msg_number.to_string());
+        }
+
+        let uidl_response = self.session.uidl(Some(msg_number)).await?;
+
+        let unique_id = expect_unique_id(&uidl_response)?;
+
+        self.uidl_used = true;
+
+        Ok(unique_id)
+    }
+}
+
+impl<S: Read + Write + Unpin + Send> Drop for PopSession<S> {
+    fn drop(&mut self) {
+        if !self.closed {
+            // Without a QUIT the server will not expunge the messages that were marked as deleted.
+            warn!(
+                target: LOG_TARGET,
+                "Pop session was dropped without logging out, messages marked as deleted will not be removed"
+            );
         }
     }
 }
 
+/// The keyword the server used to advertise a capability in its CAPA response.
+fn capability_name(capability: &Capability) -> String {
+    match capability {
+        Capability::Top => String::from("TOP"),
+        Capability::User => String::from("USER"),
+        Capability::Sasl(mechanisms) => {
+            let mechanisms: Vec<_> = mechanisms
+                .iter()
+                .map(|mechanism| String::from_utf8_lossy(mechanism))
+                .collect();
+
+            format!("SASL {}", mechanisms.join(" "))
+        }
+        Capability::RespCodes => String::from("RESP-CODES"),
+        Capability::LoginDelay(_) => String::from("LOGIN-DELAY"),
+        Capability::Pipelining => String::from("PIPELINING"),
+        Capability::Expire(_) => String::from("EXPIRE"),
+        Capability::Uidl => String::from("UIDL"),
+        Capability::Implementation(_) => String::from("IMPLEMENTATION"),
+        Capability::Stls => String::from("STLS"),
+        Capability::Other(other) => other.to_string(),
+    }
+}
+
 #[async_trait]
 impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
     async fn send_keep_alive(&mut self) -> Result<()> {
@@ -255,11 +670,57 @@ impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
         }
     }
 
-    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
-        Ok(self.get_inbox().await?.into())
+    fn protocol_name(&self) -> &'static str {
+        "POP3"
+    }
+
+    fn server_banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+
+    fn metrics(&self) -> SessionMetrics {
+        self.metrics.metrics()
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        Some(self.auth_mechanism)
+    }
+
+    async fn is_authenticated(&mut self) -> Result<bool> {
+        match self.session.stat().await {
+            Ok(_) => Ok(true),
+            Err(err) if is_logged_out(&err) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn capabilities(&mut self) -> Result<Vec<String>> {
+        let capabilities = self
+            .session
+            .capabilities()
+            .iter()
+            .map(capability_name)
+            .collect();
+
+        Ok(capabilities)
     }
 
-    async fn get_mailbox(&mut self, _mailbox_id: &str) -> Result<Node<Mailbox>> {
+    async fn get_mailbox_stream<'a>(&'a mut self) -> Result<BoxStream<'a, Result<Mailbox>>> {
+        let inbox = self.get_inbox().await?;
+
+        Ok(stream::iter([Ok(inbox)]).boxed())
+    }
+
+    /// Pop only has a single inbox, so any id other than the inbox id (or `INBOX`) results in a `MailBoxNotFound` error.
+    async fn get_mailbox(&mut self, mailbox_id: &str) -> Result<Node<Mailbox>> {
+        if mailbox_id != DEFAULT_MAILBOX_ID && !mailbox_id.eq_ignore_ascii_case(INBOX_ID) {
+            err!(
+                ErrorKind::MailBoxNotFound,
+                "Pop only supports a single inbox, could not find mailbox with id `{}`",
+                mailbox_id
+            );
+        }
+
         Ok(self.get_inbox().await?.into())
     }
 
@@ -268,6 +729,8 @@ impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
 
         self.session.quit().await?;
 
+        self.closed = true;
+
         Ok(())
     }
 
@@ -292,57 +755,126 @@ impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
         )
     }
 
-    async fn get_messages(&mut self, _: &str, start: usize, end: usize) -> Result<Vec<Preview>> {
+    async fn append(&mut self, _: &str, _: &[u8], _: &[Flag]) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support storing messages",
+        )
+    }
+
+    async fn append_from_reader(
+        &mut self,
+        _: &str,
+        _: &mut (dyn Read + Unpin + Send),
+        _: usize,
+        _: &[Flag],
+    ) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support storing messages",
+        )
+    }
+
+    async fn get_acl(&mut self, _: &str) -> Result<Vec<AclEntry>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support mailbox access control lists",
+        )
+    }
+
+    async fn set_acl(&mut self, _: &str, _: &str, _: &str) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support mailbox access control lists",
+        )
+    }
+
+    async fn get_my_rights(&mut self, _: &str) -> Result<String> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support mailbox access control lists",
+        )
+    }
+
+    async fn get_mailbox_flags(&mut self, _: &str) -> Result<Vec<Flag>> {
+        // The only thing we can do with a message is mark it for deletion.
+        Ok(vec![Flag::Deleted])
+    }
+
+    async fn get_messages(
+        &mut self,
+        _: &str,
+        start: usize,
+        end: usize,
+        order: SortOrder,
+    ) -> Result<Vec<Preview>> {
         let total_messages = self.get_stats().await?.total();
 
-        let sequence_start = if total_messages < end {
-            1
-        } else {
-            total_messages.saturating_sub(end).saturating_add(1)
+        let (sequence_start, sequence_end) = match order.sequence_range(total_messages, start, end)
+        {
+            Some(range) => range,
+            None => return Ok(Vec::new()),
         };
 
-        let sequence_end = if total_messages < start {
-            1
-        } else {
-            total_messages.saturating_sub(start).saturating_add(1)
-        };
+        let mut msg_numbers: Vec<usize> = (sequence_start..=sequence_end).collect();
 
-        let msg_count = end.saturating_sub(start) as usize;
+        if order == SortOrder::NewestFirst {
+            msg_numbers.reverse();
+        }
 
-        let mut previews: Vec<Preview> = Vec::with_capacity(msg_count);
+        self.get_previews(msg_numbers).await
+    }
 
-        for msg_number in sequence_start..sequence_end {
-            let unique_id = match self.unique_id_map.get_id(msg_number) {
-                Some(id) => id.to_string(),
-                None => {
-                    let uidl_response = self.session.uidl(Some(msg_number)).await?;
+    async fn get_changes_since(&mut self, _: &str, _: u64) -> Result<Vec<Preview>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not support fetching changes since a mod-sequence",
+        )
+    }
 
-                    let unique_id = match &uidl_response {
-                        UidlResponse::Multiple(_) => unreachable!(),
-                        UidlResponse::Single(item) => item.id(),
-                    };
+    async fn get_messages_since_uid(&mut self, _: &str, last_uid: u32) -> Result<Vec<Preview>> {
+        // Pop has no uids, but new messages are always appended, so the message number serves as one as long as no messages are deleted in the meantime.
+        let total_messages = self.get_stats().await?.total();
 
-                    unique_id.value()?
-                }
-            };
+        let msg_numbers: Vec<usize> = ((last_uid as usize + 1)..=total_messages).collect();
 
-            let body = self.session.top(msg_number, 0).await?;
+        self.get_previews(msg_numbers).await
+    }
 
-            let mut flags = vec![Flag::Read];
+    async fn watch(&mut self, _: &[&str]) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop servers cannot report changes to a mailbox",
+        )
+    }
 
-            // If we have marked a message as deleted, we will add the corresponding flag
-            if self.session.is_deleted(&msg_number) {
-                flags.push(Flag::Deleted)
-            }
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop servers cannot report changes to a mailbox",
+        )
+    }
 
-            let builder: MessageBuilder = body.as_ref().try_into()?;
+    async fn stop_watching(&mut self) -> Result<()> {
+        Ok(())
+    }
 
-            let preview: Preview = builder.flags(flags).id(&unique_id).build()?;
+    async fn check_error(&mut self, error: Error) -> Error {
+        error
+    }
 
-            previews.push(preview)
-        }
+    async fn get_unread_messages(&mut self, _: &str, _: usize) -> Result<Vec<Preview>> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not keep track of which messages have been read",
+        )
+    }
 
-        Ok(previews)
+    async fn get_unread_count(&mut self, _: &str) -> Result<usize> {
+        err!(
+            ErrorKind::Unsupported,
+            "Pop does not keep track of which messages have been read",
+        )
     }
 
     async fn get_message(&mut self, _box_id: &str, message_id: &str) -> Result<Message> {
@@ -350,12 +882,7 @@ impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
 
         let body = self.session.retr(msg_number).await?;
 
-        let mut flags = vec![Flag::Read];
-
-        // If we have marked a message as deleted, we will add the corresponding flag
-        if self.session.is_deleted(&msg_number) {
-            flags.push(Flag::Deleted)
-        }
+        let flags = self.get_flags(msg_number);
 
         let builder: MessageBuilder = body.as_ref().try_into()?;
 
@@ -364,13 +891,116 @@ impl<S: Read + Write + Unpin + Send> IncomingProtocol for PopSession<S> {
         Ok(message)
     }
 
+    async fn mark_all_read(&mut self, _: &str) -> Result<()> {
+        // Pop has no concept of read messages, so there is nothing to do here.
+        Ok(())
+    }
+
+    async fn set_flags_bulk(
+        &mut self,
+        _: &str,
+        message_ids: &[&str],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()> {
+        // Pop only knows about messages being marked for deletion, other flags are ignored.
+        if !flags.contains(&Flag::Deleted) {
+            return Ok(());
+        }
+
+        if mode == FlagMode::Remove {
+            err!(
+                ErrorKind::Unsupported,
+                "Pop does not support unmarking a single message for deletion",
+            );
+        }
+
+        for message_id in message_ids {
+            let msg_number = self.get_index(message_id).await?;
+
+            self.session.dele(msg_number).await?;
+        }
+
+        Ok(())
+    }
+
     async fn get_attachment(
+        &mut self,
+        _box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+    ) -> Result<Vec<u8>> {
+        let msg_number = self.get_index(message_id).await?;
+
+        let body = self.session.retr(msg_number).await?;
+
+        let parsed = mailparse::parse_mail(body.as_ref())?;
+
+        match parser::message::find_attachment(&parsed, attachment_id) {
+            Some(attachment) => Ok(attachment),
+            None => err!(
+                ErrorKind::AttachmentNotFound,
+                "Could not find an attachment with id '{}'",
+                attachment_id
+            ),
+        }
+    }
+
+    async fn get_attachment_with_progress(
         &mut self,
         box_id: &str,
         message_id: &str,
         attachment_id: &str,
+        progress: &mut (dyn FnMut(usize, usize) + Send),
     ) -> Result<Vec<u8>> {
-        todo!()
+        // Pop cannot fetch part of a message, so the progress is only reported once the attachment is there.
+        let attachment = self
+            .get_attachment(box_id, message_id, attachment_id)
+            .await?;
+
+        progress(attachment.len(), attachment.len());
+
+        Ok(attachment)
+    }
+
+    async fn get_specific_headers(
+        &mut self,
+        _: &str,
+        message_id: &str,
+        headers: &[&str],
+    ) -> Result<Headers> {
+        let msg_number = self.get_index(message_id).await?;
+
+        // TOP with zero lines returns only the headers.
+        let source = match self.top(msg_number, 0).await? {
+            Some(source) => source,
+            None => self.session.retr(msg_number).await?.to_vec(),
+        };
+
+        parser::message::parse_specific_headers(&source, headers)
+    }
+
+    async fn get_message_source(&mut self, _: &str, message_id: &str) -> Result<Vec<u8>> {
+        let msg_number = self.get_index(message_id).await?;
+
+        let body = self.session.retr(msg_number).await?;
+
+        Ok(body.as_ref().to_vec())
+    }
+
+    async fn get_message_part(
+        &mut self,
+        _box_id: &str,
+        message_id: &str,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>> {
+        let msg_number = self.get_index(message_id).await?;
+
+        let body = self.session.retr(msg_number).await?;
+
+        let parsed = mailparse::parse_mail(body.as_ref())?;
+
+        parser::message::find_part(&parsed, &mime_type)
     }
 }
 
@@ -382,7 +1012,7 @@ mod test {
     use dotenv::dotenv;
     use std::env;
 
-    async fn create_test_session() -> PopSession<TlsStream<TcpStream>> {
+    async fn create_test_session() -> PopSession<CountingStream<TlsStream<TcpStream>>> {
         dotenv().ok();
 
         let username = env::var("POP_USERNAME").unwrap();
@@ -391,19 +1021,148 @@ mod test {
         let server = env::var("POP_SERVER").unwrap();
         let port: u16 = 995;
 
-        let client = super::connect(server, port).await.unwrap();
+        let server = RemoteServer::new(server, port, ConnectionSecurity::Tls);
+
+        let client = super::connect(&server).await.unwrap();
 
         let session = client.login(&username, &password).await.unwrap();
 
         session
     }
 
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn top_not_supported() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufStream};
+
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(async move {
+            let mut stream = BufStream::new(server_stream);
+            let mut received = Vec::new();
+
+            stream.write_all(b"+OK ready\r\n").await.unwrap();
+            stream.flush().await.unwrap();
+
+            loop {
+                let mut line = String::new();
+
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return received;
+                }
+
+                let command = line.trim_end().to_string();
+
+                let reply = match command.split_whitespace().next().unwrap() {
+                    "CAPA" => "+OK\r\nUSER\r\n.\r\n",
+                    "TOP" => "-ERR unknown command\r\n",
+                    "RETR" => "+OK\r\nSubject: Hi\r\n\r\nHello\r\n.\r\n",
+                    _ => "+OK\r\n",
+                };
+
+                received.push(command);
+
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+        });
+
+        let client = PopClient::from_stream(client_stream).await.unwrap();
+        let mut session = client.login("user", "secret").await.unwrap();
+
+        for _ in 0..2 {
+            let headers = session
+                .get_specific_headers(DEFAULT_MAILBOX_ID, "1", &["Subject"])
+                .await
+                .unwrap();
+
+            assert_eq!(headers.get("Subject").map(String::as_str), Some("Hi"));
+        }
+
+        drop(session);
+
+        let received = server.await.unwrap();
+
+        // TOP is only tried once.
+        assert_eq!(
+            received
+                .iter()
+                .filter(|command| command.starts_with("TOP"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            received
+                .iter()
+                .filter(|command| command.starts_with("RETR"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn uidl_fallback() {
+        use async_pop::error::Error as PopError;
+
+        let unsupported = PopError::new(PopErrorKind::FeatureUnsupported, "UIDL");
+        let rejected = PopError::new(PopErrorKind::ServerError("-ERR busy".into()), "UIDL");
+
+        assert!(can_fall_back_from_uidl(&unsupported, false));
+        assert!(!can_fall_back_from_uidl(&unsupported, true));
+        assert!(!can_fall_back_from_uidl(&rejected, false));
+    }
+
+    #[test]
+    fn logged_out_errors() {
+        use async_pop::error::Error as PopError;
+
+        let expired = PopError::new(
+            PopErrorKind::ServerError("-ERR [AUTH] Session expired".into()),
+            "STAT",
+        );
+        let wrong_state = PopError::new(PopErrorKind::IncorrectStateForCommand, "STAT");
+        let busy = PopError::new(PopErrorKind::ServerError("-ERR busy".into()), "STAT");
+
+        assert!(is_logged_out(&expired));
+        assert!(is_logged_out(&wrong_state));
+        assert!(!is_logged_out(&busy));
+    }
+
+    #[test]
+    fn unexpected_uidl_responses() {
+        let single: UidlResponse = UniqueId::new("1".as_bytes(), "abc").into();
+
+        assert_eq!(expect_unique_id(&single).unwrap(), "abc");
+
+        let error = expect_uidl_list(single).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::UnexpectedBehavior));
+
+        let list: UidlResponse = Uidl::new(
+            None::<&str>,
+            vec![
+                UniqueId::new("1".as_bytes(), "abc"),
+                UniqueId::new("2".as_bytes(), "def"),
+            ],
+        )
+        .into();
+
+        let error = expect_unique_id(&list).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::UnexpectedBehavior));
+
+        assert_eq!(expect_uidl_list(list).unwrap().items().len(), 2);
+    }
+
     #[cfg_attr(feature = "runtime-async-std", async_std::test)]
     #[cfg_attr(feature = "runtime-tokio", tokio::test)]
     async fn get_messages() {
         let mut session = create_test_session().await;
 
-        let previews = session.get_messages("Inbox", 0, 10).await.unwrap();
+        let previews = session
+            .get_messages("Inbox", 0, 10, SortOrder::NewestFirst)
+            .await
+            .unwrap();
 
         for preview in previews.iter() {
             println!("{:?}", preview);
@@ -0,0 +1,121 @@
+use async_pop::{
+    error::{Error as PopError, ErrorKind as PopErrorKind, Result as PopResult},
+    sasl::{Authenticator, Communicator},
+};
+use async_trait::async_trait;
+
+use crate::{
+    client::auth::{cram_md5, Hash, ScramClient},
+    error::Error,
+    runtime::{
+        io::{Read, Write},
+        thread,
+    },
+};
+
+fn pop_error(error: Error) -> PopError {
+    PopError::new(PopErrorKind::UnexpectedResponse, error.to_string())
+}
+
+/// Answers the CRAM-MD5 challenge (RFC 2195) of the server.
+pub struct CramMd5Authenticator {
+    username: String,
+    password: String,
+}
+
+impl CramMd5Authenticator {
+    pub fn new<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for CramMd5Authenticator {
+    fn mechanism(&self) -> &str {
+        "CRAM-MD5"
+    }
+
+    async fn handle<'a, S: Read + Write + Unpin + Send>(
+        &self,
+        mut communicator: Communicator<'a, S>,
+    ) -> PopResult<()> {
+        let challenge = communicator.next_challenge().await?;
+
+        communicator
+            .send(cram_md5(&self.username, &self.password, challenge.as_ref()))
+            .await
+    }
+}
+
+/// Runs a SCRAM exchange, failing if the server cannot prove it knows the password.
+pub struct ScramAuthenticator {
+    mechanism: &'static str,
+    client: ScramClient,
+}
+
+impl ScramAuthenticator {
+    pub fn new<U: Into<String>, P: Into<String>>(
+        mechanism: &'static str,
+        hash: Hash,
+        username: U,
+        password: P,
+    ) -> Self {
+        Self {
+            mechanism,
+            client: ScramClient::new(hash, username, password),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for ScramAuthenticator {
+    fn mechanism(&self) -> &str {
+        self.mechanism
+    }
+
+    fn auth(&self) -> Option<String> {
+        Some(self.client.client_first())
+    }
+
+    async fn handle<'a, S: Read + Write + Unpin + Send>(
+        &self,
+        mut communicator: Communicator<'a, S>,
+    ) -> PopResult<()> {
+        // `handle` only borrows the authenticator, the exchange itself runs on a copy.
+        let mut client = self.client.clone();
+
+        let server_first: Vec<u8> = communicator.next_challenge().await?.as_ref().to_vec();
+
+        // Salting the password can take a while, so it should not hold up other tasks.
+        let (mut client, result) = thread::spawn_blocking(move || {
+            let result = client.client_final(&server_first);
+
+            (client, result)
+        })
+        .await;
+
+        let client_final = match result {
+            Ok(client_final) => client_final,
+            Err(error) => {
+                communicator.stop().await?;
+
+                return Err(pop_error(error));
+            }
+        };
+
+        communicator.send(client_final).await?;
+
+        let server_final = communicator.next_challenge().await?;
+
+        if let Err(error) = client.verify(server_final.as_ref()) {
+            communicator.stop().await?;
+
+            return Err(pop_error(error));
+        }
+
+        communicator.send("").await
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The rights a user or group has on a shared mailbox, as defined by the IMAP ACL extension (RFC 4314).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AclEntry {
+    identifier: String,
+    rights: String,
+}
+
+impl AclEntry {
+    pub fn new<I: Into<String>, R: Into<String>>(identifier: I, rights: R) -> Self {
+        Self {
+            identifier: identifier.into(),
+            rights: rights.into(),
+        }
+    }
+
+    /// The user or group the rights are granted to, like `anyone` or `john@example.com`.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// The rights as a string of single letter codes, e.g. `lrs` to allow listing, reading and marking messages as seen.
+    pub fn rights(&self) -> &str {
+        &self.rights
+    }
+
+    /// Whether the given right (like `r` for reading or `a` for administering) is granted.
+    pub fn has_right(&self, right: char) -> bool {
+        self.rights.contains(right)
+    }
+}
@@ -0,0 +1,44 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::mailbox::MailboxStats;
+
+/// What changed in a watched mailbox.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MailboxEventKind {
+    /// The mailbox now holds this many messages, which usually means new messages arrived.
+    Exists(usize),
+    /// The message with this sequence number was removed, the numbers of the messages after it shift down by one.
+    Expunge(usize),
+    /// The flags of the message with this sequence number changed.
+    FlagChange(usize),
+    /// The new counts of a mailbox, which is how changes to mailboxes that are not selected are reported.
+    Stats(MailboxStats),
+}
+
+/// A change the server reported in one of the mailboxes that are being watched.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MailboxEvent {
+    box_id: String,
+    kind: MailboxEventKind,
+}
+
+impl MailboxEvent {
+    pub fn new<B: Into<String>>(box_id: B, kind: MailboxEventKind) -> Self {
+        Self {
+            box_id: box_id.into(),
+            kind,
+        }
+    }
+
+    /// The id of the mailbox the change happened in.
+    pub fn box_id(&self) -> &str {
+        &self.box_id
+    }
+
+    pub fn kind(&self) -> &MailboxEventKind {
+        &self.kind
+    }
+}
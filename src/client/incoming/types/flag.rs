@@ -1,7 +1,7 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Flag {
     HasAttachment,
@@ -13,6 +13,30 @@ pub enum Flag {
     Custom(Option<String>),
 }
 
+/// How a set of flags should be applied to a message.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FlagMode {
+    /// Add the flags to the flags the message already has.
+    Add,
+    /// Remove the flags from the message.
+    Remove,
+    /// Replace all of the flags on the message.
+    Replace,
+}
+
+#[cfg(feature = "imap")]
+impl FlagMode {
+    /// The data item name used in an imap STORE command.
+    pub fn to_imap(&self) -> &'static str {
+        match self {
+            Self::Add => "+FLAGS.SILENT",
+            Self::Remove => "-FLAGS.SILENT",
+            Self::Replace => "FLAGS.SILENT",
+        }
+    }
+}
+
 #[cfg(feature = "imap")]
 use async_imap::types as imap;
 
@@ -29,4 +53,27 @@ impl Flag {
             _ => None,
         }
     }
+
+    /// The representation of this flag in an imap command, if it can be sent to the server.
+    #[cfg(feature = "imap")]
+    pub fn to_imap(&self) -> Option<String> {
+        match self {
+            Self::Read => Some(String::from("\\Seen")),
+            Self::Answered => Some(String::from("\\Answered")),
+            Self::Draft => Some(String::from("\\Draft")),
+            Self::Flagged => Some(String::from("\\Flagged")),
+            Self::Deleted => Some(String::from("\\Deleted")),
+            Self::Custom(Some(keyword)) => Some(keyword.to_string()),
+            Self::Custom(None) | Self::HasAttachment => None,
+        }
+    }
+
+    /// Like [`Flag::from_imap`], but also maps the special `\*` flag (which indicates that custom keywords can be created) to `Flag::Custom(None)`.
+    #[cfg(feature = "imap")]
+    pub fn from_imap_permanent(flag: &imap::Flag<'_>) -> Option<Self> {
+        match flag {
+            imap::Flag::MayCreate => Some(Self::Custom(None)),
+            flag => Self::from_imap(flag),
+        }
+    }
 }
@@ -4,7 +4,56 @@ use std::fmt::Display;
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "imap")]
-use async_imap::types::Mailbox as ImapCounts;
+use async_imap::{
+    imap_proto::StatusAttribute,
+    types::{Mailbox as ImapCounts, NameAttribute},
+};
+
+use crate::tree::{Find, Node};
+
+/// The role of a mailbox, as advertised by the server (RFC 6154).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SpecialUse {
+    /// A virtual mailbox containing all messages.
+    All,
+    Archive,
+    Drafts,
+    /// A virtual mailbox containing all flagged messages.
+    Flagged,
+    Junk,
+    Sent,
+    Trash,
+    /// The mailbox new messages are delivered to. This is not part of RFC 6154, but some servers report it for inboxes with a localized name.
+    Inbox,
+}
+
+#[cfg(feature = "imap")]
+const HAS_CHILDREN_ATTRIBUTE: &str = "\\HasChildren";
+
+#[cfg(feature = "imap")]
+const INBOX_ATTRIBUTE: &str = "\\Inbox";
+
+#[cfg(feature = "imap")]
+impl SpecialUse {
+    fn from_imap(attribute: &NameAttribute<'_>) -> Option<Self> {
+        match attribute {
+            NameAttribute::All => Some(Self::All),
+            NameAttribute::Archive => Some(Self::Archive),
+            NameAttribute::Drafts => Some(Self::Drafts),
+            NameAttribute::Flagged => Some(Self::Flagged),
+            NameAttribute::Junk => Some(Self::Junk),
+            NameAttribute::Sent => Some(Self::Sent),
+            NameAttribute::Trash => Some(Self::Trash),
+            NameAttribute::Extension(extension)
+                if extension.eq_ignore_ascii_case(INBOX_ATTRIBUTE) =>
+            {
+                Some(Self::Inbox)
+            }
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -13,15 +62,30 @@ pub struct Mailbox {
     selectable: bool,
     id: String,
     name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    special_use: Option<SpecialUse>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    has_children: bool,
 }
 
 #[cfg(feature = "imap")]
 impl From<&async_imap::types::Name> for Mailbox {
     fn from(mailbox: &async_imap::types::Name) -> Self {
         // Whether the inbox is selectable
-        let selectable = !mailbox
+        let selectable = !mailbox.attributes().contains(&NameAttribute::NoSelect);
+
+        let special_use = mailbox.attributes().iter().find_map(SpecialUse::from_imap);
+
+        // Only reported by servers that support the CHILDREN extension, or when it is requested using LIST-EXTENDED.
+        let has_children = mailbox
             .attributes()
-            .contains(&async_imap::types::NameAttribute::NoSelect);
+            .iter()
+            .any(|attribute| match attribute {
+                NameAttribute::Extension(extension) => {
+                    extension.eq_ignore_ascii_case(HAS_CHILDREN_ATTRIBUTE)
+                }
+                _ => false,
+            });
 
         // Create an owned string if the delimiter is specified
         let delimiter = mailbox.delimiter().map(|del| del.to_string());
@@ -35,22 +99,26 @@ impl From<&async_imap::types::Name> for Mailbox {
             None => id.to_string(),
         };
 
+        // The id is kept encoded because the server expects that form in commands, but the name is only used for display.
+        let name = crate::client::incoming::imap::utf7::decode(&name).unwrap_or(name);
+
         Self {
             id,
             selectable,
             name,
             stats: None,
+            special_use,
+            has_children,
         }
     }
 }
 
 impl From<MailboxStats> for Mailbox {
     fn from(value: MailboxStats) -> Self {
-        let mut default = Mailbox::default();
-
-        default.stats = Some(value);
-
-        default
+        Mailbox {
+            stats: Some(value),
+            ..Default::default()
+        }
     }
 }
 
@@ -72,9 +140,17 @@ impl Mailbox {
             selectable,
             id: id.into(),
             name: name.into(),
+            special_use: None,
+            has_children: false,
         }
     }
 
+    pub fn with_special_use(mut self, special_use: SpecialUse) -> Self {
+        self.special_use = Some(special_use);
+
+        self
+    }
+
     /// A struct containing some info about the message counts in this mailbox.
     pub fn stats(&self) -> Option<&MailboxStats> {
         self.stats.as_ref()
@@ -98,9 +174,93 @@ impl Mailbox {
     pub fn set_stats(&mut self, stats: MailboxStats) {
         self.stats = Some(stats);
     }
+
+    /// What the mailbox is used for, e.g. storing sent messages, if the server reported it.
+    pub fn special_use(&self) -> Option<&SpecialUse> {
+        self.special_use.as_ref()
+    }
+
+    /// Whether the mailbox contains other mailboxes, so it can be expanded in a folder list.
+    ///
+    /// This is reported by the server if it supports it, otherwise it is inferred from the mailbox tree.
+    pub fn has_children(&self) -> bool {
+        self.has_children
+    }
+
+    #[cfg(feature = "imap")]
+    pub(crate) fn set_has_children(&mut self, has_children: bool) {
+        self.has_children = has_children;
+    }
+}
+
+struct SpecialUseFinder<'a>(&'a SpecialUse);
+
+impl Find<Mailbox> for SpecialUseFinder<'_> {
+    fn find(&self, item: &Mailbox) -> bool {
+        item.special_use() == Some(self.0)
+    }
+}
+
+/// Matches mailboxes on their human readable name instead of their id, ignoring case.
+pub struct NameFinder {
+    name: String,
+    substring: bool,
+}
+
+impl NameFinder {
+    /// Matches mailboxes whose name is equal to the given name.
+    pub fn exact<N: AsRef<str>>(name: N) -> Self {
+        Self {
+            name: name.as_ref().to_lowercase(),
+            substring: false,
+        }
+    }
+
+    /// Matches mailboxes whose name contains the given name, e.g. for a folder search box.
+    pub fn containing<N: AsRef<str>>(name: N) -> Self {
+        Self {
+            name: name.as_ref().to_lowercase(),
+            substring: true,
+        }
+    }
 }
 
-const DEFAULT_MAILBOX_ID: &str = "default_inbox";
+impl Find<Mailbox> for NameFinder {
+    fn find(&self, item: &Mailbox) -> bool {
+        let name = item.name().to_lowercase();
+
+        if self.substring {
+            name.contains(&self.name)
+        } else {
+            name == self.name
+        }
+    }
+}
+
+impl Node<Mailbox> {
+    /// Finds the mailbox in this tree that is used for the given role, e.g. the mailbox sent messages should be saved to.
+    pub fn find_special_use(&self, special_use: &SpecialUse) -> Option<&Mailbox> {
+        self.find(&SpecialUseFinder(special_use))
+            .and_then(|node| node.data())
+    }
+
+    /// Finds the first mailbox in this tree with the given name, ignoring case. If `substring` is set, the name only has to contain the given name.
+    pub fn find_by_name<N: AsRef<str>>(&self, name: N, substring: bool) -> Option<&Mailbox> {
+        let finder = if substring {
+            NameFinder::containing(name)
+        } else {
+            NameFinder::exact(name)
+        };
+
+        self.find(&finder).and_then(|node| node.data())
+    }
+}
+
+/// The id of the mailbox that is used for protocols that do not support multiple mailboxes, like Pop.
+pub(crate) const DEFAULT_MAILBOX_ID: &str = "default_inbox";
+
+/// The id of the inbox, which every Imap server is required to have.
+pub(crate) const INBOX_ID: &str = "INBOX";
 const DEFAULT_MAILBOX_NAME: &str = "Inbox";
 
 impl Default for Mailbox {
@@ -110,6 +270,8 @@ impl Default for Mailbox {
             id: String::from(DEFAULT_MAILBOX_ID),
             name: String::from(DEFAULT_MAILBOX_NAME),
             selectable: true,
+            special_use: None,
+            has_children: false,
         }
     }
 }
@@ -120,6 +282,10 @@ impl Default for Mailbox {
 pub struct MailboxStats {
     unseen: usize,
     total: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    highest_modseq: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    uid_validity: Option<u32>,
 }
 
 impl Display for MailboxStats {
@@ -134,7 +300,24 @@ impl Display for MailboxStats {
 
 impl MailboxStats {
     pub fn new(unseen: usize, total: usize) -> Self {
-        MailboxStats { unseen, total }
+        MailboxStats {
+            unseen,
+            total,
+            highest_modseq: None,
+            uid_validity: None,
+        }
+    }
+
+    pub fn with_highest_modseq(mut self, highest_modseq: Option<u64>) -> Self {
+        self.highest_modseq = highest_modseq;
+
+        self
+    }
+
+    pub fn with_uid_validity(mut self, uid_validity: Option<u32>) -> Self {
+        self.uid_validity = uid_validity;
+
+        self
     }
 
     /// The total amount of message that have not been read in this mailbox
@@ -146,6 +329,20 @@ impl MailboxStats {
     pub fn total(&self) -> usize {
         self.total
     }
+
+    /// The highest mod-sequence of the messages in this mailbox, only known if the server supports CONDSTORE.
+    ///
+    /// Store this value and pass it to `get_changes_since` to only fetch the messages that changed since.
+    pub fn highest_modseq(&self) -> Option<u64> {
+        self.highest_modseq
+    }
+
+    /// Identifies the uids of the messages in this mailbox, only known for Imap mailboxes.
+    ///
+    /// When it changes, the server has renumbered the messages, so any stored uid (like the one passed to `get_messages_since_uid`) is no longer valid and the mailbox has to be synced from scratch.
+    pub fn uid_validity(&self) -> Option<u32> {
+        self.uid_validity
+    }
 }
 
 #[cfg(feature = "imap")]
@@ -155,5 +352,95 @@ impl From<ImapCounts> for MailboxStats {
             imap_counts.unseen.unwrap_or(0) as usize,
             imap_counts.exists as usize,
         )
+        .with_highest_modseq(imap_counts.highest_modseq)
+        .with_uid_validity(imap_counts.uid_validity)
+    }
+}
+
+#[cfg(feature = "imap")]
+impl From<&[StatusAttribute]> for MailboxStats {
+    fn from(attributes: &[StatusAttribute]) -> Self {
+        let mut stats = Self::default();
+
+        for attribute in attributes {
+            match attribute {
+                StatusAttribute::Messages(total) => stats.total = *total as usize,
+                StatusAttribute::Unseen(unseen) => stats.unseen = *unseen as usize,
+                StatusAttribute::HighestModSeq(modseq) => stats.highest_modseq = Some(*modseq),
+                StatusAttribute::UidValidity(uid_validity) => {
+                    stats.uid_validity = Some(*uid_validity)
+                }
+                _ => {}
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_special_use() {
+        let sent = Mailbox::new(None, true, "Sent", "Sent").with_special_use(SpecialUse::Sent);
+        let trash = Mailbox::new(None, true, "Papierkorb", "Papierkorb")
+            .with_special_use(SpecialUse::Trash);
+
+        let tree = Node::Root(vec![
+            Node::leaf(Mailbox::new(None, true, "INBOX", "INBOX")),
+            Node::branch(
+                Mailbox::new(None, false, "Other", "Other"),
+                vec![Node::leaf(trash), Node::leaf(sent)],
+            ),
+        ]);
+
+        assert_eq!(
+            tree.find_special_use(&SpecialUse::Trash).map(Mailbox::id),
+            Some("Papierkorb")
+        );
+        assert_eq!(
+            tree.find_special_use(&SpecialUse::Sent).map(Mailbox::id),
+            Some("Sent")
+        );
+        assert!(tree.find_special_use(&SpecialUse::Junk).is_none());
+    }
+
+    #[test]
+    fn find_by_name() {
+        let tree = Node::Root(vec![
+            Node::leaf(Mailbox::new(None, true, "INBOX", "Inbox")),
+            Node::branch(
+                Mailbox::new(None, false, "[Gmail]", "[Gmail]"),
+                vec![
+                    Node::leaf(Mailbox::new(None, true, "[Gmail]/Sent Mail", "Sent Mail")),
+                    Node::branch(
+                        Mailbox::new(None, true, "[Gmail]/Archive", "Archive"),
+                        vec![Node::leaf(Mailbox::new(
+                            None,
+                            true,
+                            "[Gmail]/Archive/2023",
+                            "2023",
+                        ))],
+                    ),
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            tree.find_by_name("inbox", false).map(Mailbox::id),
+            Some("INBOX")
+        );
+        assert_eq!(
+            tree.find_by_name("SENT", true).map(Mailbox::id),
+            Some("[Gmail]/Sent Mail")
+        );
+        assert_eq!(
+            tree.find_by_name("2023", false).map(Mailbox::id),
+            Some("[Gmail]/Archive/2023")
+        );
+        assert!(tree.find_by_name("sent", false).is_none());
+        assert!(tree.find_by_name("Drafts", true).is_none());
     }
 }
@@ -1,14 +1,15 @@
-use std::{collections::HashMap, result};
+use std::result;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::{
     client::{
-        address::Address, attachment::Attachment, builder::MessageBuilder, content::Content,
-        Headers,
+        address::Address, attachment::Attachment, auth_results::AuthResults,
+        builder::MessageBuilder, content::Content, delivery_status::DeliveryStatus, parser,
+        HeaderList, Headers,
     },
-    error::{err, Error, ErrorKind},
+    error::{err, Error, ErrorKind, Result},
 };
 
 use super::flag::Flag;
@@ -16,16 +17,18 @@ use super::flag::Flag;
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Preview {
-    from: Address,
+    from: Vec<Address>,
     flags: Vec<Flag>,
     id: String,
     sent: Option<i64>,
     subject: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    snippet: Option<String>,
 }
 
 impl Preview {
-    /// The sender(s) of the message.
-    pub fn from(&self) -> &Address {
+    /// The sender(s) of the message, a message can have multiple senders or a group as its sender.
+    pub fn from(&self) -> &[Address] {
         &self.from
     }
 
@@ -39,7 +42,7 @@ impl Preview {
         &self.id
     }
 
-    /// Date in milliseconds since epoch
+    /// Date in seconds since epoch
     pub fn sent(&self) -> Option<&i64> {
         self.sent.as_ref()
     }
@@ -52,9 +55,14 @@ impl Preview {
         }
     }
 
+    /// The start of the message text, if the body of the message was (partially) fetched together with its headers.
+    pub fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+
     #[cfg(feature = "json")]
     pub fn to_json(&self) -> Result<String> {
-        parse::json::to_json(self)
+        parser::json::to_json(self)
     }
 }
 
@@ -68,13 +76,20 @@ impl TryFrom<MessageBuilder> for Preview {
         };
 
         let from = match builder.from {
-            Some(from) => from,
+            // Multiple senders are collected in a group without a name, which is split up again so the list matches the header.
+            Some(Address::Group { name: None, list }) => list,
+            Some(from) => vec![from],
             None => err!(ErrorKind::InvalidMessage, "Message is missing sender"),
         };
 
         let mut flags = builder.flags;
 
-        if !builder.attachments.is_empty() {
+        // Inline parts are shown in the body, so they do not count as attachments here.
+        if builder
+            .attachments
+            .iter()
+            .any(|attachment| !attachment.is_inline())
+        {
             flags.push(Flag::HasAttachment);
         }
 
@@ -84,26 +99,48 @@ impl TryFrom<MessageBuilder> for Preview {
             id,
             sent: builder.sent,
             subject: builder.subject,
+            snippet: builder.content.snippet(),
         };
 
         Ok(preview)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Message {
     from: Address,
+    sender: Option<Address>,
+    return_path: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    read_receipt_to: Option<Address>,
     to: Address,
     cc: Option<Address>,
     bcc: Option<Address>,
     headers: Headers,
+    #[cfg_attr(feature = "serde", serde(default))]
+    raw_headers: HeaderList,
     flags: Vec<Flag>,
     id: String,
     sent: Option<i64>,
     subject: Option<String>,
     attachments: Vec<Attachment>,
     content: Content,
+    delivery_status: Option<DeliveryStatus>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    auth_results: Option<AuthResults>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    languages: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    thread_index: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    thread_topic: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    gmail_thread_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    signed: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    encrypted: bool,
 }
 
 impl TryFrom<MessageBuilder> for Message {
@@ -129,6 +166,9 @@ impl TryFrom<MessageBuilder> for Message {
             flags: builder.flags,
             to,
             from,
+            sender: builder.sender,
+            return_path: builder.return_path,
+            read_receipt_to: builder.read_receipt_to,
             bcc: builder.bcc,
             cc: builder.cc,
             id,
@@ -136,7 +176,16 @@ impl TryFrom<MessageBuilder> for Message {
             subject: builder.subject,
             content: builder.content,
             attachments: builder.attachments,
-            headers: builder.headers.unwrap_or(HashMap::new()),
+            headers: builder.headers.unwrap_or_default(),
+            raw_headers: builder.raw_headers,
+            delivery_status: builder.delivery_status,
+            auth_results: builder.auth_results,
+            languages: builder.languages,
+            thread_index: builder.thread_index,
+            thread_topic: builder.thread_topic,
+            gmail_thread_id: builder.gmail_thread_id,
+            signed: builder.signed,
+            encrypted: builder.encrypted,
         };
 
         Ok(message)
@@ -144,11 +193,33 @@ impl TryFrom<MessageBuilder> for Message {
 }
 
 impl Message {
+    /// Parses a standalone message, like an `.eml` file, without any mail server.
+    ///
+    /// The message is parsed the same way the mail servers' messages are, including its attachments, which get Imap style part numbers as ids. The id of the message itself is taken from its Message-ID header, or left empty if it has none.
+    pub fn parse_eml<B: AsRef<[u8]>>(bytes: B) -> Result<Message> {
+        let builder = parser::message::from_rfc822(bytes)?;
+
+        let id = builder
+            .raw_headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Message-ID"))
+            .map(|(_, value)| value.trim().trim_start_matches('<').trim_end_matches('>'))
+            .unwrap_or_default()
+            .to_string();
+
+        builder.id(id).build()
+    }
+
     /// The message's RFC 822 headers.
     pub fn headers(&self) -> &Headers {
         &self.headers
     }
 
+    /// The message's headers in the order they appear in, including duplicate headers, which `headers` collapses into a single value.
+    pub fn raw_headers(&self) -> &[(String, String)] {
+        &self.raw_headers
+    }
+
     /// The messages flags that indicate whether the message has been read, deleted, etc.
     pub fn flags(&self) -> &Vec<Flag> {
         &self.flags
@@ -159,7 +230,7 @@ impl Message {
         &self.id
     }
 
-    /// Date in milliseconds since epoch
+    /// Date in seconds since epoch
     pub fn sent(&self) -> Option<&i64> {
         self.sent.as_ref()
     }
@@ -179,7 +250,7 @@ impl Message {
 
     #[cfg(feature = "json")]
     pub fn to_json(&self) -> Result<String> {
-        parse::json::to_json(self)
+        parser::json::to_json(self)
     }
 
     pub fn from(&self) -> &Address {
@@ -189,4 +260,102 @@ impl Message {
     pub fn to(&self) -> &Address {
         &self.to
     }
+
+    pub fn cc(&self) -> Option<&Address> {
+        self.cc.as_ref()
+    }
+
+    pub fn bcc(&self) -> Option<&Address> {
+        self.bcc.as_ref()
+    }
+
+    /// The mailbox that actually sent the message on behalf of the author(s), if it was specified.
+    pub fn sender(&self) -> Option<&Address> {
+        self.sender.as_ref()
+    }
+
+    /// The address that bounces are sent to, as added by the receiving mail server.
+    pub fn return_path(&self) -> Option<&str> {
+        self.return_path.as_deref()
+    }
+
+    /// Where the sender wants a read receipt to be sent, if they requested one. See `MessageBuilder::build_read_receipt` to create it.
+    pub fn read_receipt_to(&self) -> Option<&Address> {
+        self.read_receipt_to.as_ref()
+    }
+
+    /// If this message is a bounce, the report on why the original message could not be delivered.
+    pub fn delivery_status(&self) -> Option<&DeliveryStatus> {
+        self.delivery_status.as_ref()
+    }
+
+    /// The SPF, DKIM and DMARC results reported by the receiving mail server, if it added any.
+    pub fn auth_results(&self) -> Option<&AuthResults> {
+        self.auth_results.as_ref()
+    }
+
+    /// The languages of the intended audience of the message, from the `Content-Language` header.
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    /// Whether the message should be rendered from right to left, e.g. by setting `dir="rtl"` on its html.
+    ///
+    /// This is based on the `Content-Language` header. With the `detect-direction` feature, messages without that header are checked for right to left scripts.
+    pub fn is_rtl(&self) -> bool {
+        if let Some(language) = self.languages.first() {
+            return parser::language::is_rtl_language(language);
+        }
+
+        #[cfg(feature = "detect-direction")]
+        if let Some(text) = self.content.text() {
+            return parser::language::is_rtl_text(text);
+        }
+
+        false
+    }
+
+    /// The `Thread-Index` header Outlook and Exchange use to group messages into conversations.
+    pub fn thread_index(&self) -> Option<&str> {
+        self.thread_index.as_deref()
+    }
+
+    /// The `Thread-Topic` header, which is the subject of the conversation without any `Re:` or `Fwd:` prefixes.
+    pub fn thread_topic(&self) -> Option<&str> {
+        self.thread_topic.as_deref()
+    }
+
+    /// The conversation id Gmail assigned to the message (`X-GM-THRID`).
+    pub fn gmail_thread_id(&self) -> Option<&str> {
+        self.gmail_thread_id.as_deref()
+    }
+
+    /// An id that is shared by every message in the same conversation, derived from the provider specific thread headers.
+    ///
+    /// Use this as a fallback when a message is missing the standard `References` and `In-Reply-To` headers.
+    pub fn conversation_id(&self) -> Option<String> {
+        if let Some(thread_id) = &self.gmail_thread_id {
+            return Some(format!("gmail:{}", thread_id));
+        }
+
+        self.thread_index
+            .as_ref()
+            .and_then(parser::thread::thread_index_root)
+            .map(|root| format!("outlook:{}", root))
+    }
+
+    /// Whether the message is signed using S/MIME or PGP. The signature itself is not verified.
+    pub fn is_signed(&self) -> bool {
+        self.signed
+    }
+
+    /// Whether the message is encrypted using S/MIME or PGP, in which case the content can not be read without decrypting it first.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The files in the message, including inline parts like images that are shown in the body.
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
 }
@@ -1,3 +1,6 @@
+pub mod acl;
+pub mod event;
 pub mod flag;
 pub mod mailbox;
 pub mod message;
+pub mod sort;
@@ -0,0 +1,65 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The order in which messages in a mailbox are paginated.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SortOrder {
+    /// The most recently received message comes first.
+    #[default]
+    NewestFirst,
+    /// The oldest message comes first.
+    OldestFirst,
+}
+
+impl SortOrder {
+    /// The inclusive range of message sequence numbers (starting at 1) that make up the page `start..end` in a mailbox with `total` messages.
+    ///
+    /// Returns `None` if the page does not contain any messages.
+    #[cfg(any(feature = "imap", feature = "pop", test))]
+    pub(crate) fn sequence_range(
+        &self,
+        total: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let end = end.min(total);
+
+        if start >= end {
+            return None;
+        }
+
+        match self {
+            Self::NewestFirst => Some((total - end + 1, total - start)),
+            Self::OldestFirst => Some((start + 1, end)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SortOrder;
+
+    #[test]
+    fn sequence_range() {
+        assert_eq!(
+            SortOrder::NewestFirst.sequence_range(100, 0, 10),
+            Some((91, 100))
+        );
+        assert_eq!(
+            SortOrder::NewestFirst.sequence_range(100, 95, 110),
+            Some((1, 5))
+        );
+        assert_eq!(
+            SortOrder::OldestFirst.sequence_range(100, 0, 10),
+            Some((1, 10))
+        );
+        assert_eq!(
+            SortOrder::OldestFirst.sequence_range(100, 95, 110),
+            Some((96, 100))
+        );
+
+        assert_eq!(SortOrder::NewestFirst.sequence_range(100, 100, 110), None);
+        assert_eq!(SortOrder::OldestFirst.sequence_range(0, 0, 10), None);
+    }
+}
@@ -10,6 +10,8 @@ use log::{info, trace, warn};
 
 use super::EmailClient;
 
+const LOG_TARGET: &str = "dust_mail::keep_alive";
+
 pub struct KeepAlive {
     client: Arc<RwLock<EmailClient>>,
     handle: Option<JoinHandle<()>>,
@@ -52,15 +54,19 @@ impl KeepAlive {
 
                 let read_lock = client.read().await;
 
-                trace!("Checking if keep alive request is needed");
+                trace!(target: LOG_TARGET, "Checking if keep alive request is needed");
 
                 if read_lock.should_keep_alive() {
                     let mut write_lock = client.write().await;
 
-                    info!("Sending keep alive request to mail server");
+                    info!(target: LOG_TARGET, "Sending keep alive request to mail server");
 
                     if let Err(err) = write_lock.send_keep_alive().await {
-                        warn!("Failed to send keep alive request to mail server: {}", err)
+                        warn!(
+                            target: LOG_TARGET,
+                            "Failed to send keep alive request to mail server: {}",
+                            err
+                        )
                     }
                 }
             }
@@ -71,7 +77,7 @@ impl KeepAlive {
 
     pub fn stop(&mut self) {
         if let Some(_handle) = &self.handle {
-            info!("Stopping keep alive requests");
+            info!(target: LOG_TARGET, "Stopping keep alive requests");
 
             #[cfg(feature = "runtime-tokio")]
             _handle.abort();
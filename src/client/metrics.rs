@@ -0,0 +1,279 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+use std::{
+    fmt::{self, Debug},
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+use crate::runtime::io::{Read, Write};
+
+/// How much data a session exchanged with the mail server, e.g. to show users their data usage or to find out why a sync is slow.
+///
+/// Bytes are counted as they are sent over the connection, so when the connection is compressed these are the compressed sizes. The overhead of Tls is not included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SessionMetrics {
+    bytes_in: u64,
+    bytes_out: u64,
+    commands: u64,
+}
+
+impl SessionMetrics {
+    /// The amount of bytes received from the server.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in
+    }
+
+    /// The amount of bytes sent to the server.
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out
+    }
+
+    /// The amount of commands sent to the server.
+    ///
+    /// A command is counted every time the client flushes what it wrote, so a command that waits for the server halfway through (like an Imap APPEND) counts more than once.
+    pub fn commands(&self) -> u64 {
+        self.commands
+    }
+}
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+#[derive(Debug, Default)]
+struct Counters {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    commands: AtomicU64,
+    /// Whether data was written since the last flush.
+    pending: AtomicBool,
+}
+
+/// Reads the counters of a `CountingStream`, which is owned by the protocol client.
+#[cfg(any(feature = "imap", feature = "pop"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MetricsHandle(Arc<Counters>);
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+impl MetricsHandle {
+    pub fn metrics(&self) -> SessionMetrics {
+        SessionMetrics {
+            bytes_in: self.0.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.0.bytes_out.load(Ordering::Relaxed),
+            commands: self.0.commands.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A stream that counts the data that is read from and written to it.
+#[cfg(any(feature = "imap", feature = "pop"))]
+pub(crate) struct CountingStream<S> {
+    inner: S,
+    counters: MetricsHandle,
+}
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+impl<S> CountingStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            counters: MetricsHandle::default(),
+        }
+    }
+
+    pub fn handle(&self) -> MetricsHandle {
+        self.counters.clone()
+    }
+
+    fn count_in(&self, bytes: usize) {
+        self.counters
+            .0
+            .bytes_in
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn count_out(&self, bytes: usize) {
+        if bytes > 0 {
+            let counters = &self.counters.0;
+
+            counters
+                .bytes_out
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+            counters.pending.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn count_flush(&self) {
+        let counters = &self.counters.0;
+
+        if counters.pending.swap(false, Ordering::Relaxed) {
+            counters.commands.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(any(feature = "imap", feature = "pop"))]
+impl<S: Debug> Debug for CountingStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountingStream")
+            .field("inner", &self.inner)
+            .field("metrics", &self.counters.metrics())
+            .finish()
+    }
+}
+
+#[cfg(all(feature = "runtime-tokio", any(feature = "imap", feature = "pop")))]
+impl<S: Read + Unpin> Read for CountingStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        let before = buf.filled().len();
+
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        this.count_in(buf.filled().len() - before);
+
+        result
+    }
+}
+
+#[cfg(all(feature = "runtime-async-std", any(feature = "imap", feature = "pop")))]
+impl<S: Read + Unpin> Read for CountingStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(read)) = result {
+            this.count_in(read);
+        }
+
+        result
+    }
+}
+
+#[cfg(all(feature = "runtime-tokio", any(feature = "imap", feature = "pop")))]
+impl<S: Write + Unpin> Write for CountingStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = result {
+            this.count_out(written);
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        let result = Pin::new(&mut this.inner).poll_flush(cx);
+
+        if let Poll::Ready(Ok(())) = result {
+            this.count_flush();
+        }
+
+        result
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(feature = "runtime-async-std", any(feature = "imap", feature = "pop")))]
+impl<S: Write + Unpin> Write for CountingStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = result {
+            this.count_out(written);
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        let result = Pin::new(&mut this.inner).poll_flush(cx);
+
+        if let Poll::Ready(Ok(())) = result {
+            this.count_flush();
+        }
+
+        result
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(all(test, any(feature = "imap", feature = "pop")))]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "runtime-async-std")]
+    use futures::io::Cursor;
+    #[cfg(feature = "runtime-tokio")]
+    use std::io::Cursor;
+
+    use crate::runtime::io::{ReadExt, WriteExt};
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn count_traffic() {
+        let response = b"a1 OK NOOP completed\r\n";
+
+        let mut stream = CountingStream::new(Cursor::new(response.to_vec()));
+
+        let handle = stream.handle();
+
+        let mut buf = vec![0; response.len()];
+
+        stream.read_exact(&mut buf).await.unwrap();
+
+        stream.write_all(b"a1 NOOP\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+
+        // Nothing was written since the last flush, so this is not a command.
+        stream.flush().await.unwrap();
+
+        stream.write_all(b"a2 NOOP\r\n").await.unwrap();
+        stream.flush().await.unwrap();
+
+        let metrics = handle.metrics();
+
+        assert_eq!(metrics.bytes_in(), response.len() as u64);
+        assert_eq!(metrics.bytes_out(), 18);
+        assert_eq!(metrics.commands(), 2);
+    }
+}
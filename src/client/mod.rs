@@ -1,8 +1,16 @@
 use std::{collections::HashMap, fmt::Display, sync::Arc};
 
+use futures::{stream, Stream, StreamExt};
+use log::{debug, warn};
+use mime::Mime;
+
 use crate::{
-    error::{Error, ErrorKind},
-    runtime::thread::RwLock,
+    cache::LruCache,
+    error::{err, Error, ErrorKind},
+    runtime::{
+        io::{Read, Write, WriteExt},
+        thread::{spawn, RwLock},
+    },
     tree::Node,
 };
 
@@ -19,19 +27,46 @@ use self::incoming::maildir;
 use self::outgoing::smtp;
 
 use self::{
+    auth::AuthMechanism,
+    calendar::CalendarInvite,
+    connection::{ConnectionState, SharedConnectionState},
+    id::{MailboxId, MessageId},
     incoming::types::{
-        mailbox::Mailbox,
+        acl::AclEntry,
+        event::MailboxEvent,
+        flag::{Flag, FlagMode},
+        mailbox::{Mailbox, SpecialUse, INBOX_ID},
         message::{Message, Preview},
+        sort::SortOrder,
     },
-    outgoing::types::sendable::SendableMessage,
+    metrics::SessionMetrics,
+    outgoing::types::{capabilities::SmtpCapabilities, sendable::SendableMessage},
     protocol::{IncomingProtocol, OutgoingProtocol},
+    summary::AccountSummary,
 };
 
 pub use self::{
+    address::{Address, EmailAddress},
     keep_alive::KeepAlive,
-    protocol::{Credentials, IncomingEmailProtocol, OutgoingEmailProtocol, ServerCredentials},
+    parser::{SanitizePolicy, SanitizedHtml},
+    protocol::{
+        default_port, Credentials, IncomingConfig, IncomingEmailProtocol, OutgoingEmailProtocol,
+        RemoteServer, ServerConfigType, ServerCredentials,
+    },
 };
 
+#[cfg(feature = "imap")]
+pub use self::protocol::ImapCredentials;
+
+#[cfg(feature = "pop")]
+pub use self::protocol::PopCredentials;
+
+#[cfg(feature = "smtp")]
+pub use self::protocol::SmtpCredentials;
+
+#[cfg(feature = "sieve")]
+pub use self::protocol::SieveCredentials;
+
 use crate::error::Result;
 
 mod incoming;
@@ -42,21 +77,64 @@ pub use outgoing::types::*;
 
 pub mod address;
 pub mod attachment;
+pub mod auth;
+pub mod auth_results;
 pub mod builder;
+pub mod calendar;
 pub mod connection;
 pub mod content;
+pub mod delivery_status;
+pub mod id;
+pub mod metrics;
+pub mod proxy;
+pub mod summary;
 
-mod parser;
+#[cfg(feature = "sieve")]
+pub mod sieve;
+
+mod export;
+pub(crate) mod parser;
 
 mod protocol;
 
 mod keep_alive;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 pub type Headers = HashMap<String, String>;
 
+/// The headers of a message in the order they appear in, including duplicate headers like `Received`.
+pub type HeaderList = Vec<(String, String)>;
+
+const LOG_TARGET: &str = "dust_mail::client";
+
+/// The amount of messages in a page, as used by `EmailClient::get_messages_page`.
+pub const DEFAULT_PAGE_SIZE: usize = 25;
+
+/// The default maximum amount of messages that can be requested at once, see `EmailClient::set_max_page_size`.
+pub const DEFAULT_MAX_PAGE_SIZE: usize = 500;
+
+/// The default amount of messages (and attachments) that are kept in memory after they are fetched, see `EmailClient::set_message_cache_size`.
+pub const DEFAULT_MESSAGE_CACHE_SIZE: usize = 20;
+
+/// Attachments larger than this are never cached, to keep the memory usage of the cache predictable.
+const MAX_CACHED_ATTACHMENT_SIZE: usize = 1024 * 1024;
+
+type MessageKey = (MailboxId, MessageId);
+
 pub struct EmailClient {
     incoming: Box<dyn IncomingProtocol + Sync + Send>,
     outgoing: Box<dyn OutgoingProtocol + Sync + Send>,
+    save_to_sent: Option<String>,
+    max_page_size: usize,
+    state: SharedConnectionState,
+    /// Whether a request to the incoming mail server was started but has not finished yet.
+    request_in_progress: bool,
+    /// Whether the server may still be reporting changes for a `watch_all` stream.
+    watching: bool,
+    message_cache: LruCache<MessageKey, Message>,
+    attachment_cache: LruCache<(MessageKey, String), Vec<u8>>,
 }
 
 impl EmailClient {
@@ -64,11 +142,135 @@ impl EmailClient {
         incoming: Box<dyn IncomingProtocol + Sync + Send>,
         outgoing: Box<dyn OutgoingProtocol + Sync + Send>,
     ) -> Self {
-        Self { incoming, outgoing }
+        Self {
+            incoming,
+            outgoing,
+            save_to_sent: None,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            state: SharedConnectionState::new(ConnectionState::Connected),
+            request_in_progress: false,
+            watching: false,
+            message_cache: LruCache::new(DEFAULT_MESSAGE_CACHE_SIZE),
+            attachment_cache: LruCache::new(DEFAULT_MESSAGE_CACHE_SIZE),
+        }
+    }
+
+    /// The state of the connection with the incoming mail server.
+    ///
+    /// Any request can be cancelled by dropping its future, but this invalidates the connection: the next request fails with `ErrorKind::Cancelled`, the state changes to `ConnectionState::Error` and a new client has to be created.
+    pub fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// A handle to the connection state, which can be read while a request holds the client (e.g. from a UI thread while a `ThreadableEmailClient` is busy).
+    pub fn shared_state(&self) -> SharedConnectionState {
+        self.state.clone()
+    }
+
+    /// Marks the start of a request to the incoming mail server, stopping the watch of a dropped `watch_all` stream first.
+    async fn start_request(&mut self) -> Result<()> {
+        self.claim_connection()?;
+
+        if self.watching {
+            self.watching = false;
+
+            let result = self.incoming.stop_watching().await;
+
+            if result.is_err() {
+                return self.track(result).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks the connection as in use by a request.
+    ///
+    /// Requests are cancelled by dropping their future (e.g. when a timeout expires or the user aborts a download). If that happens halfway through a command, the server may still send its response and the connection ends up in an unknown state. We detect this here, because the previous request never reached `track`, and refuse to use the connection again.
+    fn claim_connection(&mut self) -> Result<()> {
+        if self.request_in_progress {
+            let message = "A previous request was cancelled before it finished, the connection has to be reopened";
+
+            self.state.set(ConnectionState::Error(message.to_string()));
+
+            err!(ErrorKind::Cancelled, "{}", message)
+        }
+
+        self.request_in_progress = true;
+
+        Ok(())
+    }
+
+    /// Updates the connection state using the result of a request to the incoming mail server.
+    async fn track<T>(&mut self, result: Result<T>) -> Result<T> {
+        // The server is still there to ask what went wrong, unless the connection itself failed.
+        let result = match result {
+            Err(err) if !err.is_connection_error() => Err(self.incoming.check_error(err).await),
+            result => result,
+        };
+
+        finish_request(
+            &self.state,
+            &mut self.request_in_progress,
+            result.as_ref().err(),
+        );
+
+        result
+    }
+
+    /// Store a copy of every message that is sent in the given mailbox.
+    ///
+    /// Only enable this if the mail server does not do this by itself, otherwise the message will be saved twice.
+    ///
+    /// If the message is sent but the copy can not be saved, sending fails with `ErrorKind::SentCopyNotSaved`.
+    pub fn save_to_sent(&mut self, box_id: Option<MailboxId>) {
+        self.save_to_sent = box_id.map(|box_id| box_id.to_string());
+    }
+
+    /// The maximum amount of messages that `get_messages` fetches at once, larger windows are shortened to this size.
+    ///
+    /// This protects the mail server (and the memory of the application) from accidentally fetching an entire mailbox. A size of 0 would make every page empty, so it is raised to 1.
+    pub fn set_max_page_size(&mut self, max_page_size: usize) {
+        self.max_page_size = max_page_size.max(1);
+    }
+
+    /// The amount of messages, and separately the amount of attachments, that are kept in memory so opening them again does not require a request to the mail server.
+    ///
+    /// Cached messages are dropped when their flags change or their mailbox is renamed or deleted. Set this to 0 to disable the cache.
+    pub fn set_message_cache_size(&mut self, size: usize) {
+        self.message_cache = LruCache::new(size);
+        self.attachment_cache = LruCache::new(size);
+    }
+
+    /// Drops the cached messages and attachments of a mailbox, or only of the given messages in it.
+    fn invalidate_cache(&mut self, box_id: &MailboxId, message_ids: Option<&[MessageId]>) {
+        let matches = |(cached_box, cached_message): &MessageKey| {
+            cached_box == box_id
+                && message_ids.map_or(true, |message_ids| message_ids.contains(cached_message))
+        };
+
+        self.message_cache.retain(|key, _| !matches(key));
+        self.attachment_cache.retain(|(key, _), _| !matches(key));
+    }
+
+    /// Drops the cached messages and attachments of a mailbox and every mailbox inside it, whose ids change when it is renamed or deleted.
+    ///
+    /// The delimiter is not known here, so any mailbox whose id starts with the id of the given one is dropped. This can drop a few unrelated mailboxes (like `Workshop` for `Work`), which only costs a refetch.
+    fn invalidate_subtree(&mut self, box_id: &MailboxId) {
+        let prefix = box_id.as_ref();
+
+        let matches = |(cached_box, _): &MessageKey| cached_box.as_ref().starts_with(prefix);
+
+        self.message_cache.retain(|key, _| !matches(key));
+        self.attachment_cache.retain(|(key, _), _| !matches(key));
     }
 
     pub async fn send_keep_alive(&mut self) -> Result<()> {
-        self.incoming.send_keep_alive().await
+        self.start_request().await?;
+
+        let result = self.incoming.send_keep_alive().await;
+
+        self.track(result).await
     }
 
     pub fn should_keep_alive(&self) -> bool {
@@ -76,93 +278,788 @@ impl EmailClient {
     }
 
     pub async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
-        self.incoming.get_mailbox_list().await
+        self.start_request().await?;
+
+        let result = self.incoming.get_mailbox_list().await;
+
+        self.track(result).await
     }
 
-    pub async fn get_mailbox<BoxId: AsRef<str>>(
+    /// Lists the mailboxes one at a time as they are received, e.g. to fill a sidebar while a large account is still being listed.
+    ///
+    /// The mailboxes come as a flat list in the order the server sends them, use `get_mailbox_list` to get them as a tree. The stream ends after the first error. Like any other request, dropping the stream before it ends means the connection has to be reopened.
+    pub async fn get_mailbox_stream(&mut self) -> Result<impl Stream<Item = Result<Mailbox>> + '_> {
+        self.start_request().await?;
+
+        // The stream borrows the session, so it finishes the request itself instead of using `track`.
+        let Self {
+            incoming,
+            state,
+            request_in_progress,
+            ..
+        } = self;
+
+        let mailboxes = match incoming.get_mailbox_stream().await {
+            Ok(mailboxes) => mailboxes,
+            Err(err) => {
+                finish_request(state, request_in_progress, Some(&err));
+
+                return Err(err);
+            }
+        };
+
+        let mailboxes = stream::unfold(
+            Some((mailboxes, request_in_progress, &*state)),
+            |tracked| async move {
+                let (mut mailboxes, request_in_progress, state) = tracked?;
+
+                match mailboxes.next().await {
+                    Some(Ok(mailbox)) => {
+                        Some((Ok(mailbox), Some((mailboxes, request_in_progress, state))))
+                    }
+                    Some(Err(err)) => {
+                        finish_request(state, request_in_progress, Some(&err));
+
+                        Some((Err(err), None))
+                    }
+                    None => {
+                        finish_request(state, request_in_progress, None);
+
+                        None
+                    }
+                }
+            },
+        );
+
+        Ok(mailboxes)
+    }
+
+    /// The greeting the incoming mail server sent when we connected, which often names the server software. Useful when debugging connection problems.
+    pub fn server_banner(&self) -> Option<&str> {
+        self.incoming.server_banner()
+    }
+
+    /// How much data was exchanged with the incoming mail server since we connected, e.g. to show users their data usage.
+    pub fn metrics(&self) -> SessionMetrics {
+        self.incoming.metrics()
+    }
+
+    /// The mechanism we logged in to the incoming mail server with, e.g. to check that a password is not sent in plain text.
+    pub fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        self.incoming.auth_mechanism()
+    }
+
+    /// Whether the incoming session is still logged in. A session can be logged out by the server after being idle while the connection stays open, in which case this returns `Ok(false)` and the client should log in again.
+    ///
+    /// An error means the server could not be asked, e.g. because the connection itself is lost and has to be reopened.
+    pub async fn is_authenticated(&mut self) -> Result<bool> {
+        self.start_request().await?;
+
+        let result = self.incoming.is_authenticated().await;
+
+        self.track(result).await
+    }
+
+    /// Gathers the capabilities of the incoming mail server and the amount of mailboxes in the account, e.g. to show right after logging in.
+    pub async fn summarize(&mut self) -> Result<AccountSummary> {
+        self.start_request().await?;
+
+        let capabilities = self.incoming.capabilities().await;
+
+        let capabilities = self.track(capabilities).await?;
+
+        let mailboxes = self.get_mailbox_list().await?;
+
+        Ok(AccountSummary::new(
+            self.incoming.protocol_name(),
+            capabilities,
+            &mailboxes,
+        ))
+    }
+
+    pub async fn get_mailbox(&mut self, mailbox_id: &MailboxId) -> Result<Node<Mailbox>> {
+        self.start_request().await?;
+
+        let result = self.incoming.get_mailbox(mailbox_id.as_ref()).await;
+
+        self.track(result).await
+    }
+
+    /// Get the first mailbox whose human readable name matches the given name, ignoring case. If `substring` is set, the name only has to contain the given name.
+    ///
+    /// Useful to locate mailboxes like the sent folder on servers that do not advertise what their mailboxes are used for.
+    pub async fn get_mailbox_by_name<N: AsRef<str>>(
         &mut self,
-        mailbox_id: BoxId,
-    ) -> Result<Node<Mailbox>> {
-        self.incoming.get_mailbox(mailbox_id.as_ref()).await
+        name: N,
+        substring: bool,
+    ) -> Result<Mailbox> {
+        let list = self.get_mailbox_list().await?;
+
+        match list.find_by_name(name.as_ref(), substring) {
+            Some(mailbox) => Ok(mailbox.clone()),
+            None => err!(
+                ErrorKind::MailBoxNotFound,
+                "Could not find a mailbox named '{}'",
+                name.as_ref()
+            ),
+        }
     }
 
-    pub async fn rename_mailbox<OldName: AsRef<str>, NewName: AsRef<str>>(
+    /// Get the mailbox new messages are delivered to.
+    ///
+    /// The inbox is looked up by its special use first, in case the server gives it a localized name, falling back to the `INBOX` every Imap server has. Pop and maildir only have a single mailbox, which is returned instead.
+    pub async fn get_inbox(&mut self) -> Result<Node<Mailbox>> {
+        let list = self.get_mailbox_list().await?;
+
+        let inbox_id = match list.find_special_use(&SpecialUse::Inbox) {
+            Some(inbox) => inbox.id().to_string(),
+            None => INBOX_ID.to_string(),
+        };
+
+        self.get_mailbox(&inbox_id.into()).await
+    }
+
+    pub async fn rename_mailbox<NewName: AsRef<str>>(
         &mut self,
-        old_name: OldName,
+        box_id: &MailboxId,
         new_name: NewName,
     ) -> Result<()> {
-        self.incoming
-            .rename_mailbox(old_name.as_ref(), new_name.as_ref())
-            .await
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .rename_mailbox(box_id.as_ref(), new_name.as_ref())
+            .await;
+
+        self.invalidate_subtree(box_id);
+
+        self.track(result).await
     }
 
-    pub async fn delete_mailbox<BoxId: AsRef<str>>(&mut self, box_id: BoxId) -> Result<()> {
-        self.incoming.delete_mailbox(box_id.as_ref()).await
+    pub async fn delete_mailbox(&mut self, box_id: &MailboxId) -> Result<()> {
+        self.start_request().await?;
+
+        let result = self.incoming.delete_mailbox(box_id.as_ref()).await;
+
+        self.invalidate_subtree(box_id);
+
+        self.track(result).await
+    }
+
+    /// The rights every user or group has on a shared mailbox. Only IMAP servers with the ACL extension support this.
+    pub async fn get_acl(&mut self, box_id: &MailboxId) -> Result<Vec<AclEntry>> {
+        self.start_request().await?;
+
+        let result = self.incoming.get_acl(box_id.as_ref()).await;
+
+        self.track(result).await
+    }
+
+    /// Grants rights on a shared mailbox to a user or group, see `AclEntry::rights` for the format.
+    pub async fn set_acl<I: AsRef<str>, R: AsRef<str>>(
+        &mut self,
+        box_id: &MailboxId,
+        identifier: I,
+        rights: R,
+    ) -> Result<()> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .set_acl(box_id.as_ref(), identifier.as_ref(), rights.as_ref())
+            .await;
+
+        self.track(result).await
+    }
+
+    /// The rights the logged in user has on a mailbox, e.g. to hide actions that would be rejected.
+    pub async fn get_my_rights(&mut self, box_id: &MailboxId) -> Result<String> {
+        self.start_request().await?;
+
+        let result = self.incoming.get_my_rights(box_id.as_ref()).await;
+
+        self.track(result).await
     }
 
     pub async fn create_mailbox<BoxName: AsRef<str>>(&mut self, box_id: BoxName) -> Result<()> {
-        self.incoming.create_mailbox(box_id.as_ref()).await
+        self.start_request().await?;
+
+        let result = self.incoming.create_mailbox(box_id.as_ref()).await;
+
+        self.track(result).await
+    }
+
+    /// Get the flags that can be stored on the messages in a given mailbox.
+    ///
+    /// `Flag::Custom(None)` indicates that new custom keywords can be created.
+    pub async fn get_mailbox_flags(&mut self, box_id: &MailboxId) -> Result<Vec<Flag>> {
+        self.start_request().await?;
+
+        let result = self.incoming.get_mailbox_flags(box_id.as_ref()).await;
+
+        self.track(result).await
+    }
+
+    /// Get the previews of the messages in the page `start..end` of a mailbox, newest message first.
+    ///
+    /// If the page is larger than the maximum page size (`DEFAULT_MAX_PAGE_SIZE` unless changed with `set_max_page_size`), only the first messages that fit in it are returned.
+    pub async fn get_messages<S: Into<usize>, E: Into<usize>>(
+        &mut self,
+        box_id: &MailboxId,
+        start: S,
+        end: E,
+    ) -> Result<Vec<Preview>> {
+        self.get_messages_sorted(box_id, start, end, SortOrder::default())
+            .await
+    }
+
+    /// Get the previews of the messages in the given page of a mailbox, where every page contains `DEFAULT_PAGE_SIZE` messages and the first page (0) contains the newest messages.
+    pub async fn get_messages_page(
+        &mut self,
+        box_id: &MailboxId,
+        page: usize,
+    ) -> Result<Vec<Preview>> {
+        let start = page.saturating_mul(DEFAULT_PAGE_SIZE);
+
+        self.get_messages(box_id, start, start.saturating_add(DEFAULT_PAGE_SIZE))
+            .await
     }
 
-    pub async fn get_messages<BoxId: AsRef<str>, S: Into<usize>, E: Into<usize>>(
+    /// Like `get_messages`, but lets the page be counted from the oldest message instead of the newest.
+    pub async fn get_messages_sorted<S: Into<usize>, E: Into<usize>>(
         &mut self,
-        box_id: BoxId,
+        box_id: &MailboxId,
         start: S,
         end: E,
+        order: SortOrder,
     ) -> Result<Vec<Preview>> {
         let start = start.into();
-        let end = end.into();
+        let mut end = end.into();
 
         if start >= end {
             return Ok(Vec::new());
         }
 
-        self.incoming
-            .get_messages(box_id.as_ref(), start, end)
-            .await
+        if end - start > self.max_page_size {
+            debug!(
+                target: LOG_TARGET,
+                "Requested {} messages, clamping to the maximum page size of {}",
+                end - start,
+                self.max_page_size
+            );
+
+            end = start + self.max_page_size;
+        }
+
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_messages(box_id.as_ref(), start, end, order)
+            .await;
+
+        self.track(result).await
     }
 
-    pub async fn get_message<BoxId: AsRef<str>, MessageId: AsRef<str>>(
+    /// Get the previews of the messages in a mailbox that changed since a given mod-sequence.
+    ///
+    /// Only supported by IMAP servers with the CONDSTORE extension, otherwise an `Unsupported` error is returned and the messages have to be refetched.
+    pub async fn get_changes_since(
         &mut self,
-        box_id: BoxId,
-        message_id: MessageId,
+        box_id: &MailboxId,
+        modseq: u64,
+    ) -> Result<Vec<Preview>> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_changes_since(box_id.as_ref(), modseq)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Get the previews of the messages that arrived in a mailbox after the message with the uid `last_uid`, oldest first. Unlike `get_changes_since` this works on any Imap server, but it does not report flag changes.
+    ///
+    /// Uids are only meaningful together with the mailbox's `MailboxStats::uid_validity`: when that changes, `last_uid` is invalid and the mailbox has to be fetched again. Pop has no uids, so message numbers are used instead, which only works as long as no messages were deleted.
+    pub async fn get_messages_since_uid(
+        &mut self,
+        box_id: &MailboxId,
+        last_uid: u32,
+    ) -> Result<Vec<Preview>> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_messages_since_uid(box_id.as_ref(), last_uid)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Watches the given mailboxes for changes, like new messages arriving, using only this connection.
+    ///
+    /// Imap servers that support NOTIFY report changes to all of the mailboxes. Servers that only support IDLE can watch a single mailbox, so only the first one is watched there. The stream ends after the first error, the connection should be checked before it is used again. After the stream is dropped, the next request tells the server to stop reporting changes first.
+    pub async fn watch_all(
+        &mut self,
+        box_ids: &[MailboxId],
+    ) -> Result<impl Stream<Item = Result<MailboxEvent>> + '_> {
+        let ids: Vec<&str> = box_ids.iter().map(|id| id.as_ref()).collect();
+
+        self.start_request().await?;
+
+        let result = self.incoming.watch(&ids).await;
+
+        self.track(result).await?;
+
+        self.watching = true;
+
+        let events = stream::unfold(Some(self), |client| async move {
+            let client = client?;
+
+            match client.next_events().await {
+                Ok(events) => Some((events.into_iter().map(Ok).collect(), Some(client))),
+                Err(err) => Some((vec![Err(err)], None)),
+            }
+        })
+        .flat_map(stream::iter);
+
+        Ok(events)
+    }
+
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        self.claim_connection()?;
+
+        let result = self.incoming.next_events().await;
+
+        if let Ok(events) = &result {
+            // The flags of cached messages may have changed.
+            for event in events {
+                self.invalidate_cache(&event.box_id().into(), None);
+            }
+        }
+
+        self.track(result).await
+    }
+
+    /// Get the previews of the newest unread messages in a mailbox, at most `limit` of them, e.g. for an "unread only" view.
+    ///
+    /// This is cheaper than fetching pages with `get_messages` and filtering them, but Pop servers do not keep track of what has been read, so they return an `Unsupported` error.
+    pub async fn get_unread_messages(
+        &mut self,
+        box_id: &MailboxId,
+        limit: usize,
+    ) -> Result<Vec<Preview>> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_unread_messages(box_id.as_ref(), limit)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// The amount of unread messages in a mailbox, e.g. for a badge. This is cheap enough to poll frequently, unlike `get_mailbox`.
+    pub async fn get_unread_count(&mut self, box_id: &MailboxId) -> Result<usize> {
+        self.start_request().await?;
+
+        let result = self.incoming.get_unread_count(box_id.as_ref()).await;
+
+        self.track(result).await
+    }
+
+    pub async fn get_message(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
     ) -> Result<Message> {
-        self.incoming
+        let key = (box_id.clone(), message_id.clone());
+
+        if let Some(message) = self.message_cache.get(&key) {
+            return Ok(message.clone());
+        }
+
+        self.start_request().await?;
+
+        let result = self
+            .incoming
             .get_message(box_id.as_ref(), message_id.as_ref())
-            .await
+            .await;
+
+        let message = self.track(result).await?;
+
+        self.message_cache.insert(key, message.clone());
+
+        Ok(message)
+    }
+
+    /// Get the raw RFC 822 source of a message.
+    pub async fn get_message_source(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
+    ) -> Result<Vec<u8>> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_message_source(box_id.as_ref(), message_id.as_ref())
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Fetch only the given headers of a message, e.g. just `List-Id` to categorize it, which transfers far less data than fetching the whole message.
+    ///
+    /// The headers are keyed by the names they were requested with, and headers the message does not have are left out.
+    pub async fn get_specific_headers(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
+        headers: &[&str],
+    ) -> Result<Headers> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_specific_headers(box_id.as_ref(), message_id.as_ref(), headers)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Write every message in a mailbox to the given writer in the mbox format, oldest message first.
+    ///
+    /// The messages are fetched one page at a time, so the mailbox never has to fit in memory. Returns the amount of messages that were exported, which leaves out messages that cannot be parsed.
+    pub async fn export_mailbox<W: Write + Unpin + Send>(
+        &mut self,
+        box_id: &MailboxId,
+        mut writer: W,
+    ) -> Result<usize> {
+        let page_size = export::EXPORT_PAGE_SIZE.min(self.max_page_size);
+
+        let total = self
+            .get_mailbox(box_id)
+            .await?
+            .data()
+            .and_then(Mailbox::stats)
+            .map(|stats| stats.total());
+
+        let mut exported = 0;
+        let mut offset = 0;
+
+        while total.map_or(true, |total| offset < total) {
+            let previews = self
+                .get_messages_sorted(box_id, offset, offset + page_size, SortOrder::OldestFirst)
+                .await?;
+
+            // Messages that cannot be parsed are left out of a page, so the offset moves by what was requested rather than what was returned.
+            offset += page_size;
+
+            if previews.is_empty() && total.is_none() {
+                break;
+            }
+
+            for preview in previews.iter() {
+                let source = self
+                    .get_message_source(box_id, &preview.id().into())
+                    .await?;
+
+                let entry = export::to_mbox_entry(
+                    preview
+                        .from()
+                        .iter()
+                        .find_map(Address::first)
+                        .map(|address| address.email()),
+                    preview.sent().copied(),
+                    &source,
+                );
+
+                writer.write_all(&entry).await?;
+
+                exported += 1;
+            }
+        }
+
+        writer.flush().await?;
+
+        Ok(exported)
+    }
+
+    /// Stores a raw RFC 822 message in a given mailbox, e.g. to save a draft.
+    pub async fn append(
+        &mut self,
+        box_id: &MailboxId,
+        message: &[u8],
+        flags: &[Flag],
+    ) -> Result<()> {
+        self.start_request().await?;
+
+        let result = self.incoming.append(box_id.as_ref(), message, flags).await;
+
+        self.track(result).await
+    }
+
+    /// Like `append`, but streams the message from a reader, so large messages do not have to be loaded into memory.
+    ///
+    /// `length` must be the exact size of the message in bytes.
+    pub async fn append_from_reader<R: Read + Unpin + Send>(
+        &mut self,
+        box_id: &MailboxId,
+        message: &mut R,
+        length: usize,
+        flags: &[Flag],
+    ) -> Result<()> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .append_from_reader(box_id.as_ref(), message, length, flags)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Mark all of the messages in a given mailbox as read.
+    pub async fn mark_all_read(&mut self, box_id: &MailboxId) -> Result<()> {
+        self.start_request().await?;
+
+        let result = self.incoming.mark_all_read(box_id.as_ref()).await;
+
+        self.invalidate_cache(box_id, None);
+
+        self.track(result).await
+    }
+
+    /// Add, remove or replace the flags on multiple messages in a mailbox using a single request where the protocol allows it.
+    pub async fn set_flags_bulk(
+        &mut self,
+        box_id: &MailboxId,
+        message_ids: &[MessageId],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()> {
+        let ids: Vec<&str> = message_ids.iter().map(|id| id.as_ref()).collect();
+
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .set_flags_bulk(box_id.as_ref(), &ids, flags, mode)
+            .await;
+
+        self.invalidate_cache(box_id, Some(message_ids));
+
+        self.track(result).await
     }
 
-    pub async fn get_attachment<
-        BoxId: AsRef<str>,
-        MessageId: AsRef<str>,
+    pub async fn get_attachment<AttachmentId: AsRef<str>>(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
+        attachment_id: AttachmentId,
+    ) -> Result<Vec<u8>> {
+        let key = (
+            (box_id.clone(), message_id.clone()),
+            attachment_id.as_ref().to_string(),
+        );
+
+        if let Some(attachment) = self.attachment_cache.get(&key) {
+            return Ok(attachment.clone());
+        }
+
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_attachment(box_id.as_ref(), message_id.as_ref(), attachment_id.as_ref())
+            .await;
+
+        let attachment = self.track(result).await?;
+
+        if attachment.len() <= MAX_CACHED_ATTACHMENT_SIZE {
+            self.attachment_cache.insert(key, attachment.clone());
+        }
+
+        Ok(attachment)
+    }
+
+    /// Like `get_attachment`, but calls `progress` with the amount of bytes received so far and the total size while the attachment downloads.
+    pub async fn get_attachment_with_progress<
         AttachmentId: AsRef<str>,
+        F: FnMut(usize, usize) + Send,
     >(
         &mut self,
-        box_id: BoxId,
-        message_id: MessageId,
+        box_id: &MailboxId,
+        message_id: &MessageId,
         attachment_id: AttachmentId,
+        mut progress: F,
     ) -> Result<Vec<u8>> {
-        self.incoming
-            .get_attachment(box_id.as_ref(), message_id.as_ref(), attachment_id.as_ref())
-            .await
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_attachment_with_progress(
+                box_id.as_ref(),
+                message_id.as_ref(),
+                attachment_id.as_ref(),
+                &mut progress,
+            )
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Fetch the contents of the first part in a message that has the given mime type, e.g. `text/calendar`.
+    pub async fn get_message_part(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>> {
+        self.start_request().await?;
+
+        let result = self
+            .incoming
+            .get_message_part(box_id.as_ref(), message_id.as_ref(), mime_type)
+            .await;
+
+        self.track(result).await
+    }
+
+    /// Find and parse the meeting invite in a message, if there is one.
+    pub async fn get_calendar_invite(
+        &mut self,
+        box_id: &MailboxId,
+        message_id: &MessageId,
+    ) -> Result<Option<CalendarInvite>> {
+        let calendar_type: Mime = "text/calendar".parse().unwrap();
+
+        let part = self
+            .get_message_part(box_id, message_id, calendar_type)
+            .await?;
+
+        Ok(part.and_then(|bytes| parser::calendar::parse_invite(String::from_utf8_lossy(&bytes))))
+    }
+
+    /// What the outgoing mail server supports, e.g. to warn before composing a message that is larger than the server accepts.
+    pub async fn get_outgoing_capabilities(&mut self) -> Result<SmtpCapabilities> {
+        self.outgoing.capabilities().await
+    }
+
+    /// The greeting the outgoing mail server sent, available once we have connected to it to send a message or read its capabilities.
+    pub fn outgoing_server_banner(&self) -> Option<&str> {
+        self.outgoing.server_banner()
+    }
+
+    /// The mechanism we logged in to the outgoing mail server with, available once we have sent a message.
+    pub fn outgoing_auth_mechanism(&self) -> Option<AuthMechanism> {
+        self.outgoing.auth_mechanism()
     }
 
     pub async fn send_message<M: TryInto<SendableMessage, Error = impl Display>>(
         &mut self,
         message: M,
     ) -> Result<()> {
-        let sendable = message.try_into().map_err(|err| {
+        self.send(message, None).await
+    }
+
+    /// Sends a message to the given envelope recipients, while keeping the recipients shown in the message headers intact.
+    ///
+    /// Useful for mailing lists or messages with many hidden recipients.
+    pub async fn send_to<M: TryInto<SendableMessage, Error = impl Display>>(
+        &mut self,
+        message: M,
+        envelope_to: &[EmailAddress],
+    ) -> Result<()> {
+        self.send(message, Some(envelope_to)).await
+    }
+
+    async fn send<M: TryInto<SendableMessage, Error = impl Display>>(
+        &mut self,
+        message: M,
+        envelope_to: Option<&[EmailAddress]>,
+    ) -> Result<()> {
+        let mut sendable: SendableMessage = message.try_into().map_err(|err| {
             Error::new(
                 ErrorKind::InvalidMessage,
                 format!("Failed to create sendable message: {}", err),
             )
         })?;
 
-        self.outgoing.send_message(sendable).await
+        // Applied here rather than in the outgoing protocol, so the copy in the sent mailbox shows the same sender.
+        if let Some(name) = self.outgoing.default_display_name() {
+            sendable.set_default_sender_name(name.to_string());
+        }
+
+        let copy: Option<String> = match self.save_to_sent.as_ref() {
+            Some(_) => Some(sendable.clone().try_into()?),
+            None => None,
+        };
+
+        match envelope_to {
+            Some(envelope_to) => self.outgoing.send_to(sendable, envelope_to).await?,
+            None => self.outgoing.send_message(sendable).await?,
+        }
+
+        if let (Some(box_id), Some(copy)) = (self.save_to_sent.clone(), copy) {
+            // The message has already been sent at this point, so this gets its own error kind to keep callers from sending it again.
+            if let Err(err) = self
+                .append(&box_id.as_str().into(), copy.as_bytes(), &[Flag::Read])
+                .await
+            {
+                err!(
+                    ErrorKind::SentCopyNotSaved,
+                    "Message was sent but could not be saved to mailbox '{}': {}",
+                    box_id,
+                    err
+                )
+            }
+        }
+
+        Ok(())
     }
 
+    /// Logs out of the incoming mail server.
+    ///
+    /// Call this before dropping the client, otherwise the connection is closed without a LOGOUT/QUIT and the server may not clean up after the session.
     pub async fn logout(&mut self) -> Result<()> {
-        self.incoming.logout().await
+        self.start_request().await?;
+
+        let result = self.incoming.logout().await;
+
+        let result = self.track(result).await;
+
+        if result.is_ok() {
+            self.state.set(ConnectionState::Disconnected);
+
+            self.message_cache.clear();
+            self.attachment_cache.clear();
+        }
+
+        result
+    }
+}
+
+/// Marks the request to the incoming mail server as finished and updates the connection state with the error it failed with, if any.
+fn finish_request(
+    state: &SharedConnectionState,
+    request_in_progress: &mut bool,
+    error: Option<&Error>,
+) {
+    *request_in_progress = false;
+
+    match error {
+        None => {
+            if let ConnectionState::Error(_) = state.get() {
+                state.set(ConnectionState::Connected);
+            }
+        }
+        Some(err) if err.is_connection_error() => {
+            state.set(ConnectionState::Error(err.to_string()));
+        }
+        Some(_) => {}
     }
 }
 
@@ -170,44 +1067,71 @@ pub async fn create(
     incoming: IncomingEmailProtocol,
     outgoing: OutgoingEmailProtocol,
 ) -> Result<EmailClient> {
-    let incoming_protocol = match incoming {
+    create_with_config(incoming, outgoing, IncomingConfig::default()).await
+}
+
+/// Like `create`, but with options for the connection to the incoming mail server.
+pub async fn create_with_config(
+    incoming: IncomingEmailProtocol,
+    outgoing: OutgoingEmailProtocol,
+    config: IncomingConfig,
+) -> Result<EmailClient> {
+    create_with_state(incoming, outgoing, config, SharedConnectionState::default()).await
+}
+
+/// Like `create_with_config`, but reporting the progress of the login in the given state, which the client keeps using once it is created.
+// Without a protocol one of the enums has no variants, so there is nothing to match and the rest can not be reached.
+#[cfg_attr(
+    not(all(
+        any(feature = "imap", feature = "pop", feature = "maildir"),
+        feature = "smtp",
+        feature = "runtime-tokio"
+    )),
+    allow(unreachable_code, unused_variables)
+)]
+pub async fn create_with_state(
+    incoming: IncomingEmailProtocol,
+    outgoing: OutgoingEmailProtocol,
+    #[cfg_attr(not(feature = "imap"), allow(unused_variables))] config: IncomingConfig,
+    state: SharedConnectionState,
+) -> Result<EmailClient> {
+    let incoming_protocol: Result<Box<dyn IncomingProtocol + Sync + Send>> = match incoming {
         #[cfg(feature = "imap")]
         IncomingEmailProtocol::Imap(credentials) => {
-            imap::create(&credentials, Default::default()).await?
+            imap::create(&credentials, config, &state).await
         }
 
         #[cfg(feature = "pop")]
-        IncomingEmailProtocol::Pop(credentials) => pop::create(&credentials).await?,
+        IncomingEmailProtocol::Pop(credentials) => pop::create(&credentials, &state).await,
 
         #[cfg(feature = "maildir")]
-        IncomingEmailProtocol::Maildir(path) => maildir::create(path)?,
+        IncomingEmailProtocol::Maildir(path) => maildir::create(path),
+    };
 
-        #[cfg(not(any(feature = "imap", feature = "pop")))]
-        _ => {
-            use crate::error::{err, ErrorKind};
+    let incoming_protocol = match incoming_protocol {
+        Ok(incoming_protocol) => incoming_protocol,
+        Err(err) => {
+            state.set(ConnectionState::Error(err.to_string()));
 
-            err!(
-                ErrorKind::NoClientAvailable,
-                "There are no incoming mail clients supported",
-            );
+            return Err(err);
         }
     };
 
+    state.set(ConnectionState::Connected);
+
     let outgoing_protocol = match outgoing {
         #[cfg(all(feature = "smtp", feature = "runtime-tokio"))]
-        OutgoingEmailProtocol::Smtp(credentials) => smtp::create(credentials)?,
-        #[cfg(not(any(all(feature = "smtp", feature = "runtime-tokio"))))]
-        _ => {
-            use crate::error::{err, ErrorKind};
-
-            err!(
-                ErrorKind::NoClientAvailable,
-                "There are no outgoing mail clients supported",
-            );
-        }
+        OutgoingEmailProtocol::Smtp(credentials) => smtp::create(credentials, state.clone())?,
+        #[cfg(all(feature = "smtp", not(feature = "runtime-tokio")))]
+        _ => err!(
+            ErrorKind::NoClientAvailable,
+            "There are no outgoing mail clients supported",
+        ),
     };
 
-    let client = EmailClient::new(incoming_protocol, outgoing_protocol);
+    let mut client = EmailClient::new(incoming_protocol, outgoing_protocol);
+
+    client.state = state;
 
     Ok(client)
 }
@@ -216,6 +1140,7 @@ pub async fn create(
 pub struct ThreadableEmailClient {
     client: Arc<RwLock<EmailClient>>,
     keep_alive: KeepAlive,
+    prefetch_count: usize,
 }
 
 impl AsRef<Arc<RwLock<EmailClient>>> for ThreadableEmailClient {
@@ -228,12 +1153,94 @@ impl ThreadableEmailClient {
     pub fn new(client: Arc<RwLock<EmailClient>>, mut keep_alive: KeepAlive) -> Self {
         keep_alive.start();
 
-        Self { client, keep_alive }
+        Self {
+            client,
+            keep_alive,
+            prefetch_count: 0,
+        }
+    }
+
+    /// Fetch the full messages for the first `count` previews in the background whenever `get_messages` is called, so they open instantly with `get_message`.
+    ///
+    /// Prefetching is disabled by default. Prefetched messages are kept in the message cache of the client, so no more than `EmailClient::set_message_cache_size` messages are prefetched at once. Unread messages are never prefetched, as fetching a message marks it as read on most servers.
+    pub fn with_prefetch(mut self, count: usize) -> Self {
+        self.prefetch_count = count;
+
+        self
     }
 
     pub fn keep_alive(&self) -> &KeepAlive {
         &self.keep_alive
     }
+
+    /// Get the previews of the messages in a mailbox, like `EmailClient::get_messages`, prefetching the first messages if enabled with `with_prefetch`.
+    pub async fn get_messages<S: Into<usize>, E: Into<usize>>(
+        &self,
+        box_id: &MailboxId,
+        start: S,
+        end: E,
+    ) -> Result<Vec<Preview>> {
+        let previews = self
+            .client
+            .write()
+            .await
+            .get_messages(box_id, start, end)
+            .await?;
+
+        self.prefetch(box_id, &previews);
+
+        Ok(previews)
+    }
+
+    /// Get a message, using the prefetched copy if there is one.
+    pub async fn get_message(&self, box_id: &MailboxId, message_id: &MessageId) -> Result<Message> {
+        self.client
+            .write()
+            .await
+            .get_message(box_id, message_id)
+            .await
+    }
+
+    fn prefetch(&self, box_id: &MailboxId, previews: &[Preview]) {
+        let message_ids: Vec<MessageId> = previews
+            .iter()
+            .filter(|preview| preview.flags().contains(&Flag::Read))
+            .take(self.prefetch_count)
+            .map(|preview| preview.id().into())
+            .collect();
+
+        if message_ids.is_empty() {
+            return;
+        }
+
+        let client = Arc::clone(&self.client);
+        let box_id = box_id.clone();
+
+        spawn(async move {
+            // Prefetching more messages than fit in the cache would only push out the first ones again.
+            let limit = client.read().await.message_cache.capacity();
+
+            for message_id in message_ids.into_iter().take(limit) {
+                let key = (box_id.clone(), message_id);
+
+                if client.read().await.message_cache.contains(&key) {
+                    continue;
+                }
+
+                // The lock is taken for every message separately, so other requests do not have to wait for the whole batch. The message is cached by the client.
+                let result = client.write().await.get_message(&key.0, &key.1).await;
+
+                if let Err(err) = result {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to prefetch message {}: {}", key.1, err
+                    );
+
+                    return;
+                }
+            }
+        });
+    }
 }
 
 impl From<EmailClient> for ThreadableEmailClient {
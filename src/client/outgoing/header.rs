@@ -0,0 +1,158 @@
+/// The length lines should be kept under, excluding the line ending (RFC 5322 section 2.1.1).
+const MAX_LINE_LENGTH: usize = 78;
+/// The maximum length of a single encoded word (RFC 2047 section 2).
+const MAX_ENCODED_WORD_LENGTH: usize = 75;
+
+const ENCODED_WORD_PREFIX: &str = "=?UTF-8?Q?";
+const ENCODED_WORD_SUFFIX: &str = "?=";
+
+/// Writes an unstructured header like `Subject`, including the trailing line ending.
+///
+/// Values that are not plain ascii are split into UTF-8 encoded words, which are folded so that no line is longer than 78 characters. Plain ascii values are only folded.
+pub fn encode_header(name: &str, value: &str) -> String {
+    let words = if needs_encoding(name, value) {
+        encoded_words(value, MAX_LINE_LENGTH - name.len() - 2)
+    } else {
+        value.split(' ').map(String::from).collect()
+    };
+
+    let mut header = format!("{}:", name);
+    let mut line_length = header.len();
+
+    for (index, word) in words.iter().enumerate() {
+        // Folding means replacing a space with a line break followed by that space, so we can only fold before a word, never within it.
+        if index > 0 && line_length + 1 + word.len() > MAX_LINE_LENGTH {
+            header.push_str("\r\n");
+            line_length = 0;
+        }
+
+        header.push(' ');
+        header.push_str(word);
+
+        line_length += 1 + word.len();
+    }
+
+    header.push_str("\r\n");
+
+    header
+}
+
+fn needs_encoding(name: &str, value: &str) -> bool {
+    let first_line = MAX_LINE_LENGTH - name.len() - 2;
+
+    // Words that do not fit on a line by themselves cannot be folded.
+    let too_long = value.split(' ').enumerate().any(|(index, word)| {
+        let max_length = if index == 0 {
+            first_line
+        } else {
+            MAX_LINE_LENGTH - 1
+        };
+
+        word.len() > max_length
+    });
+
+    too_long
+        || !value.is_ascii()
+        || value.chars().any(|c| c.is_ascii_control())
+        // Text that looks like an encoded word would be decoded by the recipient.
+        || value.contains("=?")
+}
+
+/// Splits the value into 'Q' encoded words that are short enough to each fit on their own line, without splitting a character across words.
+fn encoded_words(value: &str, first_line: usize) -> Vec<String> {
+    let overhead = ENCODED_WORD_PREFIX.len() + ENCODED_WORD_SUFFIX.len();
+
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut max_length = first_line.min(MAX_ENCODED_WORD_LENGTH) - overhead;
+
+    for c in value.chars() {
+        let encoded = encode_char(c);
+
+        if !current.is_empty() && current.len() + encoded.len() > max_length {
+            words.push(format!(
+                "{}{}{}",
+                ENCODED_WORD_PREFIX, current, ENCODED_WORD_SUFFIX
+            ));
+
+            current.clear();
+            max_length = MAX_ENCODED_WORD_LENGTH - overhead;
+        }
+
+        current.push_str(&encoded);
+    }
+
+    words.push(format!(
+        "{}{}{}",
+        ENCODED_WORD_PREFIX, current, ENCODED_WORD_SUFFIX
+    ));
+
+    words
+}
+
+fn encode_char(c: char) -> String {
+    match c {
+        ' ' => String::from("_"),
+        c if c.is_ascii_alphanumeric() || "!*+-/".contains(c) => c.to_string(),
+        c => {
+            let mut buf = [0; 4];
+
+            c.encode_utf8(&mut buf)
+                .bytes()
+                .map(|byte| format!("={:02X}", byte))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_ascii_subject() {
+        assert_eq!(
+            encode_header("Subject", "Quarterly report"),
+            "Subject: Quarterly report\r\n"
+        );
+    }
+
+    #[test]
+    fn long_ascii_subject() {
+        let subject = "a long plain ascii subject line ".repeat(6);
+
+        let header = encode_header("Subject", subject.trim_end());
+
+        assert!(!header.contains("=?"));
+        assert!(header.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(
+            header.replace("\r\n", ""),
+            format!("Subject: {}", subject.trim_end())
+        );
+    }
+
+    #[test]
+    fn long_unicode_subject() {
+        let subject = "Überprüfung der Quartalszahlen für das Geschäftsjahr — bitte bis Freitag durchsehen, damit wir die Präsentation für die Vorstandssitzung rechtzeitig fertigstellen können. Vielen Dank im Voraus! ✓✓✓";
+
+        let header = encode_header("Subject", subject);
+
+        let lines: Vec<_> = header.trim_end().split("\r\n").collect();
+
+        assert!(lines.len() > 1);
+        assert!(lines[0].starts_with("Subject: =?UTF-8?Q?=C3=9Cberpr=C3=BCfung_der"));
+
+        for line in lines.iter() {
+            assert!(line.len() <= MAX_LINE_LENGTH);
+
+            let word = line.trim_start_matches("Subject:").trim_start();
+
+            assert!(word.starts_with(ENCODED_WORD_PREFIX) && word.ends_with(ENCODED_WORD_SUFFIX));
+            assert!(word.len() <= MAX_ENCODED_WORD_LENGTH);
+        }
+
+        let decoded = mailparse::parse_header(header.as_bytes()).unwrap().0;
+
+        assert_eq!(decoded.get_value(), subject);
+    }
+}
@@ -1,4 +1,6 @@
 #[cfg(all(feature = "smtp", feature = "runtime-tokio"))]
 pub mod smtp;
 
+mod header;
+
 pub mod types;
@@ -1,142 +1,598 @@
+use std::{fmt::Display, net::IpAddr};
+
 use crate::{
     client::{
-        connection::ConnectionSecurity,
-        protocol::{OutgoingProtocol, SmtpCredentials},
+        address::EmailAddress,
+        auth::{self, AuthMechanism},
+        connection::{self, ConnectionSecurity, SharedConnectionState},
+        protocol::{OutgoingProtocol, RemoteServer, SmtpCredentials},
         Credentials, ServerCredentials,
     },
-    error::Result,
+    error::{err, ErrorKind, Result},
     runtime::{
-        io::{BufRead, BufStream, Write},
+        io::{BufRead, BufReadExt, BufStream, ReadExt, Write, WriteExt},
         net::TcpStream,
     },
 };
 
 use async_native_tls::{TlsConnector, TlsStream};
-use async_smtp::{self, authentication::Mechanism, SmtpTransport};
+use async_smtp::{
+    self,
+    authentication::{Credentials as SmtpAuthCredentials, Mechanism},
+    commands::{EhloCommand, QuitCommand},
+    extension::ClientId,
+    response::Response,
+    SendableEmail, SmtpTransport,
+};
 use async_trait::async_trait;
+use log::{debug, info};
+
+use super::types::{capabilities::SmtpCapabilities, dsn::DsnOptions, sendable::SendableMessage};
 
-use super::types::sendable::SendableMessage;
+mod session;
+
+/// The log target used by the smtp client, use `RUST_LOG=dust_mail::smtp=debug` to debug smtp connections.
+const LOG_TARGET: &str = "dust_mail::smtp";
 
 pub struct SmtpClient {
     credentials: SmtpCredentials,
+    capabilities: Option<SmtpCapabilities>,
+    banner: Option<String>,
+    auth_mechanism: Option<AuthMechanism>,
+    state: SharedConnectionState,
 }
 
 impl SmtpClient {
     pub fn new(credentials: SmtpCredentials) -> Self {
-        Self { credentials }
+        Self {
+            credentials,
+            capabilities: None,
+            banner: None,
+            auth_mechanism: None,
+            state: SharedConnectionState::default(),
+        }
+    }
+
+    /// Reports the logins, which happen for every message we send, in the given connection state.
+    pub fn with_state(mut self, state: SharedConnectionState) -> Self {
+        self.state = state;
+
+        self
     }
 }
 
-async fn connect<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<SmtpTransport<BufStream<TlsStream<TcpStream>>>> {
+async fn connect(server: &RemoteServer) -> Result<BufStream<TlsStream<TcpStream>>> {
     let tls = TlsConnector::new();
 
-    let tcp_stream = TcpStream::connect((server.as_ref(), port.into())).await?;
+    let tcp_stream = connection::connect_tcp(server).await?;
+
+    let tls_stream = tls.connect(server.domain(), tcp_stream).await?;
+
+    Ok(BufStream::new(tls_stream))
+}
+
+async fn connect_plain(server: &RemoteServer) -> Result<BufStream<TcpStream>> {
+    let stream = connection::connect_tcp(server).await?;
+
+    Ok(BufStream::new(stream))
+}
+
+/// Reads a (possibly multiline) reply from the server.
+async fn read_reply<S: BufRead + Unpin>(stream: &mut S) -> Result<Response> {
+    let mut reply = String::new();
+
+    loop {
+        let mut line = String::new();
+
+        if stream.read_line(&mut line).await? == 0 {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Smtp server closed the connection unexpectedly"
+            )
+        }
+
+        // Every line but the last one has a dash after the reply code.
+        let last = line.as_bytes().get(3) != Some(&b'-');
+
+        reply.push_str(&line);
+
+        if last {
+            return Ok(reply.parse()?);
+        }
+    }
+}
+
+async fn command<S: BufRead + Write + Unpin, C: Display>(
+    stream: &mut S,
+    command: C,
+) -> Result<Response> {
+    stream.write_all(command.to_string().as_bytes()).await?;
+    stream.flush().await?;
+
+    read_reply(stream).await
+}
+
+/// Fails with the text the server sent if the reply is not positive.
+fn check_reply(reply: Response, action: &str) -> Result<Response> {
+    if !reply.is_positive() {
+        err!(
+            ErrorKind::MailServer,
+            "Smtp server rejected {}: {} {}",
+            action,
+            reply.code,
+            reply.message.join(" ")
+        )
+    }
 
-    let tls_stream = tls.connect(server.as_ref(), tcp_stream).await?;
+    Ok(reply)
+}
 
-    let buf_stream = BufStream::new(tls_stream);
+/// The name we introduce ourselves with, see `SmtpCredentials::with_hello_name`.
+fn client_id(hello_name: Option<&str>) -> ClientId {
+    match hello_name.map(|name| (name, name.parse::<IpAddr>())) {
+        Some((_, Ok(IpAddr::V4(address)))) => ClientId::Ipv4(address),
+        Some((_, Ok(IpAddr::V6(address)))) => ClientId::Ipv6(address),
+        Some((name, Err(_))) => ClientId::Domain(name.to_string()),
+        None => ClientId::default(),
+    }
+}
 
-    let client = async_smtp::SmtpClient::new();
+/// Reads the server greeting and sends EHLO ourselves, because async-smtp does not expose everything the server advertises (like the size limit).
+///
+/// Returns the text of the greeting along with the capabilities.
+async fn ehlo<S: BufRead + Write + Unpin>(
+    stream: &mut S,
+    client_id: ClientId,
+) -> Result<(String, SmtpCapabilities)> {
+    let greeting = check_reply(read_reply(stream).await?, "the connection")?;
 
-    let transport = SmtpTransport::new(client, buf_stream).await?;
+    let reply = command(stream, EhloCommand::new(client_id)).await?;
 
-    Ok(transport)
+    let reply = check_reply(reply, "EHLO")?;
+
+    Ok((
+        greeting.message.join(" "),
+        SmtpCapabilities::from_ehlo(&reply.message),
+    ))
+}
+
+/// Fails if the message is larger than the limit the server advertised, so we do not upload it only to be rejected halfway.
+fn check_size(size: usize, limit: Option<usize>) -> Result<()> {
+    match limit {
+        Some(limit) if size > limit => err!(
+            ErrorKind::MessageTooLarge,
+            "Message is {}, server limit is {}",
+            format_size(size),
+            format_size(limit)
+        ),
+        _ => Ok(()),
+    }
 }
 
-async fn connect_plain<S: AsRef<str>, P: Into<u16>>(
-    server: S,
-    port: P,
-) -> Result<SmtpTransport<BufStream<TcpStream>>> {
-    let stream = TcpStream::connect((server.as_ref(), port.into())).await?;
+fn format_size(bytes: usize) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
 
-    let buf_stream = BufStream::new(stream);
+    let bytes = bytes as f64;
 
-    let client = async_smtp::SmtpClient::new();
+    if bytes >= MB {
+        format!("{:.1}MB", bytes / MB)
+    } else if bytes >= KB {
+        format!("{:.1}KB", bytes / KB)
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
+async fn probe<S: BufRead + Write + Unpin>(
+    mut stream: S,
+    client_id: ClientId,
+) -> Result<(String, SmtpCapabilities)> {
+    let greeting = ehlo(&mut stream, client_id).await?;
 
-    let transport = SmtpTransport::new(client, buf_stream).await?;
+    command(&mut stream, QuitCommand).await?;
 
-    Ok(transport)
+    Ok(greeting)
 }
 
 async fn send<S: BufRead + Write + Unpin>(
     mut transport: SmtpTransport<S>,
-    message: SendableMessage,
+    email: SendableEmail,
 ) -> Result<()> {
-    transport.send(message.try_into()?).await?;
+    debug!(
+        target: LOG_TARGET,
+        "Sending message to {} recipient(s)",
+        email.envelope().to().len()
+    );
+
+    transport.send(email).await?;
 
     transport.quit().await?;
 
+    info!(target: LOG_TARGET, "Message was sent");
+
     Ok(())
 }
 
-const PASSWORD_MECHANISMS: [Mechanism; 2] = [Mechanism::Plain, Mechanism::Login];
-const OAUTH_MECHANISMS: [Mechanism; 1] = [Mechanism::Xoauth2];
+const CHUNKING_EXTENSION: &str = "CHUNKING";
+const DSN_EXTENSION: &str = "DSN";
 
-async fn login<S: BufRead + Write + Unpin>(
-    transport: &mut SmtpTransport<S>,
+/// The mechanisms async-smtp can log in with.
+const SUPPORTED_MECHANISMS: [AuthMechanism; 3] = [
+    AuthMechanism::Plain,
+    AuthMechanism::Login,
+    AuthMechanism::XOAuth2,
+];
+
+/// Picks the strongest mechanism the server advertises for the given credentials, if it advertises any we support.
+fn negotiate(
     creds: &Credentials,
-) -> Result<()> {
+    capabilities: &SmtpCapabilities,
+) -> Option<(AuthMechanism, Mechanism)> {
+    let mechanism = auth::negotiate(creds, &SUPPORTED_MECHANISMS, capabilities.auth_mechanisms())?;
+
+    let smtp_mechanism = match mechanism {
+        AuthMechanism::Plain => Mechanism::Plain,
+        AuthMechanism::Login => Mechanism::Login,
+        AuthMechanism::XOAuth2 => Mechanism::Xoauth2,
+        _ => return None,
+    };
+
+    Some((mechanism, smtp_mechanism))
+}
+
+fn auth_credentials(creds: &Credentials) -> SmtpAuthCredentials {
     match creds {
         Credentials::Password { username, password } => {
-            let smtp_credentials =
-                async_smtp::authentication::Credentials::new(username.clone(), password.clone());
+            SmtpAuthCredentials::new(username.clone(), password.clone())
+        }
+        Credentials::OAuth {
+            username, token, ..
+        } => SmtpAuthCredentials::new(username.clone(), token.clone()),
+    }
+}
 
-            transport
-                .try_login(&smtp_credentials, &PASSWORD_MECHANISMS)
-                .await?;
+/// Logs in with the strongest mechanism the server advertises, returning the mechanism that was used.
+async fn login<S: BufRead + Write + Unpin>(
+    transport: &mut SmtpTransport<S>,
+    capabilities: &SmtpCapabilities,
+    creds: &Credentials,
+) -> Result<Option<AuthMechanism>> {
+    // We reconnect for every message, so the token may have expired since the last one was sent.
+    let creds = creds.refreshed().await?;
+    let creds = creds.as_ref();
+
+    debug!(target: LOG_TARGET, "Logging in as {}", creds.username());
+
+    let (mechanism, smtp_mechanism) = match negotiate(creds, capabilities) {
+        Some(mechanism) => mechanism,
+        None => {
+            info!(
+                target: LOG_TARGET,
+                "No supported authentication mechanisms available"
+            );
+
+            return Ok(None);
         }
-        Credentials::OAuth { username, token } => {
-            let smtp_credentials =
-                async_smtp::authentication::Credentials::new(username.clone(), token.clone());
+    };
 
-            transport
-                .try_login(&smtp_credentials, &OAUTH_MECHANISMS)
-                .await?;
+    transport
+        .auth(smtp_mechanism, &auth_credentials(creds))
+        .await?;
+
+    Ok(Some(mechanism))
+}
+
+impl SmtpClient {
+    async fn deliver(&mut self, email: SendableEmail, dsn: Option<&DsnOptions>) -> Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Connecting to smtp server {}",
+            self.credentials.server().domain()
+        );
+
+        match self.credentials.server().security() {
+            ConnectionSecurity::Tls => {
+                let stream = connect(self.credentials.server()).await?;
+
+                self.deliver_over(stream, email, dsn).await
+            }
+            _ => {
+                let stream = connect_plain(self.credentials.server()).await?;
+
+                self.deliver_over(stream, email, dsn).await
+            }
         }
     }
 
-    Ok(())
+    async fn deliver_over<S: BufRead + Write + Unpin>(
+        &mut self,
+        stream: S,
+        email: SendableEmail,
+        dsn: Option<&DsnOptions>,
+    ) -> Result<()> {
+        let mut stream = stream;
+
+        let client_id = client_id(self.credentials.hello_name());
+
+        let (banner, capabilities) = ehlo(&mut stream, client_id.clone()).await?;
+
+        self.banner = Some(banner);
+        self.capabilities = Some(capabilities.clone());
+
+        let envelope = email.envelope().clone();
+
+        let mut message = Vec::new();
+
+        email.message().read_to_end(&mut message).await?;
+
+        check_size(message.len(), capabilities.max_size())?;
+
+        let dsn = match dsn {
+            Some(_) if !capabilities.supports(DSN_EXTENSION) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Smtp server does not support DSN, not requesting delivery notifications"
+                );
+
+                None
+            }
+            dsn => dsn,
+        };
+
+        if capabilities.supports(CHUNKING_EXTENSION) || dsn.is_some() {
+            self.auth_mechanism = self
+                .state
+                .authenticating(session::login(
+                    &mut stream,
+                    &capabilities,
+                    self.credentials.credentials(),
+                ))
+                .await?;
+
+            return session::send(stream, &capabilities, &envelope, &message, dsn).await;
+        }
+
+        // async-smtp sends its own EHLO, which servers allow to be repeated.
+        let client = async_smtp::SmtpClient::new()
+            .hello_name(client_id)
+            .without_greeting();
+
+        let mut transport = SmtpTransport::new(client, stream).await?;
+
+        self.auth_mechanism = self
+            .state
+            .authenticating(login(
+                &mut transport,
+                &capabilities,
+                self.credentials.credentials(),
+            ))
+            .await?;
+
+        send(transport, SendableEmail::new(envelope, message)).await
+    }
 }
 
 #[async_trait]
 impl OutgoingProtocol for SmtpClient {
+    fn server_banner(&self) -> Option<&str> {
+        self.banner.as_deref()
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        self.auth_mechanism
+    }
+
+    fn default_display_name(&self) -> Option<&str> {
+        self.credentials.default_display_name()
+    }
+
     async fn send_message(&mut self, message: SendableMessage) -> Result<()> {
-        match self.credentials.server().security() {
-            ConnectionSecurity::Tls => {
-                let mut transport = connect(
-                    self.credentials.server().domain(),
-                    self.credentials.server().port(),
-                )
-                .await?;
+        let dsn = message.dsn().cloned();
 
-                login(&mut transport, self.credentials.credentials()).await?;
+        self.deliver(message.try_into()?, dsn.as_ref()).await
+    }
 
-                send(transport, message).await
-            }
-            _ => {
-                let mut transport = connect_plain(
-                    self.credentials.server().domain(),
-                    self.credentials.server().port(),
-                )
-                .await?;
+    async fn send_to(
+        &mut self,
+        message: SendableMessage,
+        envelope_to: &[EmailAddress],
+    ) -> Result<()> {
+        let dsn = message.dsn().cloned();
 
-                login(&mut transport, self.credentials.credentials()).await?;
+        self.deliver(message.into_sendable_email(envelope_to)?, dsn.as_ref())
+            .await
+    }
 
-                send(transport, message).await
-            }
+    async fn capabilities(&mut self) -> Result<SmtpCapabilities> {
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
         }
+
+        let server = self.credentials.server();
+
+        debug!(
+            target: LOG_TARGET,
+            "Reading capabilities of smtp server {}",
+            server.domain()
+        );
+
+        let client_id = client_id(self.credentials.hello_name());
+
+        let (banner, capabilities) = match server.security() {
+            ConnectionSecurity::Tls => probe(connect(server).await?, client_id).await?,
+            _ => probe(connect_plain(server).await?, client_id).await?,
+        };
+
+        self.banner = Some(banner);
+        self.capabilities = Some(capabilities.clone());
+
+        Ok(capabilities)
     }
 }
 
-pub fn create(credentials: SmtpCredentials) -> Result<Box<dyn OutgoingProtocol + Sync + Send>> {
-    let client = SmtpClient::new(credentials);
+pub fn create(
+    credentials: SmtpCredentials,
+    state: SharedConnectionState,
+) -> Result<Box<dyn OutgoingProtocol + Sync + Send>> {
+    let client = SmtpClient::new(credentials).with_state(state);
 
     Ok(Box::new(client))
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::client::outgoing::types::dsn::DsnReturn;
+
+    #[test]
+    fn message_size_limit() {
+        assert!(check_size(1000, None).is_ok());
+        assert!(check_size(1000, Some(1000)).is_ok());
+
+        let err = check_size(30 * 1024 * 1024, Some(25 * 1024 * 1024)).unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::MessageTooLarge));
+        assert_eq!(err.to_string(), "Message is 30.0MB, server limit is 25.0MB");
+    }
+
+    /// Plays the server side of a session, advertising the given EHLO reply and returning everything the client sent.
+    async fn fake_server(stream: tokio::io::DuplexStream, ehlo: &'static [u8]) -> String {
+        let mut stream = BufStream::new(stream);
+        let mut received = String::new();
+
+        stream
+            .write_all(b"220 smtp.example.com ESMTP\r\n")
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+
+        loop {
+            let mut line = String::new();
+
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                return received;
+            }
+
+            received.push_str(&line);
+
+            let reply: &[u8] = if line.starts_with("EHLO") {
+                ehlo
+            } else if line.starts_with("AUTH") {
+                b"235 Authenticated\r\n"
+            } else if let Some(size) = line.strip_prefix("BDAT ") {
+                let size: usize = size.split_whitespace().next().unwrap().parse().unwrap();
+                let mut chunk = vec![0; size];
+
+                stream.read_exact(&mut chunk).await.unwrap();
+                received.push_str(&String::from_utf8(chunk).unwrap());
+
+                b"250 Message accepted\r\n"
+            } else if line.starts_with("DATA") {
+                stream.write_all(b"354 Go ahead\r\n").await.unwrap();
+                stream.flush().await.unwrap();
+
+                while line != ".\r\n" {
+                    line.clear();
+                    stream.read_line(&mut line).await.unwrap();
+                    received.push_str(&line);
+                }
+
+                b"250 Message accepted\r\n"
+            } else if line.starts_with("QUIT") {
+                b"221 Bye\r\n"
+            } else {
+                b"250 Ok\r\n"
+            };
+
+            stream.write_all(reply).await.unwrap();
+            stream.flush().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn send_with_bdat() {
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(fake_server(
+            server_stream,
+            b"250-smtp.example.com\r\n250-CHUNKING\r\n250-SIZE 1000\r\n250 AUTH PLAIN\r\n",
+        ));
+
+        let credentials = SmtpCredentials::new(
+            RemoteServer::new("smtp.example.com", 465, ConnectionSecurity::Tls),
+            Credentials::password("user", "secret"),
+        );
+
+        let address = async_smtp::EmailAddress::new("user@example.com".to_string()).unwrap();
+        let envelope = async_smtp::Envelope::new(Some(address.clone()), vec![address]).unwrap();
+        let email = SendableEmail::new(envelope, "Subject: Hi\r\n\r\nHello");
+
+        let mut client = SmtpClient::new(credentials);
+
+        client
+            .deliver_over(BufStream::new(client_stream), email, None)
+            .await
+            .unwrap();
+
+        let received = server.await.unwrap();
+
+        assert!(received.contains("MAIL FROM:<user@example.com> SIZE=20\r\n"));
+        assert!(received.contains("RCPT TO:<user@example.com>\r\n"));
+        assert!(received.ends_with("BDAT 20 LAST\r\nSubject: Hi\r\n\r\nHelloQUIT\r\n"));
+        assert!(!received.contains("DATA"));
+        assert!(received.starts_with("EHLO [127.0.0.1]\r\n"));
+        assert_eq!(client.server_banner(), Some("smtp.example.com ESMTP"));
+    }
+
+    #[test]
+    fn hello_name() {
+        assert_eq!(client_id(None).to_string(), "[127.0.0.1]");
+        assert_eq!(
+            client_id(Some("mail.example.com")).to_string(),
+            "mail.example.com"
+        );
+        assert_eq!(client_id(Some("192.0.2.1")).to_string(), "[192.0.2.1]");
+    }
+
+    #[tokio::test]
+    async fn send_with_dsn() {
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+
+        let server = tokio::spawn(fake_server(
+            server_stream,
+            b"250-smtp.example.com\r\n250-DSN\r\n250 AUTH PLAIN\r\n",
+        ));
+
+        let credentials = SmtpCredentials::new(
+            RemoteServer::new("smtp.example.com", 465, ConnectionSecurity::Tls),
+            Credentials::password("user", "secret"),
+        );
+
+        let address = async_smtp::EmailAddress::new("user@example.com".to_string()).unwrap();
+        let envelope = async_smtp::Envelope::new(Some(address.clone()), vec![address]).unwrap();
+        let email = SendableEmail::new(envelope, "Subject: Hi\r\n\r\n.Hello");
+
+        let dsn = DsnOptions::new()
+            .with_return(DsnReturn::Headers)
+            .with_envelope_id("order-42");
+
+        let mut client = SmtpClient::new(credentials);
+
+        client
+            .deliver_over(BufStream::new(client_stream), email, Some(&dsn))
+            .await
+            .unwrap();
+
+        let received = server.await.unwrap();
+
+        assert!(received.contains("MAIL FROM:<user@example.com> RET=HDRS ENVID=order-42\r\n"));
+        assert!(received.contains("RCPT TO:<user@example.com> NOTIFY=SUCCESS,FAILURE\r\n"));
+        assert!(received.ends_with("DATA\r\nSubject: Hi\r\n\r\n..Hello\r\n.\r\nQUIT\r\n"));
+    }
+}
+
 // #[cfg(test)]
 // mod test {
 //     use std::env;
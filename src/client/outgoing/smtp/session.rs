@@ -0,0 +1,244 @@
+//! Running the SMTP session ourselves, for what async-smtp cannot do: sending with BDAT (RFC 3030) instead of DATA and requesting delivery status notifications (RFC 3461).
+//!
+//! The command types from async-smtp are still used to format the commands.
+
+use async_smtp::{
+    commands::{AuthCommand, DataCommand, MailCommand, QuitCommand, RcptCommand},
+    extension::{MailBodyParameter, MailParameter, RcptParameter},
+    Envelope,
+};
+use log::{debug, info};
+
+use crate::{
+    client::{
+        auth::AuthMechanism,
+        outgoing::types::{capabilities::SmtpCapabilities, dsn::DsnOptions},
+        Credentials,
+    },
+    error::{err, ErrorKind, Result},
+    runtime::io::{BufRead, Write, WriteExt},
+};
+
+use super::{
+    auth_credentials, check_reply, command, negotiate, read_reply, CHUNKING_EXTENSION, LOG_TARGET,
+};
+
+/// The size of a single BDAT chunk, large enough to keep the amount of round trips low.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How many challenges the server may send before we give up on logging in.
+const MAX_AUTH_CHALLENGES: usize = 10;
+
+const EIGHT_BIT_MIME_EXTENSION: &str = "8BITMIME";
+const SIZE_EXTENSION: &str = "SIZE";
+
+pub(super) async fn login<S: BufRead + Write + Unpin>(
+    stream: &mut S,
+    capabilities: &SmtpCapabilities,
+    creds: &Credentials,
+) -> Result<Option<AuthMechanism>> {
+    let creds = creds.refreshed().await?;
+    let creds = creds.as_ref();
+
+    debug!(target: LOG_TARGET, "Logging in as {}", creds.username());
+
+    let (auth_mechanism, mechanism) = match negotiate(creds, capabilities) {
+        Some(mechanism) => mechanism,
+        None => {
+            info!(
+                target: LOG_TARGET,
+                "No supported authentication mechanisms available"
+            );
+
+            return Ok(None);
+        }
+    };
+
+    let smtp_credentials = auth_credentials(creds);
+
+    let mut reply = command(
+        stream,
+        AuthCommand::new(mechanism, smtp_credentials.clone(), None)?,
+    )
+    .await?;
+
+    let mut challenges = 0;
+
+    while reply.has_code(334) {
+        challenges += 1;
+
+        if challenges > MAX_AUTH_CHALLENGES {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Smtp server sent too many authentication challenges"
+            )
+        }
+
+        let answer = AuthCommand::new_from_response(mechanism, smtp_credentials.clone(), &reply)?;
+
+        reply = command(stream, answer).await?;
+    }
+
+    check_reply(reply, "the login")?;
+
+    Ok(Some(auth_mechanism))
+}
+
+/// Escapes every line that starts with a dot and terminates the message with a single dot, as DATA requires.
+fn dot_stuff(message: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(message.len() + 5);
+
+    for line in message.split_inclusive(|byte| *byte == b'\n') {
+        if line.starts_with(b".") {
+            stuffed.push(b'.');
+        }
+
+        stuffed.extend_from_slice(line);
+    }
+
+    if !stuffed.is_empty() && !stuffed.ends_with(b"\r\n") {
+        stuffed.extend_from_slice(b"\r\n");
+    }
+
+    stuffed.extend_from_slice(b".\r\n");
+
+    stuffed
+}
+
+/// Splits a message into the BDAT chunks it is sent in. An empty message is still sent as a single (empty) chunk.
+fn chunks(message: &[u8]) -> Vec<&[u8]> {
+    if message.is_empty() {
+        return vec![message];
+    }
+
+    message.chunks(CHUNK_SIZE).collect()
+}
+
+pub(super) async fn send<S: BufRead + Write + Unpin>(
+    mut stream: S,
+    capabilities: &SmtpCapabilities,
+    envelope: &Envelope,
+    message: &[u8],
+    dsn: Option<&DsnOptions>,
+) -> Result<()> {
+    let chunking = capabilities.supports(CHUNKING_EXTENSION);
+
+    debug!(
+        target: LOG_TARGET,
+        "Sending message to {} recipient(s) using {}",
+        envelope.to().len(),
+        if chunking { "BDAT" } else { "DATA" }
+    );
+
+    let mut mail_options = Vec::new();
+
+    if capabilities.supports(EIGHT_BIT_MIME_EXTENSION) {
+        mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+    }
+
+    if capabilities.supports(SIZE_EXTENSION) {
+        mail_options.push(MailParameter::Size(message.len()));
+    }
+
+    let mut rcpt_options = Vec::new();
+
+    if let Some(dsn) = dsn {
+        if let Some(return_content) = dsn.return_content() {
+            mail_options.push(MailParameter::Other {
+                keyword: String::from("RET"),
+                value: Some(return_content.as_str().to_string()),
+            });
+        }
+
+        if let Some(envelope_id) = dsn.envelope_id() {
+            mail_options.push(MailParameter::Other {
+                keyword: String::from("ENVID"),
+                value: Some(envelope_id.to_string()),
+            });
+        }
+
+        rcpt_options.push(RcptParameter::Other {
+            keyword: String::from("NOTIFY"),
+            value: Some(dsn.notify_value()),
+        });
+    }
+
+    let reply = command(
+        &mut stream,
+        MailCommand::new(envelope.from().cloned(), mail_options),
+    )
+    .await?;
+
+    check_reply(reply, "the sender")?;
+
+    for to_address in envelope.to() {
+        let reply = command(
+            &mut stream,
+            RcptCommand::new(to_address.clone(), rcpt_options.clone()),
+        )
+        .await?;
+
+        check_reply(reply, "a recipient")?;
+    }
+
+    if chunking {
+        let chunks = chunks(message);
+
+        let last = chunks.len() - 1;
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let header = if index == last {
+                format!("BDAT {} LAST\r\n", chunk.len())
+            } else {
+                format!("BDAT {}\r\n", chunk.len())
+            };
+
+            stream.write_all(header.as_bytes()).await?;
+            stream.write_all(chunk).await?;
+            stream.flush().await?;
+
+            check_reply(read_reply(&mut stream).await?, "the message")?;
+        }
+    } else {
+        let reply = command(&mut stream, DataCommand).await?;
+
+        // DATA is answered with 354 (start input) before the message is sent.
+        check_reply(reply, "the message")?;
+
+        stream.write_all(&dot_stuff(message)).await?;
+        stream.flush().await?;
+
+        check_reply(read_reply(&mut stream).await?, "the message")?;
+    }
+
+    command(&mut stream, QuitCommand).await?;
+
+    info!(target: LOG_TARGET, "Message was sent");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_into_chunks() {
+        assert_eq!(chunks(b""), [b""]);
+
+        let message = vec![b'a'; CHUNK_SIZE * 2 + 10];
+
+        let sizes: Vec<usize> = chunks(&message).iter().map(|chunk| chunk.len()).collect();
+
+        assert_eq!(sizes, [CHUNK_SIZE, CHUNK_SIZE, 10]);
+    }
+
+    #[test]
+    fn stuff_dots() {
+        assert_eq!(
+            dot_stuff(b"Subject: Hi\r\n\r\n.\r\n..two\r\nend"),
+            b"Subject: Hi\r\n\r\n..\r\n...two\r\nend\r\n.\r\n"
+        );
+        assert_eq!(dot_stuff(b""), b".\r\n");
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "testing",
+    test
+))]
+const SIZE_EXTENSION: &str = "SIZE";
+#[cfg(any(
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "testing",
+    test
+))]
+const AUTH_EXTENSION: &str = "AUTH";
+
+/// What an outgoing mail server supports, as advertised in its EHLO response.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SmtpCapabilities {
+    max_size: Option<usize>,
+    auth_mechanisms: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl SmtpCapabilities {
+    /// Parses the lines of an EHLO response, without the reply codes. The first line is the server's greeting and is skipped.
+    #[cfg(any(
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "testing",
+        test
+    ))]
+    pub(crate) fn from_ehlo<L: AsRef<str>>(lines: &[L]) -> Self {
+        let mut capabilities = Self::default();
+
+        for line in lines.iter().skip(1) {
+            // Some older servers use `AUTH=PLAIN LOGIN` instead of `AUTH PLAIN LOGIN`.
+            let mut words = line
+                .as_ref()
+                .split(|c: char| c.is_ascii_whitespace() || c == '=')
+                .filter(|word| !word.is_empty());
+
+            let keyword = match words.next() {
+                Some(keyword) => keyword.to_ascii_uppercase(),
+                None => continue,
+            };
+
+            match keyword.as_str() {
+                SIZE_EXTENSION => {
+                    // A size of zero means the server does not have a fixed limit.
+                    capabilities.max_size = words
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .filter(|size| *size > 0);
+                }
+                AUTH_EXTENSION => {
+                    for mechanism in words {
+                        let mechanism = mechanism.to_ascii_uppercase();
+
+                        if !capabilities.auth_mechanisms.contains(&mechanism) {
+                            capabilities.auth_mechanisms.push(mechanism);
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if !capabilities.extensions.contains(&keyword) {
+                capabilities.extensions.push(keyword);
+            }
+        }
+
+        capabilities
+    }
+
+    /// The largest message (in bytes) the server accepts, if it announced a limit (SIZE).
+    pub fn max_size(&self) -> Option<usize> {
+        self.max_size
+    }
+
+    /// The authentication mechanisms the server accepts, like `PLAIN`, `LOGIN` or `XOAUTH2`.
+    pub fn auth_mechanisms(&self) -> &[String] {
+        &self.auth_mechanisms
+    }
+
+    /// Every extension keyword the server advertised, like `8BITMIME` or `CHUNKING`.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Whether the server accepts the given authentication mechanism.
+    pub fn supports_auth<M: AsRef<str>>(&self, mechanism: M) -> bool {
+        self.auth_mechanisms
+            .iter()
+            .any(|supported| supported.eq_ignore_ascii_case(mechanism.as_ref()))
+    }
+
+    /// Whether the server advertised the given extension.
+    pub fn supports<E: AsRef<str>>(&self, extension: E) -> bool {
+        self.extensions
+            .iter()
+            .any(|supported| supported.eq_ignore_ascii_case(extension.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SmtpCapabilities;
+
+    #[test]
+    fn from_ehlo() {
+        let lines = [
+            "smtp.example.com at your service",
+            "SIZE 35882577",
+            "8BITMIME",
+            "AUTH LOGIN PLAIN XOAUTH2",
+            "AUTH=LOGIN",
+            "chunking",
+        ];
+
+        let capabilities = SmtpCapabilities::from_ehlo(&lines);
+
+        assert_eq!(capabilities.max_size(), Some(35882577));
+        assert_eq!(
+            capabilities.auth_mechanisms(),
+            ["LOGIN", "PLAIN", "XOAUTH2"]
+        );
+        assert!(capabilities.supports_auth("xoauth2"));
+        assert!(!capabilities.supports_auth("CRAM-MD5"));
+        assert!(capabilities.supports("CHUNKING"));
+        assert!(capabilities.supports("8bitmime"));
+        assert!(!capabilities.supports("smtp.example.com"));
+
+        let unlimited = SmtpCapabilities::from_ehlo(&["smtp.example.com", "SIZE 0"]);
+
+        assert_eq!(unlimited.max_size(), None);
+        assert!(unlimited.supports("SIZE"));
+    }
+}
@@ -0,0 +1,113 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// When the mail server should send a delivery status notification (DSN) for a recipient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DsnNotify {
+    /// The message was delivered to the recipient's mailbox.
+    Success,
+    /// The message could not be delivered.
+    Failure,
+    /// Delivery is delayed, e.g. because the receiving server is unreachable.
+    Delay,
+}
+
+impl DsnNotify {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DsnNotify::Success => "SUCCESS",
+            DsnNotify::Failure => "FAILURE",
+            DsnNotify::Delay => "DELAY",
+        }
+    }
+}
+
+/// How much of the original message a failure notification includes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DsnReturn {
+    Full,
+    Headers,
+}
+
+impl DsnReturn {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DsnReturn::Full => "FULL",
+            DsnReturn::Headers => "HDRS",
+        }
+    }
+}
+
+/// The delivery status notifications to request when sending a message, as defined by the SMTP DSN extension (RFC 3461).
+///
+/// Servers that do not advertise DSN ignore these options and use their own defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DsnOptions {
+    notify: Vec<DsnNotify>,
+    return_content: Option<DsnReturn>,
+    envelope_id: Option<String>,
+}
+
+impl DsnOptions {
+    /// Requests a notification on success and on failure, the most common choice for transactional mail.
+    pub fn new() -> Self {
+        Self::default()
+            .with_notify(DsnNotify::Success)
+            .with_notify(DsnNotify::Failure)
+    }
+
+    /// Never send a notification, not even when delivery fails.
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    pub fn with_notify(mut self, notify: DsnNotify) -> Self {
+        if !self.notify.contains(&notify) {
+            self.notify.push(notify);
+        }
+
+        self
+    }
+
+    pub fn with_return(mut self, return_content: DsnReturn) -> Self {
+        self.return_content = Some(return_content);
+
+        self
+    }
+
+    /// An id that the server includes in the notifications, so they can be matched to the sent message.
+    pub fn with_envelope_id<I: Into<String>>(mut self, envelope_id: I) -> Self {
+        self.envelope_id = Some(envelope_id.into());
+
+        self
+    }
+
+    /// The events to notify about. When this is empty, no notifications are sent at all.
+    pub fn notify(&self) -> &[DsnNotify] {
+        &self.notify
+    }
+
+    pub fn return_content(&self) -> Option<DsnReturn> {
+        self.return_content
+    }
+
+    pub fn envelope_id(&self) -> Option<&str> {
+        self.envelope_id.as_deref()
+    }
+
+    /// The value of the `NOTIFY` parameter, e.g. `SUCCESS,FAILURE`.
+    pub fn notify_value(&self) -> String {
+        if self.notify.is_empty() {
+            return String::from("NEVER");
+        }
+
+        self.notify
+            .iter()
+            .map(DsnNotify::as_str)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
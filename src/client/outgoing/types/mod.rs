@@ -1 +1,3 @@
+pub mod capabilities;
+pub mod dsn;
 pub mod sendable;
@@ -1,43 +1,142 @@
 use std::result;
 
+use mail_builder::{headers::content_type::ContentType, mime::MimePart};
+
 use crate::{
-    client::{address::Address, builder::MessageBuilder, content::Content},
+    client::{
+        address::{validate_email_address, Address},
+        builder::MessageBuilder,
+        content::Content,
+    },
     error::{err, Error, ErrorKind},
 };
 
+use super::{super::header, dsn::DsnOptions};
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SendableMessage {
     from: Address,
+    /// The mailbox that actually sent the message, required when there is more than one address in `from`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    sender: Option<Address>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    envelope_from: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    read_receipt_to: Option<Address>,
+    /// The machine readable part of a read receipt, see `MessageBuilder::build_read_receipt`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    disposition_notification: Option<String>,
     to: Address,
     cc: Option<Address>,
     bcc: Option<Address>,
     subject: String,
     content: Content,
+    #[cfg_attr(feature = "serde", serde(default))]
+    dsn: Option<DsnOptions>,
+    /// The date in the `Date` header in seconds since epoch, the moment the message is written when missing.
+    #[cfg_attr(feature = "serde", serde(default))]
+    date: Option<i64>,
+}
+
+impl SendableMessage {
+    /// The `Sender` header, which is set automatically when the message has more than one author.
+    pub fn sender(&self) -> Option<&Address> {
+        self.sender.as_ref()
+    }
+
+    /// The envelope sender, if it differs from the sender in the `From` header.
+    pub fn envelope_from(&self) -> Option<&str> {
+        self.envelope_from.as_deref()
+    }
+
+    /// Request delivery status notifications for this message, if the mail server supports them.
+    pub fn with_dsn(mut self, dsn: DsnOptions) -> Self {
+        self.dsn = Some(dsn);
+
+        self
+    }
+
+    pub fn dsn(&self) -> Option<&DsnOptions> {
+        self.dsn.as_ref()
+    }
+
+    /// Sets the date in the `Date` header in seconds since epoch, e.g. for a message that is imported or sent later than it was written.
+    pub fn with_date(mut self, date: i64) -> Self {
+        self.date = Some(date);
+
+        self
+    }
+
+    /// The date in the `Date` header in seconds since epoch, if it was set explicitly.
+    pub fn date(&self) -> Option<i64> {
+        self.date
+    }
+
+    /// Sets the display name of the sender in the `From` header, but only if the message was not given one.
+    pub fn set_default_sender_name<N: Into<String>>(&mut self, name: N) {
+        if let Address::Single(sender) = &self.from {
+            if sender.name().map_or(true, |name| name.trim().is_empty()) {
+                self.from = Address::single(Some(name.into()), sender.email().to_string());
+            }
+        }
+    }
 }
 
 #[cfg(feature = "smtp")]
 use async_smtp::SendableEmail;
 
+#[cfg(feature = "smtp")]
+use crate::{client::address::EmailAddress, error::Result};
+
+#[cfg(feature = "smtp")]
+fn to_smtp_address(address: &EmailAddress) -> Result<async_smtp::EmailAddress> {
+    match address.email().parse() {
+        Ok(address) => Ok(address),
+        Err(err) => err!(
+            ErrorKind::InvalidMessage,
+            "Invalid email address '{}': {}",
+            address.email(),
+            err
+        ),
+    }
+}
+
 #[cfg(feature = "smtp")]
 impl TryInto<SendableEmail> for SendableMessage {
     type Error = Error;
 
     fn try_into(self) -> result::Result<SendableEmail, Self::Error> {
+        let to: Vec<EmailAddress> = self.to.as_list().into_iter().cloned().collect();
+
+        self.into_sendable_email(&to)
+    }
+}
+
+#[cfg(feature = "smtp")]
+impl SendableMessage {
+    /// Creates an email that is delivered to the given envelope recipients, regardless of the recipients in its headers.
+    pub fn into_sendable_email(self, envelope_to: &[EmailAddress]) -> Result<SendableEmail> {
         use async_smtp::Envelope;
 
-        let from: Option<async_smtp::EmailAddress> =
-            self.from.first().map(|addr| addr.email().parse().unwrap());
+        let from: Option<async_smtp::EmailAddress> = match &self.envelope_from {
+            Some(envelope_from) => Some(to_smtp_address(&EmailAddress::new(
+                None,
+                envelope_from.clone(),
+            ))?),
+            None => match self.sender.as_ref().unwrap_or(&self.from).first() {
+                Some(address) => Some(to_smtp_address(address)?),
+                None => None,
+            },
+        };
 
-        let to: Vec<async_smtp::EmailAddress> = self
-            .to
-            .as_list()
+        let to = envelope_to
             .iter()
-            .filter_map(|to| to.email().parse().ok())
-            .collect();
+            .map(to_smtp_address)
+            .collect::<Result<Vec<async_smtp::EmailAddress>>>()?;
 
         let envelope = match Envelope::new(from, to) {
             Ok(envelope) => envelope,
@@ -62,8 +161,15 @@ impl TryInto<String> for SendableMessage {
     fn try_into(self) -> result::Result<String, Self::Error> {
         let mut builder = mail_builder::MessageBuilder::new()
             .from(self.from)
-            .to(self.to)
-            .subject(self.subject);
+            .to(self.to);
+
+        if let Some(sender) = self.sender {
+            builder = builder.sender(sender);
+        }
+
+        if let Some(date) = self.date {
+            builder = builder.date(date);
+        }
 
         if let Some(cc) = self.cc {
             builder = builder.cc(cc);
@@ -73,15 +179,41 @@ impl TryInto<String> for SendableMessage {
             builder = builder.bcc(bcc);
         }
 
-        if let Some(text) = self.content.text {
-            builder = builder.text_body(text);
+        if let Some(read_receipt_to) = self.read_receipt_to {
+            builder = builder.header(
+                "Disposition-Notification-To",
+                mail_builder::headers::address::Address::from(read_receipt_to),
+            );
         }
 
-        if let Some(html) = self.content.html {
-            builder = builder.html_body(html);
+        match self.disposition_notification {
+            Some(report) => {
+                let text = self.content.text.unwrap_or_default();
+
+                builder = builder.body(MimePart::new(
+                    ContentType::new("multipart/report")
+                        .attribute("report-type", "disposition-notification"),
+                    vec![
+                        MimePart::new("text/plain", text),
+                        MimePart::new("message/disposition-notification", report),
+                    ],
+                ));
+            }
+            None => {
+                if let Some(text) = self.content.text {
+                    builder = builder.text_body(text);
+                }
+
+                if let Some(html) = self.content.html {
+                    builder = builder.html_body(html);
+                }
+            }
         }
 
-        Ok(builder.write_to_string()?)
+        // mail_builder does not keep encoded subjects within the line length limit, so we write that header ourselves.
+        let subject = header::encode_header("Subject", &self.subject);
+
+        Ok(subject + &builder.write_to_string()?)
     }
 }
 
@@ -103,13 +235,67 @@ impl TryFrom<MessageBuilder> for SendableMessage {
             }
         };
 
+        let sender = match builder.sender {
+            Some(sender) => Some(sender),
+            // RFC 5322 requires a Sender header when there are multiple authors, so we use the address the message is sent from.
+            None if from.as_list().len() > 1 => match &builder.envelope_from {
+                Some(envelope_from) => Some(Address::single(None, envelope_from.clone())),
+                None => from.first().cloned().map(Address::Single),
+            },
+            None => None,
+        };
+
+        if let Some(sender) = &sender {
+            if sender.as_list().len() != 1 {
+                err!(
+                    ErrorKind::InvalidMessage,
+                    "The message sender must be a single address"
+                );
+            }
+        }
+
+        for address in [
+            Some(&from),
+            sender.as_ref(),
+            Some(&to),
+            builder.cc.as_ref(),
+            builder.bcc.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Err(invalid) = address.validate() {
+                err!(
+                    ErrorKind::InvalidMessage,
+                    "Invalid email address: '{}'",
+                    invalid.email()
+                );
+            }
+        }
+
+        if let Some(envelope_from) = &builder.envelope_from {
+            if !validate_email_address(envelope_from) {
+                err!(
+                    ErrorKind::InvalidMessage,
+                    "Invalid envelope sender address: '{}'",
+                    envelope_from
+                );
+            }
+        }
+
         let sendable = Self {
             from,
+            sender,
+            envelope_from: builder.envelope_from,
+            read_receipt_to: builder.read_receipt_to,
+            disposition_notification: builder.disposition_notification,
             to,
             bcc: builder.bcc,
             cc: builder.cc,
             content: builder.content,
-            subject: builder.subject.unwrap_or(String::new()),
+            subject: builder.subject.unwrap_or_default(),
+            dsn: None,
+            date: builder.sent,
         };
 
         Ok(sendable)
@@ -120,6 +306,8 @@ impl TryFrom<MessageBuilder> for SendableMessage {
 mod test {
     use super::*;
 
+    use crate::client::incoming::types::message::Message;
+
     #[test]
     fn test_to_mime() {
         let builder = MessageBuilder::new()
@@ -136,4 +324,226 @@ mod test {
 
         println!("{}", message_str)
     }
+
+    #[test]
+    fn explicit_date() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .subject("Imported")
+            .sent(1_700_000_000)
+            .text("Hello world!");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+
+        assert_eq!(sendable.date(), Some(1_700_000_000));
+
+        let message: String = sendable.try_into().unwrap();
+
+        assert!(message.contains("Date: Tue, 14 Nov 2023 22:13:20 +0000\r\n"));
+
+        let parsed = Message::parse_eml(message).unwrap();
+
+        assert_eq!(parsed.sent(), Some(&1_700_000_000));
+    }
+
+    #[test]
+    fn long_unicode_subject() {
+        let subject = "Erinnerung: Die Präsentation für die Vorstandssitzung muss bis Freitag fertig sein. Bitte prüft eure Folien und schickt Änderungen rechtzeitig. Danke schön! ✓";
+
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .subject(subject)
+            .text("Hello world!");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+        let message: String = sendable.try_into().unwrap();
+
+        assert!(message.starts_with("Subject: =?UTF-8?Q?Erinnerung"));
+        assert!(message.lines().all(|line| line.len() <= 78));
+
+        let parsed = Message::parse_eml(message).unwrap();
+
+        assert_eq!(parsed.subject(), Some(subject));
+    }
+
+    #[test]
+    fn multiple_authors() {
+        let authors = Address::group(
+            None,
+            vec![
+                ("Alice", "alice@example.com").into(),
+                ("Bob", "bob@example.com").into(),
+            ],
+        );
+
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(authors.clone())
+            .subject("Shared update")
+            .text("Hello world!");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+
+        assert_eq!(
+            sendable
+                .sender()
+                .and_then(Address::first)
+                .map(|address| address.email()),
+            Some("alice@example.com")
+        );
+
+        let message: String = sendable.try_into().unwrap();
+
+        assert!(message.contains("Sender: \"Alice\" <alice@example.com>\r\n"));
+        assert!(message.contains("alice@example.com"));
+        assert!(message.contains("bob@example.com"));
+
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(authors)
+            .envelope_from("delegate@example.com")
+            .subject("Shared update")
+            .text("Hello world!");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+
+        assert_eq!(
+            sendable
+                .sender()
+                .and_then(Address::first)
+                .map(|address| address.email()),
+            Some("delegate@example.com")
+        );
+
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .sender(Address::group(
+                None,
+                vec![
+                    ("Alice", "alice@example.com").into(),
+                    ("Bob", "bob@example.com").into(),
+                ],
+            ))
+            .subject("Shared update")
+            .text("Hello world!");
+
+        assert!(builder.build::<SendableMessage>().is_err());
+    }
+
+    #[test]
+    fn default_sender_name() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(Address::single(None, String::from("user@example.com")))
+            .subject("Test email")
+            .text("Hello world!");
+
+        let mut sendable: SendableMessage = builder.build().unwrap();
+
+        sendable.set_default_sender_name("Jane Doe");
+
+        let message: String = sendable.try_into().unwrap();
+
+        assert!(message.contains("From: \"Jane Doe\" <user@example.com>"));
+
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .subject("Test email")
+            .text("Hello world!");
+
+        let mut sendable: SendableMessage = builder.build().unwrap();
+
+        sendable.set_default_sender_name("Jane Doe");
+
+        let message: String = sendable.try_into().unwrap();
+
+        assert!(!message.contains("Jane Doe"));
+    }
+
+    #[test]
+    fn read_receipt() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .read_receipt_to(Address::single(None, "user@example.com".into()))
+            .subject("Quarterly report")
+            .text("Please confirm.");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+        let request: String = sendable.try_into().unwrap();
+
+        assert!(request.contains("Disposition-Notification-To: <user@example.com>"));
+
+        let original = Message::parse_eml(request).unwrap();
+
+        assert_eq!(
+            original.read_receipt_to().unwrap().first().unwrap().email(),
+            "user@example.com"
+        );
+
+        let receipt = MessageBuilder::new()
+            .senders(("Tester", "test@example.com"))
+            .build_read_receipt(&original)
+            .unwrap();
+
+        let receipt: String = receipt.try_into().unwrap();
+
+        assert!(receipt.contains("Subject: Read: Quarterly report"));
+        assert!(receipt.contains("report-type=\"disposition-notification\""));
+        assert!(receipt.contains("Final-Recipient: rfc822; test@example.com"));
+        assert!(receipt.contains(&format!("Original-Message-ID: <{}>", original.id())));
+
+        let unrequested = MessageBuilder::new()
+            .senders(("Tester", "test@example.com"))
+            .build_read_receipt(&Message::parse_eml(receipt).unwrap());
+
+        assert!(unrequested.is_err());
+    }
+
+    #[test]
+    fn invalid_recipient() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test.example.com"))
+            .senders(("User", "user@example.com"))
+            .subject("Test email")
+            .text("Hello world!");
+
+        let error = SendableMessage::try_from(builder).unwrap_err();
+
+        assert!(error.to_string().contains("test.example.com"));
+    }
+
+    #[test]
+    fn invalid_envelope_from() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .envelope_from("bounces+user=example.com");
+
+        let error = SendableMessage::try_from(builder).unwrap_err();
+
+        assert!(error.to_string().contains("bounces+user=example.com"));
+    }
+
+    #[cfg(feature = "smtp")]
+    #[test]
+    fn envelope_from() {
+        let builder = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(("User", "user@example.com"))
+            .envelope_from("bounces+test=example.com@lists.example.com");
+
+        let sendable: SendableMessage = builder.build().unwrap();
+
+        let email: SendableEmail = sendable.try_into().unwrap();
+
+        assert_eq!(
+            email.envelope().from().map(|from| from.to_string()),
+            Some(String::from("bounces+test=example.com@lists.example.com"))
+        );
+    }
 }
@@ -0,0 +1,190 @@
+use crate::client::auth_results::{AuthResults, AuthStatus};
+
+fn parse_status(status: &str) -> Option<AuthStatus> {
+    match status.trim().to_ascii_lowercase().as_str() {
+        "pass" => Some(AuthStatus::Pass),
+        // 'hardfail' is an old name for 'fail' that some servers still use.
+        "fail" | "hardfail" => Some(AuthStatus::Fail),
+        "softfail" => Some(AuthStatus::SoftFail),
+        "neutral" => Some(AuthStatus::Neutral),
+        "none" => Some(AuthStatus::None),
+        "temperror" => Some(AuthStatus::TempError),
+        "permerror" => Some(AuthStatus::PermError),
+        "policy" => Some(AuthStatus::Policy),
+        _ => None,
+    }
+}
+
+/// Removes the comments (text between parentheses) from a header value.
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0_usize;
+
+    value
+        .chars()
+        .filter(|char| match char {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+/// Parses the `method=result` pairs in an `Authentication-Results` header, see RFC 8601.
+fn parse_header(value: &str) -> Vec<(String, AuthStatus)> {
+    let value = strip_comments(value);
+
+    // The first element is the id of the server that performed the checks.
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|result| {
+            // There may be whitespace around the '=', e.g. 'spf = pass'.
+            let (method, rest) = result.split_once('=')?;
+            let status = rest.split_whitespace().next()?;
+
+            // The method can have a version, e.g. 'dkim/1'.
+            let method = method.split('/').next()?.trim().to_ascii_lowercase();
+
+            Some((method, parse_status(status)?))
+        })
+        .collect()
+}
+
+/// Parses the authentication results from the headers of a message.
+///
+/// The topmost header of each kind is used, as it was added by the server that received the message last, which is the one we trust.
+pub fn parse_auth_results<'a, H: IntoIterator<Item = &'a (String, String)>>(
+    headers: H,
+) -> Option<AuthResults> {
+    let mut results = AuthResults::default();
+    let mut found_header = false;
+    let mut found_spf_header = false;
+
+    for (key, value) in headers {
+        if key.eq_ignore_ascii_case("Authentication-Results") {
+            if found_header {
+                continue;
+            }
+
+            found_header = true;
+
+            for (method, status) in parse_header(value) {
+                match method.as_str() {
+                    "spf" if results.spf.is_none() => results.spf = Some(status),
+                    "dmarc" if results.dmarc.is_none() => results.dmarc = Some(status),
+                    // A message can have multiple signatures, only one of them has to be valid.
+                    "dkim"
+                        if results
+                            .dkim
+                            .as_ref()
+                            .map(|dkim| !dkim.is_pass())
+                            .unwrap_or(true) =>
+                    {
+                        results.dkim = Some(status)
+                    }
+                    _ => {}
+                }
+            }
+        } else if key.eq_ignore_ascii_case("Received-SPF") && !found_spf_header {
+            found_spf_header = true;
+
+            if results.spf.is_none() {
+                results.spf = value.split_whitespace().next().and_then(parse_status);
+            }
+        }
+    }
+
+    if results == AuthResults::default() {
+        return None;
+    }
+
+    Some(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_results() {
+        let headers = headers(&[(
+            "Authentication-Results",
+            "mx.example.com; dkim=fail (bad signature) header.d=example.org; dkim=pass header.d=example.org; spf=softfail (sender IP is 192.0.2.1) smtp.mailfrom=example.org; dmarc=pass (p=NONE) header.from=example.org",
+        )]);
+
+        let results = parse_auth_results(&headers).unwrap();
+
+        assert_eq!(results.spf(), Some(&AuthStatus::SoftFail));
+        assert_eq!(results.dkim(), Some(&AuthStatus::Pass));
+        assert_eq!(results.dmarc(), Some(&AuthStatus::Pass));
+        assert!(!results.is_trusted());
+    }
+
+    #[test]
+    fn topmost_header_wins() {
+        let headers = headers(&[
+            (
+                "Authentication-Results",
+                "mx.example.com; dkim=none; dmarc=fail",
+            ),
+            (
+                "Authentication-Results",
+                "evil.example.net; dkim=pass; dmarc=pass",
+            ),
+            ("Received-SPF", "Pass (mailfrom) identity=mailfrom"),
+        ]);
+
+        let results = parse_auth_results(&headers).unwrap();
+
+        assert_eq!(results.dkim(), Some(&AuthStatus::None));
+        assert_eq!(results.dmarc(), Some(&AuthStatus::Fail));
+        assert_eq!(results.spf(), Some(&AuthStatus::Pass));
+    }
+
+    #[test]
+    fn whitespace_around_equals() {
+        let headers = headers(&[(
+            "Authentication-Results",
+            "mx.example.com; spf = pass smtp.mailfrom=example.org; dkim =fail; dmarc= pass",
+        )]);
+
+        let results = parse_auth_results(&headers).unwrap();
+
+        assert_eq!(results.spf(), Some(&AuthStatus::Pass));
+        assert_eq!(results.dkim(), Some(&AuthStatus::Fail));
+        assert_eq!(results.dmarc(), Some(&AuthStatus::Pass));
+    }
+
+    #[test]
+    fn topmost_received_spf_wins() {
+        let headers = headers(&[
+            ("Received-SPF", "unknown (mx.example.com: no result)"),
+            ("Received-SPF", "Pass (evil.example.net) identity=mailfrom"),
+        ]);
+
+        assert_eq!(parse_auth_results(&headers), None);
+    }
+
+    #[test]
+    fn no_results() {
+        let headers = headers(&[
+            ("Authentication-Results", "mx.example.com; none"),
+            ("Subject", "Hi"),
+        ]);
+
+        assert_eq!(parse_auth_results(&headers), None);
+    }
+}
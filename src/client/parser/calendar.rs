@@ -0,0 +1,145 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::client::calendar::CalendarInvite;
+
+/// Joins folded lines (lines starting with a space or tab) back together as described in RFC 5545.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in ics.lines() {
+        let line = line.trim_end_matches('\r');
+
+        match (
+            line.strip_prefix(' ').or(line.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+
+    let mut chars = value.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => {}
+            }
+        } else {
+            unescaped.push(char)
+        }
+    }
+
+    unescaped
+}
+
+/// Parses an iCalendar date or date-time value into seconds since epoch.
+///
+/// Values with a timezone id are treated as if they were in UTC, because resolving timezones requires a timezone database.
+fn parse_time(value: &str) -> Option<i64> {
+    let value = value.trim().trim_end_matches('Z');
+
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Some(datetime.timestamp());
+    }
+
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|datetime| datetime.timestamp())
+}
+
+/// Parses the first event in an iCalendar (RFC 5545) document.
+pub fn parse_invite<S: AsRef<str>>(ics: S) -> Option<CalendarInvite> {
+    let mut invite = CalendarInvite::default();
+
+    let mut in_event = false;
+    let mut found_event = false;
+
+    for line in unfold(ics.as_ref()) {
+        let (property, value) = match line.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+
+        // Strip the parameters, we only need the property name itself.
+        let name = property
+            .split(';')
+            .next()
+            .unwrap_or(property)
+            .to_ascii_uppercase();
+
+        match (name.as_str(), in_event) {
+            ("BEGIN", false) if value.eq_ignore_ascii_case("VEVENT") => {
+                in_event = true;
+                found_event = true;
+            }
+            ("END", true) if value.eq_ignore_ascii_case("VEVENT") => break,
+            ("METHOD", false) => invite.method = Some(value.trim().to_ascii_uppercase()),
+            ("UID", true) => invite.uid = Some(unescape(value)),
+            ("SUMMARY", true) => invite.summary = Some(unescape(value)),
+            ("LOCATION", true) => invite.location = Some(unescape(value)),
+            ("ORGANIZER", true) => {
+                let organizer = value
+                    .strip_prefix("mailto:")
+                    .or(value.strip_prefix("MAILTO:"))
+                    .unwrap_or(value);
+
+                invite.organizer = Some(organizer.to_string())
+            }
+            ("DTSTART", true) => invite.start = parse_time(value),
+            ("DTEND", true) => invite.end = parse_time(value),
+            _ => {}
+        }
+    }
+
+    if found_event {
+        Some(invite)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\nPRODID:-//Test//EN\r\nVERSION:2.0\r\nMETHOD:REQUEST\r\nBEGIN:VEVENT\r\nDTSTART:20230601T090000Z\r\nDTEND:20230601T100000Z\r\nUID:1234@example.com\r\nORGANIZER;CN=Tester:mailto:test@example.com\r\nSUMMARY:Weekly sync\\, planning\r\nLOCATION:Meeting room \r\n 2\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    #[test]
+    fn parse_request() {
+        let invite = parse_invite(INVITE).unwrap();
+
+        assert_eq!(invite.method(), Some("REQUEST"));
+        assert_eq!(invite.uid(), Some("1234@example.com"));
+        assert_eq!(invite.summary(), Some("Weekly sync, planning"));
+        assert_eq!(invite.location(), Some("Meeting room 2"));
+        assert_eq!(invite.organizer(), Some("test@example.com"));
+        assert_eq!(invite.start(), Some(&1685610000));
+        assert_eq!(invite.end(), Some(&1685613600));
+    }
+
+    #[test]
+    fn parse_all_day() {
+        let invite = parse_invite(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART;VALUE=DATE:20230601\nEND:VEVENT\nEND:VCALENDAR",
+        )
+        .unwrap();
+
+        assert_eq!(invite.start(), Some(&1685577600));
+        assert_eq!(invite.method(), None);
+    }
+
+    #[test]
+    fn no_event() {
+        assert_eq!(parse_invite("BEGIN:VCALENDAR\nEND:VCALENDAR"), None);
+    }
+}
@@ -0,0 +1,135 @@
+use mime::Mime;
+
+use crate::client::delivery_status::{DeliveryAction, DeliveryStatus};
+
+/// The mime type of the part in a bounce message that contains the delivery status report.
+pub fn mime_type() -> Mime {
+    "message/delivery-status".parse().unwrap()
+}
+
+/// Splits a delivery status report into its blocks of fields, joining folded lines together.
+fn parse_blocks(report: &str) -> Vec<Vec<(String, String)>> {
+    let mut blocks: Vec<Vec<(String, String)>> = Vec::new();
+    let mut current: Vec<(String, String)> = Vec::new();
+
+    for line in report.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(current);
+                current = Vec::new();
+            }
+
+            continue;
+        }
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = current.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            current.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+
+    blocks
+}
+
+/// Strips the type from a field like `rfc822; user@example.com`.
+fn strip_type(value: &str) -> String {
+    match value.split_once(';') {
+        Some((_, value)) => value.trim().to_string(),
+        None => value.trim().to_string(),
+    }
+}
+
+fn parse_action(action: &str) -> Option<DeliveryAction> {
+    match action.trim().to_ascii_lowercase().as_str() {
+        "failed" => Some(DeliveryAction::Failed),
+        "delayed" => Some(DeliveryAction::Delayed),
+        "delivered" => Some(DeliveryAction::Delivered),
+        "relayed" => Some(DeliveryAction::Relayed),
+        "expanded" => Some(DeliveryAction::Expanded),
+        _ => None,
+    }
+}
+
+/// Parses the first recipient in the body of a `message/delivery-status` part.
+pub fn parse_delivery_status<S: AsRef<str>>(report: S) -> Option<DeliveryStatus> {
+    // The first block contains the fields about the message, the blocks after that contain the fields per recipient.
+    for block in parse_blocks(report.as_ref()).into_iter().skip(1) {
+        let field = |name: &str| {
+            block
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let action = match field("action").and_then(parse_action) {
+            Some(action) => action,
+            None => continue,
+        };
+
+        let recipient = field("original-recipient")
+            .or(field("final-recipient"))
+            .map(strip_type);
+
+        return Some(DeliveryStatus {
+            action,
+            status: field("status").map(|status| status.to_string()),
+            recipient,
+            diagnostic: field("diagnostic-code").map(strip_type),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_bounce() {
+        let report = "Reporting-MTA: dns; mail.example.com\r\nArrival-Date: Mon, 1 May 2023 10:00:00 +0000\r\n\r\nFinal-Recipient: rfc822; nobody@example.org\r\nAction: failed\r\nStatus: 5.1.1\r\nDiagnostic-Code: smtp; 550 5.1.1 The email account that you tried\r\n to reach does not exist\r\n";
+
+        let status = parse_delivery_status(report).unwrap();
+
+        assert_eq!(status.action(), &DeliveryAction::Failed);
+        assert_eq!(status.status(), Some("5.1.1"));
+        assert_eq!(status.recipient(), Some("nobody@example.org"));
+        assert_eq!(
+            status.diagnostic(),
+            Some("550 5.1.1 The email account that you tried to reach does not exist")
+        );
+    }
+
+    #[test]
+    fn prefers_original_recipient() {
+        let report = "Reporting-MTA: dns; mail.example.com\n\nOriginal-Recipient: rfc822; alias@example.org\nFinal-Recipient: rfc822; real@example.org\nAction: delayed\nStatus: 4.4.7\n";
+
+        let status = parse_delivery_status(report).unwrap();
+
+        assert_eq!(status.action(), &DeliveryAction::Delayed);
+        assert_eq!(status.recipient(), Some("alias@example.org"));
+        assert_eq!(status.diagnostic(), None);
+    }
+
+    #[test]
+    fn missing_recipient_block() {
+        assert_eq!(
+            parse_delivery_status("Reporting-MTA: dns; mail.example.com\r\n"),
+            None
+        );
+    }
+}
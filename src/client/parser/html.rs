@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+
+/// Tags whose content is not part of the readable text of a page.
+const HIDDEN_TAGS: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Tags that are separated from the surrounding text by an empty line when a page is rendered.
+const PARAGRAPH_TAGS: [&str; 19] = [
+    "address",
+    "article",
+    "blockquote",
+    "dl",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Tags that start on a new line when a page is rendered.
+const LINE_TAGS: [&str; 5] = ["dd", "div", "dt", "li", "tr"];
+
+/// Entities longer than this are not decoded, so a stray `&` does not make us scan the rest of the text.
+const MAX_ENTITY_LENGTH: usize = 10;
+
+/// Converts a html page to plain text by stripping its tags and decoding its entities.
+///
+/// Block elements like paragraphs and line breaks are turned into new lines, and the content of scripts and stylesheets is removed.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut text, &rest[..start]);
+
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = match comment.find("-->") {
+                Some(end) => &comment[end + 3..],
+                None => "",
+            };
+
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => {
+                rest = "";
+
+                break;
+            }
+        };
+
+        let tag = &rest[1..end];
+
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+
+        let name = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if !closing && HIDDEN_TAGS.contains(&name.as_str()) {
+            let closing_tag = format!("</{}", name);
+
+            // Lowercasing ascii characters keeps the byte offsets the same.
+            rest = match rest.to_ascii_lowercase().find(&closing_tag) {
+                Some(index) => match rest[index..].find('>') {
+                    Some(end) => &rest[index + end + 1..],
+                    None => "",
+                },
+                None => "",
+            };
+
+            continue;
+        }
+
+        if name == "br" {
+            text.push('\n');
+        } else if PARAGRAPH_TAGS.contains(&name.as_str()) {
+            break_line(&mut text, 2);
+        } else if LINE_TAGS.contains(&name.as_str()) {
+            break_line(&mut text, 1);
+        }
+    }
+
+    push_text(&mut text, rest);
+
+    let mut lines: Vec<&str> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        // Keep at most a single empty line between paragraphs.
+        if line.is_empty() && lines.last().map_or(true, |last| last.is_empty()) {
+            continue;
+        }
+
+        lines.push(line);
+    }
+
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+/// Makes sure the text ends with the given amount of new lines, so nested blocks do not add extra empty lines.
+fn break_line(text: &mut String, new_lines: usize) {
+    text.truncate(text.trim_end_matches(' ').len());
+
+    let existing = text.chars().rev().take_while(|c| *c == '\n').count();
+
+    for _ in existing..new_lines {
+        text.push('\n');
+    }
+}
+
+/// Appends the text between two tags, collapsing whitespace like a browser would.
+fn push_text(text: &mut String, raw: &str) {
+    let mut last_was_space = text.ends_with(char::is_whitespace);
+
+    for character in decode_entities(raw).chars() {
+        if character.is_whitespace() {
+            if !last_was_space {
+                text.push(' ');
+            }
+
+            last_was_space = true;
+        } else {
+            text.push(character);
+
+            last_was_space = false;
+        }
+    }
+}
+
+fn decode_entities(raw: &str) -> Cow<'_, str> {
+    if !raw.contains('&') {
+        return raw.into();
+    }
+
+    let mut decoded = String::with_capacity(raw.len());
+
+    let mut rest = raw;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+
+        rest = &rest[start + 1..];
+
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= MAX_ENTITY_LENGTH)
+            .and_then(|end| decode_entity(&rest[..end]).map(|character| (character, end)));
+
+        match entity {
+            Some((character, end)) => {
+                decoded.push(character);
+
+                rest = &rest[end + 1..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+
+    decoded.push_str(rest);
+
+    decoded.into()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix('x').or(number.strip_prefix('X')) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+
+        return char::from_u32(code);
+    }
+
+    let character = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "euro" => '€',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => return None,
+    };
+
+    Some(character)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strips_tags() {
+        let html = r#"<html><head><title>Newsletter</title><style>p { color: red; }</style></head>
+<body>
+    <p>Hello <b>John</b>,</p>
+    <p>Prices start at &euro;5 &amp; shipping is&nbsp;free.<br>See you!</p>
+    <!-- <p>Hidden</p> -->
+    <script type="text/javascript">alert("<p>hi</p>")</script>
+    <ul><li>One</li><li>Two &#x1F600;</li></ul>
+</body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Hello John,\n\nPrices start at €5 & shipping is free.\nSee you!\n\nOne\nTwo 😀"
+        );
+    }
+
+    #[test]
+    fn keeps_invalid_entities() {
+        assert_eq!(
+            html_to_text("Tom & Jerry &bogus; &#xZZ;"),
+            "Tom & Jerry &bogus; &#xZZ;"
+        );
+        assert_eq!(html_to_text("1 &lt; 2"), "1 < 2");
+    }
+}
@@ -0,0 +1,87 @@
+/// Primary language subtags of languages that are written from right to left.
+const RTL_LANGUAGES: [&str; 12] = [
+    "ar", "arc", "dv", "fa", "he", "iw", "ji", "ku", "ps", "sd", "ur", "yi",
+];
+
+/// The amount of characters that are looked at when detecting the direction of a text.
+#[cfg(feature = "detect-direction")]
+const DETECTION_SAMPLE_SIZE: usize = 1024;
+
+/// Parses the language tags in a `Content-Language` header, e.g. `en, nl-BE`.
+pub fn parse_content_language<S: AsRef<str>>(header: S) -> Vec<String> {
+    header
+        .as_ref()
+        .split(',')
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+/// Whether the language with the given tag is written from right to left.
+pub fn is_rtl_language<S: AsRef<str>>(tag: S) -> bool {
+    let primary = tag
+        .as_ref()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    RTL_LANGUAGES.contains(&primary.as_str())
+}
+
+/// Whether a character belongs to a script that is written from right to left, like Hebrew or Arabic.
+#[cfg(feature = "detect-direction")]
+fn is_rtl_char(char: char) -> bool {
+    matches!(
+        char as u32,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF
+    )
+}
+
+/// Detects whether a text is mostly written in a right to left script, by comparing the amount of right to left letters with the amount of other letters.
+#[cfg(feature = "detect-direction")]
+pub fn is_rtl_text<S: AsRef<str>>(text: S) -> bool {
+    let (rtl, ltr) = text
+        .as_ref()
+        .chars()
+        .filter(|char| char.is_alphabetic())
+        .take(DETECTION_SAMPLE_SIZE)
+        .fold((0_usize, 0_usize), |(rtl, ltr), char| {
+            if is_rtl_char(char) {
+                (rtl + 1, ltr)
+            } else {
+                (rtl, ltr + 1)
+            }
+        });
+
+    rtl > ltr
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn content_language() {
+        assert_eq!(parse_content_language("en, nl-BE"), vec!["en", "nl-BE"]);
+        assert!(parse_content_language(" ").is_empty());
+    }
+
+    #[test]
+    fn rtl_language() {
+        assert!(is_rtl_language("he"));
+        assert!(is_rtl_language("AR-eg"));
+        assert!(!is_rtl_language("en-US"));
+        assert!(!is_rtl_language(""));
+    }
+
+    #[cfg(feature = "detect-direction")]
+    #[test]
+    fn rtl_text() {
+        assert!(is_rtl_text("שלום עולם, hello"));
+        assert!(is_rtl_text("مرحبا بالعالم"));
+        assert!(!is_rtl_text("Hello world, שלום"));
+        assert!(!is_rtl_text("1234"));
+    }
+}
@@ -1,30 +1,54 @@
 use std::collections::HashMap;
 
 use chrono::DateTime;
-use mailparse::ParsedMail;
+use log::warn;
+use mailparse::{body::Body, DispositionType, MailHeaderMap, ParsedMail};
+use mime::Mime;
 
 use crate::{
-    client::{address::Address, builder::MessageBuilder},
+    client::{
+        address::Address,
+        attachment::{Attachment, Disposition},
+        builder::MessageBuilder,
+    },
     error::Result,
 };
 
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    feature = "maildir",
+    feature = "testing",
+    test
+))]
+use crate::client::Headers;
+
+use super::{auth_results, delivery_status, language, security};
+
+const LOG_TARGET: &str = "dust_mail::parser";
+
 pub fn from_parsed_mail<'a>(parsed_mail: ParsedMail<'a>) -> Result<MessageBuilder> {
     let mut headers = HashMap::new();
+    let mut raw_headers = Vec::new();
 
     for header in parsed_mail.get_headers().into_iter() {
-        match headers.insert(header.get_key(), header.get_value()) {
-            _ => {}
-        }
+        raw_headers.push((header.get_key(), header.get_value()));
+
+        headers.insert(header.get_key(), header.get_value());
     }
 
     let subject = headers.get("Subject").cloned();
 
+    // A malformed date header should not make the entire message unreadable, so we just ignore it.
     let sent = match headers.get("Date") {
-        Some(date) => {
-            let datetime = DateTime::parse_from_rfc2822(date.trim())?;
+        Some(date) => match DateTime::parse_from_rfc2822(date.trim()) {
+            Ok(datetime) => Some(datetime.timestamp()),
+            Err(err) => {
+                warn!(target: LOG_TARGET, "Failed to parse date header '{}': {}", date, err);
 
-            Some(datetime.timestamp())
-        }
+                None
+            }
+        },
         None => None,
     };
 
@@ -33,6 +57,30 @@ pub fn from_parsed_mail<'a>(parsed_mail: ParsedMail<'a>) -> Result<MessageBuilde
         None => Vec::new(),
     };
 
+    let sender = match headers.get("Sender") {
+        Some(sender) => Address::from_header(sender)?.into_iter().next(),
+        None => None,
+    };
+
+    // The return path is a single address enclosed in angle brackets, an empty path ('<>') means there is no return path.
+    let return_path = headers
+        .get("Return-Path")
+        .map(|path| {
+            path.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .trim()
+        })
+        .filter(|path| !path.is_empty())
+        .map(|path| path.to_string());
+
+    // A malformed receipt request should not make the message unreadable, so it is ignored instead.
+    let read_receipt_to = headers
+        .get("Disposition-Notification-To")
+        .and_then(|value| Address::from_header(value).ok())
+        .filter(|list| !list.is_empty())
+        .map(Address::from);
+
     let to = match headers.get("To") {
         Some(to) => Address::from_header(to)?,
         None => Vec::new(),
@@ -48,60 +96,82 @@ pub fn from_parsed_mail<'a>(parsed_mail: ParsedMail<'a>) -> Result<MessageBuilde
         None => Vec::new(),
     };
 
-    let mut message_builder = MessageBuilder::new().headers(headers);
+    let auth_results = auth_results::parse_auth_results(&raw_headers);
 
-    if from.len() > 0 {
-        message_builder = message_builder.senders(from);
+    let find_header = |name: &str| {
+        raw_headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string())
+    };
+
+    let languages = find_header("Content-Language")
+        .map(language::parse_content_language)
+        .unwrap_or_default();
+
+    let thread_index = find_header("Thread-Index");
+    let thread_topic = find_header("Thread-Topic");
+    let gmail_thread_id = find_header("X-GM-THRID");
+
+    let mut message_builder = MessageBuilder::new()
+        .headers(headers)
+        .raw_headers(raw_headers);
+
+    if let Some(auth_results) = auth_results {
+        message_builder = message_builder.auth_results(auth_results);
     }
 
-    if to.len() > 0 {
-        message_builder = message_builder.recipients(to);
+    if !languages.is_empty() {
+        message_builder = message_builder.languages(languages);
     }
 
-    if cc.len() > 0 {
-        message_builder = message_builder.cc(cc);
+    if let Some(thread_index) = thread_index {
+        message_builder = message_builder.thread_index(thread_index);
     }
 
-    if bcc.len() > 0 {
-        message_builder = message_builder.bcc(bcc);
+    if let Some(thread_topic) = thread_topic {
+        message_builder = message_builder.thread_topic(thread_topic);
     }
 
-    // let mut text: Option<String> = None;
-    // let mut html: Option<String> = None;
+    if let Some(gmail_thread_id) = gmail_thread_id {
+        message_builder = message_builder.gmail_thread_id(gmail_thread_id);
+    }
 
-    // for part in parsed_mail.parts() {
-    //     let headers = part.get_headers();
+    if !from.is_empty() {
+        message_builder = message_builder.senders(from);
+    }
 
-    //     for header in headers {
-    //         let key = header.get_key_ref().trim().to_ascii_lowercase();
+    if let Some(sender) = sender {
+        message_builder = message_builder.sender(sender);
+    }
 
-    //         if key == "content-type" {
-    //             let value = header.get_value().trim().to_ascii_lowercase();
+    if let Some(return_path) = return_path {
+        message_builder = message_builder.return_path(return_path);
+    }
+
+    if let Some(read_receipt_to) = read_receipt_to {
+        message_builder = message_builder.read_receipt_to(read_receipt_to);
+    }
+
+    if !to.is_empty() {
+        message_builder = message_builder.recipients(to);
+    }
 
-    //             let body = Some(part.get_body()?);
+    if !cc.is_empty() {
+        message_builder = message_builder.cc(cc);
+    }
 
-    //             if value.starts_with("text/plain") {
-    //                 text = match body {
-    //                     Some(body_data) => Some(super::sanitize_text(&body_data)),
-    //                     None => None,
-    //                 }
-    //             } else if value.starts_with("text/html") {
-    //                 html = match body {
-    //                     Some(body_data) => Some(super::sanitize_html(&body_data)),
-    //                     None => None,
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
+    if !bcc.is_empty() {
+        message_builder = message_builder.bcc(bcc);
+    }
 
-    // if let Some(html) = html {
-    //     message_builder = message_builder.html(html)
-    // }
+    if let Some(text) = find_text_part(&parsed_mail, &mime::TEXT_PLAIN)? {
+        message_builder = message_builder.text(text);
+    }
 
-    // if let Some(text) = text {
-    //     message_builder = message_builder.text(text)
-    // }
+    if let Some(html) = find_text_part(&parsed_mail, &mime::TEXT_HTML)? {
+        message_builder = message_builder.html(html);
+    }
 
     if let Some(subject) = subject {
         message_builder = message_builder.subject(subject);
@@ -111,11 +181,455 @@ pub fn from_parsed_mail<'a>(parsed_mail: ParsedMail<'a>) -> Result<MessageBuilde
         message_builder = message_builder.sent(sent);
     }
 
+    let protection = security::detect(
+        &parsed_mail.ctype.mimetype,
+        parsed_mail
+            .ctype
+            .params
+            .get("smime-type")
+            .map(|value| value.as_str()),
+    );
+
+    message_builder = message_builder
+        .signed(protection.signed)
+        .encrypted(protection.encrypted);
+
+    let attachments = extract_attachments(&parsed_mail)?;
+
+    if !attachments.is_empty() {
+        message_builder = message_builder.attachments(attachments);
+    }
+
+    if let Some(report) = find_part(&parsed_mail, &delivery_status::mime_type())? {
+        if let Some(status) =
+            delivery_status::parse_delivery_status(String::from_utf8_lossy(&report))
+        {
+            message_builder = message_builder.delivery_status(status);
+        }
+    }
+
     Ok(message_builder)
 }
 
+/// Lists the files in a parsed mail, following the same rules as the Imap body structure parser: parts are numbered like Imap part numbers (`1`, `2.1`, ...) and the text of the message itself is left out.
+pub fn extract_attachments<'a>(parsed_mail: &ParsedMail<'a>) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+
+    if parsed_mail.subparts.is_empty() {
+        // A message that is not multipart consists of a single part with number 1.
+        extract_attachments_rec(parsed_mail, "1".to_string(), &mut attachments)?;
+    } else {
+        extract_attachments_rec(parsed_mail, String::new(), &mut attachments)?;
+    }
+
+    Ok(attachments)
+}
+
+fn extract_attachments_rec<'a>(
+    part: &ParsedMail<'a>,
+    part_number: String,
+    attachments: &mut Vec<Attachment>,
+) -> Result<()> {
+    if !part.subparts.is_empty() {
+        for (index, subpart) in part.subparts.iter().enumerate() {
+            let subpart_number = if part_number.is_empty() {
+                (index + 1).to_string()
+            } else {
+                format!("{}.{}", part_number, index + 1)
+            };
+
+            extract_attachments_rec(subpart, subpart_number, attachments)?;
+        }
+
+        return Ok(());
+    }
+
+    let content_disposition = part.get_content_disposition();
+
+    let file_name = content_disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+
+    // Mailparse treats a missing disposition as inline, so we check whether the header is there ourselves.
+    let has_disposition = part
+        .headers
+        .get_first_value("Content-Disposition")
+        .is_some();
+
+    let disposition = match content_disposition.disposition {
+        DispositionType::Attachment => Disposition::Attachment,
+        DispositionType::Inline if has_disposition => Disposition::Inline,
+        _ if file_name.is_some() => Disposition::Inline,
+        _ => return Ok(()),
+    };
+
+    let is_body_text = part.ctype.mimetype.eq_ignore_ascii_case("text/plain")
+        || part.ctype.mimetype.eq_ignore_ascii_case("text/html");
+
+    if disposition == Disposition::Inline && file_name.is_none() && is_body_text {
+        return Ok(());
+    }
+
+    // Like the size in an Imap body structure, this is the size of the part in its transfer encoding.
+    let size = encoded_body(part).len();
+
+    attachments.push(Attachment::new(part_number, file_name, size, disposition));
+
+    Ok(())
+}
+
+/// Finds the part with the given number (as listed by `extract_attachments`) in a parsed mail and returns its body in its transfer encoding, the same way an Imap server returns a body section.
+#[cfg(any(feature = "pop", feature = "maildir", test))]
+pub fn find_attachment<'a>(parsed_mail: &ParsedMail<'a>, part_number: &str) -> Option<Vec<u8>> {
+    // A message that is not multipart consists of a single part with number 1.
+    if parsed_mail.subparts.is_empty() {
+        return (part_number == "1").then(|| encoded_body(parsed_mail).to_vec());
+    }
+
+    let mut part = parsed_mail;
+
+    for index in part_number.split('.') {
+        let index: usize = index.parse().ok()?;
+
+        part = part.subparts.get(index.checked_sub(1)?)?;
+    }
+
+    Some(encoded_body(part).to_vec())
+}
+
+fn encoded_body<'a>(part: &'a ParsedMail<'a>) -> &'a [u8] {
+    match part.get_body_encoded() {
+        Body::Base64(body) | Body::QuotedPrintable(body) => body.get_raw(),
+        Body::SevenBit(body) | Body::EightBit(body) => body.get_raw(),
+        Body::Binary(body) => body.get_raw(),
+    }
+}
+
+/// Finds the first (sub)part in a parsed mail that has the given mime type and returns its decoded body.
+pub fn find_part<'a>(parsed_mail: &ParsedMail<'a>, mime_type: &Mime) -> Result<Option<Vec<u8>>> {
+    let matches = match parsed_mail.ctype.mimetype.parse::<Mime>() {
+        Ok(part_type) => part_type.essence_str() == mime_type.essence_str(),
+        Err(_) => false,
+    };
+
+    if matches {
+        return Ok(Some(parsed_mail.get_body_raw()?));
+    }
+
+    for subpart in parsed_mail.subparts.iter() {
+        if let Some(body) = find_part(subpart, mime_type)? {
+            return Ok(Some(body));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the first (sub)part in a parsed mail with the given text mime type that is not an attachment and returns it as a string.
+fn find_text_part<'a>(parsed_mail: &ParsedMail<'a>, mime_type: &Mime) -> Result<Option<String>> {
+    let is_attachment =
+        parsed_mail.get_content_disposition().disposition == DispositionType::Attachment;
+
+    if !is_attachment
+        && parsed_mail
+            .ctype
+            .mimetype
+            .eq_ignore_ascii_case(mime_type.essence_str())
+    {
+        return Ok(Some(parsed_mail.get_body()?));
+    }
+
+    for subpart in parsed_mail.subparts.iter() {
+        if let Some(body) = find_text_part(subpart, mime_type)? {
+            return Ok(Some(body));
+        }
+    }
+
+    Ok(None)
+}
+
 pub fn from_rfc822<B: AsRef<[u8]>>(bytes: B) -> Result<MessageBuilder> {
     let parsed = mailparse::parse_mail(bytes.as_ref())?;
 
-    Ok(from_parsed_mail(parsed)?)
+    from_parsed_mail(parsed)
+}
+
+/// Parses only the given headers from the start of a message, keyed by the names they were requested with.
+///
+/// Headers the message does not have are left out. When a header appears more than once, its first value is used.
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    feature = "maildir",
+    feature = "testing",
+    test
+))]
+pub fn parse_specific_headers<B: AsRef<[u8]>>(bytes: B, names: &[&str]) -> Result<Headers> {
+    let (headers, _) = mailparse::parse_headers(bytes.as_ref())?;
+
+    let mut found = HashMap::new();
+
+    for name in names {
+        if let Some(value) = headers.get_first_value(name) {
+            found.insert(name.to_string(), value);
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalid_date_header() {
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nDate: not a real date\r\nSubject: Broken date\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert_eq!(builder.sent, None);
+        assert_eq!(builder.subject.as_deref(), Some("Broken date"));
+    }
+
+    #[test]
+    fn bounce_report() {
+        let raw = "From: Mail Delivery System <mailer-daemon@example.com>\r\nTo: user@example.com\r\nSubject: Undelivered Mail Returned to Sender\r\nContent-Type: multipart/report; report-type=delivery-status; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: text/plain\r\n\r\nYour message could not be delivered.\r\n--sep\r\nContent-Type: message/delivery-status\r\n\r\nReporting-MTA: dns; mail.example.com\r\n\r\nFinal-Recipient: rfc822; nobody@example.org\r\nAction: failed\r\nStatus: 5.1.1\r\n\r\n--sep--\r\n";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        let status = builder.delivery_status.unwrap();
+
+        assert_eq!(status.recipient(), Some("nobody@example.org"));
+        assert_eq!(status.status(), Some("5.1.1"));
+
+        let normal = from_rfc822("From: test@example.com\r\n\r\nHello world!").unwrap();
+
+        assert!(normal.delivery_status.is_none());
+    }
+
+    #[test]
+    fn ordered_headers() {
+        let raw = "Received: from b.example.com\r\nReceived: from a.example.com\r\nFrom: Tester <test@example.com>\r\nSubject: Hi\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        let keys: Vec<_> = builder
+            .raw_headers
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+
+        assert_eq!(keys, vec!["Received", "Received", "From", "Subject"]);
+        assert_eq!(builder.raw_headers[1].1, "from a.example.com");
+        assert_eq!(builder.headers.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn specific_headers() {
+        let raw = "Received: from b.example.com\r\nReceived: from a.example.com\r\nList-Id: Announcements <announce.example.com>\r\nSubject: Hi\r\n\r\nHello world!";
+
+        let headers = parse_specific_headers(raw, &["list-id", "Received", "Message-ID"]).unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(
+            headers.get("list-id").map(String::as_str),
+            Some("Announcements <announce.example.com>")
+        );
+        assert_eq!(
+            headers.get("Received").map(String::as_str),
+            Some("from b.example.com")
+        );
+    }
+
+    #[test]
+    fn thread_headers() {
+        let raw = "From: Tester <test@example.com>\r\nSubject: RE: Planning\r\nThread-Topic: Planning\r\nthread-index: AQHZa1b2c3d4e5f6g7h8i9j0k1l2m3\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert_eq!(builder.thread_topic.as_deref(), Some("Planning"));
+        assert_eq!(
+            builder.thread_index.as_deref(),
+            Some("AQHZa1b2c3d4e5f6g7h8i9j0k1l2m3")
+        );
+        assert_eq!(builder.gmail_thread_id, None);
+    }
+
+    #[test]
+    fn content_language() {
+        let raw = "From: Tester <test@example.com>\r\nContent-Language: he, en\r\nSubject: Shalom\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert_eq!(builder.languages, vec!["he", "en"]);
+    }
+
+    #[test]
+    fn parse_eml_with_attachments() {
+        use crate::client::{attachment::Disposition, message::Message};
+
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nSubject: Invoice\r\nMessage-ID: <invoice-1@example.com>\r\nContent-Type: multipart/mixed; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n--sep\r\nContent-Type: image/png; name=\"logo.png\"\r\nContent-ID: <logo>\r\nContent-Transfer-Encoding: base64\r\n\r\naGVsbG8=\r\n--sep\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"invoice.pdf\"\r\n\r\n%PDF-1.4\r\n--sep--\r\n";
+
+        let message = Message::parse_eml(raw).unwrap();
+
+        assert_eq!(message.id(), "invoice-1@example.com");
+        assert_eq!(message.content().text(), Some("See attached.\r\n"));
+
+        let attachments: Vec<_> = message
+            .attachments()
+            .iter()
+            .map(|attachment| {
+                (
+                    attachment.id(),
+                    attachment.file_name().map(String::as_str),
+                    attachment.size(),
+                    attachment.disposition(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            attachments,
+            vec![
+                ("2", Some("logo.png"), 10, Disposition::Inline),
+                ("3", Some("invoice.pdf"), 10, Disposition::Attachment),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_attachment_by_part_number() {
+        let raw = "From: Tester <test@example.com>\r\nContent-Type: multipart/mixed; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: multipart/alternative; boundary=\"inner\"\r\n\r\n--inner\r\nContent-Type: text/plain\r\n\r\nHello\r\n--inner\r\nContent-Type: text/html\r\n\r\n<p>Hello</p>\r\n--inner--\r\n--sep\r\nContent-Type: image/png; name=\"logo.png\"\r\nContent-Transfer-Encoding: base64\r\n\r\naGVsbG8=\r\n--sep--\r\n";
+
+        let parsed = mailparse::parse_mail(raw.as_bytes()).unwrap();
+
+        assert_eq!(
+            find_attachment(&parsed, "2").as_deref(),
+            Some("aGVsbG8=\r\n".as_bytes())
+        );
+        assert_eq!(
+            find_attachment(&parsed, "1.2").as_deref(),
+            Some("<p>Hello</p>\r\n".as_bytes())
+        );
+
+        for missing in ["0", "3", "1.3", "2.1", "logo", ""] {
+            assert_eq!(find_attachment(&parsed, missing), None);
+        }
+
+        let single =
+            mailparse::parse_mail(b"Content-Type: application/pdf\r\n\r\n%PDF-1.4\r\n").unwrap();
+
+        assert_eq!(
+            find_attachment(&single, "1").as_deref(),
+            Some("%PDF-1.4\r\n".as_bytes())
+        );
+        assert_eq!(find_attachment(&single, "2"), None);
+    }
+
+    #[test]
+    fn signed_message() {
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nSubject: Signed\r\nContent-Type: multipart/signed; protocol=\"application/pgp-signature\"; micalg=pgp-sha256; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: text/plain\r\n\r\nHello world!\r\n--sep\r\nContent-Type: application/pgp-signature\r\n\r\n-----BEGIN PGP SIGNATURE-----\r\n--sep--\r\n";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert!(builder.signed);
+        assert!(!builder.encrypted);
+    }
+
+    #[test]
+    fn find_calendar_part() {
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nContent-Type: multipart/alternative; boundary=\"sep\"\r\n\r\n--sep\r\nContent-Type: text/plain\r\n\r\nHello world!\r\n--sep\r\nContent-Type: text/calendar; method=REQUEST\r\n\r\nBEGIN:VCALENDAR\r\n--sep--\r\n";
+
+        let parsed = mailparse::parse_mail(raw.as_bytes()).unwrap();
+
+        let calendar = find_part(&parsed, &"text/calendar".parse().unwrap()).unwrap();
+
+        assert_eq!(calendar.as_deref(), Some("BEGIN:VCALENDAR\r\n".as_bytes()));
+
+        let missing = find_part(&parsed, &mime::IMAGE_PNG).unwrap();
+
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn sender_and_return_path() {
+        let raw = "From: Tester <test@example.com>\r\nSender: Assistant <assistant@example.com>\r\nReturn-Path: <bounces@example.com>\r\nTo: user@example.com\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        let sender = builder.sender.unwrap();
+
+        assert_eq!(sender.first().unwrap().email(), "assistant@example.com");
+        assert_eq!(builder.return_path.as_deref(), Some("bounces@example.com"));
+
+        let raw = "From: Tester <test@example.com>\r\nReturn-Path: <>\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert!(builder.sender.is_none());
+        assert!(builder.return_path.is_none());
+    }
+
+    #[test]
+    fn read_receipt_request() {
+        let raw = "From: Tester <test@example.com>\r\nDisposition-Notification-To: Tester <receipts@example.com>\r\nTo: user@example.com\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        let read_receipt_to = builder.read_receipt_to.unwrap();
+
+        assert_eq!(
+            read_receipt_to.first().unwrap().email(),
+            "receipts@example.com"
+        );
+    }
+
+    #[test]
+    fn valid_date_header() {
+        let raw = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nDate: Tue, 1 Jul 2003 10:52:37 +0200\r\n\r\nHello world!";
+
+        let builder = from_rfc822(raw).unwrap();
+
+        assert_eq!(builder.sent, Some(1057049557));
+    }
+
+    #[test]
+    fn preview_snippet() {
+        use crate::client::incoming::types::message::Preview;
+
+        let raw = "From: Tester <test@example.com>\r\nSubject: Hi\r\nContent-Type: text/html\r\n\r\n<p>Hello\r\n  <b>world</b>!</p>";
+
+        let preview: Preview = from_rfc822(raw).unwrap().id("1").build().unwrap();
+
+        assert_eq!(preview.snippet(), Some("Hello world!"));
+    }
+
+    #[test]
+    fn preview_senders() {
+        use crate::client::incoming::types::message::Preview;
+
+        let raw = "From: Alice <alice@example.com>, bob@example.com\r\nSubject: Hi\r\n\r\nHello";
+
+        let preview: Preview = from_rfc822(raw).unwrap().id("1").build().unwrap();
+
+        let senders: Vec<_> = preview
+            .from()
+            .iter()
+            .filter_map(|sender| sender.first())
+            .map(|sender| sender.email())
+            .collect();
+
+        assert_eq!(senders, vec!["alice@example.com", "bob@example.com"]);
+
+        let raw = "From: Team: alice@example.com, bob@example.com;\r\nSubject: Hi\r\n\r\nHello";
+
+        let preview: Preview = from_rfc822(raw).unwrap().id("1").build().unwrap();
+
+        assert_eq!(preview.from().len(), 1);
+        assert_eq!(preview.from()[0].display_name(), "Team");
+        assert_eq!(preview.from()[0].as_list().len(), 2);
+    }
 }
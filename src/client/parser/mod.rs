@@ -1,7 +1,24 @@
 pub mod address;
+pub mod auth_results;
+pub mod calendar;
+pub mod delivery_status;
+pub mod html;
+pub mod language;
 pub mod message;
+pub mod quote;
+pub mod security;
+pub mod thread;
+pub mod tracking;
 
-const ALLOWED_HTML_TAGS: [&str; 71] = [
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+const ALLOWED_HTML_TAGS: [&str; 72] = [
     "address",
     "article",
     "aside",
@@ -73,6 +90,21 @@ const ALLOWED_HTML_TAGS: [&str; 71] = [
     "thead",
     "tr",
     "center",
+    "img",
+];
+
+const IMAGE_HTML_ATTRIBUTES: [&str; 4] = ["src", "alt", "width", "height"];
+
+/// Url schemes that are allowed on top of the default ones, `cid` is used to reference inline attachments.
+const EXTRA_URL_SCHEMES: [&str; 2] = ["cid", "data"];
+
+/// Image types that are safe to be embedded using a `data:` url.
+const ALLOWED_DATA_IMAGE_TYPES: [&str; 5] = [
+    "data:image/png",
+    "data:image/gif",
+    "data:image/jpeg",
+    "data:image/jpg",
+    "data:image/webp",
 ];
 
 const GENERIC_HTML_ATTRIBUTES: [&str; 12] = [
@@ -90,18 +122,165 @@ const GENERIC_HTML_ATTRIBUTES: [&str; 12] = [
     "align",
 ];
 
-pub fn sanitize_html(dirty: &str) -> String {
+/// Controls what the html sanitizer lets through.
+#[derive(Debug, Clone)]
+pub struct SanitizePolicy {
+    allow_images: bool,
+    block_remote_images: bool,
+    block_trackers: bool,
+    strip_tracking_params: bool,
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        Self {
+            allow_images: true,
+            block_remote_images: true,
+            block_trackers: true,
+            strip_tracking_params: false,
+        }
+    }
+}
+
+impl SanitizePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether images should be rendered at all.
+    pub fn allow_images(mut self, allow_images: bool) -> Self {
+        self.allow_images = allow_images;
+
+        self
+    }
+
+    /// Whether images that are loaded from a remote server should be removed, enabled by default.
+    ///
+    /// Remote images are often used to track when and where an email was opened.
+    pub fn block_remote_images(mut self, block_remote_images: bool) -> Self {
+        self.block_remote_images = block_remote_images;
+
+        self
+    }
+
+    /// Whether images that look like tracking pixels should be removed, even if remote images are allowed. Enabled by default.
+    ///
+    /// Tracking pixels are recognized by their tiny size, by being hidden or by being loaded from a known tracking service.
+    pub fn block_trackers(mut self, block_trackers: bool) -> Self {
+        self.block_trackers = block_trackers;
+
+        self
+    }
+
+    /// Whether click tracking parameters, like `utm_source`, should be removed from links.
+    pub fn strip_tracking_params(mut self, strip_tracking_params: bool) -> Self {
+        self.strip_tracking_params = strip_tracking_params;
+
+        self
+    }
+
+    fn filter_attribute<'u>(
+        &self,
+        element: &str,
+        attribute: &str,
+        value: &'u str,
+        trackers_removed: &AtomicUsize,
+    ) -> Option<Cow<'u, str>> {
+        let lowercase = value.trim().to_ascii_lowercase();
+
+        if element == "a" && attribute == "href" && self.strip_tracking_params {
+            if let Some(cleaned) = tracking::strip_tracking_params(value) {
+                trackers_removed.fetch_add(1, Ordering::Relaxed);
+
+                return Some(cleaned.into());
+            }
+        }
+
+        if element == "img" && attribute == "src" {
+            if !self.allow_images {
+                return None;
+            }
+
+            let is_remote = lowercase.starts_with("http:")
+                || lowercase.starts_with("https:")
+                || lowercase.starts_with("//");
+
+            if is_remote && self.block_remote_images {
+                return None;
+            }
+
+            if lowercase.starts_with("data:")
+                && !ALLOWED_DATA_IMAGE_TYPES
+                    .iter()
+                    .any(|image_type| lowercase.starts_with(image_type))
+            {
+                return None;
+            }
+        } else if lowercase.starts_with("data:") {
+            // Data urls are only allowed as image sources.
+            return None;
+        }
+
+        Some(value.into())
+    }
+}
+
+pub fn sanitize_html_with_policy(dirty: &str, policy: &SanitizePolicy) -> String {
+    sanitize_html_with_report(dirty, policy).into_html()
+}
+
+/// A html page that was cleaned by the sanitizer.
+#[derive(Debug, Clone)]
+pub struct SanitizedHtml {
+    html: String,
+    trackers_removed: usize,
+}
+
+impl SanitizedHtml {
+    /// The cleaned html page.
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// The amount of tracking pixels and tracking links that were removed, e.g. to show "3 trackers blocked".
+    pub fn trackers_removed(&self) -> usize {
+        self.trackers_removed
+    }
+
+    pub fn into_html(self) -> String {
+        self.html
+    }
+}
+
+/// Like `sanitize_html_with_policy`, but also reports how many trackers were removed.
+pub fn sanitize_html_with_report(dirty: &str, policy: &SanitizePolicy) -> SanitizedHtml {
+    let (dirty, pixels_removed) = if policy.block_trackers {
+        tracking::strip_tracking_pixels(dirty)
+    } else {
+        (Cow::Borrowed(dirty), 0)
+    };
+
+    let links_cleaned = Arc::new(AtomicUsize::new(0));
+
+    let counter = Arc::clone(&links_cleaned);
+
+    let policy = policy.clone();
+
     let clean = ammonia::Builder::new()
         .add_tags(ALLOWED_HTML_TAGS)
         .add_generic_attributes(GENERIC_HTML_ATTRIBUTES)
-        .clean(dirty)
+        .add_tag_attributes("img", IMAGE_HTML_ATTRIBUTES)
+        .add_url_schemes(EXTRA_URL_SCHEMES)
+        .attribute_filter(move |element, attribute, value| {
+            policy.filter_attribute(element, attribute, value, &counter)
+        })
+        .clean(&dirty)
         .to_string();
 
-    clean
-}
-
-pub fn sanitize_text(dirty: &str) -> String {
-    ammonia::clean_text(dirty)
+    SanitizedHtml {
+        html: clean,
+        trackers_removed: pixels_removed + links_cleaned.load(Ordering::Relaxed),
+    }
 }
 
 #[cfg(feature = "json")]
@@ -119,3 +298,87 @@ pub mod json {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sanitize_html(dirty: &str) -> String {
+        sanitize_html_with_policy(dirty, &SanitizePolicy::default())
+    }
+
+    #[test]
+    fn blocks_remote_images_by_default() {
+        let clean = sanitize_html(r#"<img src="https://images.example.com/logo.gif" alt="logo">"#);
+
+        assert_eq!(clean, r#"<img alt="logo">"#);
+    }
+
+    #[test]
+    fn allows_inline_images() {
+        let clean = sanitize_html(r#"<img src="cid:logo@example.com" width="10">"#);
+
+        assert_eq!(clean, r#"<img src="cid:logo@example.com" width="10">"#);
+
+        let clean = sanitize_html(r#"<img src="data:image/png;base64,AAAA">"#);
+
+        assert_eq!(clean, r#"<img src="data:image/png;base64,AAAA">"#);
+    }
+
+    #[test]
+    fn blocks_dangerous_urls() {
+        let clean = sanitize_html(r#"<img src="data:image/svg+xml;base64,AAAA">"#);
+
+        assert_eq!(clean, "<img>");
+
+        let clean = sanitize_html(r#"<a href="data:text/html;base64,AAAA">link</a>"#);
+
+        assert_eq!(clean, r#"<a rel="noopener noreferrer">link</a>"#);
+
+        let clean = sanitize_html(r#"<img src="javascript:alert(1)">"#);
+
+        assert_eq!(clean, "<img>");
+    }
+
+    #[test]
+    fn allows_remote_images_when_configured() {
+        let policy = SanitizePolicy::new().block_remote_images(false);
+
+        let clean =
+            sanitize_html_with_policy(r#"<img src="https://example.com/photo.png">"#, &policy);
+
+        assert_eq!(clean, r#"<img src="https://example.com/photo.png">"#);
+
+        let policy = SanitizePolicy::new().allow_images(false);
+
+        let clean = sanitize_html_with_policy(r#"<img src="cid:logo@example.com">"#, &policy);
+
+        assert_eq!(clean, "<img>");
+    }
+
+    #[test]
+    fn reports_removed_trackers() {
+        let dirty = r#"<p>Hi</p><img src="https://example.com/open.gif" width="1" height="1"><a href="https://example.com/?utm_campaign=spring">Shop</a>"#;
+
+        let policy = SanitizePolicy::new()
+            .block_remote_images(false)
+            .strip_tracking_params(true);
+
+        let clean = sanitize_html_with_report(dirty, &policy);
+
+        assert_eq!(clean.trackers_removed(), 2);
+        assert_eq!(
+            clean.html(),
+            r#"<p>Hi</p><a href="https://example.com/" rel="noopener noreferrer">Shop</a>"#
+        );
+
+        let policy = SanitizePolicy::new()
+            .block_remote_images(false)
+            .block_trackers(false);
+
+        assert_eq!(
+            sanitize_html_with_report(dirty, &policy).trackers_removed(),
+            0
+        );
+    }
+}
@@ -0,0 +1,168 @@
+/// The line Outlook puts above the message it replies to.
+const ORIGINAL_MESSAGE_SEPARATOR: &str = "-----original message-----";
+
+/// Outlook separates the quoted message with a line of underscores when replying in html.
+const UNDERSCORE_SEPARATOR: &str = "________________________________";
+
+fn is_quoted(line: &str) -> bool {
+    line.trim_start().starts_with('>')
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Whether a line introduces a quote, like `On Mon, 1 Jan 2024 at 10:00, Jane <jane@example.com> wrote:`.
+///
+/// Long attributions are often wrapped, so the line before it is passed along to find where it starts.
+fn attribution_start(previous: Option<&str>, line: &str) -> Option<usize> {
+    let line = line.trim();
+
+    if !line.ends_with("wrote:") {
+        return None;
+    }
+
+    if line.starts_with("On ") {
+        return Some(0);
+    }
+
+    match previous {
+        Some(previous) if previous.trim().starts_with("On ") => Some(1),
+        _ => Some(0),
+    }
+}
+
+/// Whether a line separates the reply from a quoted message that follows it without being quoted line by line.
+fn is_separator(line: &str, next: Option<&str>) -> bool {
+    let line = line.trim();
+
+    if line.eq_ignore_ascii_case(ORIGINAL_MESSAGE_SEPARATOR) {
+        return true;
+    }
+
+    line.starts_with(UNDERSCORE_SEPARATOR)
+        && line.chars().all(|c| c == '_')
+        && next.map_or(false, |next| next.trim_start().starts_with("From:"))
+}
+
+/// Splits the text of a reply into what the sender wrote and the quoted message it replies to, so the quoted history can be collapsed.
+///
+/// Only a quote at the end of the message is split off, answers written in between quoted lines are kept in the new part. When the whole message looks quoted, like with a forwarded message, nothing is split off.
+pub fn split_quoted(text: &str) -> (&str, Option<&str>) {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\r', '\n'])));
+
+        offset += line.len();
+    }
+
+    // Everything after a separator is the quoted message.
+    let separator = (0..lines.len())
+        .find(|&index| is_separator(lines[index].1, lines.get(index + 1).map(|(_, line)| *line)));
+
+    // The lines quoted with `>` at the end of the message, along with the blank lines around them.
+    let mut quote_start = lines.len();
+
+    while quote_start > 0 && {
+        let line = lines[quote_start - 1].1;
+
+        is_quoted(line) || is_blank(line)
+    } {
+        quote_start -= 1;
+    }
+
+    let has_quote = lines[quote_start..].iter().any(|(_, line)| is_quoted(line));
+
+    let quote_start = if has_quote {
+        let previous = |line: usize| line.checked_sub(1).map(|previous| lines[previous].1);
+
+        // Include the line that introduces the quote, otherwise the quote starts at its first quoted line.
+        match previous(quote_start)
+            .and_then(|line| attribution_start(previous(quote_start - 1), line))
+        {
+            Some(wrapped) => Some(quote_start - 1 - wrapped),
+            None => (quote_start..lines.len()).find(|&line| is_quoted(lines[line].1)),
+        }
+    } else {
+        None
+    };
+
+    let start = match separator.into_iter().chain(quote_start).min() {
+        Some(start) => start,
+        None => return (text, None),
+    };
+
+    let (offset, _) = lines[start];
+
+    let new = text[..offset].trim_end();
+
+    if new.trim().is_empty() {
+        return (text, None);
+    }
+
+    (new, Some(&text[offset..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gmail_reply() {
+        let text = "Sounds good, see you then!\r\n\r\nOn Mon, 1 Jan 2024 at 10:00, Jane Doe <jane@example.com>\r\nwrote:\r\n\r\n> Shall we meet at noon?\r\n>\r\n> Jane\r\n";
+
+        let (new, quoted) = split_quoted(text);
+
+        assert_eq!(new, "Sounds good, see you then!");
+        assert_eq!(
+            quoted,
+            Some("On Mon, 1 Jan 2024 at 10:00, Jane Doe <jane@example.com>\r\nwrote:\r\n\r\n> Shall we meet at noon?\r\n>\r\n> Jane\r\n")
+        );
+    }
+
+    #[test]
+    fn outlook_reply() {
+        let text = "Thanks, I will take a look.\n\n-----Original Message-----\nFrom: Jane Doe <jane@example.com>\nSent: Monday, January 1, 2024 10:00 AM\nSubject: Report\n\nHere is the report.\n";
+
+        let (new, quoted) = split_quoted(text);
+
+        assert_eq!(new, "Thanks, I will take a look.");
+        assert!(quoted
+            .unwrap()
+            .starts_with("-----Original Message-----\nFrom:"));
+
+        let text = "Approved.\n\n________________________________\nFrom: Jane Doe <jane@example.com>\nSent: Monday, January 1, 2024 10:00 AM\n\nPlease approve.\n";
+
+        let (new, quoted) = split_quoted(text);
+
+        assert_eq!(new, "Approved.");
+        assert!(quoted.unwrap().ends_with("Please approve.\n"));
+    }
+
+    #[test]
+    fn plain_quotes() {
+        let text =
+            "> Can you send me the slides?\n\nSure, here they are.\n\n> And the notes?\n> Thanks\n";
+
+        let (new, quoted) = split_quoted(text);
+
+        // Inline answers are kept, only the quote at the end is split off.
+        assert_eq!(new, "> Can you send me the slides?\n\nSure, here they are.");
+        assert_eq!(quoted, Some("> And the notes?\n> Thanks\n"));
+    }
+
+    #[test]
+    fn nothing_to_split() {
+        let text = "Hi,\n\nNo quotes in here, and nobody wrote: anything.\n";
+
+        assert_eq!(split_quoted(text), (text, None));
+
+        // A message that is only a quote is shown as a whole.
+        let text = "> Forwarded\n> text\n";
+
+        assert_eq!(split_quoted(text), (text, None));
+        assert_eq!(split_quoted(""), ("", None));
+    }
+}
@@ -0,0 +1,57 @@
+/// Whether a message is signed and/or encrypted using S/MIME or PGP.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Protection {
+    pub signed: bool,
+    pub encrypted: bool,
+}
+
+/// Detects the protection of a message from the content type of its top level part.
+///
+/// `smime_type` is the value of the `smime-type` content type parameter, which tells signed and encrypted `application/pkcs7-mime` parts apart.
+pub fn detect(mime_type: &str, smime_type: Option<&str>) -> Protection {
+    let mime_type = mime_type.trim().to_ascii_lowercase();
+
+    match mime_type.as_str() {
+        "multipart/signed" => Protection {
+            signed: true,
+            encrypted: false,
+        },
+        "multipart/encrypted" => Protection {
+            signed: false,
+            encrypted: true,
+        },
+        "application/pkcs7-mime" | "application/x-pkcs7-mime" => {
+            let signed = smime_type
+                .map(|smime_type| smime_type.trim().eq_ignore_ascii_case("signed-data"))
+                .unwrap_or(false);
+
+            // Without a smime-type parameter, the part is most likely an encrypted message.
+            Protection {
+                signed,
+                encrypted: !signed,
+            }
+        }
+        _ => Protection::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_protection() {
+        assert!(detect("multipart/signed", None).signed);
+        assert!(detect("Multipart/Encrypted", None).encrypted);
+
+        let opaque_signed = detect("application/pkcs7-mime", Some("signed-data"));
+
+        assert!(opaque_signed.signed && !opaque_signed.encrypted);
+
+        let enveloped = detect("application/x-pkcs7-mime", Some("enveloped-data"));
+
+        assert!(!enveloped.signed && enveloped.encrypted);
+
+        assert_eq!(detect("multipart/mixed", None), Protection::default());
+    }
+}
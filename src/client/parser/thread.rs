@@ -0,0 +1,42 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+/// The length of the header block in a `Thread-Index`, which is the same for every message in a conversation.
+const THREAD_INDEX_HEADER_LENGTH: usize = 22;
+
+/// Extracts the part of an Outlook `Thread-Index` header that identifies the conversation.
+///
+/// The header starts with a 22 byte block (a timestamp and a guid) that is copied from the first message in the conversation, each reply appends 5 bytes to it.
+pub fn thread_index_root<S: AsRef<str>>(thread_index: S) -> Option<String> {
+    let cleaned: String = thread_index
+        .as_ref()
+        .chars()
+        .filter(|char| !char.is_whitespace())
+        .collect();
+
+    let decoded = BASE64.decode(cleaned).ok()?;
+
+    if decoded.len() < THREAD_INDEX_HEADER_LENGTH {
+        return None;
+    }
+
+    Some(BASE64.encode(&decoded[..THREAD_INDEX_HEADER_LENGTH]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thread_index() {
+        let root = BASE64.encode([7_u8; 22]);
+
+        let mut reply = vec![7_u8; 22];
+        reply.extend_from_slice(&[1, 2, 3, 4, 5]);
+
+        assert_eq!(thread_index_root(&root), Some(root.clone()));
+        assert_eq!(thread_index_root(BASE64.encode(reply)), Some(root));
+
+        assert_eq!(thread_index_root("AQHZ"), None);
+        assert_eq!(thread_index_root("not base64!"), None);
+    }
+}
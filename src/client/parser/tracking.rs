@@ -0,0 +1,262 @@
+use std::borrow::Cow;
+
+/// Domains of services that are used to track when and where an email was opened.
+const TRACKER_DOMAINS: [&str; 8] = [
+    "bananatag.com",
+    "hubspotemail.net",
+    "list-manage.com",
+    "mailtrack.io",
+    "mandrillapp.com",
+    "mixmax.com",
+    "sendgrid.net",
+    "yesware.com",
+];
+
+/// Parts of an image url that are commonly used for open tracking endpoints.
+const TRACKER_PATH_HINTS: [&str; 5] = ["/track/open", "/open.php", "/wf/open", "/pixel", "/beacon"];
+
+/// Query parameters that are only added to links to track where a click came from.
+const TRACKING_PARAMS: [&str; 8] = [
+    "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "_hsenc", "_hsmi",
+];
+
+const TRACKING_PARAM_PREFIX: &str = "utm_";
+
+/// Removes every `img` tag that looks like a tracking pixel from a html page, returning the cleaned page and the amount of removed images.
+pub fn strip_tracking_pixels(html: &str) -> (Cow<'_, str>, usize) {
+    // Lowercasing ascii characters keeps the byte offsets the same.
+    let lowercase = html.to_ascii_lowercase();
+
+    let mut clean = String::new();
+    let mut removed = 0;
+    let mut position = 0;
+
+    while let Some(start) = find_img_tag(&lowercase, position) {
+        let end = match html[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+
+        // Skip the `<img` and the closing `>`.
+        let attributes = parse_attributes(&html[start + 4..end - 1]);
+
+        if is_tracking_pixel(&attributes) {
+            clean.push_str(&html[position..start]);
+
+            removed += 1;
+        } else {
+            clean.push_str(&html[position..end]);
+        }
+
+        position = end;
+    }
+
+    if removed == 0 {
+        return (html.into(), 0);
+    }
+
+    clean.push_str(&html[position..]);
+
+    (clean.into(), removed)
+}
+
+fn find_img_tag(lowercase: &str, from: usize) -> Option<usize> {
+    let mut from = from;
+
+    while let Some(index) = lowercase[from..].find("<img") {
+        let start = from + index;
+
+        match lowercase[start + 4..].chars().next() {
+            Some(next) if next.is_whitespace() || next == '/' || next == '>' => return Some(start),
+            _ => from = start + 4,
+        }
+    }
+
+    None
+}
+
+/// Parses the attributes in a tag into lowercase names and their (unescaped) values.
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+
+    let mut rest = tag.trim_start();
+
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+
+        let name = rest[..name_end].to_ascii_lowercase();
+
+        rest = rest[name_end..].trim_start();
+
+        let mut value = "";
+
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+
+            (value, rest) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => match after[1..].find(quote) {
+                    Some(end) => (&after[1..end + 1], &after[end + 2..]),
+                    None => (&after[1..], ""),
+                },
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+
+                    (&after[..end], &after[end..])
+                }
+            };
+        } else if name.is_empty() {
+            // Skip characters that can not start an attribute, like the `/` in a self closing tag.
+            let skip = rest.chars().next().map_or(0, char::len_utf8);
+
+            rest = &rest[skip..];
+        }
+
+        if !name.is_empty() {
+            attributes.push((name, value.to_string()));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    attributes
+}
+
+fn is_tracking_pixel(attributes: &[(String, String)]) -> bool {
+    let attribute = |name: &str| {
+        attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.trim())
+    };
+
+    let src = match attribute("src") {
+        Some(src) => src.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    // Only images that are loaded from a remote server can report back that they were opened.
+    let location = match src
+        .strip_prefix("https://")
+        .or(src.strip_prefix("http://"))
+        .or(src.strip_prefix("//"))
+    {
+        Some(location) => location,
+        None => return false,
+    };
+
+    let sizes: Vec<Option<f32>> = [attribute("width"), attribute("height")]
+        .into_iter()
+        .map(|size| size.and_then(|size| size.trim_end_matches("px").trim().parse().ok()))
+        .collect();
+
+    let is_tiny = sizes.iter().any(Option::is_some)
+        && sizes
+            .iter()
+            .all(|size| size.map_or(true, |size| size <= 1.0));
+
+    let is_hidden = attribute("style")
+        .map(|style| style.replace(' ', "").to_ascii_lowercase())
+        .map_or(false, |style| style.contains("display:none"));
+
+    let host_end = location.find(['/', '?', '#']).unwrap_or(location.len());
+
+    let host = location[..host_end].rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+
+    let is_tracker_domain = TRACKER_DOMAINS
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+
+    let path = &location[host_end..];
+
+    let is_tracker_path = TRACKER_PATH_HINTS.iter().any(|hint| path.contains(hint));
+
+    is_tiny || is_hidden || is_tracker_domain || is_tracker_path
+}
+
+fn is_tracking_param(param: &str) -> bool {
+    let name = param
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    name.starts_with(TRACKING_PARAM_PREFIX) || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+/// Removes query parameters that are only used for click tracking from a link, returning `None` if the link did not contain any.
+pub fn strip_tracking_params(url: &str) -> Option<String> {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+
+    let (base, query) = url.split_once('?')?;
+
+    let params: Vec<&str> = query.split('&').collect();
+
+    let kept: Vec<&str> = params
+        .iter()
+        .copied()
+        .filter(|param| !is_tracking_param(param))
+        .collect();
+
+    if kept.len() == params.len() {
+        return None;
+    }
+
+    let mut cleaned = base.to_string();
+
+    if !kept.is_empty() {
+        cleaned.push('?');
+        cleaned.push_str(&kept.join("&"));
+    }
+
+    if let Some(fragment) = fragment {
+        cleaned.push('#');
+        cleaned.push_str(fragment);
+    }
+
+    Some(cleaned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_tracking_pixels() {
+        let html = r#"<p>Hi</p><img src="https://example.com/o.gif" width="1" height="1"><IMG SRC='https://us1.list-manage.com/track/open.php?u=1'/><img src="https://example.com/logo.png" width="120"><img src="cid:pixel" width="1" height="1"><img style="display: none" src="//example.com/x.gif">"#;
+
+        let (clean, removed) = strip_tracking_pixels(html);
+
+        assert_eq!(removed, 3);
+        assert_eq!(
+            clean,
+            r#"<p>Hi</p><img src="https://example.com/logo.png" width="120"><img src="cid:pixel" width="1" height="1">"#
+        );
+
+        let (clean, removed) = strip_tracking_pixels("<imgur>text</imgur>");
+
+        assert_eq!(removed, 0);
+        assert_eq!(clean, "<imgur>text</imgur>");
+    }
+
+    #[test]
+    fn strips_tracking_params() {
+        assert_eq!(
+            strip_tracking_params(
+                "https://example.com/shop?id=5&utm_source=news&UTM_MEDIUM=email#top"
+            ),
+            Some(String::from("https://example.com/shop?id=5#top"))
+        );
+        assert_eq!(
+            strip_tracking_params("https://example.com/?fbclid=abc"),
+            Some(String::from("https://example.com/"))
+        );
+        assert_eq!(strip_tracking_params("https://example.com/?id=5"), None);
+        assert_eq!(strip_tracking_params("https://example.com/"), None);
+    }
+}
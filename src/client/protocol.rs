@@ -1,24 +1,88 @@
+use std::str::FromStr;
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use std::borrow::Cow;
+
 use async_trait::async_trait;
+use futures::{stream::BoxStream, TryStreamExt};
+use mime::Mime;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::{error::Result, tree::Node};
+use crate::{
+    cache::{Cache, Refresher, SharedCache},
+    error::{err, Error, ErrorKind, Result},
+    runtime::io::Read,
+    tree::Node,
+};
 
 use super::{
-    connection::ConnectionSecurity,
+    address::EmailAddress,
+    auth::AuthMechanism,
+    connection::{ConnectionSecurity, SocketOptions},
     incoming::types::{
+        acl::AclEntry,
+        event::MailboxEvent,
+        flag::{Flag, FlagMode},
         mailbox::Mailbox,
         message::{Message, Preview},
+        sort::SortOrder,
     },
-    outgoing::types::sendable::SendableMessage,
+    metrics::SessionMetrics,
+    outgoing::types::{capabilities::SmtpCapabilities, sendable::SendableMessage},
+    proxy::Proxy,
+    Headers,
 };
 
+/// The kind of mail server, regardless of how it is connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ServerConfigType {
+    Imap,
+    Pop,
+    Smtp,
+    Exchange,
+}
+
+impl ServerConfigType {
+    pub fn is_outgoing(&self) -> bool {
+        matches!(self, Self::Smtp)
+    }
+}
+
+/// The standard port for a kind of server with the given connection security, e.g. to prefill the port in a setup form.
+///
+/// For Smtp this is 465 for implicit Tls, 587 for message submission with StartTls and 25 for unencrypted relaying.
+pub fn default_port(protocol: ServerConfigType, security: ConnectionSecurity) -> u16 {
+    match (protocol, security) {
+        (ServerConfigType::Imap, ConnectionSecurity::Tls) => 993,
+        (ServerConfigType::Imap, _) => 143,
+        (ServerConfigType::Pop, ConnectionSecurity::Tls) => 995,
+        (ServerConfigType::Pop, _) => 110,
+        (ServerConfigType::Smtp, ConnectionSecurity::Tls) => 465,
+        (ServerConfigType::Smtp, ConnectionSecurity::StartTls) => 587,
+        (ServerConfigType::Smtp, ConnectionSecurity::Plain) => 25,
+        // Exchange is spoken over http.
+        (ServerConfigType::Exchange, ConnectionSecurity::Plain) => 80,
+        (ServerConfigType::Exchange, _) => 443,
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RemoteServer {
     server: String,
     port: u16,
     security: ConnectionSecurity,
+    #[cfg_attr(feature = "serde", serde(default))]
+    proxy: Option<Proxy>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    socket_options: SocketOptions,
 }
 
 impl RemoteServer {
@@ -31,9 +95,33 @@ impl RemoteServer {
             server: server.into(),
             port,
             security,
+            proxy: None,
+            socket_options: SocketOptions::default(),
         }
     }
 
+    /// Connect to the server through a proxy.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+
+        self
+    }
+
+    /// Configure the tcp socket, e.g. to change how often TCP keepalive probes are sent.
+    pub fn with_socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.socket_options = socket_options;
+
+        self
+    }
+
+    pub fn proxy(&self) -> Option<&Proxy> {
+        self.proxy.as_ref()
+    }
+
+    pub fn socket_options(&self) -> &SocketOptions {
+        &self.socket_options
+    }
+
     pub fn security(&self) -> &ConnectionSecurity {
         &self.security
     }
@@ -47,10 +135,115 @@ impl RemoteServer {
     }
 }
 
+impl FromStr for RemoteServer {
+    type Err = Error;
+
+    /// Parses a server address like `imaps://imap.example.com:993` or `smtp+starttls://smtp.example.com`.
+    ///
+    /// The scheme decides the connection security: `imaps`, `pop3s` and `smtps` use Tls, schemes ending in `+starttls` use StartTls and plain `imap`, `pop3` and `smtp` use no encryption at all. The standard port for the scheme is used when no port is given.
+    fn from_str(url: &str) -> Result<Self> {
+        let (scheme, rest) = match url.trim().split_once("://") {
+            Some(parts) => parts,
+            None => err!(
+                ErrorKind::InvalidLoginConfig,
+                "Server address '{}' is missing a scheme like 'imaps://'",
+                url
+            ),
+        };
+
+        let (protocol, security) = match scheme.to_ascii_lowercase().as_str() {
+            "imap" => (ServerConfigType::Imap, ConnectionSecurity::Plain),
+            "imaps" => (ServerConfigType::Imap, ConnectionSecurity::Tls),
+            "imap+starttls" => (ServerConfigType::Imap, ConnectionSecurity::StartTls),
+            "pop" | "pop3" => (ServerConfigType::Pop, ConnectionSecurity::Plain),
+            "pops" | "pop3s" => (ServerConfigType::Pop, ConnectionSecurity::Tls),
+            "pop+starttls" | "pop3+starttls" => {
+                (ServerConfigType::Pop, ConnectionSecurity::StartTls)
+            }
+            "smtp" => (ServerConfigType::Smtp, ConnectionSecurity::Plain),
+            "smtps" => (ServerConfigType::Smtp, ConnectionSecurity::Tls),
+            "smtp+starttls" => (ServerConfigType::Smtp, ConnectionSecurity::StartTls),
+            _ => err!(
+                ErrorKind::InvalidLoginConfig,
+                "Unknown mail server scheme '{}'",
+                scheme
+            ),
+        };
+
+        // Anything after the host and port, like a path, has no meaning for a mail server.
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+
+        let (host, port) = match authority.strip_prefix('[') {
+            // Ipv6 addresses are written in brackets, because they contain colons themselves.
+            Some(ipv6) => match ipv6.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => err!(
+                        ErrorKind::InvalidLoginConfig,
+                        "Invalid server address '{}'",
+                        url
+                    ),
+                },
+                None => err!(
+                    ErrorKind::InvalidLoginConfig,
+                    "Invalid server address '{}'",
+                    url
+                ),
+            },
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+
+        if host.is_empty() {
+            err!(
+                ErrorKind::InvalidLoginConfig,
+                "Server address '{}' is missing a host",
+                url
+            );
+        }
+
+        let port = match port {
+            Some(port) => match port.parse() {
+                Ok(port) => port,
+                Err(_) => err!(
+                    ErrorKind::InvalidLoginConfig,
+                    "Invalid port '{}' in server address '{}'",
+                    port,
+                    url
+                ),
+            },
+            None => default_port(protocol, security.clone()),
+        };
+
+        Ok(Self::new(host, port, security))
+    }
+}
+
+impl TryFrom<&str> for RemoteServer {
+    type Error = Error;
+
+    fn try_from(url: &str) -> Result<Self> {
+        url.parse()
+    }
+}
+
+#[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Credentials {
-    Password { username: String, password: String },
-    OAuth { username: String, token: String },
+    Password {
+        username: String,
+        password: String,
+    },
+    OAuth {
+        username: String,
+        token: String,
+        /// Where a fresh token is fetched from before every login, see `Credentials::oauth_refreshing`.
+        #[cfg_attr(feature = "serde", serde(skip))]
+        tokens: Option<SharedCache<String>>,
+    },
 }
 
 impl Credentials {
@@ -74,8 +267,54 @@ impl Credentials {
         Credentials::OAuth {
             username: username.into(),
             token: token.into(),
+            tokens: None,
         }
     }
+
+    /// Creates OAuth credentials that fetch their access token from the given cache when logging in, so a token that expired since the last login is refreshed first.
+    ///
+    /// This also covers the login that happens for every message sent over SMTP.
+    pub fn oauth_refreshing<U: Into<String>>(username: U, tokens: SharedCache<String>) -> Self {
+        Credentials::OAuth {
+            username: username.into(),
+            token: String::new(),
+            tokens: Some(tokens),
+        }
+    }
+
+    /// The credentials to log in with, with a fresh token for credentials created with `Credentials::oauth_refreshing`.
+    #[cfg(any(
+        feature = "imap",
+        feature = "pop",
+        all(feature = "smtp", feature = "runtime-tokio"),
+        feature = "sieve"
+    ))]
+    pub(crate) async fn refreshed(&self) -> Result<Cow<'_, Self>> {
+        match self {
+            Credentials::OAuth {
+                username,
+                tokens: Some(tokens),
+                ..
+            } => Ok(Cow::Owned(Self::oauth(
+                username.clone(),
+                tokens.get().await?,
+            ))),
+            credentials => Ok(Cow::Borrowed(credentials)),
+        }
+    }
+
+    /// Creates OAuth credentials using an access token from the given cache, refreshing the token first if it expired.
+    ///
+    /// The token is only read once, use `Credentials::oauth_refreshing` for clients that stay around long enough for it to expire.
+    pub async fn oauth_cached<U: Into<String>, R: Refresher<String> + Send>(
+        username: U,
+        cache: &mut Cache<String>,
+        provider: &mut R,
+    ) -> Result<Self> {
+        let token = cache.get(provider).await?;
+
+        Ok(Self::oauth(username, token.clone()))
+    }
 }
 
 pub trait ServerCredentials {
@@ -87,6 +326,10 @@ pub trait ServerCredentials {
 pub struct SmtpCredentials {
     server: RemoteServer,
     credentials: Credentials,
+    #[cfg_attr(feature = "serde", serde(default))]
+    default_display_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    hello_name: Option<String>,
 }
 
 #[cfg(feature = "smtp")]
@@ -95,12 +338,38 @@ impl SmtpCredentials {
         Self {
             server,
             credentials,
+            default_display_name: None,
+            hello_name: None,
         }
     }
 
+    /// Sets the name that is shown to recipients when a message is sent from an address without a display name.
+    pub fn with_default_display_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.default_display_name = Some(name.into());
+
+        self
+    }
+
+    /// Sets the hostname (or IP address) we introduce ourselves with in the EHLO command.
+    ///
+    /// By default `127.0.0.1` is used, which some servers with strict anti-spam checks reject; those expect the fully qualified domain name of the sending machine.
+    pub fn with_hello_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.hello_name = Some(name.into());
+
+        self
+    }
+
     pub fn server(&self) -> &RemoteServer {
         &self.server
     }
+
+    pub fn default_display_name(&self) -> Option<&str> {
+        self.default_display_name.as_deref()
+    }
+
+    pub fn hello_name(&self) -> Option<&str> {
+        self.hello_name.as_deref()
+    }
 }
 
 #[cfg(feature = "smtp")]
@@ -166,31 +435,149 @@ impl ServerCredentials for PopCredentials {
     }
 }
 
+#[cfg(feature = "sieve")]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SieveCredentials {
+    server: RemoteServer,
+    credentials: Credentials,
+}
+
+#[cfg(feature = "sieve")]
+impl SieveCredentials {
+    pub fn new(server: RemoteServer, credentials: Credentials) -> Self {
+        Self {
+            server,
+            credentials,
+        }
+    }
+
+    pub fn server(&self) -> &RemoteServer {
+        &self.server
+    }
+}
+
+#[cfg(feature = "sieve")]
+impl ServerCredentials for SieveCredentials {
+    fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+}
+
 #[async_trait]
 pub trait IncomingProtocol {
     async fn send_keep_alive(&mut self) -> Result<()>;
 
     fn should_keep_alive(&self) -> bool;
 
-    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>>;
+    /// The name of the protocol used to talk to the server, like `IMAP` or `POP3`.
+    fn protocol_name(&self) -> &'static str;
+
+    /// The greeting the server sent when the connection was opened, which often names the server software.
+    fn server_banner(&self) -> Option<&str>;
+
+    /// How much data was exchanged with the server since the connection was opened.
+    fn metrics(&self) -> SessionMetrics;
+
+    /// The mechanism the session logged in with, if it had to log in at all.
+    fn auth_mechanism(&self) -> Option<AuthMechanism>;
+
+    /// Whether the session is still logged in, by sending a command that requires it.
+    ///
+    /// Returns `Ok(false)` when the server refuses the command because the session is not logged in anymore, e.g. because it logged us out after being idle. Any other failure, like a lost connection, is returned as an error.
+    async fn is_authenticated(&mut self) -> Result<bool>;
+
+    /// The capabilities the server advertises, like `IDLE` or `UIDL`.
+    async fn capabilities(&mut self) -> Result<Vec<String>>;
+
+    /// Lists the mailboxes one at a time as the server sends them, so a list can be shown before all of them are received.
+    ///
+    /// The mailboxes come in the order the server sends them, which is not guaranteed to have parents before their children.
+    async fn get_mailbox_stream<'a>(&'a mut self) -> Result<BoxStream<'a, Result<Mailbox>>>;
+
+    /// Collects `get_mailbox_stream` into a flat list. Protocols that know how their mailboxes are nested build the tree themselves instead.
+    async fn get_mailbox_list(&mut self) -> Result<Node<Mailbox>> {
+        let mailboxes: Vec<Mailbox> = self.get_mailbox_stream().await?.try_collect().await?;
+
+        Ok(Node::Root(mailboxes.into_iter().map(Node::leaf).collect()))
+    }
 
     async fn get_mailbox(&mut self, mailbox_id: &str) -> Result<Node<Mailbox>>;
 
+    /// Renames a mailbox, keeping it under the same parent. Any mailboxes inside it are moved along with it.
     async fn rename_mailbox(&mut self, old_name: &str, new_name: &str) -> Result<()>;
 
     async fn create_mailbox(&mut self, name: &str) -> Result<()>;
 
     async fn delete_mailbox(&mut self, box_id: &str) -> Result<()>;
 
+    /// The flags that can be stored on messages in a given mailbox.
+    async fn get_mailbox_flags(&mut self, box_id: &str) -> Result<Vec<Flag>>;
+
+    /// Get the previews of the messages in the page `start..end` of a mailbox, where the page is counted from the newest or oldest message depending on `order`.
     async fn get_messages(
         &mut self,
         box_id: &str,
         start: usize,
         end: usize,
+        order: SortOrder,
     ) -> Result<Vec<Preview>>;
 
+    /// Get the previews of all messages in a mailbox that changed since a given mod-sequence, as reported by `MailboxStats::highest_modseq`.
+    ///
+    /// This requires the CONDSTORE extension; if it is unavailable, fall back to refetching the messages with `get_messages`.
+    async fn get_changes_since(&mut self, box_id: &str, modseq: u64) -> Result<Vec<Preview>>;
+
+    /// Get the previews of the messages in a mailbox with a uid higher than `last_uid`, oldest first.
+    ///
+    /// A stored `last_uid` is only valid as long as `MailboxStats::uid_validity` stays the same.
+    async fn get_messages_since_uid(&mut self, box_id: &str, last_uid: u32)
+        -> Result<Vec<Preview>>;
+
+    /// Starts watching the given mailboxes for changes, which are then returned by `next_events`.
+    ///
+    /// Servers that only support IDLE can watch a single mailbox, in which case only the first one is watched.
+    async fn watch(&mut self, box_ids: &[&str]) -> Result<()>;
+
+    /// Waits until the server reports changes in the mailboxes passed to `watch`.
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>>;
+
+    /// Stops watching the mailboxes passed to `watch`, so the connection can be used for other requests again.
+    async fn stop_watching(&mut self) -> Result<()>;
+
+    /// Looks at the error a request failed with, so the session can forget state the error shows to be stale, and returns the error to report.
+    async fn check_error(&mut self, error: Error) -> Error;
+
+    /// Get the previews of at most `limit` unread messages in a mailbox, newest first.
+    async fn get_unread_messages(&mut self, box_id: &str, limit: usize) -> Result<Vec<Preview>>;
+
+    /// The amount of unread messages in a mailbox, without fetching any of them.
+    async fn get_unread_count(&mut self, box_id: &str) -> Result<usize>;
+
     async fn get_message(&mut self, box_id: &str, message_id: &str) -> Result<Message>;
 
+    /// Fetches the raw RFC 822 source of a message, without marking it as read.
+    async fn get_message_source(&mut self, box_id: &str, message_id: &str) -> Result<Vec<u8>>;
+
+    /// Fetches only the given headers of a message, keyed by the names they were requested with. Headers the message does not have are left out.
+    async fn get_specific_headers(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        headers: &[&str],
+    ) -> Result<Headers>;
+
+    /// Marks every message in a given mailbox as read.
+    async fn mark_all_read(&mut self, box_id: &str) -> Result<()>;
+
+    /// Add, remove or replace the flags on multiple messages in a mailbox at once.
+    async fn set_flags_bulk(
+        &mut self,
+        box_id: &str,
+        message_ids: &[&str],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()>;
+
     async fn get_attachment(
         &mut self,
         box_id: &str,
@@ -198,12 +585,78 @@ pub trait IncomingProtocol {
         attachment_id: &str,
     ) -> Result<Vec<u8>>;
 
+    /// Like `get_attachment`, but calls `progress` with the amount of bytes received so far and the total size of the attachment while it downloads.
+    async fn get_attachment_with_progress(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+        progress: &mut (dyn FnMut(usize, usize) + Send),
+    ) -> Result<Vec<u8>>;
+
+    /// Fetches the contents of the first part in a message that matches the given mime type.
+    async fn get_message_part(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>>;
+
+    /// Stores a raw RFC 822 message in a given mailbox.
+    async fn append(&mut self, box_id: &str, message: &[u8], flags: &[Flag]) -> Result<()>;
+
+    /// Like `append`, but reads the message from a stream so large messages do not have to be kept in memory.
+    ///
+    /// `length` must be the exact size of the message in bytes, as it is announced to the server before the message is sent.
+    async fn append_from_reader(
+        &mut self,
+        box_id: &str,
+        message: &mut (dyn Read + Unpin + Send),
+        length: usize,
+        flags: &[Flag],
+    ) -> Result<()>;
+
+    /// The rights every user or group has on a shared mailbox.
+    ///
+    /// This requires the ACL extension, which is only available on some IMAP servers.
+    async fn get_acl(&mut self, box_id: &str) -> Result<Vec<AclEntry>>;
+
+    /// Replaces the rights a user or group has on a shared mailbox. Prefix the rights with `+` or `-` to add or remove rights instead.
+    async fn set_acl(&mut self, box_id: &str, identifier: &str, rights: &str) -> Result<()>;
+
+    /// The rights the logged in user has on a mailbox.
+    async fn get_my_rights(&mut self, box_id: &str) -> Result<String>;
+
+    /// Ends the session with the server.
+    ///
+    /// This must be called before the session is dropped, as a dropped session closes its connection without notifying the server.
     async fn logout(&mut self) -> Result<()>;
 }
 
 #[async_trait]
 pub trait OutgoingProtocol {
+    /// The greeting the server sent the last time we connected to it, if we connected at all.
+    fn server_banner(&self) -> Option<&str>;
+
+    /// The mechanism we logged in with the last time we sent a message, if the server required a login.
+    fn auth_mechanism(&self) -> Option<AuthMechanism>;
+
+    /// The name to show recipients when a message is sent from an address without a display name.
+    fn default_display_name(&self) -> Option<&str>;
+
     async fn send_message(&mut self, message: SendableMessage) -> Result<()>;
+
+    /// Sends a message to the given envelope recipients instead of the recipients in its headers, which are left untouched.
+    async fn send_to(
+        &mut self,
+        message: SendableMessage,
+        envelope_to: &[EmailAddress],
+    ) -> Result<()>;
+
+    /// What the outgoing mail server supports, like its size limit and authentication mechanisms.
+    ///
+    /// The first call connects to the server to read them; after that they are remembered.
+    async fn capabilities(&mut self) -> Result<SmtpCapabilities>;
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -224,18 +677,124 @@ pub enum OutgoingEmailProtocol {
     Smtp(SmtpCredentials),
 }
 
-pub struct IncomingConfig {}
+pub struct IncomingConfig {
+    compression: bool,
+}
 
 impl Default for IncomingConfig {
     fn default() -> Self {
-        let config = Self::new();
-
-        config
+        Self::new()
     }
 }
 
 impl IncomingConfig {
     pub fn new() -> Self {
-        Self {}
+        Self { compression: false }
+    }
+
+    /// Compress the connection if the server supports it (Imap COMPRESS=DEFLATE). This saves a lot of bandwidth when syncing large mailboxes on slow or metered connections, at the cost of some cpu time.
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+
+        self
+    }
+
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(url: &str) -> (String, u16, ConnectionSecurity) {
+        let server: RemoteServer = url.parse().unwrap();
+
+        (server.domain().to_string(), server.port(), server.security)
+    }
+
+    #[test]
+    fn remote_server_from_url() {
+        let cases = [
+            ("imap://mail.example.com", 143, ConnectionSecurity::Plain),
+            ("imaps://mail.example.com", 993, ConnectionSecurity::Tls),
+            (
+                "imap+starttls://mail.example.com",
+                143,
+                ConnectionSecurity::StartTls,
+            ),
+            ("pop3://mail.example.com", 110, ConnectionSecurity::Plain),
+            ("pop3s://mail.example.com", 995, ConnectionSecurity::Tls),
+            (
+                "pop3+starttls://mail.example.com",
+                110,
+                ConnectionSecurity::StartTls,
+            ),
+            ("smtp://mail.example.com", 25, ConnectionSecurity::Plain),
+            ("smtps://mail.example.com", 465, ConnectionSecurity::Tls),
+            (
+                "smtp+starttls://mail.example.com",
+                587,
+                ConnectionSecurity::StartTls,
+            ),
+            (
+                "IMAPS://mail.example.com/INBOX",
+                993,
+                ConnectionSecurity::Tls,
+            ),
+        ];
+
+        for (url, port, security) in cases {
+            assert_eq!(
+                parse(url),
+                (String::from("mail.example.com"), port, security),
+                "{}",
+                url
+            );
+        }
+
+        assert_eq!(
+            parse("imaps://mail.example.com:1993"),
+            (
+                String::from("mail.example.com"),
+                1993,
+                ConnectionSecurity::Tls
+            )
+        );
+        assert_eq!(
+            parse("smtp+starttls://[::1]:2525"),
+            (String::from("::1"), 2525, ConnectionSecurity::StartTls)
+        );
+        assert_eq!(RemoteServer::try_from("pop3s://[::1]").unwrap().port(), 995);
+    }
+
+    #[test]
+    fn default_ports() {
+        use ConnectionSecurity::*;
+
+        assert_eq!(default_port(ServerConfigType::Imap, Tls), 993);
+        assert_eq!(default_port(ServerConfigType::Imap, StartTls), 143);
+        assert_eq!(default_port(ServerConfigType::Imap, Plain), 143);
+        assert_eq!(default_port(ServerConfigType::Pop, Tls), 995);
+        assert_eq!(default_port(ServerConfigType::Pop, StartTls), 110);
+        assert_eq!(default_port(ServerConfigType::Pop, Plain), 110);
+        assert_eq!(default_port(ServerConfigType::Smtp, Tls), 465);
+        assert_eq!(default_port(ServerConfigType::Smtp, StartTls), 587);
+        assert_eq!(default_port(ServerConfigType::Smtp, Plain), 25);
+    }
+
+    #[test]
+    fn invalid_remote_server_urls() {
+        for url in [
+            "mail.example.com",
+            "http://mail.example.com",
+            "imaps://",
+            "imaps://mail.example.com:imap",
+            "imaps://mail.example.com:99999",
+            "imaps://[::1",
+        ] {
+            assert!(url.parse::<RemoteServer>().is_err(), "{}", url);
+        }
     }
 }
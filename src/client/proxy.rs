@@ -0,0 +1,492 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use crate::{
+    error::{err, ErrorKind, Result},
+    runtime::{
+        io::{Read, ReadExt, Write, WriteExt},
+        net::TcpStream,
+    },
+};
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_VERSION: u8 = 0x05;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_AUTH_NONE: u8 = 0x00;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_AUTH_PASSWORD: u8 = 0x02;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_PASSWORD_VERSION: u8 = 0x01;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_COMMAND_CONNECT: u8 = 0x01;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_ADDRESS_IPV4: u8 = 0x01;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_ADDRESS_DOMAIN: u8 = 0x03;
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const SOCKS_ADDRESS_IPV6: u8 = 0x04;
+
+/// The maximum size of the response headers we accept from a http proxy.
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+const MAX_HTTP_RESPONSE_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProxyType {
+    Socks5,
+    /// A http proxy that supports the `CONNECT` method.
+    HttpConnect,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProxyCredentials {
+    username: String,
+    password: String,
+}
+
+impl ProxyCredentials {
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+/// A proxy server that is used to connect to a remote mail server, for example a local Tor daemon.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Proxy {
+    r#type: ProxyType,
+    host: String,
+    port: u16,
+    credentials: Option<ProxyCredentials>,
+}
+
+impl Proxy {
+    pub fn new<H: Into<String>>(r#type: ProxyType, host: H, port: u16) -> Self {
+        Self {
+            r#type,
+            host: host.into(),
+            port,
+            credentials: None,
+        }
+    }
+
+    pub fn socks5<H: Into<String>>(host: H, port: u16) -> Self {
+        Self::new(ProxyType::Socks5, host, port)
+    }
+
+    pub fn http<H: Into<String>>(host: H, port: u16) -> Self {
+        Self::new(ProxyType::HttpConnect, host, port)
+    }
+
+    /// Authenticate with the proxy server using a username and password.
+    pub fn with_credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.credentials = Some(ProxyCredentials {
+            username: username.into(),
+            password: password.into(),
+        });
+
+        self
+    }
+
+    pub fn r#type(&self) -> &ProxyType {
+        &self.r#type
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn credentials(&self) -> Option<&ProxyCredentials> {
+        self.credentials.as_ref()
+    }
+}
+
+#[cfg(any(
+    feature = "imap",
+    feature = "pop",
+    all(feature = "smtp", feature = "runtime-tokio"),
+    feature = "sieve"
+))]
+impl Proxy {
+    /// Opens a tcp connection to the given server, tunneled through this proxy.
+    pub(crate) async fn connect(&self, domain: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_ref(), self.port)).await?;
+
+        match self.r#type {
+            ProxyType::Socks5 => self.socks5_handshake(&mut stream, domain, port).await?,
+            ProxyType::HttpConnect => self.http_handshake(&mut stream, domain, port).await?,
+        }
+
+        Ok(stream)
+    }
+
+    async fn socks5_handshake<S: Read + Write + Unpin>(
+        &self,
+        stream: &mut S,
+        domain: &str,
+        port: u16,
+    ) -> Result<()> {
+        let auth_method = match self.credentials {
+            Some(_) => SOCKS_AUTH_PASSWORD,
+            None => SOCKS_AUTH_NONE,
+        };
+
+        stream.write_all(&[SOCKS_VERSION, 1, auth_method]).await?;
+
+        let mut response = [0u8; 2];
+
+        stream.read_exact(&mut response).await?;
+
+        if response[0] != SOCKS_VERSION {
+            err!(
+                ErrorKind::Proxy,
+                "Proxy server responded with an unsupported socks version"
+            );
+        }
+
+        match (response[1], self.credentials.as_ref()) {
+            (SOCKS_AUTH_NONE, _) => {}
+            (SOCKS_AUTH_PASSWORD, Some(credentials)) => {
+                let username = credentials.username.as_bytes();
+                let password = credentials.password.as_bytes();
+
+                if username.len() > u8::MAX as usize || password.len() > u8::MAX as usize {
+                    err!(
+                        ErrorKind::Proxy,
+                        "Proxy username and password can be at most 255 bytes long"
+                    );
+                }
+
+                let mut request = vec![SOCKS_PASSWORD_VERSION, username.len() as u8];
+                request.extend_from_slice(username);
+                request.push(password.len() as u8);
+                request.extend_from_slice(password);
+
+                stream.write_all(&request).await?;
+
+                stream.read_exact(&mut response).await?;
+
+                if response[1] != 0 {
+                    err!(ErrorKind::Proxy, "Proxy server rejected the credentials");
+                }
+            }
+            _ => {
+                err!(
+                    ErrorKind::Proxy,
+                    "Proxy server does not support any of the offered authentication methods"
+                );
+            }
+        }
+
+        if domain.len() > u8::MAX as usize {
+            err!(
+                ErrorKind::Proxy,
+                "Domain '{}' is too long to be sent to the proxy server",
+                domain
+            );
+        }
+
+        let mut request = vec![
+            SOCKS_VERSION,
+            SOCKS_COMMAND_CONNECT,
+            0x00,
+            SOCKS_ADDRESS_DOMAIN,
+            domain.len() as u8,
+        ];
+        request.extend_from_slice(domain.as_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+
+        stream.read_exact(&mut reply).await?;
+
+        if reply[1] != 0 {
+            err!(
+                ErrorKind::Proxy,
+                "Proxy server failed to connect to '{}:{}' (reply code {})",
+                domain,
+                port,
+                reply[1]
+            );
+        }
+
+        // The reply contains the address the proxy bound to, which we do not need but have to read anyway.
+        let address_length = match reply[3] {
+            SOCKS_ADDRESS_IPV4 => 4,
+            SOCKS_ADDRESS_IPV6 => 16,
+            SOCKS_ADDRESS_DOMAIN => {
+                let mut length = [0u8; 1];
+
+                stream.read_exact(&mut length).await?;
+
+                length[0] as usize
+            }
+            _ => err!(
+                ErrorKind::Proxy,
+                "Proxy server responded with an unknown address type"
+            ),
+        };
+
+        let mut bound_address = vec![0u8; address_length + 2];
+
+        stream.read_exact(&mut bound_address).await?;
+
+        Ok(())
+    }
+
+    async fn http_handshake<S: Read + Write + Unpin>(
+        &self,
+        stream: &mut S,
+        domain: &str,
+        port: u16,
+    ) -> Result<()> {
+        let mut request = format!(
+            "CONNECT {domain}:{port} HTTP/1.1\r\nHost: {domain}:{port}\r\n",
+            domain = domain,
+            port = port
+        );
+
+        if let Some(credentials) = self.credentials.as_ref() {
+            let token = BASE64.encode(format!("{}:{}", credentials.username, credentials.password));
+
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+
+        request.push_str("\r\n");
+
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read byte by byte so we don't consume any data that is sent after the headers.
+        let mut response: Vec<u8> = Vec::new();
+        let mut byte = [0u8; 1];
+
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_HTTP_RESPONSE_SIZE {
+                err!(ErrorKind::Proxy, "Proxy server response is too large");
+            }
+
+            stream.read_exact(&mut byte).await?;
+
+            response.push(byte[0]);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+
+        let status = response
+            .lines()
+            .next()
+            .and_then(|status_line| status_line.split_whitespace().nth(1));
+
+        match status {
+            Some("200") => Ok(()),
+            Some(status) => err!(
+                ErrorKind::Proxy,
+                "Proxy server refused to connect to '{}:{}' (status {})",
+                domain,
+                port,
+                status
+            ),
+            None => err!(
+                ErrorKind::Proxy,
+                "Proxy server responded with an invalid http response"
+            ),
+        }
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "runtime-tokio",
+    any(feature = "imap", feature = "pop", feature = "smtp", feature = "sieve")
+))]
+mod test {
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn socks5_connect() {
+        let (mut client, mut server) = duplex(1024);
+
+        // No authentication, then a successful reply bound to 127.0.0.1:1080.
+        server
+            .write_all(&[5, 0, 5, 0, 0, 1, 127, 0, 0, 1, 4, 56])
+            .await
+            .unwrap();
+
+        Proxy::socks5("localhost", 1080)
+            .socks5_handshake(&mut client, "imap.example.com", 993)
+            .await
+            .unwrap();
+
+        drop(client);
+
+        let mut sent = Vec::new();
+        server.read_to_end(&mut sent).await.unwrap();
+
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 16];
+        expected.extend_from_slice(b"imap.example.com");
+        expected.extend_from_slice(&993u16.to_be_bytes());
+
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn socks5_rejected_credentials() {
+        let (mut client, mut server) = duplex(1024);
+
+        // Password authentication, which fails.
+        server.write_all(&[5, 2, 1, 1]).await.unwrap();
+
+        let error = Proxy::socks5("localhost", 1080)
+            .with_credentials("user", "wrong")
+            .socks5_handshake(&mut client, "imap.example.com", 993)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::Proxy));
+
+        drop(client);
+
+        let mut sent = Vec::new();
+        server.read_to_end(&mut sent).await.unwrap();
+
+        assert_eq!(sent, b"\x05\x01\x02\x01\x04user\x05wrong");
+
+        // A proxy that accepts none of the offered methods.
+        let (mut client, mut server) = duplex(1024);
+
+        server.write_all(&[5, 0xFF]).await.unwrap();
+
+        assert!(Proxy::socks5("localhost", 1080)
+            .socks5_handshake(&mut client, "imap.example.com", 993)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn http_connect() {
+        let (mut client, mut server) = duplex(1024);
+
+        server
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n* OK IMAP ready\r\n")
+            .await
+            .unwrap();
+
+        Proxy::http("localhost", 8080)
+            .with_credentials("user", "secret")
+            .http_handshake(&mut client, "imap.example.com", 993)
+            .await
+            .unwrap();
+
+        // The data the server sends after the headers is left for the mail client.
+        let mut greeting = [0u8; 17];
+        client.read_exact(&mut greeting).await.unwrap();
+
+        assert_eq!(&greeting, b"* OK IMAP ready\r\n");
+
+        drop(client);
+
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+
+        assert_eq!(
+            sent,
+            "CONNECT imap.example.com:993 HTTP/1.1\r\nHost: imap.example.com:993\r\nProxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn http_connect_refused() {
+        let (mut client, mut server) = duplex(1024);
+
+        server
+            .write_all(
+                b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let error = Proxy::http("localhost", 8080)
+            .http_handshake(&mut client, "imap.example.com", 993)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::Proxy));
+        assert!(error.to_string().contains("407"));
+    }
+}
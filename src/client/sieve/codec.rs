@@ -0,0 +1,366 @@
+use crate::{
+    error::{err, ErrorKind, Result},
+    runtime::io::{Read, ReadExt, Write, WriteExt},
+};
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// A single item of a line the server sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A bare word, like `OK` or `ACTIVE`.
+    Atom(String),
+    /// A quoted string or a literal.
+    String(String),
+    /// The text between parentheses, like the code of a response.
+    Group(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    No,
+    Bye,
+}
+
+/// The line that ends the server's answer to a command (RFC 5804 section 1.3).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: Status,
+    /// A machine readable code, like `NONEXISTENT`.
+    pub code: Option<String>,
+    /// A human readable explanation.
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Data(Vec<Token>),
+    Response(Response),
+}
+
+impl From<Vec<Token>> for Line {
+    fn from(tokens: Vec<Token>) -> Self {
+        let status = match tokens.first() {
+            Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("OK") => Status::Ok,
+            Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("NO") => Status::No,
+            Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("BYE") => Status::Bye,
+            _ => return Self::Data(tokens),
+        };
+
+        let mut response = Response {
+            status,
+            code: None,
+            text: None,
+        };
+
+        for token in tokens.into_iter().skip(1) {
+            match token {
+                Token::Group(code) => response.code = Some(code),
+                Token::String(text) => response.text = Some(text),
+                Token::Atom(_) => {}
+            }
+        }
+
+        Self::Response(response)
+    }
+}
+
+/// Formats a value as a string argument for a command, using a literal if it cannot be quoted.
+pub fn string(value: &str) -> String {
+    if value.contains(['\r', '\n', '\0']) {
+        // Literals sent by the client are always non-synchronizing (RFC 5804 section 4).
+        format!("{{{}+}}\r\n{}", value.len(), value)
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn invalid_line(line: &str) -> Result<Option<usize>> {
+    err!(
+        ErrorKind::UnexpectedBehavior,
+        "Sieve server sent an invalid line: {}",
+        line
+    )
+}
+
+/// Splits a line into tokens, returning the length of the literal that ends the line if there is one.
+fn tokenize(line: &str, tokens: &mut Vec<Token>) -> Result<Option<usize>> {
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            ' ' => {}
+            '"' => {
+                let mut value = String::new();
+                let mut closed = false;
+
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => match chars.next() {
+                            Some((_, escaped)) => value.push(escaped),
+                            None => break,
+                        },
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+
+                if !closed {
+                    return invalid_line(line);
+                }
+
+                tokens.push(Token::String(value));
+            }
+            '(' => {
+                let mut depth = 1;
+                let mut in_quotes = false;
+                let mut end = None;
+
+                for (index, c) in chars.by_ref() {
+                    match c {
+                        '"' => in_quotes = !in_quotes,
+                        '(' if !in_quotes => depth += 1,
+                        ')' if !in_quotes => {
+                            depth -= 1;
+
+                            if depth == 0 {
+                                end = Some(index);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                match end {
+                    Some(end) => tokens.push(Token::Group(line[start + 1..end].to_string())),
+                    None => return invalid_line(line),
+                }
+            }
+            '{' => {
+                let length = line[start + 1..]
+                    .strip_suffix('}')
+                    .map(|length| length.trim_end_matches('+'))
+                    .and_then(|length| length.parse().ok());
+
+                // A literal always ends the line, its data follows on the next.
+                return match length {
+                    Some(length) => Ok(Some(length)),
+                    None => invalid_line(line),
+                };
+            }
+            _ => {
+                let mut end = line.len();
+
+                while let Some((index, c)) = chars.peek() {
+                    if *c == ' ' {
+                        end = *index;
+                        break;
+                    }
+
+                    chars.next();
+                }
+
+                tokens.push(Token::Atom(line[start..end].to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads lines from and writes commands to a ManageSieve server.
+pub struct Connection<S> {
+    stream: S,
+    /// Data that was read from the stream but not parsed yet.
+    buffer: Vec<u8>,
+}
+
+impl<S: Read + Write + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Gives back the stream, e.g. to start Tls on it after STARTTLS.
+    pub fn into_inner(self) -> Result<S> {
+        if !self.buffer.is_empty() {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Sieve server sent data before the connection was upgraded"
+            )
+        }
+
+        Ok(self.stream)
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+
+        let read = self.stream.read(&mut chunk).await?;
+
+        if read == 0 {
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "Sieve server closed the connection unexpectedly"
+            )
+        }
+
+        self.buffer.extend_from_slice(&chunk[..read]);
+
+        Ok(())
+    }
+
+    /// Reads up to the next line ending, without including it.
+    async fn read_until_line_end(&mut self) -> Result<String> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|window| window == b"\r\n") {
+                let line: Vec<u8> = self.buffer.drain(..end + 2).take(end).collect();
+
+                return Ok(std::str::from_utf8(&line)?.to_string());
+            }
+
+            self.fill().await?;
+        }
+    }
+
+    async fn read_bytes(&mut self, length: usize) -> Result<String> {
+        while self.buffer.len() < length {
+            self.fill().await?;
+        }
+
+        let bytes: Vec<u8> = self.buffer.drain(..length).collect();
+
+        Ok(std::str::from_utf8(&bytes)?.to_string())
+    }
+
+    /// Reads a single line, including any literals in it.
+    pub async fn read_line(&mut self) -> Result<Line> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let line = self.read_until_line_end().await?;
+
+            match tokenize(&line, &mut tokens)? {
+                Some(length) => tokens.push(Token::String(self.read_bytes(length).await?)),
+                None => return Ok(tokens.into()),
+            }
+        }
+    }
+
+    /// Reads lines until the response, returning the data lines that came before it.
+    pub async fn read_response(&mut self) -> Result<(Vec<Vec<Token>>, Response)> {
+        let mut data = Vec::new();
+
+        loop {
+            match self.read_line().await? {
+                Line::Data(tokens) => data.push(tokens),
+                Line::Response(response) => return Ok((data, response)),
+            }
+        }
+    }
+
+    /// Sends a single line, the line ending is added.
+    pub async fn send(&mut self, line: &str) -> Result<()> {
+        self.stream.write_all(line.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+
+        Ok(())
+    }
+
+    pub async fn command(&mut self, command: &str) -> Result<(Vec<Vec<Token>>, Response)> {
+        self.send(command).await?;
+
+        self.read_response().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(line: &str) -> (Vec<Token>, Option<usize>) {
+        let mut tokens = Vec::new();
+
+        let literal = tokenize(line, &mut tokens).unwrap();
+
+        (tokens, literal)
+    }
+
+    #[test]
+    fn tokenize_lines() {
+        assert_eq!(
+            tokens(r#""SIEVE" "fileinto vacation""#),
+            (
+                vec![
+                    Token::String("SIEVE".into()),
+                    Token::String("fileinto vacation".into())
+                ],
+                None
+            )
+        );
+
+        assert_eq!(
+            tokens(r#""my \"rules\"" ACTIVE"#),
+            (
+                vec![
+                    Token::String(r#"my "rules""#.into()),
+                    Token::Atom("ACTIVE".into())
+                ],
+                None
+            )
+        );
+
+        assert_eq!(
+            tokens("NO (NONEXISTENT) \"There is no script by that name\"").0,
+            vec![
+                Token::Atom("NO".into()),
+                Token::Group("NONEXISTENT".into()),
+                Token::String("There is no script by that name".into())
+            ]
+        );
+
+        assert_eq!(tokens("{54}"), (Vec::new(), Some(54)));
+        assert_eq!(
+            tokens("OK {12+}"),
+            (vec![Token::Atom("OK".into())], Some(12))
+        );
+
+        assert!(tokenize("\"unterminated", &mut Vec::new()).is_err());
+        assert!(tokenize("{nope}", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parse_responses() {
+        let line: Line = tokens("NO (QUOTA/MAXSIZE) \"Script is too large\"")
+            .0
+            .into();
+
+        assert_eq!(
+            line,
+            Line::Response(Response {
+                status: Status::No,
+                code: Some("QUOTA/MAXSIZE".into()),
+                text: Some("Script is too large".into()),
+            })
+        );
+
+        let line: Line = tokens(r#""IMPLEMENTATION" "Dovecot Pigeonhole""#).0.into();
+
+        assert!(matches!(line, Line::Data(_)));
+    }
+
+    #[test]
+    fn string_arguments() {
+        assert_eq!(string("rules"), "\"rules\"");
+        assert_eq!(string(r#"my "rules""#), r#""my \"rules\"""#);
+        assert_eq!(string("keep;\r\n"), "{7+}\r\nkeep;\r\n");
+    }
+}
@@ -0,0 +1,547 @@
+//! A client for managing server-side filters (Sieve scripts) using the ManageSieve protocol (RFC 5804).
+//!
+//! ```rust,no_run
+//! use dust_mail::client::{sieve, Credentials, RemoteServer, SieveCredentials};
+//! use dust_mail::client::connection::ConnectionSecurity;
+//!
+//! # async fn run() -> dust_mail::error::Result<()> {
+//! let server = RemoteServer::new("mail.example.com", sieve::DEFAULT_PORT, ConnectionSecurity::StartTls);
+//! let credentials = SieveCredentials::new(server, Credentials::password("user", "secret"));
+//!
+//! let mut client = sieve::connect(&credentials).await?;
+//!
+//! client.put_script("rules", "require \"fileinto\";\r\nfileinto \"Lists\";\r\n").await?;
+//! client.set_active(Some("rules")).await?;
+//!
+//! client.logout().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod codec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use async_native_tls::TlsConnector;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::{debug, info};
+
+use crate::{
+    client::{
+        connection::{self, ConnectionSecurity},
+        protocol::SieveCredentials,
+        Credentials, ServerCredentials,
+    },
+    error::{err, ErrorKind, Result},
+    runtime::io::{Read, Write},
+};
+
+use self::codec::{Connection, Response, Status, Token};
+
+/// The log target used by the sieve client, use `RUST_LOG=dust_mail::sieve=debug` to debug ManageSieve connections.
+const LOG_TARGET: &str = "dust_mail::sieve";
+
+/// The port ManageSieve servers listen on, which expects StartTls.
+pub const DEFAULT_PORT: u16 = 4190;
+
+const PLAIN_MECHANISM: &str = "PLAIN";
+const XOAUTH2_MECHANISM: &str = "XOAUTH2";
+const OAUTHBEARER_MECHANISM: &str = "OAUTHBEARER";
+
+trait Stream: Read + Write + Unpin + Send + Sync {}
+
+impl<S: Read + Write + Unpin + Send + Sync> Stream for S {}
+
+/// What a ManageSieve server supports, as advertised right after connecting.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SieveCapabilities {
+    implementation: Option<String>,
+    sasl_mechanisms: Vec<String>,
+    extensions: Vec<String>,
+    starttls: bool,
+    version: Option<String>,
+}
+
+impl SieveCapabilities {
+    fn from_lines(lines: &[Vec<Token>]) -> Self {
+        let mut capabilities = Self::default();
+
+        for line in lines {
+            let (name, value) = match line.as_slice() {
+                [Token::String(name)] => (name, None),
+                [Token::String(name), Token::String(value), ..] => (name, Some(value)),
+                _ => continue,
+            };
+
+            let words = || {
+                value
+                    .map(|value| value.split_whitespace().map(String::from).collect())
+                    .unwrap_or_default()
+            };
+
+            match name.to_ascii_uppercase().as_str() {
+                "IMPLEMENTATION" => capabilities.implementation = value.cloned(),
+                "SASL" => capabilities.sasl_mechanisms = words(),
+                "SIEVE" => capabilities.extensions = words(),
+                "STARTTLS" => capabilities.starttls = true,
+                "VERSION" => capabilities.version = value.cloned(),
+                _ => {}
+            }
+        }
+
+        capabilities
+    }
+
+    /// The name of the server software.
+    pub fn implementation(&self) -> Option<&str> {
+        self.implementation.as_deref()
+    }
+
+    /// The authentication mechanisms the server accepts right now. Many servers only offer these after StartTls.
+    pub fn sasl_mechanisms(&self) -> &[String] {
+        &self.sasl_mechanisms
+    }
+
+    /// The Sieve extensions scripts may use, like `fileinto` or `vacation`.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    pub fn supports_starttls(&self) -> bool {
+        self.starttls
+    }
+
+    /// The version of the protocol, which is only sent by servers that implement RFC 5804 rather than one of its drafts.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn supports_sasl<M: AsRef<str>>(&self, mechanism: M) -> bool {
+        self.sasl_mechanisms
+            .iter()
+            .any(|supported| supported.eq_ignore_ascii_case(mechanism.as_ref()))
+    }
+
+    /// Whether scripts may use the given Sieve extension.
+    pub fn supports_extension<E: AsRef<str>>(&self, extension: E) -> bool {
+        self.extensions
+            .iter()
+            .any(|supported| supported.eq_ignore_ascii_case(extension.as_ref()))
+    }
+}
+
+/// A script that is stored on the server.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SieveScript {
+    name: String,
+    active: bool,
+}
+
+impl SieveScript {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this is the script the server runs for incoming messages. At most one script is active at a time.
+    pub fn active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Fails with the text the server sent if the response is not OK.
+fn check_response(response: Response, action: &str) -> Result<()> {
+    if response.status == Status::Ok {
+        return Ok(());
+    }
+
+    let reason = match (response.code, response.text) {
+        (Some(code), Some(text)) => format!("({}) {}", code, text),
+        (Some(code), None) => code,
+        (None, Some(text)) => text,
+        (None, None) => String::from("no reason given"),
+    };
+
+    err!(
+        ErrorKind::MailServer,
+        "Sieve server rejected {}: {}",
+        action,
+        reason
+    )
+}
+
+async fn read_capabilities<S: Read + Write + Unpin>(
+    connection: &mut Connection<S>,
+) -> Result<SieveCapabilities> {
+    let (lines, response) = connection.read_response().await?;
+
+    check_response(response, "the connection")?;
+
+    Ok(SieveCapabilities::from_lines(&lines))
+}
+
+/// The mechanism and initial response to log in with, using the first mechanism the server supports.
+fn sasl_response(
+    capabilities: &SieveCapabilities,
+    credentials: &Credentials,
+) -> Result<(&'static str, String)> {
+    let (mechanism, response) = match credentials {
+        Credentials::Password { username, password }
+            if capabilities.supports_sasl(PLAIN_MECHANISM) =>
+        {
+            (PLAIN_MECHANISM, format!("\0{}\0{}", username, password))
+        }
+        Credentials::OAuth {
+            username, token, ..
+        } if capabilities.supports_sasl(OAUTHBEARER_MECHANISM) => (
+            OAUTHBEARER_MECHANISM,
+            format!("n,a={},\x01auth=Bearer {}\x01\x01", username, token),
+        ),
+        Credentials::OAuth {
+            username, token, ..
+        } if capabilities.supports_sasl(XOAUTH2_MECHANISM) => (
+            XOAUTH2_MECHANISM,
+            format!("user={}\x01auth=Bearer {}\x01\x01", username, token),
+        ),
+        _ => err!(
+            ErrorKind::Unsupported,
+            "Sieve server does not support logging in with these credentials, it supports: {}",
+            capabilities.sasl_mechanisms().join(", ")
+        ),
+    };
+
+    Ok((mechanism, BASE64.encode(response)))
+}
+
+/// Connects and logs in to a ManageSieve server.
+///
+/// With `ConnectionSecurity::StartTls` the connection is upgraded before logging in, and the login fails if the server does not support that.
+pub async fn connect(credentials: &SieveCredentials) -> Result<SieveClient> {
+    let server = credentials.server();
+
+    info!(
+        target: LOG_TARGET,
+        "Connecting to sieve server {}",
+        server.domain()
+    );
+
+    let tcp_stream = connection::connect_tcp(server).await?;
+
+    let mut client = match server.security() {
+        ConnectionSecurity::Tls => {
+            let tls_stream = TlsConnector::new()
+                .connect(server.domain(), tcp_stream)
+                .await?;
+
+            SieveClient::new(Box::new(tls_stream)).await?
+        }
+        ConnectionSecurity::StartTls => {
+            let mut connection = Connection::new(tcp_stream);
+
+            let capabilities = read_capabilities(&mut connection).await?;
+
+            if !capabilities.supports_starttls() {
+                err!(
+                    ErrorKind::Unsupported,
+                    "Sieve server does not support StartTls"
+                )
+            }
+
+            let (_, response) = connection.command("STARTTLS").await?;
+
+            check_response(response, "STARTTLS")?;
+
+            let tls_stream = TlsConnector::new()
+                .connect(server.domain(), connection.into_inner()?)
+                .await?;
+
+            // The server advertises its capabilities again once the connection is secure.
+            SieveClient::new(Box::new(tls_stream)).await?
+        }
+        ConnectionSecurity::Plain => SieveClient::new(Box::new(tcp_stream)).await?,
+    };
+
+    client.login(credentials.credentials()).await?;
+
+    Ok(client)
+}
+
+/// A session with a ManageSieve server, to list, fetch, upload and activate Sieve scripts.
+pub struct SieveClient {
+    connection: Connection<Box<dyn Stream>>,
+    capabilities: SieveCapabilities,
+}
+
+impl SieveClient {
+    async fn new(stream: Box<dyn Stream>) -> Result<Self> {
+        let mut connection = Connection::new(stream);
+
+        let capabilities = read_capabilities(&mut connection).await?;
+
+        Ok(Self {
+            connection,
+            capabilities,
+        })
+    }
+
+    async fn login(&mut self, credentials: &Credentials) -> Result<()> {
+        let credentials = credentials.refreshed().await?;
+        let credentials = credentials.as_ref();
+
+        debug!(
+            target: LOG_TARGET,
+            "Logging in as {}",
+            credentials.username()
+        );
+
+        let (mechanism, initial_response) = sasl_response(&self.capabilities, credentials)?;
+
+        self.connection
+            .send(&format!(
+                "AUTHENTICATE {} {}",
+                codec::string(mechanism),
+                codec::string(&initial_response)
+            ))
+            .await?;
+
+        loop {
+            match self.connection.read_line().await? {
+                // A challenge after the initial response carries the details of a failed OAuth login, which has to be answered before the server says NO.
+                codec::Line::Data(_) => self.connection.send("\"\"").await?,
+                codec::Line::Response(response) => return check_response(response, "the login"),
+            }
+        }
+    }
+
+    /// What the server supports, as advertised when the connection was opened.
+    pub fn capabilities(&self) -> &SieveCapabilities {
+        &self.capabilities
+    }
+
+    /// Lists the scripts stored on the server.
+    pub async fn list_scripts(&mut self) -> Result<Vec<SieveScript>> {
+        let (lines, response) = self.connection.command("LISTSCRIPTS").await?;
+
+        check_response(response, "LISTSCRIPTS")?;
+
+        let scripts = lines
+            .into_iter()
+            .filter_map(|line| {
+                let mut tokens = line.into_iter();
+
+                let name = match tokens.next() {
+                    Some(Token::String(name)) => name,
+                    _ => return None,
+                };
+
+                let active = matches!(tokens.next(), Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("ACTIVE"));
+
+                Some(SieveScript { name, active })
+            })
+            .collect();
+
+        Ok(scripts)
+    }
+
+    /// Fetches the content of a script.
+    pub async fn get_script<N: AsRef<str>>(&mut self, name: N) -> Result<String> {
+        let (lines, response) = self
+            .connection
+            .command(&format!("GETSCRIPT {}", codec::string(name.as_ref())))
+            .await?;
+
+        check_response(response, "GETSCRIPT")?;
+
+        match lines.into_iter().flatten().next() {
+            Some(Token::String(script)) => Ok(script),
+            _ => err!(
+                ErrorKind::UnexpectedBehavior,
+                "Sieve server did not return the content of script {}",
+                name.as_ref()
+            ),
+        }
+    }
+
+    /// Uploads a script, replacing any script with the same name.
+    ///
+    /// The server checks the script before storing it, so a script with errors fails with the server's description of the problem.
+    pub async fn put_script<N: AsRef<str>, S: AsRef<str>>(
+        &mut self,
+        name: N,
+        script: S,
+    ) -> Result<()> {
+        let script = script.as_ref();
+
+        let (_, response) = self
+            .connection
+            .command(&format!(
+                "PUTSCRIPT {} {{{}+}}\r\n{}",
+                codec::string(name.as_ref()),
+                script.len(),
+                script
+            ))
+            .await?;
+
+        check_response(response, "PUTSCRIPT")
+    }
+
+    /// Makes the given script the one that is run for incoming messages, or turns off filtering entirely with `None`.
+    pub async fn set_active(&mut self, name: Option<&str>) -> Result<()> {
+        let (_, response) = self
+            .connection
+            .command(&format!(
+                "SETACTIVE {}",
+                codec::string(name.unwrap_or_default())
+            ))
+            .await?;
+
+        check_response(response, "SETACTIVE")
+    }
+
+    /// Deletes a script. Servers refuse to delete the active script.
+    pub async fn delete_script<N: AsRef<str>>(&mut self, name: N) -> Result<()> {
+        let (_, response) = self
+            .connection
+            .command(&format!("DELETESCRIPT {}", codec::string(name.as_ref())))
+            .await?;
+
+        check_response(response, "DELETESCRIPT")
+    }
+
+    pub async fn logout(mut self) -> Result<()> {
+        let (_, response) = self.connection.command("LOGOUT").await?;
+
+        // Some servers say goodbye with BYE instead of OK.
+        match response.status {
+            Status::Bye => Ok(()),
+            _ => check_response(response, "LOGOUT"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod test {
+    use super::*;
+
+    use crate::runtime::io::{BufReadExt, WriteExt};
+
+    const GREETING: &str = "\"IMPLEMENTATION\" \"Example Sieve\"\r\n\"SASL\" \"PLAIN\"\r\n\"SIEVE\" \"fileinto vacation\"\r\n\"VERSION\" \"1.0\"\r\nOK \"Ready\"\r\n";
+
+    /// Answers the commands the client is expected to send, in order.
+    async fn serve(stream: tokio::io::DuplexStream, script: Vec<(&'static str, &'static str)>) {
+        let mut stream = tokio::io::BufStream::new(stream);
+
+        stream.write_all(GREETING.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+
+        for (expected, reply) in script {
+            let mut command = String::new();
+
+            while !command.ends_with(expected) {
+                assert!(stream.read_line(&mut command).await.unwrap() > 0);
+            }
+
+            assert_eq!(command.trim_end(), expected.trim_end());
+
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            stream.flush().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn manage_scripts() {
+        let (client, server) = tokio::io::duplex(4096);
+
+        let script = "require \"fileinto\";\r\nfileinto \"Lists\";\r\n";
+
+        let server = tokio::spawn(serve(
+            server,
+            vec![
+                (
+                    "AUTHENTICATE \"PLAIN\" \"AHVzZXIAc2VjcmV0\"\r\n",
+                    "OK \"Logged in\"\r\n",
+                ),
+                (
+                    "PUTSCRIPT \"rules\" {40+}\r\nrequire \"fileinto\";\r\nfileinto \"Lists\";\r\n\r\n",
+                    "OK\r\n",
+                ),
+                (
+                    "LISTSCRIPTS\r\n",
+                    "\"vacation\"\r\n\"rules\" ACTIVE\r\nOK \"Listed\"\r\n",
+                ),
+                (
+                    "GETSCRIPT \"rules\"\r\n",
+                    "{40}\r\nrequire \"fileinto\";\r\nfileinto \"Lists\";\r\n\r\nOK\r\n",
+                ),
+                (
+                    "DELETESCRIPT \"rules\"\r\n",
+                    "NO (ACTIVE) \"You may not delete an active script\"\r\n",
+                ),
+                ("LOGOUT\r\n", "OK \"Bye\"\r\n"),
+            ],
+        ));
+
+        let mut client = SieveClient::new(Box::new(client)).await.unwrap();
+
+        assert_eq!(
+            client.capabilities().implementation(),
+            Some("Example Sieve")
+        );
+        assert!(client.capabilities().supports_extension("FILEINTO"));
+        assert!(!client.capabilities().supports_starttls());
+
+        client
+            .login(&Credentials::password("user", "secret"))
+            .await
+            .unwrap();
+
+        client.put_script("rules", script).await.unwrap();
+
+        let scripts = client.list_scripts().await.unwrap();
+
+        assert_eq!(
+            scripts,
+            vec![
+                SieveScript {
+                    name: "vacation".into(),
+                    active: false
+                },
+                SieveScript {
+                    name: "rules".into(),
+                    active: true
+                }
+            ]
+        );
+
+        assert_eq!(client.get_script("rules").await.unwrap(), script);
+
+        let error = client.delete_script("rules").await.unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("You may not delete an active script"));
+
+        client.logout().await.unwrap();
+
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn login_mechanisms() {
+        let capabilities = SieveCapabilities {
+            sasl_mechanisms: vec!["PLAIN".into(), "OAUTHBEARER".into()],
+            ..Default::default()
+        };
+
+        let (mechanism, _) =
+            sasl_response(&capabilities, &Credentials::oauth("user", "token")).unwrap();
+
+        assert_eq!(mechanism, OAUTHBEARER_MECHANISM);
+
+        let capabilities = SieveCapabilities::default();
+
+        assert!(sasl_response(&capabilities, &Credentials::password("user", "secret")).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::tree::Node;
+
+use super::incoming::types::mailbox::Mailbox;
+
+const IDLE_CAPABILITY: &str = "IDLE";
+const MOVE_CAPABILITY: &str = "MOVE";
+const QUOTA_CAPABILITY: &str = "QUOTA";
+const CONDSTORE_CAPABILITY: &str = "CONDSTORE";
+const SPECIAL_USE_CAPABILITY: &str = "SPECIAL-USE";
+
+/// An overview of what an account supports, e.g. to show in a setup wizard right after logging in or to attach to a bug report.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AccountSummary {
+    protocol: String,
+    capabilities: Vec<String>,
+    mailbox_count: usize,
+    supports_idle: bool,
+    supports_move: bool,
+    supports_quota: bool,
+    supports_condstore: bool,
+    supports_special_use: bool,
+}
+
+impl AccountSummary {
+    pub(crate) fn new<P: Into<String>>(
+        protocol: P,
+        capabilities: Vec<String>,
+        mailboxes: &Node<Mailbox>,
+    ) -> Self {
+        let has_capability = |name: &str| {
+            capabilities
+                .iter()
+                .any(|capability| capability.eq_ignore_ascii_case(name))
+        };
+
+        Self {
+            protocol: protocol.into(),
+            mailbox_count: count_mailboxes(mailboxes),
+            supports_idle: has_capability(IDLE_CAPABILITY),
+            supports_move: has_capability(MOVE_CAPABILITY),
+            supports_quota: has_capability(QUOTA_CAPABILITY),
+            supports_condstore: has_capability(CONDSTORE_CAPABILITY),
+            supports_special_use: has_capability(SPECIAL_USE_CAPABILITY),
+            capabilities,
+        }
+    }
+
+    /// The protocol used to connect to the incoming mail server, like `IMAP` or `POP3`.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Every capability the incoming mail server advertised.
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    /// The amount of mailboxes in the account, including nested mailboxes.
+    pub fn mailbox_count(&self) -> usize {
+        self.mailbox_count
+    }
+
+    /// Whether the server can push new messages to the client (IDLE).
+    pub fn supports_idle(&self) -> bool {
+        self.supports_idle
+    }
+
+    /// Whether messages can be moved between mailboxes in a single step (MOVE).
+    pub fn supports_move(&self) -> bool {
+        self.supports_move
+    }
+
+    /// Whether the server reports how much storage the account is using (QUOTA).
+    pub fn supports_quota(&self) -> bool {
+        self.supports_quota
+    }
+
+    /// Whether only the changes since the last sync can be fetched (CONDSTORE).
+    pub fn supports_condstore(&self) -> bool {
+        self.supports_condstore
+    }
+
+    /// Whether the server marks what mailboxes are used for, like the sent or trash folder (SPECIAL-USE).
+    pub fn supports_special_use(&self) -> bool {
+        self.supports_special_use
+    }
+}
+
+fn count_mailboxes(node: &Node<Mailbox>) -> usize {
+    match node {
+        Node::Root(children) => children.iter().map(count_mailboxes).sum(),
+        Node::Branch { children, .. } => 1 + children.iter().map(count_mailboxes).sum::<usize>(),
+        Node::Leaf(_) => 1,
+    }
+}
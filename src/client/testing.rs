@@ -0,0 +1,1246 @@
+//! In-memory implementations of the mail protocols, so applications can test their logic without a live mail server.
+//!
+//! Enable the `testing` feature to use these.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use mime::Mime;
+
+use crate::{
+    error::{err, Error, ErrorKind, Result},
+    runtime::io::{Read, ReadExt},
+    tree::Node,
+};
+
+use super::{
+    address::EmailAddress,
+    auth::AuthMechanism,
+    builder::MessageBuilder,
+    incoming::types::{
+        acl::AclEntry,
+        event::MailboxEvent,
+        flag::{Flag, FlagMode},
+        mailbox::{Mailbox, MailboxStats},
+        message::{Message, Preview},
+        sort::SortOrder,
+    },
+    metrics::SessionMetrics,
+    outgoing::types::{capabilities::SmtpCapabilities, sendable::SendableMessage},
+    parser,
+    protocol::{IncomingProtocol, OutgoingProtocol},
+    Headers,
+};
+
+/// A change that was requested from a [`MockIncoming`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    RenameMailbox {
+        old_name: String,
+        new_name: String,
+    },
+    CreateMailbox(String),
+    DeleteMailbox(String),
+    MarkAllRead(String),
+    SetFlags {
+        box_id: String,
+        message_ids: Vec<String>,
+        flags: Vec<Flag>,
+        mode: FlagMode,
+    },
+    Append {
+        box_id: String,
+        message: Vec<u8>,
+        flags: Vec<Flag>,
+    },
+    Logout,
+}
+
+struct MockMessage {
+    id: String,
+    raw: Vec<u8>,
+    flags: Vec<Flag>,
+}
+
+impl MockMessage {
+    fn builder(&self) -> Result<MessageBuilder> {
+        let builder = MessageBuilder::try_from(self.raw.as_slice())?;
+
+        Ok(builder.id(&self.id).flags(self.flags.clone()))
+    }
+}
+
+struct MockMailbox {
+    id: String,
+    /// The messages in this mailbox, from oldest to newest.
+    messages: Vec<MockMessage>,
+}
+
+impl MockMailbox {
+    fn to_mailbox(&self) -> Mailbox {
+        let unseen = self
+            .messages
+            .iter()
+            .filter(|message| !message.flags.contains(&Flag::Read))
+            .count();
+
+        let mut mailbox = Mailbox::new(None, true, self.id.as_str(), self.id.as_str());
+
+        mailbox.set_stats(MailboxStats::new(unseen, self.messages.len()));
+
+        mailbox
+    }
+}
+
+/// An incoming mail server that serves mailboxes and messages from memory.
+///
+/// Read operations are answered from the in-memory data, while mutations are not applied but recorded, so they can be inspected using [`MockIncoming::mutations`].
+#[derive(Default)]
+pub struct MockIncoming {
+    mailboxes: Vec<MockMailbox>,
+    capabilities: Vec<String>,
+    /// Changes that are reported once their mailbox is watched.
+    events: Vec<MailboxEvent>,
+    watching: Vec<String>,
+    mutations: Arc<Mutex<Vec<Mutation>>>,
+}
+
+impl MockIncoming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mailbox<I: Into<String>>(mut self, box_id: I) -> Self {
+        let box_id = box_id.into();
+
+        if self.find_mailbox(&box_id).is_err() {
+            self.mailboxes.push(MockMailbox {
+                id: box_id,
+                messages: Vec::new(),
+            });
+        }
+
+        self
+    }
+
+    /// Adds a capability that the mock server advertises, like `IDLE`.
+    pub fn with_capability<C: Into<String>>(mut self, capability: C) -> Self {
+        self.capabilities.push(capability.into());
+
+        self
+    }
+
+    /// Adds a raw RFC 822 message to a mailbox, creating the mailbox if it does not exist yet.
+    ///
+    /// Messages should be added from oldest to newest.
+    pub fn with_message<B: Into<String>, I: Into<String>, R: Into<Vec<u8>>>(
+        mut self,
+        box_id: B,
+        message_id: I,
+        raw: R,
+        flags: Vec<Flag>,
+    ) -> Self {
+        let box_id = box_id.into();
+
+        self = self.with_mailbox(box_id.as_str());
+
+        let message = MockMessage {
+            id: message_id.into(),
+            raw: raw.into(),
+            flags,
+        };
+
+        if let Some(mailbox) = self
+            .mailboxes
+            .iter_mut()
+            .find(|mailbox| mailbox.id == box_id)
+        {
+            mailbox.messages.push(message);
+        }
+
+        self
+    }
+
+    /// Queues a change that is reported by `next_events` once its mailbox is watched.
+    pub fn with_event(mut self, event: MailboxEvent) -> Self {
+        self.events.push(event);
+
+        self
+    }
+
+    /// A handle to the list of mutations that were requested, which stays valid after the mock is moved into an `EmailClient`.
+    pub fn mutations(&self) -> Arc<Mutex<Vec<Mutation>>> {
+        Arc::clone(&self.mutations)
+    }
+
+    fn record(&self, mutation: Mutation) {
+        if let Ok(mut mutations) = self.mutations.lock() {
+            mutations.push(mutation);
+        }
+    }
+
+    fn find_mailbox(&self, box_id: &str) -> Result<&MockMailbox> {
+        match self.mailboxes.iter().find(|mailbox| mailbox.id == box_id) {
+            Some(mailbox) => Ok(mailbox),
+            None => err!(
+                ErrorKind::MailBoxNotFound,
+                "Could not find a mailbox with id '{}'",
+                box_id
+            ),
+        }
+    }
+
+    fn find_message(&self, box_id: &str, message_id: &str) -> Result<&MockMessage> {
+        let mailbox = self.find_mailbox(box_id)?;
+
+        match mailbox
+            .messages
+            .iter()
+            .find(|message| message.id == message_id)
+        {
+            Some(message) => Ok(message),
+            None => err!(
+                ErrorKind::MessageNotFound,
+                "Could not find a message with id '{}'",
+                message_id
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl IncomingProtocol for MockIncoming {
+    async fn send_keep_alive(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn should_keep_alive(&self) -> bool {
+        false
+    }
+
+    fn protocol_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn server_banner(&self) -> Option<&str> {
+        None
+    }
+
+    fn metrics(&self) -> SessionMetrics {
+        SessionMetrics::default()
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        None
+    }
+
+    async fn is_authenticated(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn capabilities(&mut self) -> Result<Vec<String>> {
+        Ok(self.capabilities.clone())
+    }
+
+    async fn get_mailbox_stream<'a>(&'a mut self) -> Result<BoxStream<'a, Result<Mailbox>>> {
+        let mailboxes: Vec<_> = self
+            .mailboxes
+            .iter()
+            .map(|mailbox| Ok(mailbox.to_mailbox()))
+            .collect();
+
+        Ok(stream::iter(mailboxes).boxed())
+    }
+
+    async fn get_mailbox(&mut self, mailbox_id: &str) -> Result<Node<Mailbox>> {
+        Ok(Node::leaf(self.find_mailbox(mailbox_id)?.to_mailbox()))
+    }
+
+    async fn rename_mailbox(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.find_mailbox(old_name)?;
+
+        self.record(Mutation::RenameMailbox {
+            old_name: old_name.to_string(),
+            new_name: new_name.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn create_mailbox(&mut self, name: &str) -> Result<()> {
+        self.record(Mutation::CreateMailbox(name.to_string()));
+
+        Ok(())
+    }
+
+    async fn delete_mailbox(&mut self, box_id: &str) -> Result<()> {
+        self.find_mailbox(box_id)?;
+
+        self.record(Mutation::DeleteMailbox(box_id.to_string()));
+
+        Ok(())
+    }
+
+    async fn get_mailbox_flags(&mut self, box_id: &str) -> Result<Vec<Flag>> {
+        self.find_mailbox(box_id)?;
+
+        Ok(vec![
+            Flag::Read,
+            Flag::Answered,
+            Flag::Flagged,
+            Flag::Draft,
+            Flag::Deleted,
+        ])
+    }
+
+    async fn get_messages(
+        &mut self,
+        box_id: &str,
+        start: usize,
+        end: usize,
+        order: SortOrder,
+    ) -> Result<Vec<Preview>> {
+        let mailbox = self.find_mailbox(box_id)?;
+
+        let messages: Box<dyn Iterator<Item = &MockMessage>> = match order {
+            SortOrder::NewestFirst => Box::new(mailbox.messages.iter().rev()),
+            SortOrder::OldestFirst => Box::new(mailbox.messages.iter()),
+        };
+
+        // Like the real clients, messages that cannot be parsed are left out.
+        let previews = messages
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .filter_map(|message| message.builder().and_then(|builder| builder.build()).ok())
+            .collect();
+
+        Ok(previews)
+    }
+
+    async fn get_changes_since(&mut self, _: &str, _: u64) -> Result<Vec<Preview>> {
+        err!(
+            ErrorKind::Unsupported,
+            "The mock client does not support fetching changes since a mod-sequence",
+        )
+    }
+
+    async fn watch(&mut self, box_ids: &[&str]) -> Result<()> {
+        for box_id in box_ids {
+            self.find_mailbox(box_id)?;
+        }
+
+        self.watching = box_ids.iter().map(|box_id| box_id.to_string()).collect();
+
+        Ok(())
+    }
+
+    async fn next_events(&mut self) -> Result<Vec<MailboxEvent>> {
+        let (events, pending) = self
+            .events
+            .drain(..)
+            .partition(|event| self.watching.iter().any(|id| id == event.box_id()));
+
+        self.events = pending;
+
+        if events.is_empty() {
+            // A real server would keep waiting, but nothing is ever going to change in the mock.
+            err!(
+                ErrorKind::UnexpectedBehavior,
+                "The mock server has no more changes to report",
+            )
+        }
+
+        Ok(events)
+    }
+
+    async fn stop_watching(&mut self) -> Result<()> {
+        self.watching.clear();
+
+        Ok(())
+    }
+
+    async fn check_error(&mut self, error: Error) -> Error {
+        error
+    }
+
+    async fn get_messages_since_uid(
+        &mut self,
+        box_id: &str,
+        last_uid: u32,
+    ) -> Result<Vec<Preview>> {
+        let mailbox = self.find_mailbox(box_id)?;
+
+        // Numeric message ids are used as uids, like the ids of Imap messages.
+        let previews = mailbox
+            .messages
+            .iter()
+            .filter(|message| {
+                message
+                    .id
+                    .parse::<u32>()
+                    .map_or(false, |uid| uid > last_uid)
+            })
+            .filter_map(|message| message.builder().and_then(|builder| builder.build()).ok())
+            .collect();
+
+        Ok(previews)
+    }
+
+    async fn get_unread_messages(&mut self, box_id: &str, limit: usize) -> Result<Vec<Preview>> {
+        let mailbox = self.find_mailbox(box_id)?;
+
+        let previews = mailbox
+            .messages
+            .iter()
+            .rev()
+            .filter(|message| !message.flags.contains(&Flag::Read))
+            .take(limit)
+            .filter_map(|message| message.builder().and_then(|builder| builder.build()).ok())
+            .collect();
+
+        Ok(previews)
+    }
+
+    async fn get_unread_count(&mut self, box_id: &str) -> Result<usize> {
+        let mailbox = self.find_mailbox(box_id)?;
+
+        Ok(mailbox
+            .messages
+            .iter()
+            .filter(|message| !message.flags.contains(&Flag::Read))
+            .count())
+    }
+
+    async fn get_message(&mut self, box_id: &str, message_id: &str) -> Result<Message> {
+        self.find_message(box_id, message_id)?.builder()?.build()
+    }
+
+    async fn mark_all_read(&mut self, box_id: &str) -> Result<()> {
+        self.find_mailbox(box_id)?;
+
+        self.record(Mutation::MarkAllRead(box_id.to_string()));
+
+        Ok(())
+    }
+
+    async fn set_flags_bulk(
+        &mut self,
+        box_id: &str,
+        message_ids: &[&str],
+        flags: &[Flag],
+        mode: FlagMode,
+    ) -> Result<()> {
+        for message_id in message_ids {
+            self.find_message(box_id, message_id)?;
+        }
+
+        self.record(Mutation::SetFlags {
+            box_id: box_id.to_string(),
+            message_ids: message_ids.iter().map(|id| id.to_string()).collect(),
+            flags: flags.to_vec(),
+            mode,
+        });
+
+        Ok(())
+    }
+
+    async fn get_attachment(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+    ) -> Result<Vec<u8>> {
+        self.find_message(box_id, message_id)?;
+
+        err!(
+            ErrorKind::AttachmentNotFound,
+            "Could not find an attachment with id '{}'",
+            attachment_id
+        )
+    }
+
+    async fn get_attachment_with_progress(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        attachment_id: &str,
+        progress: &mut (dyn FnMut(usize, usize) + Send),
+    ) -> Result<Vec<u8>> {
+        let attachment = self
+            .get_attachment(box_id, message_id, attachment_id)
+            .await?;
+
+        progress(attachment.len(), attachment.len());
+
+        Ok(attachment)
+    }
+
+    async fn get_specific_headers(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        headers: &[&str],
+    ) -> Result<Headers> {
+        let message = self.find_message(box_id, message_id)?;
+
+        parser::message::parse_specific_headers(&message.raw, headers)
+    }
+
+    async fn get_message_source(&mut self, box_id: &str, message_id: &str) -> Result<Vec<u8>> {
+        Ok(self.find_message(box_id, message_id)?.raw.clone())
+    }
+
+    async fn get_message_part(
+        &mut self,
+        box_id: &str,
+        message_id: &str,
+        mime_type: Mime,
+    ) -> Result<Option<Vec<u8>>> {
+        let message = self.find_message(box_id, message_id)?;
+
+        let parsed = mailparse::parse_mail(&message.raw)?;
+
+        parser::message::find_part(&parsed, &mime_type)
+    }
+
+    async fn append(&mut self, box_id: &str, message: &[u8], flags: &[Flag]) -> Result<()> {
+        self.find_mailbox(box_id)?;
+
+        self.record(Mutation::Append {
+            box_id: box_id.to_string(),
+            message: message.to_vec(),
+            flags: flags.to_vec(),
+        });
+
+        Ok(())
+    }
+
+    async fn append_from_reader(
+        &mut self,
+        box_id: &str,
+        message: &mut (dyn Read + Unpin + Send),
+        length: usize,
+        flags: &[Flag],
+    ) -> Result<()> {
+        let mut buffer = Vec::with_capacity(length);
+
+        message.read_to_end(&mut buffer).await?;
+
+        self.append(box_id, &buffer, flags).await
+    }
+
+    async fn get_acl(&mut self, _: &str) -> Result<Vec<AclEntry>> {
+        err!(
+            ErrorKind::Unsupported,
+            "The mock client does not support mailbox access control lists",
+        )
+    }
+
+    async fn set_acl(&mut self, _: &str, _: &str, _: &str) -> Result<()> {
+        err!(
+            ErrorKind::Unsupported,
+            "The mock client does not support mailbox access control lists",
+        )
+    }
+
+    async fn get_my_rights(&mut self, _: &str) -> Result<String> {
+        err!(
+            ErrorKind::Unsupported,
+            "The mock client does not support mailbox access control lists",
+        )
+    }
+
+    async fn logout(&mut self) -> Result<()> {
+        self.record(Mutation::Logout);
+
+        Ok(())
+    }
+}
+
+/// An outgoing mail server that stores the messages it is asked to send in memory.
+#[derive(Default)]
+pub struct MockOutgoing {
+    sent: Arc<Mutex<Vec<SendableMessage>>>,
+    extensions: Vec<String>,
+    default_display_name: Option<String>,
+}
+
+impl MockOutgoing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A handle to the list of messages that were sent, which stays valid after the mock is moved into an `EmailClient`.
+    pub fn sent(&self) -> Arc<Mutex<Vec<SendableMessage>>> {
+        Arc::clone(&self.sent)
+    }
+
+    /// Adds a line to the EHLO response of the mock server, like `SIZE 1000` or `AUTH PLAIN XOAUTH2`.
+    pub fn with_extension<E: Into<String>>(mut self, extension: E) -> Self {
+        self.extensions.push(extension.into());
+
+        self
+    }
+
+    /// Sets the name that is shown to recipients when a message is sent from an address without a display name.
+    pub fn with_default_display_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.default_display_name = Some(name.into());
+
+        self
+    }
+}
+
+#[async_trait]
+impl OutgoingProtocol for MockOutgoing {
+    fn server_banner(&self) -> Option<&str> {
+        None
+    }
+
+    fn auth_mechanism(&self) -> Option<AuthMechanism> {
+        None
+    }
+
+    fn default_display_name(&self) -> Option<&str> {
+        self.default_display_name.as_deref()
+    }
+
+    async fn send_message(&mut self, message: SendableMessage) -> Result<()> {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(message);
+        }
+
+        Ok(())
+    }
+
+    async fn send_to(
+        &mut self,
+        message: SendableMessage,
+        _envelope_to: &[EmailAddress],
+    ) -> Result<()> {
+        self.send_message(message).await
+    }
+
+    async fn capabilities(&mut self) -> Result<SmtpCapabilities> {
+        let greeting = String::from("mock.example.com");
+
+        let lines: Vec<&String> = std::iter::once(&greeting)
+            .chain(self.extensions.iter())
+            .collect();
+
+        Ok(SmtpCapabilities::from_ehlo(&lines))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::client::{address::Address, EmailClient, ThreadableEmailClient};
+
+    const WELCOME: &str = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nSubject: Welcome\r\n\r\nHello world!";
+    const REMINDER: &str = "From: Tester <test@example.com>\r\nTo: user@example.com\r\nSubject: Reminder\r\n\r\nDon't forget!";
+
+    fn create_client() -> (EmailClient, Arc<Mutex<Vec<Mutation>>>) {
+        let incoming = MockIncoming::new()
+            .with_mailbox("Sent")
+            .with_message("INBOX", "1", WELCOME, vec![Flag::Read])
+            .with_message("INBOX", "2", REMINDER, Vec::new());
+
+        let mutations = incoming.mutations();
+
+        let client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        (client, mutations)
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn read_messages() {
+        let (mut client, _) = create_client();
+
+        assert!(client.is_authenticated().await.unwrap());
+
+        let inbox = client.get_inbox().await.unwrap();
+        let stats = inbox.data().unwrap().stats().unwrap();
+
+        assert_eq!(stats.total(), 2);
+        assert_eq!(stats.unseen(), 1);
+
+        let previews = client
+            .get_messages(&"INBOX".into(), 0_usize, 10_usize)
+            .await
+            .unwrap();
+
+        let subjects: Vec<_> = previews.iter().map(|preview| preview.subject()).collect();
+
+        assert_eq!(subjects, vec![Some("Reminder"), Some("Welcome")]);
+
+        let previews = client
+            .get_messages_sorted(&"INBOX".into(), 0_usize, 1_usize, SortOrder::OldestFirst)
+            .await
+            .unwrap();
+
+        assert_eq!(previews[0].subject(), Some("Welcome"));
+
+        client.set_max_page_size(1);
+
+        let previews = client.get_messages_page(&"INBOX".into(), 0).await.unwrap();
+
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].subject(), Some("Reminder"));
+
+        client.set_max_page_size(0);
+
+        let previews = client.get_messages_page(&"INBOX".into(), 0).await.unwrap();
+
+        assert_eq!(previews.len(), 1);
+
+        let message = client
+            .get_message(&"INBOX".into(), &"1".into())
+            .await
+            .unwrap();
+
+        assert_eq!(message.flags(), &vec![Flag::Read]);
+
+        assert!(client
+            .get_message(&"INBOX".into(), &"3".into())
+            .await
+            .is_err());
+        assert!(client.get_mailbox(&"Trash".into()).await.is_err());
+
+        let sent = client.get_mailbox_by_name("sent", false).await.unwrap();
+
+        assert_eq!(sent.id(), "Sent");
+        assert!(client.get_mailbox_by_name("Trash", true).await.is_err());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn unread_messages() {
+        let (mut client, _) = create_client();
+
+        let previews = client
+            .get_unread_messages(&"INBOX".into(), 10)
+            .await
+            .unwrap();
+
+        let subjects: Vec<_> = previews.iter().map(|preview| preview.subject()).collect();
+
+        assert_eq!(subjects, vec![Some("Reminder")]);
+
+        let previews = client
+            .get_unread_messages(&"INBOX".into(), 0)
+            .await
+            .unwrap();
+
+        assert!(previews.is_empty());
+
+        assert_eq!(client.get_unread_count(&"INBOX".into()).await.unwrap(), 1);
+        assert_eq!(client.get_unread_count(&"Sent".into()).await.unwrap(), 0);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn messages_since_uid() {
+        let (mut client, _) = create_client();
+
+        let previews = client
+            .get_messages_since_uid(&"INBOX".into(), 1)
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = previews.iter().map(|preview| preview.id()).collect();
+
+        assert_eq!(ids, vec!["2"]);
+
+        let previews = client
+            .get_messages_since_uid(&"INBOX".into(), 2)
+            .await
+            .unwrap();
+
+        assert!(previews.is_empty());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn watch_mailboxes() {
+        use crate::client::event::{MailboxEvent, MailboxEventKind};
+        use futures::StreamExt;
+
+        let incoming = MockIncoming::new()
+            .with_mailbox("INBOX")
+            .with_mailbox("Sent")
+            .with_mailbox("Trash")
+            .with_event(MailboxEvent::new("INBOX", MailboxEventKind::Exists(3)))
+            .with_event(MailboxEvent::new("Trash", MailboxEventKind::Expunge(1)))
+            .with_event(MailboxEvent::new(
+                "Sent",
+                MailboxEventKind::Stats(MailboxStats::new(0, 5)),
+            ));
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        assert!(client.watch_all(&["Unknown".into()]).await.is_err());
+
+        let events: Vec<_> = client
+            .watch_all(&["INBOX".into(), "Sent".into()])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        // The stream ends with the error the mock returns when it runs out of changes.
+        assert_eq!(events.len(), 3);
+        assert!(events[2].is_err());
+
+        let boxes: Vec<_> = events[..2]
+            .iter()
+            .map(|event| event.as_ref().unwrap().box_id())
+            .collect();
+
+        assert_eq!(boxes, vec!["INBOX", "Sent"]);
+        assert!(matches!(
+            events[0].as_ref().unwrap().kind(),
+            MailboxEventKind::Exists(3)
+        ));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn specific_headers() {
+        let (mut client, _) = create_client();
+
+        let headers = client
+            .get_specific_headers(&"INBOX".into(), &"2".into(), &["Subject", "List-Id"])
+            .await
+            .unwrap();
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.get("Subject").map(String::as_str), Some("Reminder"));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn missing_messages() {
+        use crate::error::ErrorKind;
+
+        let (mut client, _) = create_client();
+
+        // The sent mailbox is empty.
+        let err = client
+            .get_message(&"Sent".into(), &"1".into())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::MessageNotFound));
+
+        let err = client
+            .get_message(&"INBOX".into(), &"3".into())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::MessageNotFound));
+
+        let empty = client
+            .get_messages(&"Sent".into(), 0_usize, 10_usize)
+            .await
+            .unwrap();
+
+        assert!(empty.is_empty());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn skip_broken_messages() {
+        // Without a sender, this message cannot be turned into a preview.
+        let broken = "To: user@example.com\r\nSubject: Broken\r\n\r\nNo sender";
+
+        let incoming = MockIncoming::new()
+            .with_message("INBOX", "1", WELCOME, Vec::new())
+            .with_message("INBOX", "2", broken, Vec::new());
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        let previews = client
+            .get_messages(&"INBOX".into(), 0_usize, 10_usize)
+            .await
+            .unwrap();
+
+        let subjects: Vec<_> = previews.iter().map(|preview| preview.subject()).collect();
+
+        assert_eq!(subjects, vec![Some("Welcome")]);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn summarize_account() {
+        let incoming = MockIncoming::new()
+            .with_capability("IMAP4rev1")
+            .with_capability("idle")
+            .with_capability("MOVE")
+            .with_mailbox("INBOX")
+            .with_mailbox("Sent");
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        let summary = client.summarize().await.unwrap();
+
+        assert_eq!(summary.protocol(), "Mock");
+        assert_eq!(summary.mailbox_count(), 2);
+        assert!(summary.supports_idle());
+        assert!(summary.supports_move());
+        assert!(!summary.supports_quota());
+        assert!(!summary.supports_condstore());
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn cancelled_request() {
+        use crate::{
+            client::connection::ConnectionState,
+            error::ErrorKind,
+            runtime::time::{timeout, Duration},
+        };
+
+        let (mut client, _) = create_client();
+
+        // Nothing is ever written to this reader, so the append hangs until it is cancelled.
+        let (mut reader, _writer) = tokio::io::duplex(64);
+
+        let box_id = "INBOX".into();
+
+        let append = client.append_from_reader(&box_id, &mut reader, 10, &[]);
+
+        assert!(timeout(Duration::from_millis(10), append).await.is_err());
+
+        let err = client.get_mailbox_list().await.unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::Cancelled));
+        assert!(matches!(client.state(), ConnectionState::Error(_)));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn outgoing_capabilities() {
+        let outgoing = MockOutgoing::new()
+            .with_extension("SIZE 1000")
+            .with_extension("AUTH PLAIN XOAUTH2");
+
+        let mut client = EmailClient::new(Box::new(MockIncoming::new()), Box::new(outgoing));
+
+        let capabilities = client.get_outgoing_capabilities().await.unwrap();
+
+        assert_eq!(capabilities.max_size(), Some(1000));
+        assert!(capabilities.supports_auth("XOAUTH2"));
+        assert!(!capabilities.supports("CHUNKING"));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn save_to_sent() {
+        let incoming = MockIncoming::new().with_mailbox("Sent");
+        let mutations = incoming.mutations();
+
+        let outgoing = MockOutgoing::new();
+        let sent = outgoing.sent();
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(outgoing));
+
+        let message = || -> SendableMessage {
+            MessageBuilder::new()
+                .recipients(("Tester", "test@example.com"))
+                .senders(("User", "user@example.com"))
+                .subject("Hello")
+                .text("Hello world!")
+                .build()
+                .unwrap()
+        };
+
+        client.save_to_sent(Some("Sent".into()));
+        client.send_message(message()).await.unwrap();
+
+        assert!(matches!(
+            mutations.lock().unwrap().last(),
+            Some(Mutation::Append { box_id, .. }) if box_id == "Sent"
+        ));
+
+        client.save_to_sent(Some("Missing".into()));
+
+        let err = client.send_message(message()).await.unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::SentCopyNotSaved));
+        assert_eq!(sent.lock().unwrap().len(), 2);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn default_display_name() {
+        let incoming = MockIncoming::new().with_mailbox("Sent");
+        let mutations = incoming.mutations();
+
+        let outgoing = MockOutgoing::new().with_default_display_name("Default Name");
+        let sent = outgoing.sent();
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(outgoing));
+
+        let message: SendableMessage = MessageBuilder::new()
+            .recipients(("Tester", "test@example.com"))
+            .senders(Address::single(None, "user@example.com".to_string()))
+            .subject("Hello")
+            .text("Hello world!")
+            .build()
+            .unwrap();
+
+        client.save_to_sent(Some("Sent".into()));
+        client.send_message(message).await.unwrap();
+
+        let delivered: String = sent.lock().unwrap()[0].clone().try_into().unwrap();
+
+        let copy = match mutations.lock().unwrap().last() {
+            Some(Mutation::Append { message, .. }) => String::from_utf8(message.clone()).unwrap(),
+            _ => panic!("Sent copy was not appended"),
+        };
+
+        assert!(delivered.contains("Default Name"));
+        assert!(copy.contains("Default Name"));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn export_mailbox() {
+        let (mut client, _) = create_client();
+
+        let mut mbox = Vec::new();
+
+        let exported = client
+            .export_mailbox(&"INBOX".into(), &mut mbox)
+            .await
+            .unwrap();
+
+        assert_eq!(exported, 2);
+
+        let mbox = String::from_utf8(mbox).unwrap();
+
+        assert!(mbox.starts_with("From test@example.com "));
+        assert_eq!(mbox.matches("\n\nFrom test@example.com ").count(), 1);
+        assert!(mbox.find("Welcome").unwrap() < mbox.find("Reminder").unwrap());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn export_mailbox_with_broken_message() {
+        let broken = "To: user@example.com\r\nSubject: Broken\r\n\r\nNo sender";
+
+        let incoming = MockIncoming::new()
+            .with_message("INBOX", "1", WELCOME, Vec::new())
+            .with_message("INBOX", "2", broken, Vec::new())
+            .with_message("INBOX", "3", REMINDER, Vec::new());
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        // One message per page, so the broken message makes up a page of its own.
+        client.set_max_page_size(1);
+
+        let mut mbox = Vec::new();
+
+        let exported = client
+            .export_mailbox(&"INBOX".into(), &mut mbox)
+            .await
+            .unwrap();
+
+        assert_eq!(exported, 2);
+
+        let mbox = String::from_utf8(mbox).unwrap();
+
+        assert_eq!(mbox.matches("Subject: Welcome").count(), 1);
+        assert_eq!(mbox.matches("Subject: Reminder").count(), 1);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn record_mutations() {
+        let (mut client, mutations) = create_client();
+
+        client.mark_all_read(&"INBOX".into()).await.unwrap();
+        client
+            .set_flags_bulk(
+                &"INBOX".into(),
+                &["1".into(), "2".into()],
+                &[Flag::Flagged],
+                FlagMode::Add,
+            )
+            .await
+            .unwrap();
+        client
+            .append_from_reader(
+                &"Sent".into(),
+                &mut WELCOME.as_bytes(),
+                WELCOME.len(),
+                &[Flag::Read],
+            )
+            .await
+            .unwrap();
+        client.logout().await.unwrap();
+
+        let mutations = mutations.lock().unwrap();
+
+        assert_eq!(mutations.len(), 4);
+        assert_eq!(mutations[0], Mutation::MarkAllRead(String::from("INBOX")));
+        assert_eq!(
+            mutations[2],
+            Mutation::Append {
+                box_id: String::from("Sent"),
+                message: WELCOME.as_bytes().to_vec(),
+                flags: vec![Flag::Read],
+            }
+        );
+        assert_eq!(mutations[3], Mutation::Logout);
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn mailbox_stream() {
+        let (mut client, _) = create_client();
+
+        let streamed: Vec<String> = client
+            .get_mailbox_stream()
+            .await
+            .unwrap()
+            .map(|mailbox| mailbox.unwrap().id().to_string())
+            .collect()
+            .await;
+
+        let list = client.get_mailbox_list().await.unwrap();
+
+        let listed: Vec<_> = list.flatten().iter().map(|mailbox| mailbox.id()).collect();
+
+        assert_eq!(streamed, listed);
+        assert!(streamed.contains(&String::from("Sent")));
+
+        // A stream that is dropped halfway leaves the connection in an unknown state.
+        let mut mailboxes = Box::pin(client.get_mailbox_stream().await.unwrap());
+
+        mailboxes.next().await.unwrap().unwrap();
+
+        drop(mailboxes);
+
+        let err = client.get_mailbox_list().await.unwrap_err();
+
+        assert!(matches!(err.kind(), ErrorKind::Cancelled));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn prefetch_read_messages() {
+        use crate::runtime::time::{sleep, Duration};
+
+        let (client, _) = create_client();
+
+        let client = ThreadableEmailClient::from(client).with_prefetch(10);
+
+        let box_id = "INBOX".into();
+
+        let previews = client
+            .get_messages(&box_id, 0_usize, 10_usize)
+            .await
+            .unwrap();
+
+        assert_eq!(previews.len(), 2);
+
+        let read = (box_id.clone(), "1".into());
+
+        for _ in 0..100 {
+            if client.as_ref().read().await.message_cache.contains(&read) {
+                break;
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        // Only the message that was already read is prefetched, into the same cache the client uses for every message.
+        assert_eq!(client.as_ref().read().await.message_cache.len(), 1);
+
+        let message = client.get_message(&read.0, &read.1).await.unwrap();
+
+        assert_eq!(message.subject(), Some("Welcome"));
+
+        // Prefetched messages are dropped like any other cached message when they change.
+        client
+            .as_ref()
+            .write()
+            .await
+            .set_flags_bulk(&box_id, &["1".into()], &[Flag::Flagged], FlagMode::Add)
+            .await
+            .unwrap();
+
+        assert!(client.as_ref().read().await.message_cache.is_empty());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn message_cache() {
+        let (mut client, _) = create_client();
+
+        let box_id = "INBOX".into();
+        let message_id = "2".into();
+
+        let message = client.get_message(&box_id, &message_id).await.unwrap();
+
+        assert!(message.flags().is_empty());
+        assert_eq!(client.message_cache.len(), 1);
+
+        let cached = client.get_message(&box_id, &message_id).await.unwrap();
+
+        assert_eq!(cached.subject(), Some("Reminder"));
+
+        client
+            .set_flags_bulk(&box_id, &["2".into()], &[Flag::Read], FlagMode::Add)
+            .await
+            .unwrap();
+
+        assert!(client.message_cache.is_empty());
+
+        client.set_message_cache_size(0);
+
+        client.get_message(&box_id, &message_id).await.unwrap();
+
+        assert!(client.message_cache.is_empty());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn rename_drops_cached_subtree() {
+        let incoming = MockIncoming::new()
+            .with_message("INBOX", "1", WELCOME, Vec::new())
+            .with_message("INBOX.Work", "1", WELCOME, Vec::new())
+            .with_message("INBOX.Work.Old", "1", REMINDER, Vec::new());
+
+        let mut client = EmailClient::new(Box::new(incoming), Box::new(MockOutgoing::new()));
+
+        for box_id in ["INBOX", "INBOX.Work", "INBOX.Work.Old"] {
+            client
+                .get_message(&box_id.into(), &"1".into())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(client.message_cache.len(), 3);
+
+        client
+            .rename_mailbox(&"INBOX.Work".into(), "Projects")
+            .await
+            .unwrap();
+
+        // The message in `INBOX.Work.Old` now lives in `INBOX.Projects.Old`, so its cached copy is dropped as well.
+        assert_eq!(client.message_cache.len(), 1);
+        assert!(client.message_cache.contains(&("INBOX".into(), "1".into())));
+
+        client.delete_mailbox(&"INBOX".into()).await.unwrap();
+
+        assert!(client.message_cache.is_empty());
+    }
+}
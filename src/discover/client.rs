@@ -1,4 +1,14 @@
-use super::{Config, Result};
+use log::warn;
+
+use crate::runtime::time::{timeout, Duration};
+
+use super::{
+    error::{Error, ErrorKind},
+    Config, Result, LOG_TARGET,
+};
+
+/// How many times DNS discovery is attempted before giving up.
+const DNS_ATTEMPTS: usize = 2;
 
 pub struct Client {}
 
@@ -37,4 +47,44 @@ impl Client {
 
         Ok(config)
     }
+
+    /// Runs DNS discovery with a timeout, retrying once after a timeout or connection error, as lookups can be slow or flaky on mobile networks.
+    ///
+    /// The errors of every failed attempt are added to `errors`.
+    pub async fn from_dns_with_retry<D: AsRef<str>>(
+        domain: D,
+        dns_timeout: Duration,
+        errors: &mut Vec<Error>,
+    ) -> Option<Config> {
+        for attempt in 1..=DNS_ATTEMPTS {
+            let error = match timeout(dns_timeout, Self::from_dns(domain.as_ref())).await {
+                Ok(Ok(config)) => return Some(config),
+                Ok(Err(error)) => error,
+                Err(_) => Error::new(
+                    ErrorKind::Timeout,
+                    format!(
+                        "DNS discovery for {} timed out after {:?}",
+                        domain.as_ref(),
+                        dns_timeout
+                    ),
+                ),
+            };
+
+            // A domain without the records would give the same answer again.
+            let retry = attempt < DNS_ATTEMPTS && error.is_connection_error();
+
+            errors.push(error);
+
+            if retry {
+                warn!(
+                    target: LOG_TARGET,
+                    "DNS discovery attempt {} for {} failed, retrying",
+                    attempt,
+                    domain.as_ref()
+                );
+            }
+        }
+
+        None
+    }
 }
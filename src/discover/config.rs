@@ -1,29 +1,25 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::client::connection::ConnectionSecurity;
+use crate::client::{
+    connection::ConnectionSecurity, Credentials, IncomingEmailProtocol, OutgoingEmailProtocol,
+    RemoteServer,
+};
+
+pub use crate::client::ServerConfigType;
 #[cfg(feature = "json")]
 use crate::error::Result;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub enum ServerConfigType {
-    Imap,
-    Pop,
-    Smtp,
-    Exchange,
-}
+#[cfg(feature = "imap")]
+use crate::client::ImapCredentials;
+#[cfg(feature = "pop")]
+use crate::client::PopCredentials;
+#[cfg(feature = "smtp")]
+use crate::client::SmtpCredentials;
 
-impl ServerConfigType {
-    pub fn is_outgoing(&self) -> bool {
-        match self {
-            Self::Smtp => true,
-            _ => false,
-        }
-    }
-}
+use super::error::{err, ErrorKind};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -73,9 +69,14 @@ impl ServerConfig {
     pub fn auth_type(&self) -> &Vec<AuthenticationType> {
         &self.auth_type
     }
+
+    /// The server in the form that is used to connect to it.
+    pub fn remote_server(&self) -> RemoteServer {
+        RemoteServer::new(self.domain.clone(), self.port, self.security.clone())
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum AuthenticationType {
     ClearText,
@@ -85,8 +86,12 @@ pub enum AuthenticationType {
     Unknown,
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
 pub struct OAuth2Config {
     token_url: String,
     oauth_url: String,
@@ -116,8 +121,12 @@ impl OAuth2Config {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
 pub enum ConfigType {
     MultiServer {
         incoming: Vec<ServerConfig>,
@@ -131,8 +140,12 @@ impl ConfigType {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase")
+)]
 pub struct Config {
     r#type: ConfigType,
     provider: String,
@@ -174,8 +187,217 @@ impl Config {
         &self.display_name
     }
 
+    fn find_server(&self, server_type: &ServerConfigType) -> Option<&ServerConfig> {
+        match &self.r#type {
+            ConfigType::MultiServer { incoming, outgoing } => incoming
+                .iter()
+                .chain(outgoing.iter())
+                .find(|server| server.r#type() == server_type),
+        }
+    }
+
+    /// Creates the protocol to connect to the incoming mail server in this config with, preferring Imap over Pop if it contains both.
+    pub fn build_incoming(
+        &self,
+        #[cfg_attr(not(any(feature = "imap", feature = "pop")), allow(unused_variables))]
+        credentials: Credentials,
+    ) -> super::error::Result<IncomingEmailProtocol> {
+        #[cfg(feature = "imap")]
+        {
+            if let Some(server) = self.find_server(&ServerConfigType::Imap) {
+                let credentials = ImapCredentials::new(server.remote_server(), credentials);
+
+                return Ok(IncomingEmailProtocol::Imap(credentials));
+            }
+        }
+
+        #[cfg(feature = "pop")]
+        {
+            if let Some(server) = self.find_server(&ServerConfigType::Pop) {
+                let credentials = PopCredentials::new(server.remote_server(), credentials);
+
+                return Ok(IncomingEmailProtocol::Pop(credentials));
+            }
+        }
+
+        // Exchange configs (like Office 365) often also list Imap and Smtp servers, which are used above if they are present.
+        if self.find_server(&ServerConfigType::Exchange).is_some() {
+            err!(
+                ErrorKind::UnsupportedServer,
+                "The config for {} only contains an Exchange server, which is not supported",
+                self.provider
+            )
+        }
+
+        err!(
+            ErrorKind::InvalidConfig,
+            "The config for {} does not contain a supported incoming mail server",
+            self.provider
+        )
+    }
+
+    /// Creates the protocol to connect to the outgoing mail server in this config with.
+    pub fn build_outgoing(
+        &self,
+        #[cfg_attr(not(feature = "smtp"), allow(unused_variables))] credentials: Credentials,
+    ) -> super::error::Result<OutgoingEmailProtocol> {
+        #[cfg(feature = "smtp")]
+        {
+            if let Some(server) = self.find_server(&ServerConfigType::Smtp) {
+                let credentials = SmtpCredentials::new(server.remote_server(), credentials);
+
+                return Ok(OutgoingEmailProtocol::Smtp(credentials));
+            }
+        }
+
+        if self.find_server(&ServerConfigType::Exchange).is_some() {
+            err!(
+                ErrorKind::UnsupportedServer,
+                "The config for {} only contains an Exchange server, which is not supported",
+                self.provider
+            )
+        }
+
+        err!(
+            ErrorKind::InvalidConfig,
+            "The config for {} does not contain a supported outgoing mail server",
+            self.provider
+        )
+    }
+
     #[cfg(feature = "json")]
     pub fn to_json(&self) -> Result<String> {
-        parse::json::to_json(self)
+        crate::client::parser::json::to_json(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "imap")]
+    #[test]
+    fn build_protocols() {
+        let pop = ServerConfig::new(
+            ServerConfigType::Pop,
+            995,
+            "pop.example.com",
+            ConnectionSecurity::Tls,
+            vec![AuthenticationType::ClearText],
+        );
+
+        let imap = ServerConfig::new(
+            ServerConfigType::Imap,
+            993,
+            "imap.example.com",
+            ConnectionSecurity::Tls,
+            vec![AuthenticationType::ClearText],
+        );
+
+        let config = Config::new(
+            ConfigType::new_multiserver(vec![pop, imap], Vec::new()),
+            "example.com",
+            None,
+            None::<String>,
+        );
+
+        let incoming = config
+            .build_incoming(Credentials::password("user", "password"))
+            .unwrap();
+
+        match incoming {
+            IncomingEmailProtocol::Imap(credentials) => {
+                assert_eq!(credentials.server().domain(), "imap.example.com");
+                assert_eq!(credentials.server().port(), 993);
+            }
+            _ => panic!("Expected the imap server to be preferred"),
+        }
+
+        assert!(config
+            .build_outgoing(Credentials::password("user", "password"))
+            .is_err());
+    }
+
+    #[test]
+    fn exchange_only() {
+        let exchange = ServerConfig::new(
+            ServerConfigType::Exchange,
+            443,
+            "outlook.office365.com",
+            ConnectionSecurity::Tls,
+            vec![AuthenticationType::OAuth2],
+        );
+
+        let config = Config::new(
+            ConfigType::new_multiserver(vec![exchange.clone()], Vec::new()),
+            "office365.com",
+            None,
+            None::<String>,
+        );
+
+        let error = config
+            .build_incoming(Credentials::password("user", "password"))
+            .err()
+            .unwrap();
+
+        assert!(matches!(error.kind(), ErrorKind::UnsupportedServer));
+
+        let imap = ServerConfig::new(
+            ServerConfigType::Imap,
+            993,
+            "outlook.office365.com",
+            ConnectionSecurity::Tls,
+            vec![AuthenticationType::OAuth2],
+        );
+
+        let config = Config::new(
+            ConfigType::new_multiserver(vec![exchange, imap], Vec::new()),
+            "office365.com",
+            None,
+            None::<String>,
+        );
+
+        assert!(config
+            .build_incoming(Credentials::password("user", "password"))
+            .is_ok());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_roundtrip() {
+        let imap = ServerConfig::new(
+            ServerConfigType::Imap,
+            993,
+            "imap.example.com",
+            ConnectionSecurity::Tls,
+            vec![AuthenticationType::ClearText, AuthenticationType::OAuth2],
+        );
+
+        let smtp = ServerConfig::new(
+            ServerConfigType::Smtp,
+            587,
+            "smtp.example.com",
+            ConnectionSecurity::StartTls,
+            vec![AuthenticationType::ClearText],
+        );
+
+        let config = Config::new(
+            ConfigType::new_multiserver(vec![imap], vec![smtp]),
+            "example.com",
+            Some(OAuth2Config::new(
+                "https://example.com/token",
+                "https://example.com/auth",
+                vec!["mail"],
+            )),
+            Some("Example Mail"),
+        );
+
+        let json = serde_json::to_string(&config).unwrap();
+
+        assert!(json.contains("\"displayName\":\"Example Mail\""));
+
+        let parsed: Config = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed, config);
     }
 }
@@ -5,13 +5,17 @@ use autoconfig::error::Error as AutoconfigError;
 #[cfg(feature = "autodiscover")]
 use ms_autodiscover::error::Error as AutodiscoverError;
 
-use dns_mail_discover::error::Error as DnsDiscoverError;
+use dns_mail_discover::error::{Error as DnsDiscoverError, ErrorKind as DnsDiscoverErrorKind};
 
 #[derive(Debug)]
 pub enum ErrorKind {
     InvalidEmailAddress,
     InvalidConfig,
+    /// The config only contains servers that use a protocol this crate can not connect to, like Exchange.
+    UnsupportedServer,
     NotFound(Vec<Error>),
+    /// A discovery mechanism did not respond in time.
+    Timeout,
     DnsDiscover(DnsDiscoverError),
     #[cfg(feature = "autoconfig")]
     Autoconfig(AutoconfigError),
@@ -36,6 +40,20 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// Whether the discovery timed out or could not reach the server, which may not happen again when it is retried.
+    ///
+    /// Failed DNS lookups are not included, the resolver already retries those itself.
+    pub fn is_connection_error(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Timeout => true,
+            ErrorKind::DnsDiscover(error) => matches!(
+                error.kind(),
+                DnsDiscoverErrorKind::Io(_) | DnsDiscoverErrorKind::NoBytesSent
+            ),
+            _ => false,
+        }
+    }
 }
 
 impl error::Error for Error {}
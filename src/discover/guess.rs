@@ -0,0 +1,173 @@
+use async_native_tls::TlsConnector;
+use futures::{future::join_all, Future};
+use log::debug;
+
+use crate::{
+    client::{connection::ConnectionSecurity, default_port},
+    runtime::{
+        net::TcpStream,
+        time::{timeout, Duration},
+    },
+};
+
+use super::{
+    config::{AuthenticationType, Config, ConfigType, ServerConfig, ServerConfigType},
+    error::{err, ErrorKind, Result},
+    LOG_TARGET,
+};
+
+/// How long connecting to a single guessed server may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Host names (prefixed to the domain) that mail servers commonly use. They are probed on the standard implicit TLS port.
+static CANDIDATES: [(ServerConfigType, &str); 6] = [
+    (ServerConfigType::Imap, "imap"),
+    (ServerConfigType::Imap, "mail"),
+    (ServerConfigType::Pop, "pop"),
+    (ServerConfigType::Pop, "pop3"),
+    (ServerConfigType::Smtp, "smtp"),
+    (ServerConfigType::Smtp, "mail"),
+];
+
+/// Whether a TLS connection can be set up with the given server within the probe timeout.
+async fn accepts_tls(host: &str, port: u16) -> bool {
+    let connect = async {
+        let tcp_stream = TcpStream::connect((host, port)).await.ok()?;
+
+        TlsConnector::new().connect(host, tcp_stream).await.ok()
+    };
+
+    matches!(timeout(PROBE_TIMEOUT, connect).await, Ok(Some(_)))
+}
+
+/// The host names and ports to probe for a domain, the first one of each server type that answers is preferred.
+fn candidates(domain: &str) -> impl Iterator<Item = (ServerConfigType, String, u16)> + '_ {
+    CANDIDATES.iter().map(move |(server_type, prefix)| {
+        let port = default_port(server_type.clone(), ConnectionSecurity::Tls);
+
+        (server_type.clone(), format!("{}.{}", prefix, domain), port)
+    })
+}
+
+/// Creates a config from the well known host names (like `imap.<domain>` and `smtp.<domain>`) that accept a TLS connection.
+///
+/// This is a last resort for small or self hosted domains that do not publish any autoconfig or SRV records. Every candidate is probed at the same time, so this takes at most a few seconds.
+pub async fn from_common_guesses<D: AsRef<str>>(domain: D) -> Result<Config> {
+    from_guesses(domain.as_ref(), |host, port| async move {
+        accepts_tls(&host, port).await
+    })
+    .await
+}
+
+async fn from_guesses<P, F>(domain: &str, probe: P) -> Result<Config>
+where
+    P: Fn(String, u16) -> F,
+    F: Future<Output = bool>,
+{
+    let probes = candidates(domain).map(|(server_type, host, port)| {
+        let accepted = probe(host.clone(), port);
+
+        async move {
+            if !accepted.await {
+                return None;
+            }
+
+            debug!(target: LOG_TARGET, "Found a server at {}:{}", host, port);
+
+            Some(ServerConfig::new(
+                server_type,
+                port,
+                host,
+                ConnectionSecurity::Tls,
+                vec![AuthenticationType::ClearText],
+            ))
+        }
+    });
+
+    let (outgoing, incoming): (Vec<_>, Vec<_>) = join_all(probes)
+        .await
+        .into_iter()
+        .flatten()
+        .partition(|server| server.r#type().is_outgoing());
+
+    if incoming.is_empty() && outgoing.is_empty() {
+        err!(
+            ErrorKind::NotFound(Vec::new()),
+            "None of the common mail server names for {} accepted a connection",
+            domain
+        )
+    }
+
+    let config = Config::new(
+        ConfigType::new_multiserver(incoming, outgoing),
+        domain,
+        None,
+        None::<String>,
+    );
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn candidate_order() {
+        let candidates: Vec<_> = candidates("example.com").collect();
+
+        assert_eq!(
+            candidates,
+            [
+                (
+                    ServerConfigType::Imap,
+                    String::from("imap.example.com"),
+                    993
+                ),
+                (
+                    ServerConfigType::Imap,
+                    String::from("mail.example.com"),
+                    993
+                ),
+                (ServerConfigType::Pop, String::from("pop.example.com"), 995),
+                (ServerConfigType::Pop, String::from("pop3.example.com"), 995),
+                (
+                    ServerConfigType::Smtp,
+                    String::from("smtp.example.com"),
+                    465
+                ),
+                (
+                    ServerConfigType::Smtp,
+                    String::from("mail.example.com"),
+                    465
+                ),
+            ]
+        );
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn first_accepted_guess() {
+        // Only the generic `mail` host and the second Pop host are up.
+        let config = from_guesses("example.com", |host, port| async move {
+            host == "mail.example.com" || (host == "pop3.example.com" && port == 995)
+        })
+        .await
+        .unwrap();
+
+        let ConfigType::MultiServer { incoming, outgoing } = config.config_type();
+
+        let incoming: Vec<_> = incoming.iter().map(|server| server.domain()).collect();
+        let outgoing: Vec<_> = outgoing.iter().map(|server| server.domain()).collect();
+
+        assert_eq!(incoming, ["mail.example.com", "pop3.example.com"]);
+        assert_eq!(outgoing, ["mail.example.com"]);
+        assert_eq!(config.provider(), "example.com");
+
+        let error = from_guesses("example.com", |_, _| async { false })
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::NotFound(_)));
+    }
+}
@@ -1,22 +1,30 @@
 mod client;
 pub mod config;
 mod error;
+mod guess;
 mod parse;
 
 use error::{err, Result};
 pub use error::{Error, ErrorKind};
+pub use guess::from_common_guesses;
 
 use config::{AuthenticationType, ConfigType, ServerConfig, ServerConfigType};
 
 use client::Client;
-use validator::validate_email;
+
+use crate::{runtime::time::Duration, validate_email_address};
 
 use self::config::Config;
 
 const INVALID_EMAIL_MESSAGE: &str = "Invalid email address";
 
+const LOG_TARGET: &str = "dust_mail::discover";
+
+/// How long a single DNS discovery attempt may take, as used by `from_email`.
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn parse_domain<E: AsRef<str>>(email: E) -> Result<String> {
-    if !validate_email(email.as_ref()) {
+    if !validate_email_address(email.as_ref()) {
         err!(ErrorKind::InvalidEmailAddress, "{}", INVALID_EMAIL_MESSAGE);
     };
 
@@ -36,6 +44,17 @@ fn parse_domain<E: AsRef<str>>(email: E) -> Result<String> {
 pub async fn from_email<E: AsRef<str>, P: AsRef<str>>(
     email: E,
     password: Option<P>,
+) -> Result<Config> {
+    from_email_with_timeout(email, password, DEFAULT_DNS_TIMEOUT).await
+}
+
+/// Like `from_email`, but with a custom timeout for each DNS discovery attempt.
+///
+/// DNS is the last discovery mechanism that is tried, so this bounds how long discovery can take when the others already failed.
+pub async fn from_email_with_timeout<E: AsRef<str>, P: AsRef<str>>(
+    email: E,
+    password: Option<P>,
+    dns_timeout: Duration,
 ) -> Result<Config> {
     let email = email.as_ref();
     let domain = parse_domain(email)?;
@@ -62,9 +81,11 @@ pub async fn from_email<E: AsRef<str>, P: AsRef<str>>(
         }
     }
 
-    let result = Client::from_dns(domain).await;
+    if let Some(config) = Client::from_dns_with_retry(&domain, dns_timeout, &mut errors).await {
+        return Ok(config);
+    }
 
-    match result {
+    match from_common_guesses(&domain).await {
         Ok(config) => return Ok(config),
         Err(error) => errors.push(error),
     }
@@ -85,4 +106,27 @@ mod test {
 
         println!("{:?}", config);
     }
+
+    #[test]
+    fn retried_errors() {
+        use dns_mail_discover::error::{Error as DnsError, ErrorKind as DnsErrorKind};
+
+        use super::error::{Error, ErrorKind};
+
+        let timeout = Error::new(ErrorKind::Timeout, "Timed out");
+        let unreachable = Error::new(
+            ErrorKind::DnsDiscover(DnsError::new(DnsErrorKind::NoBytesSent, "No bytes sent")),
+            "Dns discover failed",
+        );
+        let missing = Error::new(
+            ErrorKind::DnsDiscover(DnsError::new(DnsErrorKind::NotFound, "No records")),
+            "Dns discover failed",
+        );
+        let invalid = Error::new(ErrorKind::InvalidConfig, "Invalid config");
+
+        assert!(timeout.is_connection_error());
+        assert!(unreachable.is_connection_error());
+        assert!(!missing.is_connection_error());
+        assert!(!invalid.is_connection_error());
+    }
 }
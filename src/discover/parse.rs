@@ -20,7 +20,7 @@ impl AutoConfigParser {
         {
             let domain: String = server.hostname()?.into();
 
-            let port: u16 = server.port()?.clone();
+            let port: u16 = *server.port()?;
 
             let security: ConnectionSecurity = match server.security_type() {
                 Some(security) => match security {
@@ -80,13 +80,9 @@ impl AutoConfigParser {
 
         let config_type = ConfigType::MultiServer { incoming, outgoing };
 
-        let oauth2_config = autoconfig.oauth2().map(|config| {
-            OAuth2Config::new(
-                config.token_url().into(),
-                config.auth_url().into(),
-                config.scope(),
-            )
-        });
+        let oauth2_config = autoconfig
+            .oauth2()
+            .map(|config| OAuth2Config::new(config.token_url(), config.auth_url(), config.scope()));
 
         Config::new(config_type, provider, oauth2_config, display_name)
     }
@@ -102,7 +98,7 @@ impl AutodiscoverParser {
 
         let protocol = account.protocol()?;
 
-        let port = protocol.port()?.clone();
+        let port = *protocol.port()?;
         let domain = protocol.server()?;
         let security = if protocol.ssl() {
             ConnectionSecurity::Tls
@@ -119,13 +115,13 @@ impl AutodiscoverParser {
                 let server_config =
                     ServerConfig::new(ServerConfigType::Imap, port, domain, security, auth_type);
 
-                return Some(server_config);
+                Some(server_config)
             }
             Smtp => {
                 let server_config =
                     ServerConfig::new(ServerConfigType::Smtp, port, domain, security, auth_type);
 
-                return Some(server_config);
+                Some(server_config)
             }
             _ => None,
         }
@@ -70,19 +70,31 @@ pub enum ErrorKind {
     ParseInt(ParseIntError),
     /// Failed to parse a socket address which is used to connect to the remote mail server
     ParseAddress,
+    /// Failed to connect to the remote mail server through the configured proxy.
+    Proxy,
     /// Failed to parse provided login config.
     InvalidLoginConfig,
     /// Failed to parse mail message.
     ParseMessage(MailParseError),
     InvalidMessage,
+    /// The message is larger than the mail server accepts.
+    MessageTooLarge,
     /// Error from the remote mail server.
     MailServer,
     /// Failed to serialize the given data to JSON.
     SerializeJSON,
     ParseEmailAddress(AddressParseError),
     ParseString(Utf8Error),
+    /// Failed to decode the content transfer encoding (base64, quoted-printable) of a message part.
+    DecodeContent,
     MailBoxNotFound,
     NoClientAvailable,
+    /// A value that was passed to the client is not valid.
+    InvalidInput,
+    /// A request was cancelled halfway through, which left the connection in an unknown state.
+    Cancelled,
+    /// The message was sent, but saving a copy of it to the sent mailbox failed.
+    SentCopyNotSaved,
 }
 
 #[derive(Debug)]
@@ -102,6 +114,25 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// Whether this error was caused by a problem with the connection to the mail server, rather than the request itself.
+    pub fn is_connection_error(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Io(_) | ErrorKind::Tls(_) | ErrorKind::Cancelled => true,
+            #[cfg(feature = "imap")]
+            ErrorKind::Imap(ImapError::Io(_)) | ErrorKind::Imap(ImapError::ConnectionLost) => true,
+            #[cfg(feature = "pop")]
+            ErrorKind::Pop(err) => matches!(
+                err.kind(),
+                async_pop::error::ErrorKind::Io(_)
+                    | async_pop::error::ErrorKind::Tls(_)
+                    | async_pop::error::ErrorKind::ConnectionClosed
+            ),
+            #[cfg(feature = "smtp")]
+            ErrorKind::Smtp(SmtpError::Io(_)) => true,
+            _ => false,
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -120,60 +151,52 @@ impl error::Error for Error {
 }
 
 #[cfg(feature = "pop")]
-impl_from_error!(PopError, |err| ErrorKind::Pop(err), "Error from pop server");
+impl_from_error!(PopError, ErrorKind::Pop, "Error from pop server");
 #[cfg(feature = "imap")]
-impl_from_error!(
-    ImapError,
-    |err| ErrorKind::Imap(err),
-    "Error from imap server"
-);
+impl_from_error!(ImapError, ErrorKind::Imap, "Error from imap server");
 #[cfg(feature = "smtp")]
-impl_from_error!(
-    SmtpError,
-    |err| ErrorKind::Smtp(err),
-    "Error from smtp server"
-);
+impl_from_error!(SmtpError, ErrorKind::Smtp, "Error from smtp server");
 impl_from_error!(
     TlsError,
-    |err| ErrorKind::Tls(err),
+    ErrorKind::Tls,
     "Error creating a secure connection"
 );
-impl_from_error!(IoError, |err| ErrorKind::Io(err), "Io operation failed");
+impl_from_error!(IoError, ErrorKind::Io, "Io operation failed");
 impl_from_error!(
     ParseTimeError,
-    |err| ErrorKind::ParseTime(err),
+    ErrorKind::ParseTime,
     "Failed to parse date time"
 );
 impl_from_error!(
     MailParseError,
-    |err| ErrorKind::ParseMessage(err),
+    ErrorKind::ParseMessage,
     "Failed to parse mail message"
 );
 impl_from_error!(
     AddressParseError,
-    |err| ErrorKind::ParseEmailAddress(err),
+    ErrorKind::ParseEmailAddress,
     "Failed to parse email address"
 );
 impl_from_error!(
     ParseIntError,
-    |err| ErrorKind::ParseInt(err),
+    ErrorKind::ParseInt,
     "Failed to parse integer value from string"
 );
 #[cfg(feature = "maildir")]
 impl_from_error!(
     maildir::MaildirError,
-    |err| ErrorKind::Maildir(err),
+    ErrorKind::Maildir,
     "Failed to store email in local directory"
 );
 #[cfg(feature = "maildir")]
 impl_from_error!(
     maildir::MailEntryError,
-    |err| ErrorKind::MailEntry(err),
+    ErrorKind::MailEntry,
     "Failed to retrieve email from local directory"
 );
 impl_from_error!(
     Utf8Error,
-    |err| ErrorKind::ParseString(err),
+    ErrorKind::ParseString,
     "Failed to parse bytes to utf-8 string"
 );
 
@@ -1,9 +1,12 @@
 mod runtime;
 pub mod tree;
 
+pub mod cache;
 pub mod client;
 pub mod error;
 
+pub use client::address::validate_email_address;
+
 #[cfg(feature = "discover")]
 pub mod discover;
 
@@ -1,22 +1,38 @@
-pub mod io {
+// Which of the re-exports below are used depends on the enabled protocols, so they are allowed to go unused.
 
+pub mod io {
     #[cfg(feature = "runtime-async-std")]
-    pub(crate) use async_std::io::{Read, Write};
+    #[allow(unused_imports)]
+    pub(crate) use async_std::io::{BufRead, BufReadExt, Read, ReadExt, Write, WriteExt};
 
     #[cfg(feature = "runtime-tokio")]
+    #[allow(unused_imports)]
     pub(crate) use tokio::io::{
-        AsyncBufRead as BufRead, AsyncRead as Read, AsyncWrite as Write, BufStream,
+        AsyncBufRead as BufRead, AsyncBufReadExt as BufReadExt, AsyncRead as Read,
+        AsyncReadExt as ReadExt, AsyncWrite as Write, AsyncWriteExt as WriteExt, BufStream,
     };
+
+    /// Closes the writing half of a stream, after which the other side reads the end of the stream.
+    #[cfg(feature = "imap")]
+    pub(crate) async fn shutdown<W: Write + Unpin + ?Sized>(stream: &mut W) -> std::io::Result<()> {
+        #[cfg(feature = "runtime-async-std")]
+        return WriteExt::close(stream).await;
+
+        #[cfg(feature = "runtime-tokio")]
+        return WriteExt::shutdown(stream).await;
+    }
 }
 
 pub mod time {
     #[cfg(feature = "runtime-async-std")]
-    pub use async_std::task::sleep;
+    #[allow(unused_imports)]
+    pub use async_std::{future::timeout, task::sleep};
     #[cfg(feature = "runtime-async-std")]
     pub use std::time::{Duration, Instant};
 
     #[cfg(feature = "runtime-tokio")]
-    pub use tokio::time::{sleep, Duration, Instant};
+    #[allow(unused_imports)]
+    pub use tokio::time::{sleep, timeout, Duration, Instant};
 }
 
 pub mod thread {
@@ -25,13 +41,30 @@ pub mod thread {
 
     #[cfg(feature = "runtime-tokio")]
     pub(crate) use tokio::{sync::RwLock, task::spawn};
+
+    /// Runs a blocking function, like an expensive computation, on a thread where it does not hold up other tasks.
+    #[cfg(any(feature = "imap", feature = "pop"))]
+    pub(crate) async fn spawn_blocking<T: Send + 'static, F: FnOnce() -> T + Send + 'static>(
+        function: F,
+    ) -> T {
+        #[cfg(feature = "runtime-async-std")]
+        return async_std::task::spawn_blocking(function).await;
+
+        #[cfg(feature = "runtime-tokio")]
+        match tokio::task::spawn_blocking(function).await {
+            Ok(result) => result,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
 }
 
 pub mod net {
     #[cfg(feature = "runtime-async-std")]
+    #[allow(unused_imports)]
     pub(crate) use async_std::net::TcpStream;
 
     #[cfg(feature = "runtime-tokio")]
+    #[allow(unused_imports)]
     pub(crate) use tokio::net::TcpStream;
 }
 
@@ -96,6 +96,23 @@ impl<T: Default> Node<T> {
         }
     }
 
+    /// The data of every node in the tree, parents before their children.
+    pub fn flatten(&self) -> Vec<&T> {
+        let mut items = Vec::new();
+
+        if let Some(data) = self.data() {
+            items.push(data);
+        }
+
+        if let Node::Root(children) | Node::Branch { children, .. } = self {
+            for child in children {
+                items.append(&mut child.flatten());
+            }
+        }
+
+        items
+    }
+
     pub fn find<P: Find<T>>(&self, predicate: &P) -> Option<&Self> {
         match self {
             Node::Leaf(data) | Node::Branch { data, .. } if predicate.find(data) => Some(self),
@@ -114,7 +131,7 @@ impl<T: Default> Node<T> {
 
     pub fn find_mut<P: Find<T>>(&mut self, predicate: &P) -> Option<&mut Self> {
         match self {
-            Node::Leaf(data) | Node::Branch { data, .. } if predicate.find(&data) => Some(self),
+            Node::Leaf(data) | Node::Branch { data, .. } if predicate.find(data) => Some(self),
             Node::Root(children) | Node::Branch { children, .. } => {
                 for child in children {
                     if let Some(found) = Self::find_mut(child, predicate) {
@@ -175,4 +192,14 @@ mod test {
 
         assert_eq!(None, test_tree.find(&GreaterThanFour));
     }
+
+    #[test]
+    fn test_flatten() {
+        let test_tree = Node::Root(vec![
+            Node::branch(1, vec![2.into(), Node::branch(3, vec![4.into()])]),
+            5.into(),
+        ]);
+
+        assert_eq!(test_tree.flatten(), [&1, &2, &3, &4, &5]);
+    }
 }